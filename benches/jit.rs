@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+use simple_interpreter::ast::Ast;
+use simple_interpreter::driver::Driver;
+use simple_interpreter::hir::Hir;
+use simple_interpreter::interpreter::Interpreter;
+use simple_interpreter::jit;
+
+const RUNS: u32 = 200;
+
+// Straight-line arithmetic with a procedure called many times, the shape of
+// program where the tree-walker spends most of its time on dispatch.
+fn generate_program(statements: usize, calls: usize) -> String {
+    let mut source = String::from("program Bench;\nvar a, b, c : real;\n");
+    source.push_str("procedure Work(x : real; y : real);\nvar t : real;\nbegin\n");
+    for i in 0..statements {
+        source.push_str(&format!("   t := (x + {i}) * (y - 2) / 3 + x div 7;\n"));
+    }
+    source.push_str("   t := t\nend;\n\nbegin\n   a := 1.5;\n   b := 2;\n   c := 0;\n");
+    for _ in 0..calls {
+        source.push_str("   Work(a, b);\n   c := a * b + c div 3;\n");
+    }
+    source.push_str("   c := c + 1\nend.\n");
+    source
+}
+
+fn parse(source: &str) -> (Ast, Hir) {
+    let mut driver = Driver::new(source);
+    driver.analyze().expect("invalid program");
+    let hir = driver.hir().expect("analyzed").clone();
+    (driver.into_ast().expect("analyzed"), hir)
+}
+
+fn time(runs: u32, mut f: impl FnMut()) -> Duration {
+    // Warm up once so first-touch costs do not skew the numbers.
+    f();
+    let start = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    let source = generate_program(50, 200);
+    let (ast, hir) = parse(&source);
+
+    let compile_start = Instant::now();
+    let program = jit::compile(&ast).expect("benchmark program must be JIT-compilable");
+    let compile_time = compile_start.elapsed();
+
+    let interpreted = time(RUNS, || {
+        Interpreter::new().interpret(&ast, &hir).unwrap();
+    });
+    let jitted = time(RUNS, || {
+        program.run().unwrap();
+    });
+
+    println!("interpreter : {interpreted:>12?} per run");
+    println!("jit         : {jitted:>12?} per run (compiled once in {compile_time:?})");
+    println!(
+        "speedup     : {:>12.1}x",
+        interpreted.as_secs_f64() / jitted.as_secs_f64()
+    );
+}
@@ -0,0 +1,48 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use simple_interpreter::lexer::{is_identifier_char, is_identifier_start, Lexer};
+use simple_interpreter::token::Token;
+
+// Properties of the lexer on any input: it never panics, tokens cover the
+// source in order, and together with their trivia they reproduce it.
+// Identifiers start with a letter or underscore, so a number is never
+// followed directly by one.
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut rebuilt = String::new();
+    let mut end = 0;
+    let mut previous_was_number = false;
+    for token in Lexer::with_trivia(source) {
+        let Ok(token) = token else {
+            return;
+        };
+        assert!(token.span.start >= end && token.span.end <= source.len());
+        end = token.span.end;
+        let text = &source[token.span.start..token.span.end];
+        match &token.token {
+            Token::Id(name) => {
+                assert!(text.starts_with(is_identifier_start));
+                assert!(text.chars().all(is_identifier_char));
+                assert!(!previous_was_number || !token.leading.is_empty());
+                assert_eq!(*name, text.to_ascii_lowercase());
+            }
+            Token::IntegerConst(_) | Token::RealConst(_) => {
+                assert!(text.starts_with(|ch: char| ch.is_ascii_digit()));
+            }
+            _ => {}
+        }
+        previous_was_number = matches!(token.token, Token::IntegerConst(_) | Token::RealConst(_))
+            && token.trailing.is_empty();
+        for trivia in &token.leading {
+            rebuilt.push_str(trivia.text(source));
+        }
+        rebuilt.push_str(text);
+        for trivia in &token.trailing {
+            rebuilt.push_str(trivia.text(source));
+        }
+    }
+    assert_eq!(rebuilt, source);
+});
@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use simple_interpreter::check::parse_and_check_with_dialect;
+use simple_interpreter::dialect::Dialect;
+
+// The first byte picks the dialect, the rest is the program. Any panic or
+// stack overflow is a bug; errors are expected.
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, source)) = data.split_first() else {
+        return;
+    };
+    let dialect = match selector % 3 {
+        0 => Dialect::Iso,
+        1 => Dialect::Turbo,
+        _ => Dialect::Fpc,
+    };
+    if let Ok(source) = std::str::from_utf8(source) {
+        let _ = parse_and_check_with_dialect(source, dialect);
+    }
+});
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// A file the CLI writes next to running a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Artifact {
+    AstSvg,
+    DumpText,
+    DumpJson,
+    Coverage,
+    Profile,
+    Turtle,
+    Trace,
+}
+
+impl Artifact {
+    pub const ALL: [Artifact; 7] = [
+        Artifact::AstSvg,
+        Artifact::DumpText,
+        Artifact::DumpJson,
+        Artifact::Coverage,
+        Artifact::Profile,
+        Artifact::Turtle,
+        Artifact::Trace,
+    ];
+
+    pub fn default_name(self) -> &'static str {
+        match self {
+            Artifact::AstSvg => "ast.svg",
+            Artifact::DumpText => "dump.txt",
+            Artifact::DumpJson => "dump.json",
+            Artifact::Coverage => "coverage.lcov",
+            Artifact::Profile => "profile.json",
+            Artifact::Turtle => "turtle.svg",
+            Artifact::Trace => "trace.jsonl",
+        }
+    }
+}
+
+impl FromStr for Artifact {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Artifact::ALL
+            .into_iter()
+            .find(|artifact| artifact.to_string() == s)
+            .ok_or_else(|| format!("Unknown artifact '{s}'"))
+    }
+}
+
+impl fmt::Display for Artifact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Artifact::AstSvg => "ast-svg",
+            Artifact::DumpText => "dump-text",
+            Artifact::DumpJson => "dump-json",
+            Artifact::Coverage => "coverage",
+            Artifact::Profile => "profile",
+            Artifact::Turtle => "turtle",
+            Artifact::Trace => "trace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Decides where each artifact goes and writes it there. A file only
+// appears under its name once it is complete: it is written to a temporary
+// file in the same directory and renamed, so a failed write or a crash
+// never leaves a partial artifact behind, nor clobbers the previous one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArtifactWriter {
+    // Relative names are resolved against it; the working directory if unset
+    dir: Option<PathBuf>,
+    names: HashMap<Artifact, String>,
+}
+
+impl ArtifactWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_name(mut self, artifact: Artifact, name: impl Into<String>) -> Self {
+        self.names.insert(artifact, name.into());
+        self
+    }
+
+    pub fn path(&self, artifact: Artifact) -> PathBuf {
+        let name = self
+            .names
+            .get(&artifact)
+            .map_or(artifact.default_name(), String::as_str);
+        match &self.dir {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        }
+    }
+
+    // Writes the whole artifact at once and returns where it went.
+    pub fn write(&self, artifact: Artifact, contents: impl AsRef<[u8]>) -> io::Result<PathBuf> {
+        let (mut file, pending) = self.create(artifact)?;
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+        drop(file);
+        pending.commit()
+    }
+
+    // For an artifact written bit by bit, such as a trace: the file to write
+    // to, and the handle that puts it in place once it is complete.
+    // Dropping the handle without committing removes the file.
+    pub fn create(&self, artifact: Artifact) -> io::Result<(File, PendingArtifact)> {
+        let path = self.path(artifact);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = temp_path(&path);
+        let file = File::create(&temp)?;
+        Ok((
+            file,
+            PendingArtifact {
+                temp,
+                path,
+                committed: false,
+            },
+        ))
+    }
+}
+
+// `.name.tmp-PID` beside `path`, so the rename stays on one file system.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.tmp-{}", name, std::process::id()))
+}
+
+#[derive(Debug)]
+pub struct PendingArtifact {
+    temp: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl PendingArtifact {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Moves the finished file to its name, replacing any earlier one.
+    pub fn commit(mut self) -> io::Result<PathBuf> {
+        fs::rename(&self.temp, &self.path)?;
+        self.committed = true;
+        Ok(self.path.clone())
+    }
+}
+
+impl Drop for PendingArtifact {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
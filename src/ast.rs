@@ -1,43 +1,164 @@
-use crate::{symbols::Symbol, token::Token};
-use std::{cell::RefCell, fmt};
+use crate::{
+    token::{LocatedToken, Location, Span, Token, Trivia},
+    value::Value,
+    visitor::ensure_stack,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, fmt, ops, ops::Range};
 
-#[derive(Debug, Clone)]
+// Ids are handed out in the order the parser finishes nodes, so parsing the
+// same text always gives the same ids. Passes change nodes in place and
+// never renumber them, so an id found in the optimized or analyzed tree
+// still names the node parsed from the source.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// In JSON a node is an object whose `kind` names the variant; see
+// `ast.schema.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
 pub enum ASTNode {
     Program {
         name: String,
-        block: Box<ASTNode>,
+        block: NodeId,
     },
     Block {
-        declarations: Vec<Box<ASTNode>>,
-        compound_statement: Box<ASTNode>,
+        declarations: Vec<NodeId>,
+        compound_statement: NodeId,
     },
+    // `type_params` are the `T` of `generic procedure Swap<T>(var a, b: T);`
     ProcedureDecl {
         proc_name: String,
-        params: Vec<Box<ASTNode>>,
-        block_node: Box<ASTNode>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        type_params: Vec<String>,
+        params: Vec<NodeId>,
+        block_node: NodeId,
     },
     Param {
-        var_node: Box<ASTNode>,
-        type_node: Box<ASTNode>,
+        var_node: NodeId,
+        type_node: NodeId,
+        mode: ParamMode,
     },
     ProcedureCall {
         proc_name: String,
-        arguments: Vec<Box<ASTNode>>,
-        proc_symbol: RefCell<Option<Box<Symbol>>>,
+        arguments: Vec<NodeId>,
     },
+    // `x : integer = 1`. The initializer runs when the block is entered,
+    // in declaration order.
     VarDecl {
-        var_node: Box<ASTNode>,
-        type_node: Box<ASTNode>,
+        var_node: NodeId,
+        type_node: NodeId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        initializer: Option<NodeId>,
     },
     Type {
         value: String,
     },
+    // `type_name = type_node;` in a TYPE section. The type is a `Type` for
+    // an alias or a `ProcedureType`.
+    TypeDecl {
+        type_name: String,
+        type_node: NodeId,
+    },
+    // `procedure(params)`, the type of a procedure value
+    ProcedureType {
+        params: Vec<NodeId>,
+    },
+    // `class ... end`: `VarDecl` fields, `MethodHeading`s and `Property`s
+    ClassType {
+        members: Vec<NodeId>,
+    },
+    // A method as declared inside a class
+    MethodHeading {
+        method_kind: MethodKind,
+        name: String,
+        params: Vec<NodeId>,
+    },
+    // `property name: type read reader write writer;` in a class. The
+    // reader is a field; the writer is a field or a method taking the value.
+    Property {
+        name: String,
+        type_node: NodeId,
+        reader: Option<String>,
+        writer: Option<String>,
+    },
+    // The implementation of a method, `procedure TCounter.Add(n: integer);`
+    MethodDecl {
+        class_name: String,
+        method_kind: MethodKind,
+        method_name: String,
+        params: Vec<NodeId>,
+        block_node: NodeId,
+    },
+    // `object.field`; also a constructor call without arguments, as in
+    // `TCounter.Create`, once the analyzer knows `object` names a class
+    FieldAccess {
+        object: NodeId,
+        field: String,
+    },
+    // `object.method(arguments)`, where `object` is an instance or, for a
+    // constructor, the class
+    MethodCall {
+        object: NodeId,
+        method: String,
+        arguments: Vec<NodeId>,
+    },
     Compound {
-        children: Vec<Box<ASTNode>>,
+        children: Vec<NodeId>,
+    },
+    // `IF condition THEN statement ELSE statement`. An ELSE belongs to the
+    // nearest IF before it that has none.
+    If {
+        condition: NodeId,
+        then_branch: NodeId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        else_branch: Option<NodeId>,
+    },
+    // `WHILE condition DO statement`
+    While {
+        condition: NodeId,
+        body: NodeId,
+    },
+    // `REPEAT statements UNTIL condition`, whose body runs at least once
+    Repeat {
+        body: Vec<NodeId>,
+        condition: NodeId,
+    },
+    // `CASE selector OF arms ELSE statements END`. The statements of the
+    // ELSE part run when no arm has a label that matches.
+    Case {
+        selector: NodeId,
+        arms: Vec<NodeId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        else_branch: Option<Vec<NodeId>>,
+    },
+    // `1, 3..5: statement`. A label is an INTEGER constant, possibly signed,
+    // or a `Range` of them.
+    CaseArm {
+        labels: Vec<NodeId>,
+        statement: NodeId,
+    },
+    // `low..high`, both included
+    Range {
+        low: NodeId,
+        high: NodeId,
     },
     Assign {
-        left: Box<ASTNode>,
-        right: Box<ASTNode>,
+        left: NodeId,
+        right: NodeId,
         token: Token,
     },
     Var {
@@ -45,85 +166,1009 @@ pub enum ASTNode {
     },
     NoOp,
     UnaryOpNode {
-        expr: Box<ASTNode>,
+        expr: NodeId,
         token: Token,
     },
     BinOpNode {
-        left: Box<ASTNode>,
-        right: Box<ASTNode>,
+        left: NodeId,
+        right: NodeId,
         op: Token,
     },
     NumNode {
-        value: BuiltinNumTypes,
+        value: Value,
     },
 }
 
-#[derive(Debug, Copy, Clone)]
-pub enum BuiltinNumTypes {
-    I32(i32),
-    F32(f32),
+// How an argument is passed. VAR and OUT parameters refer to the caller's
+// variable, which gets the parameter's final value when the call returns.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParamMode {
+    #[default]
+    Value,
+    Var,
+    // Passed by value but read-only in the procedure
+    Const,
+    // Like VAR, but uninitialized on entry and must be assigned
+    Out,
+}
+
+impl ParamMode {
+    pub fn by_reference(self) -> bool {
+        matches!(self, ParamMode::Var | ParamMode::Out)
+    }
 }
 
-impl fmt::Display for BuiltinNumTypes {
+impl fmt::Display for ParamMode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            BuiltinNumTypes::I32(val) => write!(f, "{}", val),
-            BuiltinNumTypes::F32(val) => write!(f, "{}", val),
+            ParamMode::Value => Ok(()),
+            ParamMode::Var => write!(f, "VAR "),
+            ParamMode::Const => write!(f, "CONST "),
+            ParamMode::Out => write!(f, "OUT "),
         }
     }
 }
 
-impl fmt::Display for ASTNode {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MethodKind {
+    Procedure,
+    Constructor,
+    Destructor,
+}
+
+impl fmt::Display for MethodKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ASTNode::Program { name, block } => write!(f, "PROGRAM {};\n{}", name, block),
+            MethodKind::Procedure => write!(f, "PROCEDURE"),
+            MethodKind::Constructor => write!(f, "CONSTRUCTOR"),
+            MethodKind::Destructor => write!(f, "DESTRUCTOR"),
+        }
+    }
+}
+
+impl ASTNode {
+    // Nodes that make up a line of executable code, as far as coverage is
+    // concerned. Compound statements only group other statements.
+    pub fn is_statement(&self) -> bool {
+        matches!(
+            self,
+            ASTNode::Assign { .. }
+                | ASTNode::ProcedureCall { .. }
+                | ASTNode::MethodCall { .. }
+                | ASTNode::If { .. }
+                | ASTNode::While { .. }
+                | ASTNode::Repeat { .. }
+                | ASTNode::Case { .. }
+        )
+    }
+
+    // The name of the variant, as the JSON form's `kind` has it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ASTNode::Program { .. } => "Program",
+            ASTNode::Block { .. } => "Block",
+            ASTNode::ProcedureDecl { .. } => "ProcedureDecl",
+            ASTNode::Param { .. } => "Param",
+            ASTNode::ProcedureCall { .. } => "ProcedureCall",
+            ASTNode::VarDecl { .. } => "VarDecl",
+            ASTNode::Type { .. } => "Type",
+            ASTNode::TypeDecl { .. } => "TypeDecl",
+            ASTNode::ProcedureType { .. } => "ProcedureType",
+            ASTNode::ClassType { .. } => "ClassType",
+            ASTNode::MethodHeading { .. } => "MethodHeading",
+            ASTNode::Property { .. } => "Property",
+            ASTNode::MethodDecl { .. } => "MethodDecl",
+            ASTNode::FieldAccess { .. } => "FieldAccess",
+            ASTNode::MethodCall { .. } => "MethodCall",
+            ASTNode::Compound { .. } => "Compound",
+            ASTNode::If { .. } => "If",
+            ASTNode::While { .. } => "While",
+            ASTNode::Repeat { .. } => "Repeat",
+            ASTNode::Case { .. } => "Case",
+            ASTNode::CaseArm { .. } => "CaseArm",
+            ASTNode::Range { .. } => "Range",
+            ASTNode::Assign { .. } => "Assign",
+            ASTNode::Var { .. } => "Var",
+            ASTNode::NoOp => "NoOp",
+            ASTNode::UnaryOpNode { .. } => "UnaryOpNode",
+            ASTNode::BinOpNode { .. } => "BinOpNode",
+            ASTNode::NumNode { .. } => "NumNode",
+        }
+    }
+
+    pub fn children(&self) -> Vec<NodeId> {
+        match self {
+            ASTNode::Program { block, .. } => vec![*block],
+            ASTNode::Block {
+                declarations,
+                compound_statement,
+            } => declarations
+                .iter()
+                .copied()
+                .chain(std::iter::once(*compound_statement))
+                .collect(),
+            ASTNode::ProcedureDecl {
+                params, block_node, ..
+            } => params
+                .iter()
+                .copied()
+                .chain(std::iter::once(*block_node))
+                .collect(),
+            ASTNode::Param {
+                var_node,
+                type_node,
+                ..
+            } => vec![*var_node, *type_node],
+            ASTNode::VarDecl {
+                var_node,
+                type_node,
+                initializer,
+            } => [*var_node, *type_node]
+                .into_iter()
+                .chain(*initializer)
+                .collect(),
+            ASTNode::ProcedureCall { arguments, .. } => arguments.clone(),
+            ASTNode::TypeDecl { type_node, .. } | ASTNode::Property { type_node, .. } => {
+                vec![*type_node]
+            }
+            ASTNode::ProcedureType { params } | ASTNode::MethodHeading { params, .. } => {
+                params.clone()
+            }
+            ASTNode::ClassType { members } => members.clone(),
+            ASTNode::MethodDecl {
+                params, block_node, ..
+            } => params
+                .iter()
+                .copied()
+                .chain(std::iter::once(*block_node))
+                .collect(),
+            ASTNode::FieldAccess { object, .. } => vec![*object],
+            ASTNode::MethodCall {
+                object, arguments, ..
+            } => std::iter::once(*object)
+                .chain(arguments.iter().copied())
+                .collect(),
+            ASTNode::Compound { children } => children.clone(),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => [*condition, *then_branch]
+                .into_iter()
+                .chain(*else_branch)
+                .collect(),
+            ASTNode::While { condition, body } => vec![*condition, *body],
+            ASTNode::Repeat { body, condition } => {
+                body.iter().copied().chain([*condition]).collect()
+            }
+            ASTNode::Case {
+                selector,
+                arms,
+                else_branch,
+            } => [*selector]
+                .into_iter()
+                .chain(arms.iter().copied())
+                .chain(else_branch.iter().flatten().copied())
+                .collect(),
+            ASTNode::CaseArm { labels, statement } => {
+                labels.iter().copied().chain([*statement]).collect()
+            }
+            ASTNode::Range { low, high } => vec![*low, *high],
+            ASTNode::Assign { left, right, .. } | ASTNode::BinOpNode { left, right, .. } => {
+                vec![*left, *right]
+            }
+            ASTNode::UnaryOpNode { expr, .. } => vec![*expr],
+            ASTNode::Type { .. }
+            | ASTNode::Var { .. }
+            | ASTNode::NoOp
+            | ASTNode::NumNode { .. } => {
+                vec![]
+            }
+        }
+    }
+
+    // Whether the two nodes are of the same kind, with the same names,
+    // operators and values. Their children are not compared.
+    pub fn same_fields(&self, other: &ASTNode) -> bool {
+        match (self, other) {
+            (ASTNode::Program { name: a, .. }, ASTNode::Program { name: b, .. }) => a == b,
+            (
+                ASTNode::ProcedureDecl {
+                    proc_name: a,
+                    type_params: a_params,
+                    ..
+                },
+                ASTNode::ProcedureDecl {
+                    proc_name: b,
+                    type_params: b_params,
+                    ..
+                },
+            ) => a == b && a_params == b_params,
+            (ASTNode::Param { mode: a, .. }, ASTNode::Param { mode: b, .. }) => a == b,
+            (
+                ASTNode::ProcedureCall { proc_name: a, .. },
+                ASTNode::ProcedureCall { proc_name: b, .. },
+            ) => a == b,
+            (ASTNode::Type { value: a }, ASTNode::Type { value: b }) => a == b,
+            (ASTNode::TypeDecl { type_name: a, .. }, ASTNode::TypeDecl { type_name: b, .. }) => {
+                a == b
+            }
+            (
+                ASTNode::MethodHeading {
+                    method_kind: a_kind,
+                    name: a,
+                    ..
+                },
+                ASTNode::MethodHeading {
+                    method_kind: b_kind,
+                    name: b,
+                    ..
+                },
+            ) => a_kind == b_kind && a == b,
+            (
+                ASTNode::Property {
+                    name: a,
+                    reader: a_reader,
+                    writer: a_writer,
+                    ..
+                },
+                ASTNode::Property {
+                    name: b,
+                    reader: b_reader,
+                    writer: b_writer,
+                    ..
+                },
+            ) => a == b && a_reader == b_reader && a_writer == b_writer,
+            (
+                ASTNode::MethodDecl {
+                    class_name: a_class,
+                    method_kind: a_kind,
+                    method_name: a,
+                    ..
+                },
+                ASTNode::MethodDecl {
+                    class_name: b_class,
+                    method_kind: b_kind,
+                    method_name: b,
+                    ..
+                },
+            ) => a_class == b_class && a_kind == b_kind && a == b,
+            (ASTNode::FieldAccess { field: a, .. }, ASTNode::FieldAccess { field: b, .. }) => {
+                a == b
+            }
+            (ASTNode::MethodCall { method: a, .. }, ASTNode::MethodCall { method: b, .. }) => {
+                a == b
+            }
+            (ASTNode::Assign { token: a, .. }, ASTNode::Assign { token: b, .. })
+            | (ASTNode::UnaryOpNode { token: a, .. }, ASTNode::UnaryOpNode { token: b, .. })
+            | (ASTNode::BinOpNode { op: a, .. }, ASTNode::BinOpNode { op: b, .. }) => a == b,
+            (ASTNode::Var { name: a }, ASTNode::Var { name: b }) => a == b,
+            (ASTNode::NumNode { value: a }, ASTNode::NumNode { value: b }) => a == b,
+            (ASTNode::Block { .. }, ASTNode::Block { .. })
+            | (ASTNode::VarDecl { .. }, ASTNode::VarDecl { .. })
+            | (ASTNode::ProcedureType { .. }, ASTNode::ProcedureType { .. })
+            | (ASTNode::ClassType { .. }, ASTNode::ClassType { .. })
+            | (ASTNode::Compound { .. }, ASTNode::Compound { .. })
+            | (ASTNode::If { .. }, ASTNode::If { .. })
+            | (ASTNode::While { .. }, ASTNode::While { .. })
+            | (ASTNode::Repeat { .. }, ASTNode::Repeat { .. })
+            | (ASTNode::Case { .. }, ASTNode::Case { .. })
+            | (ASTNode::CaseArm { .. }, ASTNode::CaseArm { .. })
+            | (ASTNode::Range { .. }, ASTNode::Range { .. })
+            | (ASTNode::NoOp, ASTNode::NoOp) => true,
+            _ => false,
+        }
+    }
+}
+
+// All nodes of a program live in one arena and refer to each other by
+// `NodeId`, which keeps the tree cheap to clone and lets later stages keep
+// per-node data in side tables instead of inside the nodes.
+#[derive(Debug, Clone, Default)]
+pub struct Ast {
+    nodes: Vec<ASTNode>,
+    locations: Vec<Location>,
+    // Only filled when parsing from a source that keeps trivia: every token
+    // consumed, and which of them each node spans
+    tokens: Vec<LocatedToken>,
+    token_ranges: HashMap<NodeId, Range<usize>>,
+    // The bytes of the source each parsed node covers
+    spans: HashMap<NodeId, Span>,
+    root: Option<NodeId>,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Ast {
+            nodes: vec![],
+            locations: vec![],
+            tokens: vec![],
+            token_ranges: HashMap::new(),
+            spans: HashMap::new(),
+            root: None,
+        }
+    }
+
+    pub fn push(&mut self, node: ASTNode, location: Location) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(node);
+        self.locations.push(location);
+        id
+    }
+
+    // Drops every node from id `len` on, e.g. after a failed partial parse.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.nodes.truncate(len);
+        self.locations.truncate(len);
+        self.token_ranges.retain(|id, _| id.index() < len);
+        self.spans.retain(|id, _| id.index() < len);
+    }
+
+    // Where in the source the node starts; binary operations are located at
+    // their operator.
+    pub fn location(&self, id: NodeId) -> Location {
+        self.locations[id.index()]
+    }
+
+    pub(crate) fn set_location(&mut self, id: NodeId, location: Location) {
+        self.locations[id.index()] = location;
+    }
+
+    pub fn push_token(&mut self, token: LocatedToken) {
+        self.tokens.push(token);
+    }
+
+    // Removes and returns the tokens from index `at` on.
+    pub(crate) fn split_off_tokens(&mut self, at: usize) -> Vec<LocatedToken> {
+        self.tokens.split_off(at)
+    }
+
+    pub fn set_token_range(&mut self, id: NodeId, range: Range<usize>) {
+        self.token_ranges.insert(id, range);
+    }
+
+    pub(crate) fn remove_token_range(&mut self, id: NodeId) {
+        self.token_ranges.remove(&id);
+    }
+
+    // Source tokens with their trivia; together they reproduce the source
+    // text exactly. Empty unless the lexer was created with trivia.
+    pub fn tokens(&self) -> &[LocatedToken] {
+        &self.tokens
+    }
+
+    pub fn token_range(&self, id: NodeId) -> Option<Range<usize>> {
+        self.token_ranges.get(&id).cloned()
+    }
+
+    pub fn set_span(&mut self, id: NodeId, span: Span) {
+        self.spans.insert(id, span);
+    }
+
+    pub(crate) fn remove_span(&mut self, id: NodeId) {
+        self.spans.remove(&id);
+    }
+
+    // Byte offsets into the source from the start of the node's first token
+    // to the end of its last, recorded whether or not the lexer kept trivia.
+    // An empty statement has an empty span where it would be. `None` for a
+    // node that wasn't parsed from source text.
+    pub fn span(&self, id: NodeId) -> Option<Span> {
+        self.spans.get(&id).copied()
+    }
+
+    // The text of `source` the node was parsed from.
+    pub fn source_text<'s>(&self, id: NodeId, source: &'s str) -> Option<&'s str> {
+        let span = self.span(id)?;
+        source.get(span.start..span.end)
+    }
+
+    // Comments and whitespace directly before the node's first token.
+    pub fn leading_trivia(&self, id: NodeId) -> &[Trivia] {
+        match self.token_range(id) {
+            Some(range) if !range.is_empty() => &self.tokens[range.start].leading,
+            _ => &[],
+        }
+    }
+
+    // Comments and whitespace after the node's last token, up to the end
+    // of that line.
+    pub fn trailing_trivia(&self, id: NodeId) -> &[Trivia] {
+        match self.token_range(id) {
+            Some(range) if !range.is_empty() => &self.tokens[range.end - 1].trailing,
+            _ => &[],
+        }
+    }
+
+    pub fn set_root(&mut self, root: NodeId) {
+        self.root = Some(root);
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root.expect("AST has no root node")
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len() as u32).map(NodeId)
+    }
+
+    pub fn children(&self, id: NodeId) -> Vec<NodeId> {
+        self[id].children()
+    }
+
+    pub fn display(&self, id: NodeId) -> NodeDisplay<'_> {
+        NodeDisplay { ast: self, id }
+    }
+
+    // The values a CASE label matches, from `low` to `high`: one for a
+    // constant, more for a range. `None` for anything that isn't a label.
+    pub fn case_label_bounds(&self, label: NodeId) -> Option<(i32, i32)> {
+        match &self[label] {
+            ASTNode::Range { low, high } => Some((
+                self.case_label_bounds(*low)?.0,
+                self.case_label_bounds(*high)?.0,
+            )),
+            ASTNode::NumNode {
+                value: Value::Int(value),
+            } => Some((*value, *value)),
+            ASTNode::UnaryOpNode { expr, token } => {
+                let (value, _) = self.case_label_bounds(*expr)?;
+                match token {
+                    Token::Minus => Some((-value, -value)),
+                    _ => Some((value, value)),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+// Version of the JSON form of an `Ast`, bumped whenever a change to it could
+// break a consumer. `ast.schema.json` describes the current version.
+pub const AST_JSON_VERSION: u32 = 1;
+
+// The JSON form of an `Ast`: its nodes in id order, each with its location,
+// byte span and, when parsed with trivia, its token range, plus the tokens
+// themselves.
+#[derive(Serialize)]
+struct AstJson<'a> {
+    version: u32,
+    root: Option<NodeId>,
+    nodes: Vec<NodeJson<'a>>,
+    tokens: &'a [LocatedToken],
+}
+
+#[derive(Serialize)]
+struct NodeJson<'a> {
+    id: NodeId,
+    location: Location,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tokens: Option<Range<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span: Option<Span>,
+    #[serde(flatten)]
+    node: &'a ASTNode,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OwnedAstJson {
+    version: u32,
+    root: Option<NodeId>,
+    nodes: Vec<OwnedNodeJson>,
+    #[serde(default)]
+    tokens: Vec<LocatedToken>,
+}
+
+#[derive(Deserialize)]
+struct OwnedNodeJson {
+    id: NodeId,
+    location: Location,
+    #[serde(default)]
+    tokens: Option<Range<usize>>,
+    #[serde(default)]
+    span: Option<Span>,
+    #[serde(flatten)]
+    node: ASTNode,
+}
+
+impl Serialize for Ast {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        AstJson {
+            version: AST_JSON_VERSION,
+            root: self.root,
+            nodes: self
+                .ids()
+                .map(|id| NodeJson {
+                    id,
+                    location: self.location(id),
+                    tokens: self.token_range(id),
+                    span: self.span(id),
+                    node: &self[id],
+                })
+                .collect(),
+            tokens: &self.tokens,
+        }
+        .serialize(serializer)
+    }
+}
+
+// Rejects documents of another version and ones whose node ids, child
+// references or token ranges don't fit together. The nodes must form trees,
+// with the root at the top of one of them, just like the parser builds them.
+impl<'de> Deserialize<'de> for Ast {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = OwnedAstJson::deserialize(deserializer)?;
+        if json.version != AST_JSON_VERSION {
+            return Err(de::Error::custom(format!(
+                "unsupported AST version {}, expected {}",
+                json.version, AST_JSON_VERSION
+            )));
+        }
+
+        let mut ast = Ast::new();
+        ast.tokens = json.tokens;
+        for (index, node) in json.nodes.into_iter().enumerate() {
+            if node.id.index() != index {
+                return Err(de::Error::custom(format!(
+                    "node {} is listed at position {}",
+                    node.id, index
+                )));
+            }
+            if let Some(range) = node.tokens {
+                if range.start > range.end || range.end > ast.tokens.len() {
+                    return Err(de::Error::custom(format!(
+                        "node {} has token range {:?} outside the tokens",
+                        node.id, range
+                    )));
+                }
+                ast.set_token_range(node.id, range);
+            }
+            if let Some(span) = node.span {
+                if span.start > span.end {
+                    return Err(de::Error::custom(format!(
+                        "node {} has span {}..{} that ends before it starts",
+                        node.id, span.start, span.end
+                    )));
+                }
+                ast.set_span(node.id, span);
+            }
+            ast.push(node.node, node.location);
+        }
+
+        let mut has_parent = vec![false; ast.len()];
+        for id in ast.ids() {
+            for child in ast.children(id) {
+                match has_parent.get_mut(child.index()) {
+                    None => {
+                        return Err(de::Error::custom(format!(
+                            "node {} refers to {}, which does not exist",
+                            id, child
+                        )))
+                    }
+                    Some(true) => {
+                        return Err(de::Error::custom(format!(
+                            "node {} has more than one parent",
+                            child
+                        )))
+                    }
+                    Some(seen) => *seen = true,
+                }
+            }
+        }
+        // With at most one parent each, nodes in a cycle are the ones that
+        // can't be reached from a node without a parent
+        let mut pending: Vec<NodeId> = ast.ids().filter(|id| !has_parent[id.index()]).collect();
+        let mut reached = 0;
+        while let Some(id) = pending.pop() {
+            reached += 1;
+            pending.extend(ast.children(id));
+        }
+        if reached != ast.len() {
+            return Err(de::Error::custom(
+                "the nodes' child references form a cycle",
+            ));
+        }
+        if let Some(root) = json.root {
+            match has_parent.get(root.index()) {
+                None => return Err(de::Error::custom(format!("root {} does not exist", root))),
+                Some(true) => return Err(de::Error::custom(format!("root {} has a parent", root))),
+                Some(false) => ast.set_root(root),
+            }
+        }
+        Ok(ast)
+    }
+}
+
+impl ops::Index<NodeId> for Ast {
+    type Output = ASTNode;
+
+    fn index(&self, id: NodeId) -> &ASTNode {
+        &self.nodes[id.index()]
+    }
+}
+
+impl ops::IndexMut<NodeId> for Ast {
+    fn index_mut(&mut self, id: NodeId) -> &mut ASTNode {
+        &mut self.nodes[id.index()]
+    }
+}
+
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(self.root()))
+    }
+}
+
+pub struct NodeDisplay<'a> {
+    ast: &'a Ast,
+    id: NodeId,
+}
+
+impl NodeDisplay<'_> {
+    fn child(&self, id: NodeId) -> NodeDisplay<'_> {
+        self.ast.display(id)
+    }
+
+    fn join(&self, ids: &[NodeId], separator: &str) -> String {
+        ids.iter()
+            .map(|id| self.child(*id).to_string())
+            .collect::<Vec<String>>()
+            .join(separator)
+    }
+
+    fn write_operand(&self, f: &mut fmt::Formatter<'_>, operand: NodeId) -> fmt::Result {
+        ensure_stack(|| match &self.ast[operand] {
+            ASTNode::BinOpNode { .. } => write!(f, "({})", self.child(operand)),
+            _ => write!(f, "{}", self.child(operand)),
+        })
+    }
+}
+
+impl fmt::Display for NodeDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.ast[self.id] {
+            ASTNode::Program { name, block } => {
+                write!(f, "PROGRAM {};\n{}.", name, self.child(*block))
+            }
             ASTNode::Block {
                 declarations,
                 compound_statement,
             } => {
                 for decl in declarations {
-                    write!(f, "{}\n", decl)?;
+                    writeln!(f, "{}", self.child(*decl))?;
                 }
-                write!(f, "{}", compound_statement)
+                write!(f, "{}", self.child(*compound_statement))
             }
             ASTNode::VarDecl {
                 var_node,
                 type_node,
-            } => write!(f, "VAR {} : {};", var_node, type_node),
+                initializer,
+            } => {
+                write!(
+                    f,
+                    "VAR {} : {}",
+                    self.child(*var_node),
+                    self.child(*type_node)
+                )?;
+                if let Some(initializer) = initializer {
+                    write!(f, " = {}", self.child(*initializer))?;
+                }
+                write!(f, ";")
+            }
             ASTNode::Type { value, .. } => write!(f, "{}", value),
+            ASTNode::TypeDecl {
+                type_name,
+                type_node,
+            } => write!(f, "TYPE {} = {};", type_name, self.child(*type_node)),
+            ASTNode::ProcedureType { params } => {
+                write!(f, "PROCEDURE")?;
+                if !params.is_empty() {
+                    write!(f, "({})", self.join(params, "; "))?;
+                }
+                Ok(())
+            }
+            ASTNode::ClassType { members } => {
+                writeln!(f, "CLASS")?;
+                for member in members {
+                    writeln!(f, "{}", self.child(*member))?;
+                }
+                write!(f, "END")
+            }
+            ASTNode::MethodHeading {
+                method_kind,
+                name,
+                params,
+            } => {
+                write!(f, "{method_kind} {name}")?;
+                if !params.is_empty() {
+                    write!(f, "({})", self.join(params, "; "))?;
+                }
+                write!(f, ";")
+            }
+            ASTNode::Property {
+                name,
+                type_node,
+                reader,
+                writer,
+            } => {
+                write!(f, "PROPERTY {} : {}", name, self.child(*type_node))?;
+                if let Some(reader) = reader {
+                    write!(f, " READ {reader}")?;
+                }
+                if let Some(writer) = writer {
+                    write!(f, " WRITE {writer}")?;
+                }
+                write!(f, ";")
+            }
+            ASTNode::MethodDecl {
+                class_name,
+                method_kind,
+                method_name,
+                params,
+                block_node,
+            } => {
+                write!(f, "{method_kind} {class_name}.{method_name}")?;
+                if !params.is_empty() {
+                    write!(f, "({})", self.join(params, "; "))?;
+                }
+                write!(f, ";\n{};", self.child(*block_node))
+            }
+            ASTNode::FieldAccess { object, field } => {
+                write!(f, "{}.{}", self.child(*object), field)
+            }
+            ASTNode::MethodCall {
+                object,
+                method,
+                arguments,
+            } => write!(
+                f,
+                "{}.{}({})",
+                self.child(*object),
+                method,
+                self.join(arguments, ", ")
+            ),
             ASTNode::Compound { children } => {
-                write!(f, "BEGIN\n")?;
+                writeln!(f, "BEGIN")?;
                 for child in children {
-                    write!(f, "{};\n", child)?;
+                    writeln!(f, "{};", self.child(*child))?;
                 }
                 write!(f, "END")
             }
-            ASTNode::Assign { left, right, .. } => write!(f, "{} := {}", left, right),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                write!(
+                    f,
+                    "IF {} THEN {}",
+                    self.child(*condition),
+                    self.child(*then_branch)
+                )?;
+                if let Some(else_branch) = else_branch {
+                    write!(f, " ELSE {}", self.child(*else_branch))?;
+                }
+                Ok(())
+            }
+            ASTNode::While { condition, body } => write!(
+                f,
+                "WHILE {} DO {}",
+                self.child(*condition),
+                self.child(*body)
+            ),
+            ASTNode::Repeat { body, condition } => {
+                writeln!(f, "REPEAT")?;
+                for child in body {
+                    writeln!(f, "{};", self.child(*child))?;
+                }
+                write!(f, "UNTIL {}", self.child(*condition))
+            }
+            ASTNode::Case {
+                selector,
+                arms,
+                else_branch,
+            } => {
+                writeln!(f, "CASE {} OF", self.child(*selector))?;
+                for arm in arms {
+                    writeln!(f, "{};", self.child(*arm))?;
+                }
+                if let Some(else_branch) = else_branch {
+                    writeln!(f, "ELSE")?;
+                    for child in else_branch {
+                        writeln!(f, "{};", self.child(*child))?;
+                    }
+                }
+                write!(f, "END")
+            }
+            ASTNode::CaseArm { labels, statement } => {
+                for (i, label) in labels.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", self.child(*label))?;
+                }
+                write!(f, ": {}", self.child(*statement))
+            }
+            ASTNode::Range { low, high } => {
+                write!(f, "{}..{}", self.child(*low), self.child(*high))
+            }
+            ASTNode::Assign { left, right, .. } => {
+                write!(f, "{} := {}", self.child(*left), self.child(*right))
+            }
             ASTNode::Var { name } => write!(f, "{}", name),
             ASTNode::NoOp => Ok(()),
-            ASTNode::UnaryOpNode { expr, token } => write!(f, "{}{}", token, expr),
-            ASTNode::BinOpNode { left, right, op } => write!(f, "{} {} {}", left, op, right),
+            ASTNode::UnaryOpNode { expr, token } => {
+                match token {
+                    Token::Not => write!(f, "{} ", token)?,
+                    _ => write!(f, "{}", token)?,
+                }
+                self.write_operand(f, *expr)
+            }
+            ASTNode::BinOpNode { left, right, op } => {
+                self.write_operand(f, *left)?;
+                write!(f, " {} ", op)?;
+                self.write_operand(f, *right)
+            }
             ASTNode::NumNode { value, .. } => write!(f, "{}", value),
             ASTNode::ProcedureDecl {
-                proc_name: name, ..
-            } => write!(f, "fn {name}"),
+                proc_name,
+                type_params,
+                params,
+                block_node,
+            } => {
+                if type_params.is_empty() {
+                    write!(f, "PROCEDURE {proc_name}")?;
+                } else {
+                    write!(
+                        f,
+                        "GENERIC PROCEDURE {proc_name}<{}>",
+                        type_params.join(", ")
+                    )?;
+                }
+                if !params.is_empty() {
+                    write!(f, "({})", self.join(params, "; "))?;
+                }
+                write!(f, ";\n{};", self.child(*block_node))
+            }
             ASTNode::Param {
                 var_node,
                 type_node,
-            } => write!(f, "param({}: {})", var_node.as_ref(), type_node.as_ref()),
+                mode,
+            } => write!(
+                f,
+                "{mode}{} : {}",
+                self.child(*var_node),
+                self.child(*type_node)
+            ),
             ASTNode::ProcedureCall {
                 proc_name,
                 arguments,
                 ..
-            } => write!(
-                f,
-                "{}({:#?})",
-                proc_name,
-                arguments
-                    .iter()
-                    .map(|a| a.to_string())
-                    .collect::<Vec<String>>()
-            ),
+            } => write!(f, "{}({})", proc_name, self.join(arguments, ", ")),
+        }
+    }
+}
+
+// One difference between two parsed programs, as found by `diff`. Old
+// nodes are ids in the first tree, new nodes ids in the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AstEdit {
+    // A subtree of the new program with no counterpart in the old one
+    Inserted { new: NodeId },
+    // A subtree of the old program with no counterpart in the new one
+    Removed { old: NodeId },
+    // Counterparts of the same kind that differ in a name, operator or
+    // value; their children are compared on their own
+    Changed { old: NodeId, new: NodeId },
+}
+
+// How program `b` differs from program `a`, in the order of a walk over
+// both trees. Only the trees are compared, so layout, comments and the
+// case of keywords and identifiers never show up.
+pub fn diff(a: &Ast, b: &Ast) -> Vec<AstEdit> {
+    let mut edits = vec![];
+    diff_nodes(a, a.root(), b, b.root(), &mut edits);
+    edits
+}
+
+fn diff_nodes(a: &Ast, old: NodeId, b: &Ast, new: NodeId, edits: &mut Vec<AstEdit>) {
+    if std::mem::discriminant(&a[old]) != std::mem::discriminant(&b[new]) {
+        edits.push(AstEdit::Removed { old });
+        edits.push(AstEdit::Inserted { new });
+        return;
+    }
+    if !a[old].same_fields(&b[new]) {
+        edits.push(AstEdit::Changed { old, new });
+    }
+    diff_children(a, &a.children(old), b, &b.children(new), edits);
+}
+
+// Children with identical subtrees are matched up first, keeping their
+// order, so that inserting a statement doesn't make every statement after
+// it look changed. Between two such matches, children of the same kind
+// are matched up and compared, and the rest were removed or inserted.
+fn diff_children(a: &Ast, olds: &[NodeId], b: &Ast, news: &[NodeId], edits: &mut Vec<AstEdit>) {
+    let (mut gap_i, mut gap_j) = (0, 0);
+    for (i, j) in matching(olds, news, |old, new| same_tree(a, old, b, new)) {
+        diff_gap(a, &olds[gap_i..i], b, &news[gap_j..j], edits);
+        (gap_i, gap_j) = (i + 1, j + 1);
+    }
+    diff_gap(a, &olds[gap_i..], b, &news[gap_j..], edits);
+}
+
+fn diff_gap(a: &Ast, olds: &[NodeId], b: &Ast, news: &[NodeId], edits: &mut Vec<AstEdit>) {
+    let (mut i, mut j) = (0, 0);
+    let same_kind = |old: NodeId, new: NodeId| {
+        std::mem::discriminant(&a[old]) == std::mem::discriminant(&b[new])
+    };
+    for (next_i, next_j) in matching(olds, news, same_kind)
+        .into_iter()
+        .chain([(olds.len(), news.len())])
+    {
+        for old in &olds[i..next_i] {
+            edits.push(AstEdit::Removed { old: *old });
+        }
+        for new in &news[j..next_j] {
+            edits.push(AstEdit::Inserted { new: *new });
+        }
+        if next_i < olds.len() {
+            diff_nodes(a, olds[next_i], b, news[next_j], edits);
+        }
+        (i, j) = (next_i + 1, next_j + 1);
+    }
+}
+
+// The positions of a longest common subsequence of `olds` and `news`,
+// where `matches` says which nodes may pair up.
+fn matching(
+    olds: &[NodeId],
+    news: &[NodeId],
+    matches: impl Fn(NodeId, NodeId) -> bool,
+) -> Vec<(usize, usize)> {
+    // `common[i][j]`: length of the longest common subsequence of
+    // `olds[i..]` and `news[j..]`
+    let mut common = vec![vec![0usize; news.len() + 1]; olds.len() + 1];
+    for i in (0..olds.len()).rev() {
+        for j in (0..news.len()).rev() {
+            common[i][j] = if matches(olds[i], news[j]) {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < olds.len() && j < news.len() {
+        if common[i][j] == common[i + 1][j + 1] + 1 && matches(olds[i], news[j]) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
         }
     }
+    pairs
+}
+
+fn same_tree(a: &Ast, old: NodeId, b: &Ast, new: NodeId) -> bool {
+    ensure_stack(|| {
+        let (old_children, new_children) = (a.children(old), b.children(new));
+        a[old].same_fields(&b[new])
+            && old_children.len() == new_children.len()
+            && std::iter::zip(old_children, new_children)
+                .all(|(old, new)| same_tree(a, old, b, new))
+    })
 }
@@ -0,0 +1,127 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::ast::Ast;
+use crate::directives::Switches;
+use crate::hir::Hir;
+use crate::interpreter::{InterpretResult, Interpreter};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+// Wraps the system allocator and counts allocations. With the
+// `alloc-stats` feature the CLI installs it as its `#[global_allocator]`,
+// and `bench` reports the count. It isn't installed otherwise, so ordinary
+// runs don't pay for an atomic update on every allocation.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+pub fn allocation_count() -> u64 {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+pub struct BenchReport {
+    pub iterations: u32,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub steps: u64,
+    // `None` without the `alloc-stats` feature
+    pub allocations: Option<u64>,
+    pub max_call_depth: usize,
+}
+
+// Runs an analyzed program, with the `Hir` of its analysis, `warmup` times
+// untimed, then `iterations` times timed. Steps, allocations and call depth
+// are per run; a program always executes the same way, so every timed run
+// reports the same numbers.
+pub fn run(
+    ast: &Ast,
+    hir: &Hir,
+    switches: Switches,
+    warmup: u32,
+    iterations: u32,
+) -> InterpretResult<BenchReport> {
+    let interpreter = || {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_switches(switches);
+        interpreter
+    };
+    for _ in 0..warmup {
+        interpreter().interpret(ast, hir)?;
+    }
+
+    let mut report = BenchReport {
+        iterations,
+        total: Duration::ZERO,
+        min: Duration::MAX,
+        max: Duration::ZERO,
+        steps: 0,
+        allocations: None,
+        max_call_depth: 0,
+    };
+    for _ in 0..iterations {
+        let allocations_before = allocation_count();
+        let start = Instant::now();
+
+        let mut interpreter = interpreter();
+        interpreter.interpret(ast, hir)?;
+
+        let elapsed = start.elapsed();
+        if cfg!(feature = "alloc-stats") {
+            report.allocations = Some(allocation_count() - allocations_before);
+        }
+        report.steps = interpreter.steps();
+        report.max_call_depth = interpreter.max_call_depth();
+        report.total += elapsed;
+        report.min = report.min.min(elapsed);
+        report.max = report.max.max(elapsed);
+    }
+    Ok(report)
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.iterations == 0 {
+            return writeln!(f, "no iterations run");
+        }
+        let mean = self.total / self.iterations;
+        writeln!(f, "iterations     : {}", self.iterations)?;
+        writeln!(f, "total time     : {:?}", self.total)?;
+        writeln!(
+            f,
+            "time per run   : {:?} (min {:?}, max {:?})",
+            mean, self.min, self.max
+        )?;
+        writeln!(f, "steps per run  : {}", self.steps)?;
+        match self.allocations {
+            Some(allocations) => writeln!(f, "allocations    : {allocations} per run")?,
+            None => writeln!(
+                f,
+                "allocations    : not counted (needs --features alloc-stats)"
+            )?,
+        }
+        write!(f, "max call depth : {}", self.max_call_depth)
+    }
+}
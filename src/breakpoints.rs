@@ -0,0 +1,95 @@
+use std::collections::BTreeSet;
+
+use crate::ast::{Ast, NodeId};
+use crate::call_stack::CallStack;
+use crate::interpreter::Resume;
+use crate::value::Value;
+
+// Line breakpoints and variable watchpoints. The interpreter checks them as
+// it runs and calls its break handler when one triggers.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    lines: BTreeSet<usize>,
+    watched: BTreeSet<String>,
+    // Call depth and line of the previous statement, so a breakpoint on a
+    // line with several statements triggers once per visit to the line
+    previous: Option<(usize, usize)>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns false if there already was a breakpoint on the line.
+    pub fn add_line(&mut self, line: usize) -> bool {
+        self.lines.insert(line)
+    }
+
+    pub fn remove_line(&mut self, line: usize) -> bool {
+        self.lines.remove(&line)
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = usize> + '_ {
+        self.lines.iter().copied()
+    }
+
+    // Breaks whenever an assignment changes the variable's value, in
+    // whichever frame it lives. Names are case-insensitive like Pascal
+    // identifiers.
+    pub fn watch(&mut self, name: &str) -> bool {
+        self.watched.insert(name.to_ascii_lowercase())
+    }
+
+    pub fn unwatch(&mut self, name: &str) -> bool {
+        self.watched.remove(&name.to_ascii_lowercase())
+    }
+
+    pub fn watched(&self) -> impl Iterator<Item = &str> {
+        self.watched.iter().map(String::as_str)
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.watched.clear();
+    }
+
+    // Whether a statement at `line`, `depth` frames deep, triggers a line
+    // breakpoint. Called for every statement, in order.
+    pub(crate) fn hits_line(&mut self, depth: usize, line: usize) -> bool {
+        let previous = self.previous.replace((depth, line));
+        self.lines.contains(&line) && previous != Some((depth, line))
+    }
+
+    pub(crate) fn is_watched(&self, name: &str) -> bool {
+        self.watched.contains(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BreakReason {
+    // About to run the first statement of a visit to the line
+    Breakpoint {
+        line: usize,
+    },
+    // The variable was just assigned a new value; `old` is `None` if it had
+    // no value before
+    Watchpoint {
+        name: String,
+        old: Option<Value>,
+        new: Value,
+    },
+}
+
+// Where the program stopped. `node` is the statement about to run for a
+// breakpoint and the assignment that just ran for a watchpoint.
+pub struct BreakEvent<'a> {
+    pub reason: BreakReason,
+    pub ast: &'a Ast,
+    pub node: NodeId,
+    pub call_stack: &'a CallStack,
+}
+
+// Called when a breakpoint or watchpoint triggers. The breakpoints can be
+// changed before the program resumes.
+pub type BreakHandler = Box<dyn FnMut(&BreakEvent<'_>, &mut Breakpoints) -> Resume>;
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use corosensei::stack::{DefaultStack, Stack};
+use corosensei::{CoroutineResult, ScopedCoroutine, Yielder};
+
+use crate::ast::Ast;
+use crate::hir::Hir;
+use crate::interpreter::{InterpretResult, Interpreter};
+use crate::stats::RunReport;
+use crate::visitor;
+
+// Reading the clock for every node would slow a run down noticeably, so a
+// time budget is only checked every this many steps
+const STEPS_PER_CLOCK_CHECK: u64 = 256;
+
+// What ran out when a budgeted run paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetExhausted {
+    Steps { steps: u64 },
+    Time { millis: u64 },
+}
+
+impl fmt::Display for BudgetExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetExhausted::Steps { steps } => {
+                write!(f, "The program used up its budget of {steps} steps")
+            }
+            BudgetExhausted::Time { millis } => {
+                write!(f, "The program used up its budget of {millis} ms")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunOutcome {
+    Finished(RunReport),
+    // The program stopped where its budget ran out, and goes on from there
+    // with the next `BudgetedRun::run_with_budget`
+    Paused(BudgetExhausted),
+}
+
+// What a budgeted run may use up until it next pauses.
+pub(crate) struct Budget {
+    steps: u64,
+    millis: u64,
+    // The value of `Interpreter::steps` the run may reach, and when it has
+    // to pause. No deadline if that's too far off to represent.
+    max_steps: u64,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    pub(crate) fn new(steps_so_far: u64, steps: u64, millis: u64) -> Self {
+        Budget {
+            steps,
+            millis,
+            max_steps: steps_so_far.saturating_add(steps),
+            deadline: Instant::now().checked_add(Duration::from_millis(millis)),
+        }
+    }
+
+    pub(crate) fn check(&self, steps_so_far: u64) -> Option<BudgetExhausted> {
+        if steps_so_far > self.max_steps {
+            return Some(BudgetExhausted::Steps { steps: self.steps });
+        }
+        if steps_so_far.is_multiple_of(STEPS_PER_CLOCK_CHECK)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Some(BudgetExhausted::Time {
+                millis: self.millis,
+            });
+        }
+        None
+    }
+}
+
+// What a paused run is resumed with: the steps and milliseconds of its next
+// budget, or nothing when the run is dropped and has to wind up instead.
+pub(crate) type Allowance = Option<(u64, u64)>;
+
+// How the interpreter pauses the coroutine it runs on.
+pub(crate) type Pauser = Yielder<Allowance, BudgetExhausted>;
+
+// A run of a program that pauses whenever its budget runs out, keeping the
+// interpreter as it is, so a host can run many programs in turns as a
+// fuel-based scheduler does. The interpreter walks the tree recursively, so
+// the run has a stack of its own to leave the paused walk on.
+// `Interpreter::budgeted` starts one.
+pub struct BudgetedRun<'a> {
+    coroutine:
+        ScopedCoroutine<'a, Allowance, BudgetExhausted, InterpretResult<RunReport>, DefaultStack>,
+}
+
+impl<'a> BudgetedRun<'a> {
+    pub(crate) fn new(interpreter: &'a mut Interpreter, ast: &'a Ast, hir: &'a Hir) -> Self {
+        let stack = visitor::new_stack();
+        let limit = stack.limit().get();
+        let coroutine = ScopedCoroutine::with_stack(stack, move |pauser, allowance| {
+            visitor::with_stack_limit(Some(limit), || {
+                interpreter.run_budgeted(ast, hir, pauser, allowance)
+            })
+        });
+        BudgetedRun { coroutine }
+    }
+
+    // Runs the program until it ends, or pauses it once it has evaluated
+    // `steps` more nodes or run for `millis` more milliseconds, whichever
+    // comes first. The clock is read every 256 steps, so a run may overshoot
+    // its time budget by that much work. Panics if the run already ended.
+    pub fn run_with_budget(&mut self, steps: u64, millis: u64) -> InterpretResult<RunOutcome> {
+        assert!(!self.coroutine.done(), "the run already ended");
+        let result = visitor::keep_stack_limit(|| self.coroutine.resume(Some((steps, millis))));
+        match result {
+            CoroutineResult::Yield(exhausted) => Ok(RunOutcome::Paused(exhausted)),
+            CoroutineResult::Return(result) => result.map(RunOutcome::Finished),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.coroutine.done()
+    }
+}
+
+impl Drop for BudgetedRun<'_> {
+    // A run dropped while paused fails with `InterpretError::Aborted`, so
+    // that what is on its stack is dropped on the way out
+    fn drop(&mut self) {
+        if self.coroutine.started() && !self.coroutine.done() {
+            let _ = visitor::keep_stack_limit(|| self.coroutine.resume(None));
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::fmt;
+use std::io::Write;
+
+use crate::ast::ParamMode;
+use crate::interpreter::{InterpretError, InterpretResult};
+use crate::symbols::BuiltinProcedure;
+use crate::value::{PrettyOptions, RealFormat, Value};
+
+// What a parameter of a builtin takes, or what it returns. A REAL
+// parameter also takes an INTEGER, which it gets widened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinType {
+    Integer,
+    Real,
+    Boolean,
+    Any,
+}
+
+impl BuiltinType {
+    // `value` as a parameter of this type gets it, or `None` if the type
+    // doesn't take it.
+    pub fn convert(self, value: &Value) -> Option<Value> {
+        match (self, value) {
+            (BuiltinType::Real, Value::Int(v)) => Some(Value::Real(*v as f32)),
+            _ if self.accepts(value.type_name()) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    // Whether a value of the type named `type_name`, e.g. INTEGER, fits.
+    pub fn accepts(self, type_name: &str) -> bool {
+        match self {
+            BuiltinType::Any => true,
+            BuiltinType::Real => {
+                type_name.eq_ignore_ascii_case("real") || type_name.eq_ignore_ascii_case("integer")
+            }
+            _ => type_name.eq_ignore_ascii_case(&self.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for BuiltinType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuiltinType::Integer => write!(f, "INTEGER"),
+            BuiltinType::Real => write!(f, "REAL"),
+            BuiltinType::Boolean => write!(f, "BOOLEAN"),
+            BuiltinType::Any => write!(f, "ANY"),
+        }
+    }
+}
+
+// How a builtin is called, e.g. `forward(REAL)` or `write(ANY...)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    // Lowercase, like every identifier once lexed
+    pub name: String,
+    pub params: Vec<(BuiltinType, ParamMode)>,
+    // The last parameter takes any number of arguments, including none
+    pub variadic: bool,
+    // `None` for a procedure, whose calls have no value
+    pub returns: Option<BuiltinType>,
+}
+
+impl Signature {
+    pub fn new(name: &str) -> Self {
+        Signature {
+            name: name.to_string(),
+            params: vec![],
+            variadic: false,
+            returns: None,
+        }
+    }
+
+    pub fn with_param(mut self, param_type: BuiltinType, mode: ParamMode) -> Self {
+        self.params.push((param_type, mode));
+        self
+    }
+
+    pub fn with_variadic(mut self) -> Self {
+        self.variadic = true;
+        self
+    }
+
+    pub fn with_return(mut self, return_type: BuiltinType) -> Self {
+        self.returns = Some(return_type);
+        self
+    }
+
+    pub fn accepts_count(&self, count: usize) -> bool {
+        match self.variadic {
+            true => count + 1 >= self.params.len(),
+            false => count == self.params.len(),
+        }
+    }
+
+    // The parameter the argument at `index` is passed to.
+    pub fn param(&self, index: usize) -> Option<(BuiltinType, ParamMode)> {
+        match self.params.get(index) {
+            Some(param) => Some(*param),
+            None if self.variadic => self.params.last().copied(),
+            None => None,
+        }
+    }
+
+    // The arguments as the builtin gets them, or what is wrong with them.
+    fn convert(&self, arguments: &[Value]) -> Result<Vec<Value>, String> {
+        if !self.accepts_count(arguments.len()) {
+            let expected = match self.variadic {
+                true => format!("at least {}", self.params.len().saturating_sub(1)),
+                false => self.params.len().to_string(),
+            };
+            return Err(format!(
+                "Expected {expected} arguments, got {}",
+                arguments.len()
+            ));
+        }
+        arguments
+            .iter()
+            .enumerate()
+            .map(|(index, argument)| {
+                let (param_type, _) = self
+                    .param(index)
+                    .unwrap_or((BuiltinType::Any, ParamMode::Value));
+                param_type.convert(argument).ok_or_else(|| {
+                    format!(
+                        "Argument {} must be {param_type}, found {}",
+                        index + 1,
+                        argument.type_name()
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (index, (param_type, mode)) in self.params.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{mode}{param_type}")?;
+        }
+        if self.variadic {
+            write!(f, "...")?;
+        }
+        write!(f, ")")?;
+        if let Some(return_type) = self.returns {
+            write!(f, ": {return_type}")?;
+        }
+        Ok(())
+    }
+}
+
+// Builtin procedures from outside the interpreter, such as graphics or
+// networking. A provider gives the signatures of its procedures up front so
+// the analyzer can check calls to them, and the interpreter passes it the
+// argument values, already checked against the signature, when the program
+// calls one.
+pub trait BuiltinProvider {
+    fn signatures(&self) -> Vec<Signature>;
+
+    // Runs the procedure `name`, one of `signatures()`, and returns its
+    // result. The interpreter writes what it leaves in VAR and OUT
+    // arguments back to the variables passed. `output` is where the
+    // program's `write` and `writeln` print. An `Err` stops the program
+    // with that message.
+    fn call(
+        &mut self,
+        name: &str,
+        arguments: &mut [Value],
+        output: &mut dyn Write,
+    ) -> Result<Option<Value>, String>;
+}
+
+// Every builtin of a run with its signature: `write` and `writeln`, then
+// those of the providers. The analyzer checks calls against `signatures`
+// and the interpreter runs them through `call`, so the two agree. When two
+// have the same name, the one added first wins.
+pub struct BuiltinRegistry {
+    providers: Vec<Box<dyn BuiltinProvider>>,
+    builtins: Vec<(Signature, Implementation)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Implementation {
+    Interpreter(BuiltinProcedure),
+    // An index into `providers`
+    Provider(usize),
+}
+
+impl Default for BuiltinRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuiltinRegistry {
+    pub fn new() -> Self {
+        BuiltinRegistry {
+            providers: vec![],
+            builtins: BuiltinProcedure::ALL
+                .into_iter()
+                .map(|builtin| (builtin.signature(), Implementation::Interpreter(builtin)))
+                .collect(),
+        }
+    }
+
+    pub fn add(&mut self, provider: Box<dyn BuiltinProvider>) {
+        let index = self.providers.len();
+        for signature in provider.signatures() {
+            if self.signature(&signature.name).is_none() {
+                self.builtins
+                    .push((signature, Implementation::Provider(index)));
+            }
+        }
+        self.providers.push(provider);
+    }
+
+    pub fn signatures(&self) -> Vec<Signature> {
+        self.builtins
+            .iter()
+            .map(|(signature, _)| signature.clone())
+            .collect()
+    }
+
+    pub fn signature(&self, name: &str) -> Option<&Signature> {
+        self.builtins
+            .iter()
+            .map(|(signature, _)| signature)
+            .find(|signature| signature.name == name)
+    }
+
+    // Checks the arguments against the signature of `name` and runs it.
+    // Afterwards `arguments` holds what the builtin left in them, for the
+    // interpreter to write back to VAR and OUT arguments. `write` and
+    // `writeln` print REALs in `real_format`.
+    pub(crate) fn call(
+        &mut self,
+        name: &str,
+        arguments: &mut Vec<Value>,
+        output: &mut dyn Write,
+        real_format: RealFormat,
+    ) -> InterpretResult<Option<Value>> {
+        let failed = |message: String| InterpretError::Builtin {
+            name: name.to_string(),
+            message,
+        };
+        let Some((signature, implementation)) = self
+            .builtins
+            .iter()
+            .find(|(signature, _)| signature.name == name)
+        else {
+            return Err(InterpretError::UndefinedFunction {
+                name: name.to_string(),
+            });
+        };
+        *arguments = signature.convert(arguments).map_err(failed)?;
+        let result = match *implementation {
+            Implementation::Interpreter(builtin) => {
+                let options = PrettyOptions::UNLIMITED.with_real_format(real_format);
+                for value in arguments.iter() {
+                    write!(output, "{}", value.pretty(options))?;
+                }
+                if builtin == BuiltinProcedure::Writeln {
+                    writeln!(output)?;
+                }
+                return Ok(None);
+            }
+            Implementation::Provider(index) => self.providers[index]
+                .call(name, arguments, output)
+                .map_err(failed)?,
+        };
+        match (result, signature.returns) {
+            (None, None) => Ok(None),
+            (Some(value), Some(return_type)) => {
+                return_type.convert(&value).map(Some).ok_or_else(|| {
+                    failed(format!(
+                        "Returned {}, but its signature says {return_type}",
+                        value.type_name()
+                    ))
+                })
+            }
+            (Some(value), None) => Err(failed(format!(
+                "Returned {}, but its signature has no result",
+                value.type_name()
+            ))),
+            (None, Some(return_type)) => Err(failed(format!(
+                "Returned nothing, but its signature says {return_type}"
+            ))),
+        }
+    }
+}
+
+// The function a plugin exports under this name, e.g.
+// `#[no_mangle] pub fn pascal_builtins() -> Box<dyn BuiltinProvider>`.
+// Trait objects have no stable ABI, so a plugin must be built with the same
+// compiler and the same version of this crate as the interpreter loading it.
+pub const PLUGIN_ENTRY_POINT: &str = "pascal_builtins";
+
+// Loads the provider of the shared library at `path`. Loading a library
+// runs its initialization code, so only load plugins you trust.
+#[cfg(feature = "plugins")]
+pub fn load_plugin(path: &str) -> Result<Box<dyn BuiltinProvider>, String> {
+    // SAFETY: the library is trusted to export `PLUGIN_ENTRY_POINT` with
+    // the signature above
+    unsafe {
+        let library = libloading::Library::new(path).map_err(|e| e.to_string())?;
+        let entry_point = library
+            .get::<fn() -> Box<dyn BuiltinProvider>>(PLUGIN_ENTRY_POINT.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let provider = entry_point();
+        Ok(Box::new(Plugin {
+            provider,
+            _library: library,
+        }))
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+pub fn load_plugin(_path: &str) -> Result<Box<dyn BuiltinProvider>, String> {
+    Err("Loading plugins needs the `plugins` feature".to_string())
+}
+
+// Keeps the library loaded for as long as its provider lives. Fields drop
+// in order, so the provider goes first.
+#[cfg(feature = "plugins")]
+struct Plugin {
+    provider: Box<dyn BuiltinProvider>,
+    _library: libloading::Library,
+}
+
+#[cfg(feature = "plugins")]
+impl BuiltinProvider for Plugin {
+    fn signatures(&self) -> Vec<Signature> {
+        self.provider.signatures()
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        arguments: &mut [Value],
+        output: &mut dyn Write,
+    ) -> Result<Option<Value>, String> {
+        self.provider.call(name, arguments, output)
+    }
+}
@@ -1,6 +1,6 @@
-use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+use std::{cell::RefCell, collections::BTreeMap, fmt, rc::Rc};
 
-use crate::ast::BuiltinNumTypes;
+use crate::value::{PrettyOptions, Value};
 
 pub enum ARType {
     Program,
@@ -20,30 +20,43 @@ pub struct ActivationRecord {
     name: String,
     ar_type: ARType,
     nesting_level: usize,
-    members: HashMap<String, BuiltinNumTypes>,
+    // Ordered, so everything that lists them prints the same every run
+    members: BTreeMap<String, Value>,
 }
 
 impl ActivationRecord {
     pub fn new(name: &str, ar_type: ARType, nesting_level: usize) -> Self {
         ActivationRecord {
             name: name.to_string(),
-            ar_type: ar_type,
-            nesting_level: nesting_level,
-            members: HashMap::new(),
+            ar_type,
+            nesting_level,
+            members: BTreeMap::new(),
         }
     }
 
-    pub fn set(&mut self, name: &str, value: BuiltinNumTypes) {
+    pub fn set(&mut self, name: &str, value: Value) {
         self.members.insert(name.to_owned(), value);
     }
 
-    pub fn get(&self, name: &str) -> Option<&BuiltinNumTypes> {
+    pub fn get(&self, name: &str) -> Option<&Value> {
         self.members.get(name)
     }
 
     pub fn nesting_level(&self) -> usize {
         self.nesting_level
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Variables that have a value, sorted by name.
+    pub fn members(&self) -> Vec<(&str, &Value)> {
+        self.members
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect()
+    }
 }
 
 impl fmt::Display for ActivationRecord {
@@ -52,28 +65,53 @@ impl fmt::Display for ActivationRecord {
         writeln!(f, "Type: {}", self.ar_type)?;
         writeln!(f, "Members:")?;
 
-        // deterministic ordering for printing
-        let mut keys: Vec<&String> = self.members.keys().collect();
-        keys.sort();
-        for k in keys {
-            let v = &self.members[k];
-            writeln!(f, "  {} = {:?}", k, v)?;
+        for (k, v) in &self.members {
+            writeln!(f, "  {} = {}", k, v.pretty(PrettyOptions::default()))?;
         }
         Ok(())
     }
 }
 
+// Far deeper than any recursion a program written by hand needs, so a
+// program reaching it almost certainly recurses without end.
+pub const DEFAULT_DEPTH_LIMIT: usize = 10_000;
+
 pub struct CallStack {
     stack: Vec<Rc<RefCell<ActivationRecord>>>,
+    max_depth: usize,
+    depth_limit: usize,
+}
+
+impl Default for CallStack {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CallStack {
     pub fn new() -> Self {
-        CallStack { stack: vec![] }
+        CallStack {
+            stack: vec![],
+            max_depth: 0,
+            depth_limit: DEFAULT_DEPTH_LIMIT,
+        }
+    }
+
+    // Limits how many frames, the program's included, may be on the stack
+    // before a call fails with `InterpretError::CallDepthExceeded` instead
+    // of recursing until memory runs out.
+    pub fn with_depth_limit(mut self, depth_limit: usize) -> Self {
+        self.depth_limit = depth_limit;
+        self
+    }
+
+    pub fn depth_limit(&self) -> usize {
+        self.depth_limit
     }
 
     pub fn push(&mut self, ar: Rc<RefCell<ActivationRecord>>) {
         self.stack.push(ar);
+        self.max_depth = self.max_depth.max(self.stack.len());
     }
 
     pub fn pop(&mut self) -> Option<Rc<RefCell<ActivationRecord>>> {
@@ -83,6 +121,24 @@ impl CallStack {
     pub fn peek(&self) -> Option<&Rc<RefCell<ActivationRecord>>> {
         self.stack.last()
     }
+
+    // Outermost frame first.
+    pub fn frames(&self) -> &[Rc<RefCell<ActivationRecord>>] {
+        &self.stack
+    }
+
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    // Deepest the stack has been since it was created.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
 }
 
 impl fmt::Display for CallStack {
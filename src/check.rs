@@ -0,0 +1,168 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::ast::Ast;
+use crate::dialect::Dialect;
+use crate::driver::{Driver, StageError};
+use crate::lexer::LexerError;
+use crate::parser::SyntaxError;
+use crate::token::Location;
+
+// Something wrong with a program, found while lexing, parsing or analyzing
+// it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub location: Location,
+    pub message: String,
+    // Edits that would fix it, for editors to offer as quick fixes
+    pub suggestions: Vec<Suggestion>,
+}
+
+// An edit to the source that fixes a mistake: the text from `start` up to
+// `end` is replaced with `replacement`, so an insertion has `start` equal to
+// `end`. Columns count characters, as in every `Location`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Suggestion {
+    // What the edit does, e.g. "insert ';' at 4:10"
+    pub message: String,
+    pub start: Location,
+    pub end: Location,
+    pub replacement: String,
+}
+
+impl Suggestion {
+    pub fn insert(at: Location, text: &str, message: impl Into<String>) -> Self {
+        Suggestion {
+            message: message.into(),
+            start: at,
+            end: at,
+            replacement: text.to_string(),
+        }
+    }
+
+    // Applies the edit to `source`, which must be the text the locations
+    // point into.
+    pub fn apply(&self, source: &str) -> String {
+        let start = offset(source, self.start);
+        let end = offset(source, self.end).max(start);
+        format!("{}{}{}", &source[..start], self.replacement, &source[end..])
+    }
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// The byte offset of `location` in `source`, clamped to the end of its line
+// and of the source.
+fn offset(source: &str, location: Location) -> usize {
+    let mut line_start = 0;
+    for _ in 1..location.line {
+        match source[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return source.len(),
+        }
+    }
+    let line = &source[line_start..];
+    let line_end = line.find('\n').unwrap_or(line.len());
+    line[..line_end]
+        .char_indices()
+        .nth(location.column.saturating_sub(1))
+        .map_or(line_start + line_end, |(index, _)| line_start + index)
+}
+
+impl Diagnostic {
+    // Where a failed parse went wrong. Lexer and syntax errors know their
+    // position; anything else is reported at the start of the program.
+    pub fn from_parse_error(e: &anyhow::Error) -> Self {
+        if let Some(error) = e.downcast_ref::<SyntaxError>() {
+            Diagnostic {
+                location: error.location(),
+                message: error.message(),
+                suggestions: error.suggestion().cloned().into_iter().collect(),
+            }
+        } else if let Some(error) = e.downcast_ref::<LexerError>() {
+            Diagnostic {
+                location: Location {
+                    line: error.line,
+                    column: error.column,
+                },
+                message: error.message.clone(),
+                suggestions: vec![],
+            }
+        } else {
+            Diagnostic {
+                location: Location { line: 1, column: 1 },
+                message: e.to_string(),
+                suggestions: vec![],
+            }
+        }
+    }
+
+    // Where a stage of `driver` went wrong.
+    pub fn from_stage_error(e: &StageError, driver: &Driver) -> Self {
+        let (node, message) = match e {
+            StageError::Parse(e) => return Diagnostic::from_parse_error(e),
+            StageError::Analyze { error, node } => (Some(*node), error.to_string()),
+            StageError::Optimize(e) => (None, e.to_string()),
+            StageError::Execute(e) => (None, e.to_string()),
+            StageError::Internal { .. } => (None, e.to_string()),
+        };
+        // Parsing can only leave no AST behind by panicking
+        let location = match driver.ast() {
+            Some(ast) => ast.location(node.unwrap_or(ast.root())),
+            None => Location { line: 1, column: 1 },
+        };
+        Diagnostic {
+            location,
+            message,
+            suggestions: vec![],
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)?;
+        for suggestion in &self.suggestions {
+            write!(f, " ({})", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, diagnostic) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+// Parses and analyzes a program in the default dialect. This never panics
+// and recursion stays bounded by the parser's nesting limit whatever the
+// input, which makes it the entry point for fuzzing and for checking
+// untrusted source.
+pub fn parse_and_check(source: &str) -> Result<Ast, Diagnostics> {
+    parse_and_check_with_dialect(source, Dialect::default())
+}
+
+pub fn parse_and_check_with_dialect(source: &str, dialect: Dialect) -> Result<Ast, Diagnostics> {
+    let mut driver = Driver::new(source).with_dialect(dialect);
+    if let Err(e) = driver.analyze() {
+        return Err(Diagnostics(vec![Diagnostic::from_stage_error(&e, &driver)]));
+    }
+    Ok(driver.into_ast().expect("analyzed"))
+}
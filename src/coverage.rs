@@ -0,0 +1,157 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::visitor::{ensure_stack, walk, Visitor};
+
+// Execution counts per node, filled in by the interpreter while it runs.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    hits: HashMap<NodeId, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hit(&mut self, node: NodeId) {
+        *self.hits.entry(node).or_default() += 1;
+    }
+
+    pub fn hits(&self, node: NodeId) -> u64 {
+        self.hits.get(&node).copied().unwrap_or(0)
+    }
+
+    pub fn report(&self, ast: &Ast) -> CoverageReport {
+        let mut collector = StatementCollector {
+            coverage: self,
+            report: CoverageReport::default(),
+        };
+        collector.visit(ast, ast.root());
+        collector.report
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LineCoverage {
+    pub statements: usize,
+    pub executed: usize,
+    // Most times any statement on the line ran
+    pub hits: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcedureCoverage {
+    pub name: String,
+    pub line: usize,
+    pub calls: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct CoverageReport {
+    pub lines: BTreeMap<usize, LineCoverage>,
+    pub procedures: Vec<ProcedureCoverage>,
+}
+
+struct StatementCollector<'a> {
+    coverage: &'a Coverage,
+    report: CoverageReport,
+}
+
+impl Visitor for StatementCollector<'_> {
+    fn visit(&mut self, ast: &Ast, node: NodeId) {
+        ensure_stack(|| {
+            let line = ast.location(node).line;
+            match &ast[node] {
+                statement if statement.is_statement() => {
+                    let hits = self.coverage.hits(node);
+                    let entry = self.report.lines.entry(line).or_default();
+                    entry.hits = entry.hits.max(hits);
+                    entry.statements += 1;
+                    if hits > 0 {
+                        entry.executed += 1;
+                    }
+                }
+                ASTNode::ProcedureDecl {
+                    proc_name,
+                    block_node,
+                    ..
+                } => self.report.procedures.push(ProcedureCoverage {
+                    name: proc_name.clone(),
+                    line,
+                    calls: self.coverage.hits(*block_node),
+                }),
+                _ => {}
+            }
+            walk(self, ast, node);
+        })
+    }
+}
+
+impl CoverageReport {
+    pub fn statements(&self) -> usize {
+        self.lines.values().map(|line| line.statements).sum()
+    }
+
+    pub fn executed(&self) -> usize {
+        self.lines.values().map(|line| line.executed).sum()
+    }
+
+    // gcov-style listing: execution count for lines with statements,
+    // `#####` for lines whose statements never ran, `-` for everything else.
+    pub fn annotate(&self, source: &str) -> String {
+        let mut out = String::new();
+        for (index, text) in source.lines().enumerate() {
+            let count = match self.lines.get(&(index + 1)) {
+                Some(line) if line.executed == 0 => "#####".to_string(),
+                Some(line) => line.hits.to_string(),
+                None => "-".to_string(),
+            };
+            let _ = writeln!(out, "{:>9}:{:>5}: {}", count, index + 1, text);
+        }
+
+        let statements = self.statements();
+        let executed = self.executed();
+        let percent = if statements == 0 {
+            100.0
+        } else {
+            executed as f64 * 100.0 / statements as f64
+        };
+        let _ = writeln!(
+            out,
+            "\n{} of {} statements executed ({:.1}%)",
+            executed, statements, percent
+        );
+        out
+    }
+
+    pub fn to_lcov(&self, source_file: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "TN:");
+        let _ = writeln!(out, "SF:{}", source_file);
+        for procedure in &self.procedures {
+            let _ = writeln!(out, "FN:{},{}", procedure.line, procedure.name);
+        }
+        for procedure in &self.procedures {
+            let _ = writeln!(out, "FNDA:{},{}", procedure.calls, procedure.name);
+        }
+        let _ = writeln!(out, "FNF:{}", self.procedures.len());
+        let _ = writeln!(
+            out,
+            "FNH:{}",
+            self.procedures.iter().filter(|p| p.calls > 0).count()
+        );
+        for (line, coverage) in &self.lines {
+            let _ = writeln!(out, "DA:{},{}", line, coverage.hits);
+        }
+        let _ = writeln!(out, "LF:{}", self.lines.len());
+        let _ = writeln!(
+            out,
+            "LH:{}",
+            self.lines.values().filter(|line| line.executed > 0).count()
+        );
+        let _ = writeln!(out, "end_of_record");
+        out
+    }
+}
@@ -0,0 +1,266 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::ast::{Ast, NodeId};
+use crate::token::{LocatedToken, Span, Trivia, TriviaKind};
+use crate::visitor::ensure_stack;
+
+// A lossless view of a parsed program: the AST's nodes with every token
+// between them, and every token with its comments and whitespace. The
+// leaves in order are the source text exactly, which tools that rewrite
+// part of a program need and the AST alone can't give, since it drops
+// punctuation and layout. It is built from an AST parsed with trivia, and
+// each CST node refers back to the AST node it stands for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst {
+    root: CstNode,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstNode {
+    // `None` for the source file around the program
+    pub ast: Option<NodeId>,
+    pub kind: &'static str,
+    pub children: Vec<CstElement>,
+    // The indices into `Ast::tokens` the node covers, which are its
+    // tokens and those of the nodes nested in it
+    pub token_range: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstElement {
+    Node(CstNode),
+    // An index into `Ast::tokens`
+    Token(usize),
+}
+
+impl Cst {
+    // `None` unless `ast` was parsed with trivia.
+    pub fn new(ast: &Ast) -> Option<Self> {
+        let tokens = ast.tokens();
+        if tokens.is_empty() {
+            return None;
+        }
+        let program = ast.root();
+        let range = ast
+            .token_range(program)
+            .filter(|range| !range.is_empty() && range.end <= tokens.len())
+            .unwrap_or(0..tokens.len() - 1);
+        let mut children: Vec<_> = (0..range.start).map(CstElement::Token).collect();
+        children.push(CstElement::Node(node(ast, program, range.clone())));
+        children.extend((range.end..tokens.len()).map(CstElement::Token));
+        Some(Cst {
+            root: CstNode {
+                ast: None,
+                kind: "SourceFile",
+                children,
+                token_range: 0..tokens.len(),
+            },
+        })
+    }
+
+    pub fn root(&self) -> &CstNode {
+        &self.root
+    }
+
+    // The CST node standing for AST node `id`, if it has tokens of its own.
+    pub fn find(&self, id: NodeId) -> Option<&CstNode> {
+        self.root.find(id)
+    }
+
+    // Reassembles the source text from the leaves, which gives back the
+    // text that was parsed.
+    pub fn text(&self, ast: &Ast, source: &str) -> String {
+        self.root.text(ast, source)
+    }
+
+    pub fn display<'a>(&'a self, ast: &'a Ast, source: &'a str) -> CstDisplay<'a> {
+        CstDisplay {
+            cst: self,
+            ast,
+            source,
+        }
+    }
+}
+
+// Lays out the tokens of `range`, which node `id` spans, around the child
+// nodes that nest inside it. A child whose tokens overlap an earlier
+// sibling's (e.g. a type shared by several declarations) or reach outside
+// the range only contributes its tokens, so every token still appears
+// exactly once.
+fn node(ast: &Ast, id: NodeId, range: Range<usize>) -> CstNode {
+    ensure_stack(|| {
+        let mut nested: Vec<_> = ast
+            .children(id)
+            .into_iter()
+            .filter_map(|child| Some((ast.token_range(child)?, child)))
+            .filter(|(child_range, _)| !child_range.is_empty())
+            .collect();
+        nested.sort_by_key(|(child_range, _)| child_range.start);
+
+        let mut children = vec![];
+        let mut next = range.start;
+        for (child_range, child) in nested {
+            if child_range.start < next || child_range.end > range.end {
+                continue;
+            }
+            children.extend((next..child_range.start).map(CstElement::Token));
+            next = child_range.end;
+            children.push(CstElement::Node(node(ast, child, child_range)));
+        }
+        children.extend((next..range.end).map(CstElement::Token));
+        CstNode {
+            ast: Some(id),
+            kind: ast[id].kind(),
+            children,
+            token_range: range,
+        }
+    })
+}
+
+impl CstNode {
+    pub fn find(&self, id: NodeId) -> Option<&CstNode> {
+        ensure_stack(|| {
+            if self.ast == Some(id) {
+                return Some(self);
+            }
+            self.children.iter().find_map(|child| match child {
+                CstElement::Node(node) => node.find(id),
+                CstElement::Token(_) => None,
+            })
+        })
+    }
+
+    // Indices into `Ast::tokens`, in source order.
+    pub fn tokens(&self) -> Vec<usize> {
+        self.token_range.clone().collect()
+    }
+
+    // The text the node covers, from the comments and whitespace before
+    // its first token to those after its last one. Replacing this span
+    // replaces the node without disturbing its neighbours' layout.
+    pub fn span(&self, ast: &Ast) -> Option<Span> {
+        if self.token_range.is_empty() {
+            return None;
+        }
+        let first = &ast.tokens()[self.token_range.start];
+        let last = &ast.tokens()[self.token_range.end - 1];
+        Some(Span::new(full_span(first).start, full_span(last).end))
+    }
+
+    pub fn text(&self, ast: &Ast, source: &str) -> String {
+        let mut text = String::new();
+        for token in &ast.tokens()[self.token_range.clone()] {
+            let span = full_span(token);
+            text.push_str(&source[span.start..span.end]);
+        }
+        text
+    }
+}
+
+// The children of a long chain of operators nest as deep as the chain is
+// long, and dropping them is as recursive as building them
+impl Drop for CstNode {
+    fn drop(&mut self) {
+        let children = std::mem::take(&mut self.children);
+        ensure_stack(|| drop(children));
+    }
+}
+
+// The token with its leading and trailing trivia.
+fn full_span(token: &LocatedToken) -> Span {
+    let start = token
+        .leading
+        .first()
+        .map_or(token.span.start, |t| t.span.start);
+    let end = token.trailing.last().map_or(token.span.end, |t| t.span.end);
+    Span::new(start, end)
+}
+
+// Nodes nested deeper than this are shown as a single line with the text
+// they cover, so that a long chain of operators still prints in time linear
+// in its length
+const MAX_DISPLAY_DEPTH: usize = 64;
+
+// The tree, one element per line: nodes with their AST id and the byte
+// range they cover, then tokens and trivia with their text.
+pub struct CstDisplay<'a> {
+    cst: &'a Cst,
+    ast: &'a Ast,
+    source: &'a str,
+}
+
+impl CstDisplay<'_> {
+    fn node(&self, f: &mut fmt::Formatter<'_>, node: &CstNode, depth: usize) -> fmt::Result {
+        ensure_stack(|| {
+            write!(f, "{:indent$}{}", "", node.kind, indent = depth * 2)?;
+            if let Some(id) = node.ast {
+                write!(f, " {}", id)?;
+            }
+            let span = node.span(self.ast);
+            if let Some(span) = span {
+                write!(f, "@{}..{}", span.start, span.end)?;
+            }
+            writeln!(f)?;
+            if depth >= MAX_DISPLAY_DEPTH {
+                if let Some(span) = span {
+                    let text = node.text(self.ast, self.source);
+                    self.leaf(f, "...", span, &text, depth + 1)?;
+                }
+                return Ok(());
+            }
+            for child in &node.children {
+                match child {
+                    CstElement::Node(child) => self.node(f, child, depth + 1)?,
+                    CstElement::Token(index) => {
+                        let token = &self.ast.tokens()[*index];
+                        for trivia in &token.leading {
+                            self.trivia(f, trivia, depth + 1)?;
+                        }
+                        let text = &self.source[token.span.start..token.span.end];
+                        self.leaf(f, token.token.kind(), token.span, text, depth + 1)?;
+                        for trivia in &token.trailing {
+                            self.trivia(f, trivia, depth + 1)?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn trivia(&self, f: &mut fmt::Formatter<'_>, trivia: &Trivia, depth: usize) -> fmt::Result {
+        let kind = match trivia.kind {
+            TriviaKind::Whitespace => "Whitespace",
+            TriviaKind::Newline => "Newline",
+            TriviaKind::Comment => "Comment",
+        };
+        self.leaf(f, kind, trivia.span, trivia.text(self.source), depth)
+    }
+
+    fn leaf(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        kind: &str,
+        span: Span,
+        text: &str,
+        depth: usize,
+    ) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{}@{}..{} {:?}",
+            "",
+            kind,
+            span.start,
+            span.end,
+            text,
+            indent = depth * 2
+        )
+    }
+}
+
+impl fmt::Display for CstDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.node(f, &self.cst.root, 0)
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use crate::ast::{Ast, NodeId};
+use crate::call_stack::CallStack;
+use crate::interpreter::{Interpreter, Resume, StatementHook};
+use crate::replay::{Recording, Replay};
+use crate::value::{PrettyOptions, RealFormat};
+
+// Source lines shown around the current one.
+const SOURCE_CONTEXT: usize = 7;
+
+// Values stay on their line, so the panes keep their shape
+const PRETTY: PrettyOptions = PrettyOptions {
+    max_depth: 2,
+    max_elements: 8,
+    multiline: false,
+    indent: 0,
+    real_format: RealFormat::DEFAULT,
+};
+
+const HIGHLIGHT: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+const HELP: &str = "(s)tep, (n)ext, (f)inish, (c)ontinue, r prefix to reverse, (b)reak LINE, (p)rint EXPR, (w)atch EXPR, (q)uit; empty repeats the last step";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Step,
+    Next,
+    Finish,
+    Continue,
+}
+
+enum Command {
+    Go { motion: Motion, reverse: bool },
+    Quit,
+}
+
+// When to pause next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    // At the next statement
+    Step,
+    // At the next statement at most this deep in the call stack
+    Next(usize),
+    // At the next statement less deep than this
+    Finish(usize),
+    // At breakpoints only
+    Continue,
+}
+
+// Interactive terminal debugger. Before each statement it decides whether
+// to pause; while paused it draws the source around the current line, the
+// variables of the current frame and the call stack, then reads commands
+// from `input` until one resumes the program. Over a recorded run it can
+// also step backwards; see `replay`.
+pub struct TerminalDebugger<R, W> {
+    input: R,
+    output: W,
+    lines: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    // Expressions shown with their values whenever the program pauses
+    watches: Vec<String>,
+    mode: Mode,
+    // The last command that resumed the program
+    last_command: String,
+    // The line each frame is at, outermost first; for callers, the line of
+    // the call
+    frame_lines: Vec<usize>,
+    // Where the previous statement was, so a breakpoint on a line with
+    // several statements only pauses once
+    previous: Option<(usize, usize)>,
+    message: String,
+}
+
+impl<R: BufRead, W: Write> TerminalDebugger<R, W> {
+    // Starts out stepping, so the program pauses at its first statement.
+    pub fn new(source: &str, input: R, output: W) -> Self {
+        TerminalDebugger {
+            input,
+            output,
+            lines: source.lines().map(str::to_string).collect(),
+            breakpoints: BTreeSet::new(),
+            watches: vec![],
+            mode: Mode::Step,
+            last_command: "step".to_string(),
+            frame_lines: vec![],
+            previous: None,
+            message: HELP.to_string(),
+        }
+    }
+
+    fn should_pause(&self, depth: usize, line: usize) -> bool {
+        let at_breakpoint =
+            self.breakpoints.contains(&line) && self.previous != Some((depth, line));
+        at_breakpoint
+            || match self.mode {
+                Mode::Step => true,
+                Mode::Next(max) => depth <= max,
+                Mode::Finish(below) => depth < below,
+                Mode::Continue => false,
+            }
+    }
+
+    fn draw(&mut self, call_stack: &CallStack) -> std::io::Result<()> {
+        let current = self.frame_lines.last().copied().unwrap_or(1);
+        let mut screen = String::from(CLEAR_SCREEN);
+
+        screen.push_str(&pane_title("Source"));
+        let first = current.saturating_sub(SOURCE_CONTEXT).max(1);
+        let last = (current + SOURCE_CONTEXT).min(self.lines.len());
+        for number in first..=last {
+            let marker = match (number == current, self.breakpoints.contains(&number)) {
+                (true, _) => '>',
+                (false, true) => '*',
+                (false, false) => ' ',
+            };
+            let text = format!("{marker}{number:>5} | {}", self.lines[number - 1]);
+            if number == current {
+                screen.push_str(&format!("{HIGHLIGHT}{text}{RESET}\n"));
+            } else {
+                screen.push_str(&format!("{text}\n"));
+            }
+        }
+
+        screen.push_str(&pane_title("Variables"));
+        if let Some(frame) = call_stack.peek() {
+            let frame = frame.borrow();
+            let members = frame.members();
+            if members.is_empty() {
+                screen.push_str("  (none assigned yet)\n");
+            }
+            for (name, value) in members {
+                screen.push_str(&format!("  {name} = {}\n", value.pretty(PRETTY)));
+            }
+        }
+
+        if !self.watches.is_empty() {
+            screen.push_str(&pane_title("Watches"));
+            for expression in &self.watches {
+                screen.push_str(&format!(
+                    "  {expression} = {}\n",
+                    evaluate(expression, call_stack)
+                ));
+            }
+        }
+
+        screen.push_str(&pane_title("Call stack"));
+        for (depth, frame) in call_stack.frames().iter().enumerate().rev() {
+            let line = self.frame_lines.get(depth).copied().unwrap_or(0);
+            screen.push_str(&format!(
+                "  #{} {} at line {}\n",
+                call_stack.len() - 1 - depth,
+                frame.borrow().name(),
+                line
+            ));
+        }
+
+        screen.push_str(&pane_title(""));
+        screen.push_str(&format!("{}\n(debug) ", self.message));
+        self.output.write_all(screen.as_bytes())?;
+        self.output.flush()
+    }
+
+    // Reads commands until one moves the program, handling breakpoint
+    // changes and typos along the way. `None` means the input has ended.
+    fn read_command(&mut self, call_stack: &CallStack) -> Option<Command> {
+        loop {
+            if self.draw(call_stack).is_err() {
+                return Some(Command::Quit);
+            }
+            let mut line = String::new();
+            if self.input.read_line(&mut line).unwrap_or(0) == 0 {
+                return None;
+            }
+            let mut command = line.trim().to_string();
+            if command.is_empty() {
+                command = self.last_command.clone();
+            }
+            self.message = HELP.to_string();
+
+            // The rest of the line is the expression, spaces and all
+            if let Some((name, expression)) = command.split_once(char::is_whitespace) {
+                let expression = expression.trim().to_string();
+                match name {
+                    "p" | "print" => {
+                        self.message =
+                            format!("{expression} = {}", evaluate(&expression, call_stack));
+                        continue;
+                    }
+                    "w" | "watch" => {
+                        if let Some(index) = self.watches.iter().position(|w| *w == expression) {
+                            self.watches.remove(index);
+                            self.message = format!("Stopped watching {expression}");
+                        } else {
+                            self.message = format!("Watching {expression}");
+                            self.watches.push(expression);
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut words = command.split_whitespace();
+            let (name, argument) = (words.next().unwrap_or_default(), words.next());
+            let (reverse, name) = match name.strip_prefix("reverse-") {
+                Some(name) => (true, name),
+                None if name.len() == 2 && name.starts_with('r') => (true, &name[1..]),
+                None => (false, name),
+            };
+            let motion = match (name, argument) {
+                ("s" | "step", None) => Motion::Step,
+                ("n" | "next", None) => Motion::Next,
+                ("f" | "finish", None) => Motion::Finish,
+                ("c" | "continue", None) => Motion::Continue,
+                ("q" | "quit", None) if !reverse => return Some(Command::Quit),
+                ("b" | "break", Some(line)) if !reverse => {
+                    self.message = match line.parse::<usize>() {
+                        Ok(line) if (1..=self.lines.len()).contains(&line) => {
+                            if self.breakpoints.insert(line) {
+                                format!("Breakpoint set at line {line}")
+                            } else {
+                                self.breakpoints.remove(&line);
+                                format!("Breakpoint at line {line} removed")
+                            }
+                        }
+                        _ => format!("No line '{line}' in the program"),
+                    };
+                    continue;
+                }
+                _ => {
+                    self.message = format!("Unknown command '{command}'. {HELP}");
+                    continue;
+                }
+            };
+            self.last_command = command;
+            return Some(Command::Go { motion, reverse });
+        }
+    }
+
+    // Reads commands until one resumes the program.
+    fn prompt(&mut self, call_stack: &CallStack) -> Resume {
+        let depth = call_stack.len();
+        loop {
+            self.mode = match self.read_command(call_stack) {
+                Some(Command::Quit) => return Resume::Abort,
+                Some(Command::Go { reverse: true, .. }) => {
+                    self.message =
+                        "Going backwards needs a recorded run; start the debugger with --replay"
+                            .to_string();
+                    continue;
+                }
+                Some(Command::Go { motion, .. }) => match motion {
+                    Motion::Step => Mode::Step,
+                    Motion::Next => Mode::Next(depth),
+                    Motion::Finish => Mode::Finish(depth),
+                    Motion::Continue => Mode::Continue,
+                },
+                // Without input there is nobody left to drive the program,
+                // so let it run to the end
+                None => {
+                    self.breakpoints.clear();
+                    Mode::Continue
+                }
+            };
+            return Resume::Continue;
+        }
+    }
+
+    // Browses a finished run, forwards and backwards, until the user quits
+    // or the input ends.
+    pub fn replay(&mut self, ast: &Ast, recording: &Recording) {
+        let Some(last) = recording.statement_count().checked_sub(1) else {
+            return;
+        };
+        let mut replay = Replay::new(recording);
+        let mut position = 0;
+        loop {
+            replay.seek(position);
+            self.frame_lines = frame_lines(ast, recording, position);
+            let Some(Command::Go { motion, reverse }) = self.read_command(replay.call_stack())
+            else {
+                return;
+            };
+            position = match self.target(ast, recording, position, motion, reverse) {
+                Some(target) => target,
+                None if reverse => {
+                    self.message = "Reached the start of the recording".to_string();
+                    0
+                }
+                None => {
+                    self.message = "Reached the end of the recording".to_string();
+                    last
+                }
+            };
+        }
+    }
+
+    // Where `motion` leads from the `from`th statement of a recording,
+    // stopping early at breakpoints like a live run would.
+    fn target(
+        &self,
+        ast: &Ast,
+        recording: &Recording,
+        from: usize,
+        motion: Motion,
+        reverse: bool,
+    ) -> Option<usize> {
+        let at = |index: usize| {
+            let (node, depth) = recording.statement(index);
+            (depth, ast.location(node).line)
+        };
+        let depth = at(from).0;
+        let stops = |index: usize| {
+            let (statement_depth, line) = at(index);
+            let at_breakpoint =
+                self.breakpoints.contains(&line) && (index == 0 || at(index - 1) != at(index));
+            at_breakpoint
+                || match motion {
+                    Motion::Step => true,
+                    Motion::Next => statement_depth <= depth,
+                    Motion::Finish => statement_depth < depth,
+                    Motion::Continue => false,
+                }
+        };
+        if reverse {
+            (0..from).rev().find(|&index| stops(index))
+        } else {
+            (from + 1..recording.statement_count()).find(|&index| stops(index))
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> StatementHook for TerminalDebugger<R, W> {
+    fn before_statement(&mut self, ast: &Ast, node: NodeId, call_stack: &CallStack) -> Resume {
+        let depth = call_stack.len();
+        let line = ast.location(node).line;
+        self.frame_lines.resize(depth, 0);
+        if let Some(current) = self.frame_lines.last_mut() {
+            *current = line;
+        }
+
+        let resume = if self.should_pause(depth, line) {
+            self.prompt(call_stack)
+        } else {
+            Resume::Continue
+        };
+        self.previous = Some((depth, line));
+        resume
+    }
+}
+
+// The line each frame of a recorded run is at when the `position`th
+// statement runs: the latest statement run at each depth up to its own.
+fn frame_lines(ast: &Ast, recording: &Recording, position: usize) -> Vec<usize> {
+    let (_, depth) = recording.statement(position);
+    let mut lines = vec![0; depth];
+    for index in (0..=position).rev() {
+        let (node, statement_depth) = recording.statement(index);
+        if let Some(line @ 0) = lines.get_mut(statement_depth.wrapping_sub(1)) {
+            *line = ast.location(node).line;
+        }
+        if !lines.contains(&0) {
+            break;
+        }
+    }
+    lines
+}
+
+// The value of `expression` in the current frame, or why there is none.
+fn evaluate(expression: &str, call_stack: &CallStack) -> String {
+    let Some(frame) = call_stack.peek() else {
+        return "(no frame)".to_string();
+    };
+    match Interpreter::eval_expression(expression, frame) {
+        Ok(value) => value.pretty(PRETTY).to_string(),
+        Err(e) => format!("<{e}>"),
+    }
+}
+
+fn pane_title(title: &str) -> String {
+    let title = if title.is_empty() {
+        String::new()
+    } else {
+        format!(" {title} ")
+    };
+    format!("--{title:-<60}\n")
+}
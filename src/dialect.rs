@@ -0,0 +1,100 @@
+use std::fmt;
+use std::str::FromStr;
+
+// The flavour of Pascal a program is written in. Each dialect switches a
+// handful of features on or off in the lexer, parser and analyzer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    // ISO 7185 standard Pascal
+    Iso,
+    #[default]
+    Turbo,
+    // Free Pascal in its default (FPC/ObjFPC) modes
+    Fpc,
+}
+
+impl Dialect {
+    // `// ...` comments running to the end of the line
+    pub fn line_comments(self) -> bool {
+        matches!(self, Dialect::Turbo | Dialect::Fpc)
+    }
+
+    // `{ outer { inner } still outer }`
+    pub fn nested_comments(self) -> bool {
+        matches!(self, Dialect::Fpc)
+    }
+
+    // `procedure P();`
+    pub fn empty_parameter_lists(self) -> bool {
+        matches!(self, Dialect::Turbo | Dialect::Fpc)
+    }
+
+    // `type TCallback = procedure(x: integer);` and variables of such a type
+    pub fn procedural_types(self) -> bool {
+        matches!(self, Dialect::Turbo | Dialect::Fpc)
+    }
+
+    // `type TCounter = class ... end;` with methods, constructors and
+    // destructors, as in FPC's ObjFPC mode
+    pub fn classes(self) -> bool {
+        matches!(self, Dialect::Fpc)
+    }
+
+    // `procedure P(const x: integer)`: a parameter the procedure can't
+    // assign
+    pub fn const_params(self) -> bool {
+        matches!(self, Dialect::Turbo | Dialect::Fpc)
+    }
+
+    // `generic procedure Swap<T>(var a, b: T);`, specialized for the types
+    // of the arguments at each call
+    pub fn generics(self) -> bool {
+        matches!(self, Dialect::Fpc)
+    }
+
+    // `procedure P(out x: integer)`: a VAR parameter whose value on entry
+    // doesn't count
+    pub fn out_params(self) -> bool {
+        matches!(self, Dialect::Fpc)
+    }
+
+    // `var x : integer = 1;`, set when the block is entered
+    pub fn initialized_variables(self) -> bool {
+        matches!(self, Dialect::Fpc)
+    }
+
+    // `case x of 1: a else b end`: statements for the values no arm
+    // matches
+    pub fn case_else(self) -> bool {
+        matches!(self, Dialect::Turbo | Dialect::Fpc)
+    }
+
+    // Variable and procedure declarations in any order; ISO wants all
+    // variables of a block declared before its procedures.
+    pub fn mixed_declarations(self) -> bool {
+        matches!(self, Dialect::Turbo | Dialect::Fpc)
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "iso" => Ok(Dialect::Iso),
+            "tp" | "turbo" => Ok(Dialect::Turbo),
+            "fpc" | "objfpc" => Ok(Dialect::Fpc),
+            _ => Err(format!("Unknown dialect '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dialect::Iso => write!(f, "ISO Pascal"),
+            Dialect::Turbo => write!(f, "Turbo Pascal"),
+            Dialect::Fpc => write!(f, "Free Pascal"),
+        }
+    }
+}
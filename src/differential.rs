@@ -0,0 +1,211 @@
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+use crate::dialect::Dialect;
+use crate::directives::Switches;
+use crate::driver::{Driver, StageError};
+use crate::harness::{self, Case};
+use crate::interpreter::Interpreter;
+use crate::source::{self, Encoding};
+
+// Differential testing against Free Pascal: a conformance case is compiled
+// with `fpc` and run natively, then run on the interpreter, and any
+// difference in what the two print or whether they fail is a divergence
+// in the interpreter's semantics (or a case that relies on something FPC
+// does differently on purpose, such as 16-bit INTEGER outside objfpc mode).
+
+// The compiler named by `FPC`, or `fpc` from `PATH`.
+pub fn find_fpc() -> Option<PathBuf> {
+    if let Some(fpc) = env::var_os("FPC") {
+        return Some(PathBuf::from(fpc));
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join("fpc"))
+        .find(|path| path.is_file())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Comparison {
+    Same,
+    Differs {
+        // From FPC's stdout to the interpreter's, empty if only success or
+        // failure differs
+        diff: String,
+        fpc_failed: bool,
+        failed: bool,
+    },
+    // Not a plain run, or FPC rejects the program
+    Skipped(String),
+    Error(String),
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::Same => write!(f, "same"),
+            Comparison::Differs {
+                diff,
+                fpc_failed,
+                failed,
+            } => {
+                write!(f, "DIFFERS")?;
+                if fpc_failed != failed {
+                    let outcome = |failed: bool| if failed { "fails" } else { "succeeds" };
+                    write!(
+                        f,
+                        "\n  FPC {}, the interpreter {}",
+                        outcome(*fpc_failed),
+                        outcome(*failed)
+                    )?;
+                }
+                if !diff.is_empty() {
+                    write!(f, "\n  stdout differs (-fpc +interpreter):")?;
+                    for line in diff.lines() {
+                        write!(f, "\n  {}", line)?;
+                    }
+                }
+                Ok(())
+            }
+            Comparison::Skipped(reason) => write!(f, "skipped ({})", reason),
+            Comparison::Error(message) => write!(f, "ERROR: {}", message),
+        }
+    }
+}
+
+// Compiles and runs `case` with `fpc`, with its output in `work_dir`, and
+// compares that with the interpreter. Only the dialect is taken from the
+// case's `.args`; cases with any other argument are skipped, since they
+// test the interpreter's tooling rather than the language.
+pub fn compare(fpc: &Path, case: &Case, work_dir: &Path) -> Comparison {
+    let args = match case.args() {
+        Ok(args) => args,
+        Err(e) => return Comparison::Error(e.to_string()),
+    };
+    let mut dialect = Dialect::default();
+    for arg in &args {
+        match arg.strip_prefix("--dialect=").map(str::parse) {
+            Some(Ok(value)) => dialect = value,
+            _ => return Comparison::Skipped(format!("runs with {}", arg)),
+        }
+    }
+    if case.input().is_some() {
+        return Comparison::Skipped("reads input".to_string());
+    }
+
+    let native = match run_fpc(fpc, case, dialect, work_dir) {
+        Ok(Ok(run)) => run,
+        Ok(Err(reason)) => return Comparison::Skipped(reason),
+        Err(e) => return Comparison::Error(format!("cannot run {}: {}", fpc.display(), e)),
+    };
+    let (stdout, failed) = match interpret(case, dialect) {
+        Ok(run) => run,
+        Err(e) => return Comparison::Error(e),
+    };
+
+    let fpc_failed = native.status != 0;
+    if native.stdout == stdout && fpc_failed == failed {
+        return Comparison::Same;
+    }
+    let diff = if native.stdout == stdout {
+        String::new()
+    } else {
+        harness::diff(&native.stdout, &stdout)
+    };
+    Comparison::Differs {
+        diff,
+        fpc_failed,
+        failed,
+    }
+}
+
+// The compiler mode closest to each dialect.
+fn mode(dialect: Dialect) -> &'static str {
+    match dialect {
+        Dialect::Iso => "-Miso",
+        Dialect::Turbo => "-Mtp",
+        Dialect::Fpc => "-Mobjfpc",
+    }
+}
+
+// The native run, or why the program didn't compile.
+fn run_fpc(
+    fpc: &Path,
+    case: &Case,
+    dialect: Dialect,
+    work_dir: &Path,
+) -> io::Result<Result<harness::Run, String>> {
+    let source = fs::canonicalize(&case.source)?;
+    let executable = work_dir.join(&case.name);
+    let compile = Command::new(fpc)
+        .arg(mode(dialect))
+        .arg("-v0")
+        .arg(format!("-FE{}", work_dir.display()))
+        .arg(format!("-o{}", executable.display()))
+        .arg(source)
+        .current_dir(work_dir)
+        .stdin(Stdio::null())
+        .output()?;
+    if !compile.status.success() {
+        let message = String::from_utf8_lossy(&compile.stdout);
+        let first = message.lines().find(|line| !line.trim().is_empty());
+        return Ok(Err(format!(
+            "fpc: {}",
+            first.unwrap_or("compilation failed").trim()
+        )));
+    }
+
+    let output = Command::new(&executable)
+        .current_dir(work_dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    let mut stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    // FPC reports a runtime error and a backtrace on stdout; the
+    // interpreter reports its errors on stderr
+    if let Some(start) = stdout.find("Runtime error ") {
+        stdout.truncate(start);
+    }
+    Ok(Ok(harness::Run {
+        stdout,
+        status: output.status.code().unwrap_or(-1),
+    }))
+}
+
+// The program's output on the interpreter and whether the run failed.
+fn interpret(case: &Case, dialect: Dialect) -> Result<(String, bool), String> {
+    let text = source::read(&case.source, Encoding::Auto)
+        .map_err(|e| format!("cannot read {}: {}", case.source.display(), e))?;
+    let mut driver = Driver::new(&text).with_dialect(dialect);
+    let output = Output::default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_switches(Switches::default().with_directives(&text, dialect));
+    interpreter.set_output(Box::new(output.clone()));
+    let failed = match driver.execute(&mut interpreter) {
+        Ok(_) => false,
+        Err(StageError::Execute(_)) => true,
+        // FPC compiled it, so the interpreter should have too
+        Err(e) => return Ok((format!("{}\n", e), true)),
+    };
+    let stdout = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+    Ok((stdout, failed))
+}
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
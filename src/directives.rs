@@ -0,0 +1,125 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::dialect::Dialect;
+use crate::lexer::Lexer;
+use crate::token::TriviaKind;
+use crate::value::RealFormat;
+
+// What REAL arithmetic does when a result is infinite or NaN, e.g. after
+// `1 / 0` or `0 / 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RealPolicy {
+    // IEEE 754: the infinity or NaN becomes the value and carries through
+    // later operations
+    #[default]
+    Propagate,
+    // Stop with a runtime error naming the operation
+    Error,
+}
+
+impl FromStr for RealPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "propagate" => Ok(RealPolicy::Propagate),
+            "error" => Ok(RealPolicy::Error),
+            _ => Err(format!("Unknown real policy '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for RealPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RealPolicy::Propagate => write!(f, "propagate"),
+            RealPolicy::Error => write!(f, "error"),
+        }
+    }
+}
+
+// Compiler switches, set on the command line and most of them also by
+// directives such as `{$Q+}` in the source. A directive applies to the whole
+// program wherever it appears, and the last one for a switch wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Switches {
+    // `{$Q}`/`{$OVERFLOWCHECKS}`: fail on INTEGER overflow instead of
+    // wrapping around
+    pub overflow_checks: bool,
+    // No directive; FPC sets this at runtime through the FPU exception mask
+    pub real_policy: RealPolicy,
+    // `{$REALFORMAT fixed:2}`: how `write` prints a REAL. Not a check, so
+    // the strictest switches leave it alone.
+    pub real_format: RealFormat,
+}
+
+impl Default for Switches {
+    fn default() -> Self {
+        Switches {
+            overflow_checks: false,
+            real_policy: RealPolicy::Propagate,
+            real_format: RealFormat::DEFAULT,
+        }
+    }
+}
+
+impl Switches {
+    // Every check that can turn a result into an error. Constant folding
+    // evaluates with these, so it leaves alone whatever could fail at
+    // runtime.
+    pub const STRICT: Switches = Switches {
+        overflow_checks: true,
+        real_policy: RealPolicy::Error,
+        real_format: RealFormat::DEFAULT,
+    };
+
+    // These switches with the directives in `source` applied. Directives
+    // after a lexer error are not seen, but such a program won't run anyway.
+    pub fn with_directives(mut self, source: &str, dialect: Dialect) -> Self {
+        for token in Lexer::with_trivia(source).with_dialect(dialect) {
+            let Ok(token) = token else {
+                break;
+            };
+            for trivia in token.leading.iter().chain(&token.trailing) {
+                if trivia.kind == TriviaKind::Comment {
+                    self.apply(trivia.text(source));
+                }
+            }
+        }
+        self
+    }
+
+    // Takes a whole comment, braces included. Other comments and unknown
+    // switches are ignored, like FPC ignores the ones it doesn't know.
+    fn apply(&mut self, comment: &str) {
+        let Some(directive) = comment
+            .strip_prefix("{$")
+            .and_then(|rest| rest.strip_suffix('}'))
+        else {
+            return;
+        };
+        let directive = directive.trim().to_ascii_uppercase();
+        if let Some(format) = directive.strip_prefix("REALFORMAT ") {
+            if let Ok(format) = format.trim().to_ascii_lowercase().parse() {
+                self.real_format = format;
+            }
+            return;
+        }
+        let (name, on) = if let Some(name) = directive.strip_suffix('+') {
+            (name, true)
+        } else if let Some(name) = directive.strip_suffix('-') {
+            (name, false)
+        } else if let Some(name) = directive.strip_suffix(" ON") {
+            (name, true)
+        } else if let Some(name) = directive.strip_suffix(" OFF") {
+            (name, false)
+        } else {
+            return;
+        };
+        match name.trim() {
+            "Q" | "OVERFLOWCHECKS" => self.overflow_checks = on,
+            _ => {}
+        }
+    }
+}
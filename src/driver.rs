@@ -0,0 +1,372 @@
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+
+use crate::ast::{Ast, NodeId};
+use crate::budget::{BudgetExhausted, RunOutcome};
+use crate::builtins::Signature;
+use crate::dialect::Dialect;
+use crate::hir::Hir;
+use crate::interpreter::{panic_message, InterpretError, InterpretResult, Interpreter};
+use crate::keywords::Keywords;
+use crate::lexer::Lexer;
+use crate::optimizer::{self, OptLevel};
+use crate::parser::{Parser, Recovery, SyntaxError};
+use crate::passes::{PassManager, Remark};
+use crate::semantic_analyzer::SemanticAnalyzer;
+use crate::stats::{RunReport, StageTimes};
+
+// The stages of the pipeline, in the order they run. Lexing happens on
+// demand while parsing, so it is part of `Parse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Parse,
+    Optimize,
+    Analyze,
+    Execute,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Parse => write!(f, "parse"),
+            Stage::Optimize => write!(f, "optimize"),
+            Stage::Analyze => write!(f, "analyze"),
+            Stage::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StageError {
+    // A `LexerError` or `SyntaxError`
+    Parse(anyhow::Error),
+    Optimize(anyhow::Error),
+    // The analyzer rejected the program at `node`
+    Analyze { error: InterpretError, node: NodeId },
+    Execute(InterpretError),
+    // A bug rather than a problem with the program: the stage panicked
+    Internal { stage: Stage, message: String },
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageError::Parse(e) => match e.downcast_ref::<SyntaxError>() {
+                Some(syntax_error) => write!(f, "{}", syntax_error),
+                None => write!(f, "Error: {}", e),
+            },
+            StageError::Optimize(e) => write!(f, "Error: {}", e),
+            StageError::Analyze { error, .. } | StageError::Execute(error) => {
+                write!(f, "Error: {}", error)
+            }
+            StageError::Internal { stage, message } => {
+                write!(
+                    f,
+                    "Internal error while running the {} stage: {}",
+                    stage, message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for StageError {}
+
+type Hook<'a> = Box<dyn FnMut(Stage, &Driver<'a>) + 'a>;
+
+// Takes one source text through parsing, optimization, analysis and
+// execution, keeping what each stage produced and how long it took. Running
+// a stage runs the ones before it first, once; what they produced is then
+// read through the accessors. The CLI, the language server and
+// `check` all go through a driver, and so can embedders.
+pub struct Driver<'a> {
+    source: &'a str,
+    dialect: Dialect,
+    keywords: Option<Keywords>,
+    strict: bool,
+    keep_trivia: bool,
+    opt_level: OptLevel,
+    builtins: Vec<Signature>,
+    hooks: Vec<Hook<'a>>,
+    // The last stage that succeeded
+    completed: Option<Stage>,
+    ast: Option<Ast>,
+    recoveries: Vec<Recovery>,
+    optimizations: PassManager,
+    analyzer: Option<SemanticAnalyzer>,
+    times: StageTimes,
+}
+
+impl<'a> Driver<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Driver {
+            source,
+            dialect: Dialect::default(),
+            keywords: None,
+            strict: false,
+            keep_trivia: false,
+            opt_level: OptLevel::O0,
+            builtins: vec![],
+            hooks: vec![],
+            completed: None,
+            ast: None,
+            recoveries: vec![],
+            optimizations: PassManager::new(),
+            analyzer: None,
+            times: StageTimes::default(),
+        }
+    }
+
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    // See `Lexer::with_keywords`.
+    pub fn with_keywords(mut self, keywords: Keywords) -> Self {
+        self.keywords = Some(keywords);
+        self
+    }
+
+    // See `Parser::with_strict`.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Keeps comments and whitespace, so `ast().tokens()` reproduces the
+    // source, as the formatter and the language server need.
+    pub fn with_trivia(mut self) -> Self {
+        self.keep_trivia = true;
+        self
+    }
+
+    pub fn with_opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    // Signatures of the builtins a `BuiltinRegistry` holds, for the
+    // analyzer to check calls against. The interpreter gets the registry
+    // itself.
+    pub fn with_builtins(mut self, signatures: impl IntoIterator<Item = Signature>) -> Self {
+        self.builtins.extend(signatures);
+        self
+    }
+
+    // Calls `hook` after every stage that succeeds, with the driver holding
+    // what the stage produced.
+    pub fn after_stage(mut self, hook: impl FnMut(Stage, &Driver<'a>) + 'a) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    // After parsing; optimization rewrites it in place.
+    pub fn ast(&self) -> Option<&Ast> {
+        self.ast.as_ref()
+    }
+
+    // The source text a node of `ast()` was parsed from, the same before
+    // and after optimization. Empty for an empty statement, or before
+    // parsing.
+    pub fn source_text(&self, id: NodeId) -> &'a str {
+        self.ast
+            .as_ref()
+            .and_then(|ast| ast.source_text(id, self.source))
+            .unwrap_or("")
+    }
+
+    pub fn into_ast(self) -> Option<Ast> {
+        self.ast
+    }
+
+    // What the parser recovered from, with fixes.
+    pub fn recoveries(&self) -> &[Recovery] {
+        &self.recoveries
+    }
+
+    // What the optimizations changed.
+    pub fn remarks(&self) -> Vec<Remark> {
+        self.optimizations.remarks()
+    }
+
+    // After analysis, even one that failed, for the cross-references it
+    // collected up to there.
+    pub fn analyzer(&self) -> Option<&SemanticAnalyzer> {
+        self.analyzer.as_ref()
+    }
+
+    // After a successful analysis.
+    pub fn hir(&self) -> Option<&Hir> {
+        match &self.analyzer {
+            Some(analyzer) if self.done(Stage::Analyze) => Some(analyzer.hir()),
+            _ => None,
+        }
+    }
+
+    // Of the stages run so far.
+    pub fn times(&self) -> StageTimes {
+        self.times
+    }
+
+    fn done(&self, stage: Stage) -> bool {
+        self.completed >= Some(stage)
+    }
+
+    fn finish(&mut self, stage: Stage) {
+        tracing::debug!("{} finished", stage);
+        self.completed = Some(stage);
+        let mut hooks = std::mem::take(&mut self.hooks);
+        for hook in &mut hooks {
+            hook(stage, self);
+        }
+        self.hooks = hooks;
+    }
+
+    // Runs the body of a stage. A panic in it, which would be a bug in this
+    // crate, becomes a `StageError::Internal` instead of unwinding into the
+    // host. What the stage produced may then be incomplete, so the driver
+    // shouldn't be used any further.
+    fn guarded(
+        &mut self,
+        stage: Stage,
+        run: impl FnOnce(&mut Self) -> Result<(), StageError>,
+    ) -> Result<(), StageError> {
+        panic::catch_unwind(AssertUnwindSafe(|| run(self))).unwrap_or_else(|payload| {
+            Err(StageError::Internal {
+                stage,
+                message: panic_message(payload.as_ref()),
+            })
+        })
+    }
+
+    pub fn parse(&mut self) -> Result<(), StageError> {
+        if !self.done(Stage::Parse) {
+            self.guarded(Stage::Parse, Self::run_parse)?;
+            self.finish(Stage::Parse);
+        }
+        Ok(())
+    }
+
+    fn run_parse(&mut self) -> Result<(), StageError> {
+        let _span = tracing::info_span!("stage", stage = %Stage::Parse).entered();
+        let start = Instant::now();
+        let mut lexer = if self.keep_trivia {
+            Lexer::with_trivia(self.source)
+        } else {
+            Lexer::new(self.source)
+        }
+        .with_dialect(self.dialect);
+        if let Some(keywords) = &self.keywords {
+            lexer = lexer.with_keywords(keywords.clone());
+        }
+        let mut parser = Parser::new(lexer)
+            .map_err(StageError::Parse)?
+            .with_strict(self.strict);
+        let ast = parser.parse().map_err(StageError::Parse)?;
+        self.times.lex = parser.lex_time();
+        self.times.parse = start.elapsed().saturating_sub(self.times.lex);
+        self.recoveries = parser.recoveries().to_vec();
+        self.ast = Some(ast);
+        Ok(())
+    }
+
+    pub fn optimize(&mut self) -> Result<(), StageError> {
+        self.parse()?;
+        if !self.done(Stage::Optimize) {
+            self.guarded(Stage::Optimize, Self::run_optimize)?;
+            self.finish(Stage::Optimize);
+        }
+        Ok(())
+    }
+
+    fn run_optimize(&mut self) -> Result<(), StageError> {
+        let _span = tracing::info_span!("stage", stage = %Stage::Optimize).entered();
+        let start = Instant::now();
+        let ast = self.ast.as_mut().expect("parsed");
+        let mut optimizations = PassManager::new();
+        optimizer::add_passes(&mut optimizations, self.opt_level);
+        optimizations.run(ast).map_err(StageError::Optimize)?;
+        self.optimizations = optimizations;
+        self.times.optimize = start.elapsed();
+        Ok(())
+    }
+
+    pub fn analyze(&mut self) -> Result<(), StageError> {
+        self.optimize()?;
+        if !self.done(Stage::Analyze) {
+            self.guarded(Stage::Analyze, Self::run_analyze)?;
+            self.finish(Stage::Analyze);
+        }
+        Ok(())
+    }
+
+    fn run_analyze(&mut self) -> Result<(), StageError> {
+        let _span = tracing::info_span!("stage", stage = %Stage::Analyze).entered();
+        let start = Instant::now();
+        let ast = self.ast.as_ref().expect("parsed");
+        let mut analyzer = SemanticAnalyzer::new()
+            .with_dialect(self.dialect)
+            .with_builtins(self.builtins.iter().cloned());
+        let result = analyzer.analyze(ast);
+        self.times.analyze = start.elapsed();
+        let failed_at = analyzer.failed_at().unwrap_or(ast.root());
+        self.analyzer = Some(analyzer);
+        if let Err(error) = result {
+            return Err(StageError::Analyze {
+                error,
+                node: failed_at,
+            });
+        }
+        Ok(())
+    }
+
+    // Runs the program on `interpreter`, set up with whatever output,
+    // switches and builtins the caller wants. Unlike the other stages this
+    // runs again every time, e.g. for benchmarking.
+    pub fn execute(&mut self, interpreter: &mut Interpreter) -> Result<RunReport, StageError> {
+        self.run_execute(|ast, hir| interpreter.interpret(ast, hir))
+    }
+
+    // Like `execute`, but gives the program `steps` steps at a time, as a
+    // fuel-based scheduler would, calling `paused` whenever they run out
+    // before going on with as many again.
+    pub fn execute_in_turns(
+        &mut self,
+        interpreter: &mut Interpreter,
+        steps: u64,
+        mut paused: impl FnMut(BudgetExhausted),
+    ) -> Result<RunReport, StageError> {
+        self.run_execute(|ast, hir| {
+            let mut run = interpreter.budgeted(ast, hir);
+            loop {
+                match run.run_with_budget(steps, u64::MAX)? {
+                    RunOutcome::Finished(report) => return Ok(report),
+                    RunOutcome::Paused(exhausted) => paused(exhausted),
+                }
+            }
+        })
+    }
+
+    fn run_execute(
+        &mut self,
+        run: impl FnOnce(&Ast, &Hir) -> InterpretResult<RunReport>,
+    ) -> Result<RunReport, StageError> {
+        self.analyze()?;
+        let ast = self.ast.as_ref().expect("parsed");
+        let hir = self.hir().expect("analyzed");
+        let _span = tracing::info_span!("stage", stage = %Stage::Execute).entered();
+        let start = Instant::now();
+        // `interpret` catches its own panics
+        let result = run(ast, hir);
+        self.times.execute = start.elapsed();
+        let report = result.map_err(StageError::Execute)?;
+        self.finish(Stage::Execute);
+        Ok(report)
+    }
+}
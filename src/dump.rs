@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::call_stack::CallStack;
+use crate::heap::Heap;
+use crate::token::Location;
+use crate::value::{PrettyOptions, RealFormat, Value};
+
+// A dump is read after the fact, so it shows more of each value than the
+// debugger does, a line per element, lined up under the member's name.
+const PRETTY: PrettyOptions = PrettyOptions {
+    max_depth: 8,
+    max_elements: 100,
+    multiline: true,
+    indent: 4,
+    real_format: RealFormat::DEFAULT,
+};
+
+// The program state at the moment a run failed: every activation record and
+// every instance still on the heap. Written by `--dump-on-error`, so a
+// failure can be looked into without running the program again.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateDump {
+    pub error: String,
+    // Start of the innermost node that failed
+    pub location: Location,
+    // Innermost frame first
+    pub frames: Vec<FrameDump>,
+    pub objects: Vec<ObjectDump>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrameDump {
+    pub name: String,
+    pub nesting_level: usize,
+    // Sorted by name
+    pub members: Vec<NamedValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ObjectDump {
+    pub class_name: String,
+    pub id: usize,
+    // In declaration order
+    pub fields: Vec<NamedValue>,
+}
+
+// A variable or field and its value.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NamedValue {
+    pub name: String,
+    pub value: Value,
+}
+
+impl NamedValue {
+    fn new(name: &str, value: &Value) -> Self {
+        NamedValue {
+            name: name.to_string(),
+            value: value.clone(),
+        }
+    }
+}
+
+impl StateDump {
+    pub fn capture(error: String, location: Location, call_stack: &CallStack, heap: &Heap) -> Self {
+        let frames = call_stack
+            .frames()
+            .iter()
+            .rev()
+            .map(|frame| {
+                let frame = frame.borrow();
+                FrameDump {
+                    name: frame.name().to_string(),
+                    nesting_level: frame.nesting_level(),
+                    members: frame
+                        .members()
+                        .into_iter()
+                        .map(|(name, value)| NamedValue::new(name, value))
+                        .collect(),
+                }
+            })
+            .collect();
+        let objects = heap
+            .live_objects()
+            .map(|(object, fields)| ObjectDump {
+                class_name: object.class_name.to_string(),
+                id: object.id,
+                fields: match fields {
+                    Value::Record(fields) => fields
+                        .iter()
+                        .map(|(name, value)| NamedValue::new(name, value))
+                        .collect(),
+                    _ => vec![],
+                },
+            })
+            .collect();
+        StateDump {
+            error,
+            location,
+            frames,
+            objects,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("state dump is always serializable")
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Error at {}: {}", self.location, self.error);
+
+        let _ = writeln!(out, "\nCall stack (innermost first):");
+        for frame in &self.frames {
+            let _ = writeln!(out, "  {} (level {})", frame.name, frame.nesting_level);
+            for member in &frame.members {
+                let _ = writeln!(out, "    {} = {}", member.name, member.value.pretty(PRETTY));
+            }
+        }
+
+        let _ = writeln!(out, "\nHeap:");
+        if self.objects.is_empty() {
+            let _ = writeln!(out, "  (no live objects)");
+        }
+        for object in &self.objects {
+            let _ = writeln!(out, "  {} #{}", object.class_name, object.id);
+            for field in &object.fields {
+                let _ = writeln!(out, "    {} = {}", field.name, field.value.pretty(PRETTY));
+            }
+        }
+        out
+    }
+}
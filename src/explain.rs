@@ -0,0 +1,80 @@
+use std::fmt::Write;
+
+use crate::ast::{Ast, NodeId};
+
+// Output lines wider than this don't push the annotations further right
+const MAX_ALIGN: usize = 40;
+
+// The output of a run, split into the pieces each statement wrote, for
+// `--explain-run`. A statement is the `write`, `writeln` or builtin call
+// that printed the text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Explanation {
+    pieces: Vec<(NodeId, String)>,
+}
+
+impl Explanation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&mut self, statement: NodeId, text: &str) {
+        match self.pieces.last_mut() {
+            Some((last, written)) if *last == statement => written.push_str(text),
+            _ => self.pieces.push((statement, text.to_string())),
+        }
+    }
+
+    // In the order they were written.
+    pub fn pieces(&self) -> &[(NodeId, String)] {
+        &self.pieces
+    }
+
+    // Every output line followed by the location and text of the
+    // statements that wrote it, e.g. `42    <- 5:4 writeln(x * 2)`.
+    pub fn annotate(&self, ast: &Ast) -> String {
+        let mut lines: Vec<(String, Vec<NodeId>)> = vec![(String::new(), vec![])];
+        for (statement, text) in &self.pieces {
+            let parts = text.split('\n').collect::<Vec<_>>();
+            for (i, part) in parts.iter().enumerate() {
+                if i > 0 {
+                    lines.push((String::new(), vec![]));
+                }
+                let (line, statements) = lines.last_mut().expect("there is always a line");
+                line.push_str(part);
+                // Ending a line counts as writing to it, as an empty
+                // `writeln()` does
+                let ends_line = i + 1 < parts.len();
+                if (!part.is_empty() || ends_line) && !statements.contains(statement) {
+                    statements.push(*statement);
+                }
+            }
+        }
+        // Output ending in a newline has nothing after it
+        if lines
+            .last()
+            .is_some_and(|(line, statements)| line.is_empty() && statements.is_empty())
+        {
+            lines.pop();
+        }
+
+        let width = lines
+            .iter()
+            .map(|(line, _)| line.chars().count())
+            .filter(|width| *width <= MAX_ALIGN)
+            .max()
+            .unwrap_or(0);
+        let mut out = String::new();
+        for (line, statements) in lines {
+            let sources = statements
+                .iter()
+                .map(|statement| {
+                    format!("{} {}", ast.location(*statement), ast.display(*statement))
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            let _ = writeln!(out, "{line:<width$}  <- {sources}");
+        }
+        out
+    }
+}
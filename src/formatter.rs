@@ -0,0 +1,865 @@
+use crate::ast::{ASTNode, Ast, MethodKind, NodeId, ParamMode};
+use crate::parser::{binary_precedence, Precedence};
+use crate::token::{LocatedToken, Token, Trivia, TriviaKind};
+use crate::value::Value;
+use crate::visitor::ensure_stack;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordCase {
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatOptions {
+    pub indent_width: usize,
+    pub keyword_case: KeywordCase,
+    // `a + b` rather than `a+b`
+    pub space_around_operators: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_width: 2,
+            keyword_case: KeywordCase::Lower,
+            space_around_operators: true,
+        }
+    }
+}
+
+// Prints a program as canonically formatted Pascal. The layout comes from
+// the AST alone; when the AST was parsed with trivia, the printer also walks
+// the source tokens in step with it, so comments, blank lines, parentheses
+// and the spelling of identifiers and numbers survive formatting.
+pub fn format(ast: &Ast, source: &str, options: &FormatOptions) -> String {
+    let mut printer = Printer {
+        ast,
+        source,
+        options,
+        tokens: ast.tokens(),
+        cursor: 0,
+        out: String::new(),
+        indent: 0,
+        at_line_start: true,
+        break_line: false,
+    };
+    printer.node(ast.root());
+    if let Some(eof) = printer.tokens.len().checked_sub(1) {
+        printer.comments_before(eof);
+    }
+    printer.newline();
+    printer.out
+}
+
+struct Printer<'a> {
+    ast: &'a Ast,
+    source: &'a str,
+    options: &'a FormatOptions,
+    tokens: &'a [LocatedToken],
+    // First source token not printed yet
+    cursor: usize,
+    out: String,
+    indent: usize,
+    at_line_start: bool,
+    // Set after a `//` comment, which nothing may follow on its line
+    break_line: bool,
+}
+
+impl Printer<'_> {
+    fn write(&mut self, text: &str) {
+        if self.break_line {
+            self.newline();
+        }
+        if self.at_line_start {
+            let width = self.indent * self.options.indent_width;
+            self.out.extend(std::iter::repeat_n(' ', width));
+            self.at_line_start = false;
+        }
+        self.out.push_str(text);
+    }
+
+    fn space(&mut self) {
+        if !self.at_line_start && !self.break_line && !self.out.ends_with(' ') {
+            self.out.push(' ');
+        }
+    }
+
+    fn newline(&mut self) {
+        if !self.at_line_start {
+            self.out.push('\n');
+            self.at_line_start = true;
+        }
+        self.break_line = false;
+    }
+
+    fn blank_line(&mut self) {
+        self.newline();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    // The source token `token` stands for: the next one of its kind.
+    fn find(&self, token: &Token) -> Option<usize> {
+        (self.cursor..self.tokens.len()).find(|&i| match (&self.tokens[i].token, token) {
+            (Token::Id(a), Token::Id(b)) => a == b,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        })
+    }
+
+    // Prints a token along with the comments that surround it in the
+    // source. `text` is only used when there is no source token to print.
+    fn token(&mut self, token: &Token, text: &str) {
+        let Some(index) = self.find(token) else {
+            let text = self.keyword_case(token, text);
+            self.write(&text);
+            return;
+        };
+        self.comments_before(index);
+        let span = self.tokens[index].span;
+        let text = self.keyword_case(token, &self.source[span.start..span.end]);
+        self.write(&text);
+        self.cursor = index + 1;
+        self.trailing_comments(index);
+    }
+
+    // Consumes `token` if it comes next in the source without printing it,
+    // keeping its comments where they were.
+    fn skip(&mut self, token: &Token) {
+        if self
+            .tokens
+            .get(self.cursor)
+            .is_some_and(|t| &t.token == token)
+        {
+            self.leading_comments(self.cursor);
+            self.cursor += 1;
+            self.trailing_comments(self.cursor - 1);
+        }
+    }
+
+    fn keyword_case(&self, token: &Token, text: &str) -> String {
+        let keyword = matches!(
+            token,
+            Token::Program
+                | Token::Var
+                | Token::Procedure
+                | Token::Type
+                | Token::Class
+                | Token::Constructor
+                | Token::Destructor
+                | Token::Property
+                | Token::Const
+                | Token::Begin
+                | Token::End
+                | Token::If
+                | Token::Then
+                | Token::Else
+                | Token::While
+                | Token::Do
+                | Token::Repeat
+                | Token::Until
+                | Token::Case
+                | Token::Of
+                | Token::IntegerDiv
+                | Token::And
+                | Token::Or
+                | Token::Not
+                | Token::Integer
+                | Token::Real
+        );
+        match self.options.keyword_case {
+            _ if !keyword => text.to_string(),
+            KeywordCase::Lower => text.to_ascii_lowercase(),
+            KeywordCase::Upper => text.to_ascii_uppercase(),
+        }
+    }
+
+    // Flushes the comments of every source token up to `index` that the
+    // printer skipped (extra semicolons, repeated VAR keywords and the like)
+    // and those leading up to `index` itself.
+    fn comments_before(&mut self, index: usize) {
+        for skipped in self.cursor..index {
+            self.leading_comments(skipped);
+            let own_line = self.at_line_start;
+            self.trailing_comments(skipped);
+            if own_line {
+                self.newline();
+            }
+        }
+        self.leading_comments(index);
+        self.cursor = self.cursor.max(index);
+    }
+
+    fn leading_comments(&mut self, index: usize) {
+        let trivia: &[Trivia] = &self.tokens[index].leading;
+        if self.at_line_start
+            && trivia
+                .iter()
+                .find(|t| t.kind != TriviaKind::Whitespace)
+                .is_some_and(|t| t.kind == TriviaKind::Newline)
+        {
+            self.blank_line();
+        }
+        for (position, piece) in trivia.iter().enumerate() {
+            if piece.kind != TriviaKind::Comment {
+                continue;
+            }
+            let own_line = trivia[position + 1..]
+                .iter()
+                .find(|t| t.kind != TriviaKind::Whitespace)
+                .is_some_and(|t| t.kind == TriviaKind::Newline);
+            if own_line {
+                self.newline();
+            }
+            self.comment(piece);
+            if own_line {
+                self.newline();
+            } else {
+                self.space();
+            }
+        }
+    }
+
+    fn trailing_comments(&mut self, index: usize) {
+        let trivia: &[Trivia] = &self.tokens[index].trailing;
+        for piece in trivia.iter().filter(|t| t.kind == TriviaKind::Comment) {
+            self.space();
+            self.comment(piece);
+        }
+    }
+
+    fn comment(&mut self, piece: &Trivia) {
+        let text = piece.text(self.source);
+        self.write(text);
+        if text.starts_with("//") {
+            self.break_line = true;
+        }
+    }
+
+    fn node(&mut self, node: NodeId) {
+        let ast = self.ast;
+        match &ast[node] {
+            ASTNode::Program { name, block } => {
+                self.token(&Token::Program, "program");
+                self.space();
+                self.token(&Token::Id(name.clone()), name);
+                self.token(&Token::Semi, ";");
+                self.newline();
+                self.block(*block, false);
+                self.token(&Token::Dot, ".");
+            }
+            ASTNode::Compound { children } => {
+                self.token(&Token::Begin, "begin");
+                self.statement_list(children);
+                self.token(&Token::End, "end");
+            }
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.token(&Token::If, "if");
+                self.space();
+                self.expression(*condition, None);
+                self.space();
+                self.token(&Token::Then, "then");
+                self.branch(*then_branch, false);
+                if let Some(else_branch) = else_branch {
+                    if matches!(ast[*then_branch], ASTNode::Compound { .. }) {
+                        self.space();
+                    } else {
+                        self.newline();
+                    }
+                    self.token(&Token::Else, "else");
+                    self.branch(*else_branch, true);
+                }
+            }
+            ASTNode::While { condition, body } => {
+                self.token(&Token::While, "while");
+                self.space();
+                self.expression(*condition, None);
+                self.space();
+                self.token(&Token::Do, "do");
+                self.branch(*body, false);
+            }
+            ASTNode::Repeat { body, condition } => {
+                self.token(&Token::Repeat, "repeat");
+                self.statement_list(body);
+                self.token(&Token::Until, "until");
+                self.space();
+                self.expression(*condition, None);
+            }
+            ASTNode::Case {
+                selector,
+                arms,
+                else_branch,
+            } => {
+                self.token(&Token::Case, "case");
+                self.space();
+                self.expression(*selector, None);
+                self.space();
+                self.token(&Token::Of, "of");
+                self.newline();
+                self.indent += 1;
+                for (index, arm) in arms.iter().enumerate() {
+                    self.node(*arm);
+                    if index + 1 < arms.len() {
+                        self.token(&Token::Semi, ";");
+                    } else {
+                        self.skip(&Token::Semi);
+                    }
+                    self.newline();
+                }
+                self.indent -= 1;
+                if let Some(else_branch) = else_branch {
+                    self.token(&Token::Else, "else");
+                    self.statement_list(else_branch);
+                }
+                self.token(&Token::End, "end");
+            }
+            // A BEGIN block stays on the line of its labels
+            ASTNode::CaseArm { labels, statement } => {
+                for (index, label) in labels.iter().enumerate() {
+                    if index > 0 {
+                        self.token(&Token::Comma, ",");
+                        self.space();
+                    }
+                    self.node(*label);
+                }
+                self.token(&Token::Colon, ":");
+                if !matches!(ast[*statement], ASTNode::NoOp) {
+                    self.space();
+                    self.node(*statement);
+                }
+            }
+            ASTNode::Range { low, high } => {
+                self.expression(*low, None);
+                self.token(&Token::Range, "..");
+                self.expression(*high, None);
+            }
+            ASTNode::Assign { left, right, .. } => {
+                self.node(*left);
+                self.space();
+                self.token(&Token::Assign, ":=");
+                self.space();
+                self.expression(*right, None);
+            }
+            ASTNode::ProcedureCall {
+                proc_name,
+                arguments,
+                ..
+            } => {
+                self.token(&Token::Id(proc_name.clone()), proc_name);
+                // `DoThing` and `DoThing()` are the same call
+                let parenthesized = self
+                    .tokens
+                    .get(self.cursor)
+                    .is_some_and(|t| t.token == Token::LParenthesis);
+                if arguments.is_empty() && !parenthesized {
+                    return;
+                }
+                self.token(&Token::LParenthesis, "(");
+                for (index, argument) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        self.token(&Token::Comma, ",");
+                        self.space();
+                    }
+                    self.expression(*argument, None);
+                }
+                self.token(&Token::RParenthesis, ")");
+            }
+            ASTNode::Var { name } => self.token(&Token::Id(name.clone()), name),
+            ASTNode::FieldAccess { object, field } => {
+                self.node(*object);
+                self.token(&Token::Dot, ".");
+                self.token(&Token::Id(field.clone()), field);
+            }
+            ASTNode::MethodCall {
+                object,
+                method,
+                arguments,
+            } => {
+                self.node(*object);
+                self.token(&Token::Dot, ".");
+                self.token(&Token::Id(method.clone()), method);
+                // `c.Free` and `c.Free()` are the same call
+                let parenthesized = self
+                    .tokens
+                    .get(self.cursor)
+                    .is_some_and(|t| t.token == Token::LParenthesis);
+                if arguments.is_empty() && !parenthesized {
+                    return;
+                }
+                self.token(&Token::LParenthesis, "(");
+                for (index, argument) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        self.token(&Token::Comma, ",");
+                        self.space();
+                    }
+                    self.expression(*argument, None);
+                }
+                self.token(&Token::RParenthesis, ")");
+            }
+            ASTNode::NoOp => {}
+            _ => self.expression(node, None),
+        }
+    }
+
+    // The statements between BEGIN and END or REPEAT and UNTIL, one per
+    // line and indented.
+    fn statement_list(&mut self, children: &[NodeId]) {
+        let ast = self.ast;
+        self.newline();
+        self.indent += 1;
+        let statements = children
+            .iter()
+            .filter(|child| !matches!(ast[**child], ASTNode::NoOp))
+            .collect::<Vec<_>>();
+        for (index, statement) in statements.iter().enumerate() {
+            self.node(**statement);
+            if index + 1 < statements.len() {
+                self.token(&Token::Semi, ";");
+            } else {
+                self.skip(&Token::Semi);
+            }
+            self.newline();
+        }
+        self.indent -= 1;
+    }
+
+    // A BEGIN block goes on the line of its THEN or ELSE, as does the IF of
+    // an `else if` chain; any other statement is indented on the next line.
+    fn branch(&mut self, node: NodeId, after_else: bool) {
+        match &self.ast[node] {
+            ASTNode::NoOp => {}
+            ASTNode::Compound { .. } => {
+                self.space();
+                self.node(node);
+            }
+            ASTNode::If { .. } if after_else => {
+                self.space();
+                self.node(node);
+            }
+            _ => {
+                self.newline();
+                self.indent += 1;
+                self.node(node);
+                self.indent -= 1;
+            }
+        }
+    }
+
+    // Declarations, then the compound statement. Procedures nested in
+    // another procedure are indented one level.
+    fn block(&mut self, block: NodeId, nested: bool) {
+        let ast = self.ast;
+        let ASTNode::Block {
+            declarations,
+            compound_statement,
+        } = &ast[block]
+        else {
+            return;
+        };
+
+        let mut index = 0;
+        while index < declarations.len() {
+            match &ast[declarations[index]] {
+                ASTNode::VarDecl { .. } => {
+                    let end = declarations[index..]
+                        .iter()
+                        .position(|d| !matches!(ast[*d], ASTNode::VarDecl { .. }))
+                        .map_or(declarations.len(), |n| index + n);
+                    self.token(&Token::Var, "var");
+                    self.newline();
+                    self.indent += 1;
+                    for group in self.groups(&declarations[index..end]) {
+                        self.typed_names(&group);
+                        // Only a variable declared on its own has one
+                        if let [declaration] = group[..] {
+                            if let ASTNode::VarDecl {
+                                initializer: Some(initializer),
+                                ..
+                            } = ast[declaration]
+                            {
+                                self.space();
+                                self.token(&Token::Equal, "=");
+                                self.space();
+                                self.expression(initializer, None);
+                            }
+                        }
+                        self.token(&Token::Semi, ";");
+                        self.newline();
+                    }
+                    self.indent -= 1;
+                    index = end;
+                }
+                ASTNode::TypeDecl { .. } => {
+                    let end = declarations[index..]
+                        .iter()
+                        .position(|d| !matches!(ast[*d], ASTNode::TypeDecl { .. }))
+                        .map_or(declarations.len(), |n| index + n);
+                    self.token(&Token::Type, "type");
+                    self.newline();
+                    self.indent += 1;
+                    for declaration in &declarations[index..end] {
+                        self.type_declaration(*declaration);
+                    }
+                    self.indent -= 1;
+                    index = end;
+                }
+                ASTNode::ProcedureDecl { .. } | ASTNode::MethodDecl { .. } => {
+                    if nested {
+                        self.indent += 1;
+                    }
+                    self.procedure(declarations[index]);
+                    if nested {
+                        self.indent -= 1;
+                    }
+                    index += 1;
+                }
+                _ => index += 1,
+            }
+        }
+        self.node(*compound_statement);
+    }
+
+    fn procedure(&mut self, node: NodeId) {
+        let ast = self.ast;
+        let (params, block_node) = match &ast[node] {
+            ASTNode::ProcedureDecl {
+                proc_name,
+                type_params,
+                params,
+                block_node,
+            } => {
+                if !type_params.is_empty() {
+                    self.token(&Token::Id("generic".to_string()), "generic");
+                    self.space();
+                }
+                self.token(&Token::Procedure, "procedure");
+                self.space();
+                self.token(&Token::Id(proc_name.clone()), proc_name);
+                self.type_params(type_params);
+                (params, block_node)
+            }
+            ASTNode::MethodDecl {
+                class_name,
+                method_kind,
+                method_name,
+                params,
+                block_node,
+            } => {
+                self.method_kind(*method_kind);
+                self.space();
+                self.token(&Token::Id(class_name.clone()), class_name);
+                self.token(&Token::Dot, ".");
+                self.token(&Token::Id(method_name.clone()), method_name);
+                (params, block_node)
+            }
+            _ => return,
+        };
+        self.params(params);
+        self.token(&Token::Semi, ";");
+        self.newline();
+        self.block(*block_node, true);
+        self.token(&Token::Semi, ";");
+        self.newline();
+    }
+
+    // `name = integer;` or `name = procedure(x : integer);`
+    fn type_declaration(&mut self, node: NodeId) {
+        let ast = self.ast;
+        let ASTNode::TypeDecl {
+            type_name,
+            type_node,
+        } = &ast[node]
+        else {
+            return;
+        };
+        self.token(&Token::Id(type_name.clone()), type_name);
+        self.space();
+        self.token(&Token::Equal, "=");
+        self.space();
+        match &ast[*type_node] {
+            ASTNode::ProcedureType { params } => {
+                self.token(&Token::Procedure, "procedure");
+                self.params(params);
+            }
+            ASTNode::ClassType { members } => self.class(members),
+            ASTNode::Type { value } => self.type_name(value),
+            _ => {}
+        }
+        self.token(&Token::Semi, ";");
+        self.newline();
+    }
+
+    // `class`, the fields, method headings and properties one per line,
+    // then `end`.
+    fn class(&mut self, members: &[NodeId]) {
+        let ast = self.ast;
+        self.token(&Token::Class, "class");
+        self.newline();
+        self.indent += 1;
+        let mut index = 0;
+        while index < members.len() {
+            if let ASTNode::MethodHeading {
+                method_kind,
+                name,
+                params,
+            } = &ast[members[index]]
+            {
+                self.method_kind(*method_kind);
+                self.space();
+                self.token(&Token::Id(name.clone()), name);
+                self.params(params);
+                self.token(&Token::Semi, ";");
+                self.newline();
+                index += 1;
+                continue;
+            }
+            if let ASTNode::Property { .. } = &ast[members[index]] {
+                self.property(members[index]);
+                self.token(&Token::Semi, ";");
+                self.newline();
+                index += 1;
+                continue;
+            }
+            let end = members[index..]
+                .iter()
+                .position(|m| !matches!(ast[*m], ASTNode::VarDecl { .. }))
+                .map_or(members.len(), |n| index + n);
+            for group in self.groups(&members[index..end]) {
+                self.typed_names(&group);
+                self.token(&Token::Semi, ";");
+                self.newline();
+            }
+            index = end.max(index + 1);
+        }
+        self.indent -= 1;
+        self.token(&Token::End, "end");
+    }
+
+    // READ and WRITE keep their spelling, being identifiers to the lexer.
+    fn property(&mut self, node: NodeId) {
+        let ast = self.ast;
+        let ASTNode::Property {
+            name,
+            type_node,
+            reader,
+            writer,
+        } = &ast[node]
+        else {
+            return;
+        };
+        self.token(&Token::Property, "property");
+        self.space();
+        self.token(&Token::Id(name.clone()), name);
+        self.space();
+        self.token(&Token::Colon, ":");
+        self.space();
+        if let ASTNode::Type { value } = &ast[*type_node] {
+            self.type_name(value);
+        }
+        for (directive, accessor) in [("read", reader), ("write", writer)] {
+            if let Some(accessor) = accessor {
+                self.space();
+                self.token(&Token::Id(directive.to_string()), directive);
+                self.space();
+                self.token(&Token::Id(accessor.clone()), accessor);
+            }
+        }
+    }
+
+    fn method_kind(&mut self, method_kind: MethodKind) {
+        match method_kind {
+            MethodKind::Procedure => self.token(&Token::Procedure, "procedure"),
+            MethodKind::Constructor => self.token(&Token::Constructor, "constructor"),
+            MethodKind::Destructor => self.token(&Token::Destructor, "destructor"),
+        }
+    }
+
+    fn params(&mut self, params: &[NodeId]) {
+        if params.is_empty() {
+            return;
+        }
+        self.token(&Token::LParenthesis, "(");
+        for (index, group) in self.groups(params).into_iter().enumerate() {
+            if index > 0 {
+                self.token(&Token::Semi, ";");
+                self.space();
+            }
+            let mode = match group.first().map(|param| &self.ast[*param]) {
+                Some(ASTNode::Param { mode, .. }) => *mode,
+                _ => ParamMode::Value,
+            };
+            match mode {
+                ParamMode::Value => {}
+                ParamMode::Var => self.token(&Token::Var, "var"),
+                ParamMode::Const => self.token(&Token::Const, "const"),
+                ParamMode::Out => self.token(&Token::Id("out".to_string()), "out"),
+            }
+            if mode != ParamMode::Value {
+                self.space();
+            }
+            self.typed_names(&group);
+        }
+        self.token(&Token::RParenthesis, ")");
+    }
+
+    // `<T, U>` after the name of a generic procedure
+    fn type_params(&mut self, type_params: &[String]) {
+        if type_params.is_empty() {
+            return;
+        }
+        self.token(&Token::LessThan, "<");
+        for (index, name) in type_params.iter().enumerate() {
+            if index > 0 {
+                self.token(&Token::Comma, ",");
+                self.space();
+            }
+            self.token(&Token::Id(name.clone()), name);
+        }
+        self.token(&Token::GreaterThan, ">");
+    }
+
+    // Splits variable or parameter declarations into the groups they were
+    // declared in (`a, b : integer`): the parser gives every name its own
+    // type node, but all of them at the location of the shared type.
+    fn groups(&self, declarations: &[NodeId]) -> Vec<Vec<NodeId>> {
+        let mut groups: Vec<Vec<NodeId>> = vec![];
+        let mut last_type = None;
+        for declaration in declarations {
+            let type_location = self
+                .ast
+                .children(*declaration)
+                .get(1)
+                .map(|type_node| self.ast.location(*type_node));
+            match groups.last_mut() {
+                Some(group) if type_location.is_some() && type_location == last_type => {
+                    group.push(*declaration)
+                }
+                _ => groups.push(vec![*declaration]),
+            }
+            last_type = type_location;
+        }
+        groups
+    }
+
+    // `a, b : integer` for a group of VarDecl or Param nodes.
+    fn typed_names(&mut self, group: &[NodeId]) {
+        let ast = self.ast;
+        for (index, declaration) in group.iter().enumerate() {
+            if index > 0 {
+                self.token(&Token::Comma, ",");
+                self.space();
+            }
+            if let Some(var_node) = ast.children(*declaration).first() {
+                self.node(*var_node);
+            }
+        }
+        let Some(type_node) = group
+            .first()
+            .and_then(|declaration| ast.children(*declaration).get(1).copied())
+        else {
+            return;
+        };
+        let ASTNode::Type { value } = &ast[type_node] else {
+            return;
+        };
+        self.space();
+        self.token(&Token::Colon, ":");
+        self.space();
+        self.type_name(value);
+    }
+
+    fn type_name(&mut self, value: &str) {
+        let token = match value {
+            "INTEGER" => Token::Integer,
+            "REAL" => Token::Real,
+            name => Token::Id(name.to_string()),
+        };
+        self.token(&token, value);
+    }
+
+    // Prints an expression, in parentheses where the source had them or,
+    // for nodes without source tokens, where precedence requires them.
+    // `parent` is the precedence of the enclosing operator and whether this
+    // is its right operand.
+    fn expression(&mut self, node: NodeId, parent: Option<(Precedence, bool)>) {
+        ensure_stack(|| {
+            let ast = self.ast;
+            let parens = match ast.token_range(node) {
+                Some(range) if !self.tokens.is_empty() => {
+                    let opens = self.tokens[self.cursor.min(range.start)..range.start]
+                        .iter()
+                        .rev()
+                        .take_while(|t| t.token == Token::LParenthesis)
+                        .count();
+                    let closes = self.tokens[range.end..]
+                        .iter()
+                        .take_while(|t| t.token == Token::RParenthesis)
+                        .count();
+                    opens.min(closes)
+                }
+                _ => usize::from(needs_parens(ast, node, parent)),
+            };
+
+            for _ in 0..parens {
+                self.token(&Token::LParenthesis, "(");
+            }
+            match &ast[node] {
+                ASTNode::BinOpNode { left, right, op } => {
+                    let precedence = binary_precedence(op).unwrap_or(Precedence::Lowest);
+                    // A word operator always needs spaces to stay apart from its
+                    // operands
+                    let spaced = self.options.space_around_operators || is_word_operator(op);
+                    self.expression(*left, Some((precedence, false)));
+                    if spaced {
+                        self.space();
+                    }
+                    self.token(op, &op.to_string());
+                    if spaced {
+                        self.space();
+                    }
+                    self.expression(*right, Some((precedence, true)));
+                }
+                ASTNode::UnaryOpNode { expr, token } => {
+                    self.token(token, &token.to_string());
+                    if is_word_operator(token) {
+                        self.space();
+                    }
+                    self.expression(*expr, Some((Precedence::Unary, true)));
+                }
+                ASTNode::NumNode { value } => {
+                    let token = match value {
+                        Value::Real(_) => Token::RealConst(0.0),
+                        _ => Token::IntegerConst(0),
+                    };
+                    self.token(&token, &value.to_string());
+                }
+                _ => self.node(node),
+            }
+            for _ in 0..parens {
+                self.token(&Token::RParenthesis, ")");
+            }
+        })
+    }
+}
+
+fn is_word_operator(op: &Token) -> bool {
+    matches!(op, Token::IntegerDiv | Token::And | Token::Or | Token::Not)
+}
+
+fn needs_parens(ast: &Ast, node: NodeId, parent: Option<(Precedence, bool)>) -> bool {
+    let Some((parent, right)) = parent else {
+        return false;
+    };
+    match &ast[node] {
+        ASTNode::BinOpNode { op, .. } => {
+            let precedence = binary_precedence(op).unwrap_or(Precedence::Lowest);
+            precedence < parent || (right && precedence == parent)
+        }
+        _ => false,
+    }
+}
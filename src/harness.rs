@@ -0,0 +1,447 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+
+use crate::token::Location;
+
+// A conformance test: `name.pas`, run with `name.in` (if present) as its
+// stdin, must print exactly `name.out` and exit with the status in
+// `name.exit`, or 0 without one. `name.args` can hold extra command-line
+// arguments for the run, e.g. `--emit ast-json` for a golden parse tree.
+// A case with `//~` annotations is a diagnostics case instead: it passes
+// when its diagnostics are exactly the annotated ones, and has no `.out`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub source: PathBuf,
+}
+
+impl Case {
+    fn sibling(&self, extension: &str) -> PathBuf {
+        self.source.with_extension(extension)
+    }
+
+    pub fn input(&self) -> Option<PathBuf> {
+        Some(self.sibling("in")).filter(|path| path.is_file())
+    }
+
+    pub fn args(&self) -> io::Result<Vec<String>> {
+        match fs::read_to_string(self.sibling("args")) {
+            Ok(args) => Ok(args.split_whitespace().map(str::to_string).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn expected_output(&self) -> io::Result<String> {
+        fs::read_to_string(self.sibling("out"))
+    }
+
+    pub fn expected_status(&self) -> io::Result<i32> {
+        match fs::read_to_string(self.sibling("exit")) {
+            Ok(status) => status.trim().parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not an exit status", self.sibling("exit").display()),
+                )
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn annotations(&self) -> io::Result<Vec<Annotation>> {
+        // Annotations are ASCII, whatever the encoding of the rest
+        let source = fs::read(&self.source)?;
+        annotations(&String::from_utf8_lossy(&source)).map_err(|message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", self.source.display(), message),
+            )
+        })
+    }
+
+    // Overwrites the expectations with what the program actually did.
+    pub fn bless(&self, run: &Run) -> io::Result<()> {
+        fs::write(self.sibling("out"), &run.stdout)?;
+        if run.status == 0 {
+            match fs::remove_file(self.sibling("exit")) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        } else {
+            fs::write(self.sibling("exit"), format!("{}\n", run.status))?;
+        }
+        Ok(())
+    }
+}
+
+// Every `.pas` file directly inside `dir`, sorted by name.
+pub fn discover(dir: &Path) -> io::Result<Vec<Case>> {
+    let mut cases = vec![];
+    for entry in fs::read_dir(dir)? {
+        let source = entry?.path();
+        if source
+            .extension()
+            .is_some_and(|extension| extension == "pas")
+        {
+            let name = source
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            cases.push(Case { name, source });
+        }
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+// What running a case printed and how it exited. A process killed by a
+// signal has status -1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub stdout: String,
+    pub status: i32,
+}
+
+// Runs `program args... case-args... case.pas` with the case's input on
+// stdin. The process runs in `work_dir`, where artifacts such as `ast.svg`
+// end up.
+pub fn run(program: &Path, args: &[String], case: &Case, work_dir: &Path) -> io::Result<Run> {
+    let source = fs::canonicalize(&case.source)?;
+    let stdin = match case.input() {
+        Some(input) => Stdio::from(fs::File::open(input)?),
+        None => Stdio::null(),
+    };
+    let output = Command::new(program)
+        .args(args)
+        .args(case.args()?)
+        .arg(source)
+        .current_dir(work_dir)
+        .stdin(stdin)
+        .stderr(Stdio::null())
+        .output()?;
+    Ok(Run {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        status: output.status.code().unwrap_or(-1),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// `//~ ERROR undefined variable` expects an error on its own line whose
+// message contains the text, ignoring case. Each `^` in `//~^` moves the
+// expectation up a line, for a diagnostic on a line that can't hold a
+// comment of its own. `WARNING` expects a warning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Annotation {
+    fn matches(&self, diagnostic: &Reported) -> bool {
+        self.line == diagnostic.location.line
+            && self.severity == diagnostic.severity
+            && diagnostic
+                .message
+                .to_lowercase()
+                .contains(&self.message.to_lowercase())
+    }
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = self.severity.to_string().to_uppercase();
+        write!(f, "line {}: {} {}", self.line, severity, self.message)
+    }
+}
+
+// The annotations in `source`, in line order.
+pub fn annotations(source: &str) -> Result<Vec<Annotation>, String> {
+    let mut annotations = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let Some((_, annotation)) = line.split_once("//~") else {
+            continue;
+        };
+        let up = annotation.len() - annotation.trim_start_matches('^').len();
+        let annotation = annotation[up..].trim();
+        let (severity, message) = annotation.split_once(' ').unwrap_or((annotation, ""));
+        let severity = match severity {
+            "ERROR" => Severity::Error,
+            "WARNING" => Severity::Warning,
+            _ => {
+                return Err(format!(
+                    "line {}: an annotation starts with ERROR or WARNING",
+                    index + 1
+                ))
+            }
+        };
+        if up > index {
+            return Err(format!(
+                "line {}: the annotation points above the first line",
+                index + 1
+            ));
+        }
+        annotations.push(Annotation {
+            line: index + 1 - up,
+            severity,
+            message: message.trim().to_string(),
+        });
+    }
+    Ok(annotations)
+}
+
+// A diagnostic without its suggested fixes, as a diagnostics case compares
+// them.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub struct Reported {
+    pub location: Location,
+    #[serde(skip, default = "error")]
+    pub severity: Severity,
+    pub message: String,
+}
+
+fn error() -> Severity {
+    Severity::Error
+}
+
+// What `--emit diagnostics-json` prints
+#[derive(Deserialize)]
+struct Emitted {
+    errors: Vec<Reported>,
+    warnings: Vec<Reported>,
+}
+
+// Diagnostics one per line as `name:line:column: severity: message`, sorted
+// by position, so the text depends on neither the order they were found in
+// nor the directory `file_name` is in.
+pub fn snapshot(file_name: &str, diagnostics: &[Reported]) -> String {
+    let mut diagnostics = diagnostics.iter().collect::<Vec<_>>();
+    diagnostics.sort();
+    let mut out = String::new();
+    for diagnostic in diagnostics {
+        out.push_str(&format!(
+            "{}:{}: {}: {}\n",
+            file_name, diagnostic.location, diagnostic.severity, diagnostic.message
+        ));
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail {
+        // Unified-style diff from the expected to the actual stdout, empty if
+        // only the status differs
+        diff: String,
+        expected_status: i32,
+        status: i32,
+    },
+    // The diagnostics of a diagnostics case: annotations no diagnostic
+    // matched, and the snapshot of the diagnostics no annotation matched
+    Unmatched {
+        missing: Vec<Annotation>,
+        unexpected: String,
+    },
+    // The case could not be run or has no `.out` file
+    Error(String),
+}
+
+pub fn check(program: &Path, args: &[String], case: &Case, work_dir: &Path) -> Outcome {
+    match case.annotations() {
+        Ok(annotations) if !annotations.is_empty() => {
+            return check_diagnostics(program, args, case, work_dir, annotations)
+        }
+        Ok(_) => {}
+        Err(e) => return Outcome::Error(e.to_string()),
+    }
+    let expected = match case.expected_output() {
+        Ok(expected) => expected,
+        Err(e) => return Outcome::Error(format!("cannot read expected output: {}", e)),
+    };
+    let expected_status = match case.expected_status() {
+        Ok(status) => status,
+        Err(e) => return Outcome::Error(e.to_string()),
+    };
+    let run = match run(program, args, case, work_dir) {
+        Ok(run) => run,
+        Err(e) => return Outcome::Error(format!("cannot run {}: {}", program.display(), e)),
+    };
+    if run.stdout == expected && run.status == expected_status {
+        return Outcome::Pass;
+    }
+    let diff = if run.stdout == expected {
+        String::new()
+    } else {
+        diff(&expected, &run.stdout)
+    };
+    Outcome::Fail {
+        diff,
+        expected_status,
+        status: run.status,
+    }
+}
+
+// Runs a diagnostics case with `--emit diagnostics-json` after its own
+// arguments and pairs each annotation with a diagnostic it matches.
+fn check_diagnostics(
+    program: &Path,
+    args: &[String],
+    case: &Case,
+    work_dir: &Path,
+    annotations: Vec<Annotation>,
+) -> Outcome {
+    let args = [
+        args,
+        &["--emit".to_string(), "diagnostics-json".to_string()],
+    ]
+    .concat();
+    let run = match run(program, &args, case, work_dir) {
+        Ok(run) => run,
+        Err(e) => return Outcome::Error(format!("cannot run {}: {}", program.display(), e)),
+    };
+    let emitted: Emitted = match serde_json::from_str(&run.stdout) {
+        Ok(emitted) => emitted,
+        Err(e) => return Outcome::Error(format!("cannot read the diagnostics: {}", e)),
+    };
+    let mut unexpected = emitted.errors;
+    unexpected.extend(emitted.warnings.into_iter().map(|warning| Reported {
+        severity: Severity::Warning,
+        ..warning
+    }));
+
+    let mut missing = vec![];
+    for annotation in annotations {
+        match unexpected
+            .iter()
+            .position(|diagnostic| annotation.matches(diagnostic))
+        {
+            Some(index) => {
+                unexpected.remove(index);
+            }
+            None => missing.push(annotation),
+        }
+    }
+    if missing.is_empty() && unexpected.is_empty() {
+        return Outcome::Pass;
+    }
+    let file_name = case
+        .source
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    Outcome::Unmatched {
+        missing,
+        unexpected: snapshot(&file_name, &unexpected),
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "ok"),
+            Outcome::Fail {
+                diff,
+                expected_status,
+                status,
+            } => {
+                write!(f, "FAILED")?;
+                if expected_status != status {
+                    write!(
+                        f,
+                        "\n  expected exit status {}, got {}",
+                        expected_status, status
+                    )?;
+                }
+                if !diff.is_empty() {
+                    write!(f, "\n  stdout differs (-expected +actual):")?;
+                    for line in diff.lines() {
+                        write!(f, "\n  {}", line)?;
+                    }
+                }
+                Ok(())
+            }
+            Outcome::Unmatched {
+                missing,
+                unexpected,
+            } => {
+                write!(f, "FAILED")?;
+                if !missing.is_empty() {
+                    write!(f, "\n  annotated, but not reported:")?;
+                    for annotation in missing {
+                        write!(f, "\n  {}", annotation)?;
+                    }
+                }
+                if !unexpected.is_empty() {
+                    write!(f, "\n  reported, but not annotated:")?;
+                    for line in unexpected.lines() {
+                        write!(f, "\n  {}", line)?;
+                    }
+                }
+                Ok(())
+            }
+            Outcome::Error(message) => write!(f, "ERROR: {}", message),
+        }
+    }
+}
+
+// Line diff of two texts from their longest common subsequence, marking
+// removed lines with `-`, added ones with `+` and common ones with a space.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // lcs[i][j] is the LCS length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        } else {
+            out.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        }
+    }
+    // Texts that differ only in the final newline have identical lines
+    if out.lines().all(|line| line.starts_with(' ')) {
+        out.push_str("(differs in the trailing newline)\n");
+    }
+    out
+}
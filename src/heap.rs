@@ -0,0 +1,61 @@
+use std::rc::Rc;
+
+use crate::value::{ObjectRef, Value};
+
+// Where class instances live. An instance's fields are a `Value::Record`;
+// variables hold an `ObjectRef` to it, so copying one shares the instance.
+// Slots are never reused, which makes any use of a freed instance an error
+// instead of a read of whatever got allocated in its place.
+#[derive(Debug, Default)]
+pub struct Heap {
+    objects: Vec<Option<Value>>,
+    // The class of each slot's instance, kept after it is freed
+    class_names: Vec<Rc<str>>,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allocate(&mut self, class_name: &str, fields: Value) -> ObjectRef {
+        let class_name: Rc<str> = class_name.into();
+        self.objects.push(Some(fields));
+        self.class_names.push(Rc::clone(&class_name));
+        ObjectRef {
+            class_name,
+            id: self.objects.len() - 1,
+        }
+    }
+
+    // `None` once the instance has been freed.
+    pub fn get(&self, object: &ObjectRef) -> Option<&Value> {
+        self.objects.get(object.id)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, object: &ObjectRef) -> Option<&mut Value> {
+        self.objects.get_mut(object.id)?.as_mut()
+    }
+
+    // The instances not freed yet, oldest first, with their fields.
+    pub fn live_objects(&self) -> impl Iterator<Item = (ObjectRef, &Value)> {
+        self.objects
+            .iter()
+            .zip(&self.class_names)
+            .enumerate()
+            .filter_map(|(id, (fields, class_name))| {
+                let object = ObjectRef {
+                    class_name: Rc::clone(class_name),
+                    id,
+                };
+                Some((object, fields.as_ref()?))
+            })
+    }
+
+    // Whether the instance was still allocated.
+    pub fn free(&mut self, object: &ObjectRef) -> bool {
+        self.objects
+            .get_mut(object.id)
+            .is_some_and(|slot| slot.take().is_some())
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::BTreeMap;
+
+use crate::ast::NodeId;
+use crate::symbols::BuiltinProcedure;
+
+// What the semantic analyzer resolved about a program, kept beside the AST
+// rather than written into it. The AST stays as parsed, so one tree can be
+// analyzed several times, e.g. with different builtins. Entries are keyed by
+// the node they describe and ordered by it, like `CrossReferences`. There
+// are no slot indices yet, since frames hold their variables by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hir {
+    // What each `ProcedureCall` node calls
+    pub callees: BTreeMap<NodeId, Callee>,
+    // The declared type of each `Var` node naming a variable or parameter
+    pub types: BTreeMap<NodeId, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Callee {
+    // A declared procedure, by its `ProcedureDecl` node
+    Procedure(NodeId),
+    // The procedure held by a variable of a procedure type
+    Variable,
+    Builtin(BuiltinProcedure),
+    // A procedure of a `BuiltinProvider`
+    External,
+}
+
+impl Hir {
+    pub fn callee(&self, call: NodeId) -> Option<Callee> {
+        self.callees.get(&call).copied()
+    }
+
+    pub fn type_of(&self, var: NodeId) -> Option<&str> {
+        self.types.get(&var).map(String::as_str)
+    }
+}
@@ -0,0 +1,255 @@
+use std::ops::Range;
+
+use anyhow::{bail, Result};
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::{LocatedToken, Location, Span};
+
+// Replace the bytes in `range` with `replacement`.
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+// How much of the program an edit made the parser look at again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reparse {
+    // Only this procedure declaration was re-parsed, into `node`. Every
+    // other node kept its id.
+    Declaration { replaced: NodeId, node: NodeId },
+    Full,
+}
+
+// Source text and its AST, kept in sync across edits for editor
+// integration. An edit inside a procedure declaration re-lexes and
+// re-parses just the innermost such declaration; anything else re-parses
+// the whole text.
+pub struct Document {
+    source: String,
+    ast: Ast,
+}
+
+impl Document {
+    pub fn parse(source: impl Into<String>) -> Result<Self> {
+        let source = source.into();
+        let ast = Parser::new(Lexer::with_trivia(&source))?.parse()?;
+        Ok(Document { source, ast })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // Replaced declarations stay in the arena but are no longer reachable
+    // from the root, so walk the tree rather than iterating over `ids()`.
+    pub fn ast(&self) -> &Ast {
+        &self.ast
+    }
+
+    // Applies the edit and brings the AST up to date. If the edited text
+    // does not parse, the error is returned and the document is unchanged.
+    pub fn edit(&mut self, edit: &TextEdit) -> Result<Reparse> {
+        let Range { start, end } = edit.range;
+        if start > end
+            || end > self.source.len()
+            || !self.source.is_char_boundary(start)
+            || !self.source.is_char_boundary(end)
+        {
+            bail!("edit range {}..{} is not valid for the source", start, end);
+        }
+        let mut source = self.source.clone();
+        source.replace_range(start..end, &edit.replacement);
+
+        let reparse = match self.reparse_declaration(&source, edit) {
+            Some(reparse) => reparse,
+            None => {
+                self.ast = Parser::new(Lexer::with_trivia(&source))?.parse()?;
+                Reparse::Full
+            }
+        };
+        self.source = source;
+        Ok(reparse)
+    }
+
+    // Re-parses the innermost procedure declaration around the edit. Gives
+    // up, leaving the AST as it was, unless the new text parses as a
+    // declaration that ends right where the old one did.
+    fn reparse_declaration(&mut self, source: &str, edit: &TextEdit) -> Option<Reparse> {
+        let decl = enclosing_declaration(&self.ast, &edit.range)?;
+        let range = self.ast.token_range(decl)?;
+        let first = self.ast.tokens()[range.start].clone();
+        let old_last = self.ast.tokens()[range.end - 1].clone();
+        let old_next = self.ast.tokens().get(range.end)?.clone();
+        let delta = edit.replacement.len() as isize - edit.range.len() as isize;
+
+        let mut lexer = Lexer::with_trivia(source).starting_at(first.span.start, first.location());
+        // Lex the first token before handing the AST over, so the parser
+        // cannot fail to start
+        lexer.peek_token().ok()?;
+
+        let mut ast = std::mem::take(&mut self.ast);
+        let node_count = ast.len();
+        let mut tail = ast.split_off_tokens(range.start);
+        let mut parser = Parser::continuing(lexer, ast).ok()?;
+        let parsed = parser.procedure_declaration();
+        let (mut ast, next) = parser.finish();
+
+        let node = match parsed {
+            Ok(node)
+                if next.token == old_next.token
+                    && next.span.start == shift_offset(old_next.span.start, delta) =>
+            {
+                node
+            }
+            _ => {
+                ast.truncate(node_count);
+                ast.split_off_tokens(range.start);
+                for token in tail {
+                    ast.push_token(token);
+                }
+                self.ast = ast;
+                return None;
+            }
+        };
+
+        let new_end = ast.tokens().len();
+        let new_last = ast.tokens()[new_end - 1].clone();
+        let shift = Shift {
+            bytes: delta,
+            from: end_location(&old_last, &self.source),
+            to: end_location(&new_last, source),
+        };
+        // Lexing started at the declaration's first token, so its leading
+        // trivia, which the edit did not touch, has to be carried over.
+        let mut tokens = ast.split_off_tokens(range.start);
+        tokens[0].leading = first.leading;
+        for token in tokens
+            .into_iter()
+            .chain(tail.drain(range.len()..).map(|token| shift.token(token)))
+        {
+            ast.push_token(token);
+        }
+
+        let count_delta = new_end as isize - range.end as isize;
+        for id in ast.ids().take(node_count) {
+            if let Some(old) = ast.token_range(id) {
+                let moved = |index: usize| {
+                    if index >= range.end {
+                        shift_offset(index, count_delta)
+                    } else {
+                        index
+                    }
+                };
+                ast.set_token_range(id, moved(old.start)..moved(old.end));
+            }
+            if let Some(old) = ast.span(id) {
+                let moved = |offset: usize| {
+                    if offset >= old_last.span.end {
+                        shift_offset(offset, delta)
+                    } else {
+                        offset
+                    }
+                };
+                ast.set_span(id, Span::new(moved(old.start), moved(old.end)));
+            }
+            let location = ast.location(id);
+            if location >= shift.from {
+                ast.set_location(id, shift.location(location));
+            }
+        }
+
+        let mut replaced = vec![decl];
+        while let Some(id) = replaced.pop() {
+            ast.remove_token_range(id);
+            ast.remove_span(id);
+            replaced.extend(ast.children(id));
+        }
+        for id in ast.ids().take(node_count) {
+            if let ASTNode::Block { declarations, .. } = &mut ast[id] {
+                for declaration in declarations.iter_mut() {
+                    if *declaration == decl {
+                        *declaration = node;
+                    }
+                }
+            }
+        }
+
+        self.ast = ast;
+        Some(Reparse::Declaration {
+            replaced: decl,
+            node,
+        })
+    }
+}
+
+// The innermost procedure declaration whose text contains `range`.
+fn enclosing_declaration(ast: &Ast, range: &Range<usize>) -> Option<NodeId> {
+    let mut found = None;
+    let mut pending = vec![ast.root()];
+    while let Some(id) = pending.pop() {
+        if let ASTNode::ProcedureDecl { .. } = ast[id] {
+            let tokens = ast.token_range(id)?;
+            let start = ast.tokens()[tokens.start].span.start;
+            let end = ast.tokens()[tokens.end - 1].span.end;
+            if range.start < start || range.end > end {
+                continue;
+            }
+            found = Some(id);
+        }
+        pending.extend(ast.children(id));
+    }
+    found
+}
+
+fn end_location(token: &LocatedToken, source: &str) -> Location {
+    let text = &source[token.span.start..token.span.end];
+    Location {
+        line: token.line,
+        column: token.column + text.chars().count(),
+    }
+}
+
+fn shift_offset(offset: usize, delta: isize) -> usize {
+    (offset as isize + delta) as usize
+}
+
+// Moves positions after an edited region to account for the edit: byte
+// offsets by the change in length, and line/column so that `from`, the old
+// end of the region, lands on `to`.
+struct Shift {
+    bytes: isize,
+    from: Location,
+    to: Location,
+}
+
+impl Shift {
+    fn location(&self, location: Location) -> Location {
+        if location.line == self.from.line {
+            Location {
+                line: self.to.line,
+                column: location.column - self.from.column + self.to.column,
+            }
+        } else {
+            Location {
+                line: location.line + self.to.line - self.from.line,
+                column: location.column,
+            }
+        }
+    }
+
+    fn token(&self, mut token: LocatedToken) -> LocatedToken {
+        let location = self.location(token.location());
+        token.line = location.line;
+        token.column = location.column;
+        token.span.start = shift_offset(token.span.start, self.bytes);
+        token.span.end = shift_offset(token.span.end, self.bytes);
+        for trivia in token.leading.iter_mut().chain(token.trailing.iter_mut()) {
+            trivia.span.start = shift_offset(trivia.span.start, self.bytes);
+            trivia.span.end = shift_offset(trivia.span.end, self.bytes);
+        }
+        token
+    }
+}
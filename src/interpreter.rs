@@ -1,12 +1,36 @@
+use std::any::Any;
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::fmt;
+use std::io::{self, Write};
 use std::iter::zip;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::NonNull;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use crate::ast::{ASTNode, BuiltinNumTypes};
+use crate::ast::{ASTNode, Ast, MethodKind, NodeId, ParamMode};
+use crate::breakpoints::{BreakEvent, BreakHandler, BreakReason, Breakpoints};
+use crate::budget::{Allowance, Budget, BudgetedRun, Pauser};
+use crate::builtins::BuiltinRegistry;
 use crate::call_stack::{ARType, ActivationRecord, CallStack};
-use crate::symbols::{Symbol, SymbolKind};
+use crate::check::Diagnostic;
+use crate::coverage::Coverage;
+use crate::dialect::Dialect;
+use crate::directives::{RealPolicy, Switches};
+use crate::dump::StateDump;
+use crate::explain::Explanation;
+use crate::heap::Heap;
+use crate::hir::{Callee, Hir};
+use crate::lexer::Lexer;
+use crate::parser::{binary_precedence, Parser, Precedence};
+use crate::profiler::{ProfileReport, Profiler};
+use crate::replay::Recording;
+use crate::stats::{RunReport, RuntimeStats, RuntimeWarning};
 use crate::token::Token;
+use crate::trace::Tracer;
+use crate::value::{ObjectRef, ProcedureValue, Value};
+use crate::visitor::{self, ensure_stack};
 
 pub type InterpretResult<T> = std::result::Result<T, InterpretError>;
 
@@ -33,6 +57,98 @@ pub enum InterpretError {
         expected: usize,
         got: usize,
     },
+    // A call with fewer arguments than a variadic builtin needs
+    TooFewArguments {
+        proc_name: String,
+        at_least: usize,
+        got: usize,
+    },
+    // An argument of a builtin whose type its signature doesn't take
+    ArgumentType {
+        proc_name: String,
+        position: usize,
+        expected: String,
+        found: String,
+    },
+    UndefinedClass {
+        name: String,
+    },
+    UnknownMember {
+        class_name: String,
+        member: String,
+    },
+    NotAnObject {
+        expression: String,
+    },
+    // A call to a procedure or method where a value is needed; only
+    // constructor calls give one
+    NoValue {
+        call: String,
+    },
+    ConstParamAssignment {
+        name: String,
+    },
+    // Initializers of a block that use each other, in a loop from the first
+    // name back to it
+    InitializerCycle {
+        names: Vec<String>,
+    },
+    // An initializer that uses a variable of its block before it has a
+    // value
+    InitializerUsesUnset {
+        name: String,
+        uses: String,
+    },
+    // Two labels of one CASE that share a value; `previous` is the earlier
+    DuplicateCaseLabel {
+        label: String,
+        previous: String,
+    },
+    // A case range whose low bound is above its high bound
+    EmptyCaseRange {
+        range: String,
+    },
+    // A VAR or OUT argument that is not a variable or field
+    ArgumentMustBeVariable {
+        proc_name: String,
+        param: String,
+        mode: ParamMode,
+    },
+    OutParamNotAssigned {
+        proc_name: String,
+        param: String,
+    },
+    // Two arguments of a generic procedure that give one type parameter
+    // different types
+    TypeParamMismatch {
+        proc_name: String,
+        type_param: String,
+        first: String,
+        second: String,
+    },
+    CannotInferTypeParam {
+        proc_name: String,
+        type_param: String,
+    },
+    InvalidPropertyAccessor {
+        property: String,
+        accessor: String,
+        expected: String,
+    },
+    ReadOnlyProperty {
+        class_name: String,
+        property: String,
+    },
+    WriteOnlyProperty {
+        class_name: String,
+        property: String,
+    },
+    // Using a field or method of an object variable that holds NIL
+    NilObject,
+    // Using an object after its destructor ran or `Free` freed it
+    FreedObject {
+        class_name: String,
+    },
     UninitializedVariable {
         name: String,
     },
@@ -46,9 +162,58 @@ pub enum InterpretError {
     InvalidBinaryOperator {
         token: Token,
     },
+    InvalidOperandType {
+        token: Token,
+        type_name: &'static str,
+    },
+    // `statement` is the IF, WHILE or REPEAT, e.g. "an IF"
+    ConditionNotBoolean {
+        statement: &'static str,
+        type_name: &'static str,
+    },
+    CaseSelectorNotInteger {
+        type_name: &'static str,
+    },
     MissingAssignmentValue {
         name: String,
     },
+    DivisionByZero,
+    // Only raised with overflow checks on; `expression` shows the operands
+    IntegerOverflow {
+        expression: String,
+    },
+    // Only raised with `RealPolicy::Error`
+    NonFiniteReal {
+        expression: String,
+        value: f32,
+    },
+    NotInDialect {
+        feature: String,
+        dialect: Dialect,
+    },
+    Aborted,
+    // A call would have gone past the call stack's depth limit
+    CallDepthExceeded {
+        limit: usize,
+    },
+    // A bug in the interpreter rather than in the program, such as a panic
+    // caught by `interpret`
+    Internal {
+        message: String,
+    },
+    // A builtin failed or broke its signature
+    Builtin {
+        name: String,
+        message: String,
+    },
+    // Writing the program's output failed, e.g. because stdout was closed
+    Output {
+        message: String,
+    },
+    // Text given to `eval_expression` that isn't an expression with a value
+    InvalidExpression {
+        message: String,
+    },
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -103,65 +268,993 @@ impl fmt::Display for InterpretError {
             InterpretError::InvalidBinaryOperator { token } => {
                 write!(f, "Invalid binary operator '{token}'")
             }
+            InterpretError::InvalidOperandType { token, type_name } => {
+                write!(
+                    f,
+                    "Operator '{token}' cannot be applied to a {type_name} value"
+                )
+            }
+            InterpretError::ConditionNotBoolean {
+                statement,
+                type_name,
+            } => {
+                write!(
+                    f,
+                    "The condition of {statement} must be BOOLEAN, found {type_name}"
+                )
+            }
+            InterpretError::CaseSelectorNotInteger { type_name } => {
+                write!(
+                    f,
+                    "The selector of a CASE must be INTEGER, found {type_name}"
+                )
+            }
             InterpretError::MissingAssignmentValue { name } => {
                 write!(f, "Assignment to '{name}' is missing a value")
             }
+            InterpretError::DivisionByZero => write!(f, "Division by zero"),
+            InterpretError::IntegerOverflow { expression } => {
+                write!(f, "Integer overflow in {expression}")
+            }
+            InterpretError::NonFiniteReal { expression, value } if value.is_nan() => {
+                write!(f, "Invalid real operation {expression}")
+            }
+            InterpretError::NonFiniteReal { expression, .. } => {
+                write!(f, "Real overflow in {expression}")
+            }
+            InterpretError::Aborted => write!(f, "Execution was aborted"),
+            InterpretError::CallDepthExceeded { limit } => {
+                write!(f, "Call depth exceeded: the limit is {limit}")
+            }
+            InterpretError::Internal { message } => write!(f, "Internal error: {message}"),
+            InterpretError::Builtin { name, message } => write!(f, "{name}: {message}"),
+            InterpretError::Output { message } => {
+                write!(f, "Cannot write the program's output: {message}")
+            }
+            InterpretError::InvalidExpression { message } => {
+                write!(f, "Invalid expression: {message}")
+            }
+            InterpretError::NotInDialect { feature, dialect } => {
+                write!(f, "{feature} is not allowed in {dialect}")
+            }
             InterpretError::SymbolAlreadyDefined { name } => {
                 write!(f, "Symbol '{name}' is already defined")
             }
             InterpretError::UndefinedFunction { name } => {
                 write!(f, "Trying to call an undefined function '{name}'")
             }
-            InterpretError::ProcCallMissingArgs {
-                proc_name,
-                expected,
-                got,
-            } => {
-                write!(
-                    f,
-                    "Function {} expects {} arguments but got {}",
-                    proc_name, expected, got
-                )
+            InterpretError::UndefinedClass { name } => write!(f, "Undefined class '{name}'"),
+            InterpretError::UnknownMember { class_name, member } => {
+                write!(f, "Class '{class_name}' has no member '{member}'")
+            }
+            InterpretError::NotAnObject { expression } => {
+                write!(f, "'{expression}' is not an object")
+            }
+            InterpretError::NoValue { call } => {
+                write!(f, "'{call}' calls a procedure, which has no value")
+            }
+            InterpretError::ConstParamAssignment { name } => {
+                write!(f, "Cannot assign to CONST parameter '{name}'")
+            }
+            InterpretError::InitializerCycle { names } => write!(
+                f,
+                "Initializers form a cycle: {}",
+                names
+                    .iter()
+                    .map(|name| format!("'{name}'"))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+            InterpretError::InitializerUsesUnset { name, uses } => write!(
+                f,
+                "The initializer of '{name}' uses '{uses}', which has no value yet"
+            ),
+            InterpretError::DuplicateCaseLabel { label, previous } if label == previous => {
+                write!(f, "Duplicate case label '{label}'")
+            }
+            InterpretError::DuplicateCaseLabel { label, previous } => {
+                write!(f, "The case label '{label}' overlaps '{previous}'")
+            }
+            InterpretError::EmptyCaseRange { range } => {
+                write!(f, "The case range '{range}' is empty")
+            }
+            InterpretError::ArgumentMustBeVariable {
+                proc_name,
+                param,
+                mode,
+            } => write!(
+                f,
+                "The argument for {mode}parameter '{param}' of '{proc_name}' must be a variable"
+            ),
+            InterpretError::OutParamNotAssigned { proc_name, param } => write!(
+                f,
+                "OUT parameter '{param}' of '{proc_name}' is never assigned"
+            ),
+            InterpretError::TypeParamMismatch {
+                proc_name,
+                type_param,
+                first,
+                second,
+            } => write!(
+                f,
+                "Type parameter '{type_param}' of '{proc_name}' cannot be both {first} and {second}"
+            ),
+            InterpretError::CannotInferTypeParam {
+                proc_name,
+                type_param,
+            } => write!(
+                f,
+                "Cannot infer type parameter '{type_param}' of '{proc_name}' from the arguments"
+            ),
+            InterpretError::InvalidPropertyAccessor {
+                property,
+                accessor,
+                expected,
+            } => write!(
+                f,
+                "Accessor '{accessor}' of property '{property}' must be {expected}"
+            ),
+            InterpretError::ReadOnlyProperty {
+                class_name,
+                property,
+            } => write!(f, "Property '{class_name}.{property}' is read-only"),
+            InterpretError::WriteOnlyProperty {
+                class_name,
+                property,
+            } => write!(f, "Property '{class_name}.{property}' is write-only"),
+            InterpretError::NilObject => write!(f, "Access violation: the object is NIL"),
+            InterpretError::FreedObject { class_name } => write!(
+                f,
+                "Access violation: the {class_name} instance has already been freed"
+            ),
+            InterpretError::ProcCallMissingArgs {
+                proc_name,
+                expected,
+                got,
+            } => {
+                write!(
+                    f,
+                    "Function {} expects {} arguments but got {}",
+                    proc_name, expected, got
+                )
+            }
+            InterpretError::TooFewArguments {
+                proc_name,
+                at_least,
+                got,
+            } => write!(
+                f,
+                "Function {proc_name} expects at least {at_least} arguments but got {got}"
+            ),
+            InterpretError::ArgumentType {
+                proc_name,
+                position,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Argument {position} of '{proc_name}' must be {expected}, found {found}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+impl From<io::Error> for InterpretError {
+    fn from(e: io::Error) -> Self {
+        InterpretError::Output {
+            message: e.to_string(),
+        }
+    }
+}
+
+fn as_real(token: &Token, value: &Value) -> InterpretResult<f32> {
+    value
+        .as_real()
+        .ok_or_else(|| InterpretError::InvalidOperandType {
+            token: token.clone(),
+            type_name: value.type_name(),
+        })
+}
+
+// INTEGER operations wrap around on overflow unless overflow checks are on.
+fn integer_result(
+    checked: Option<i32>,
+    wrapped: i32,
+    switches: &Switches,
+    expression: impl FnOnce() -> String,
+) -> InterpretResult<Value> {
+    match checked {
+        Some(v) => Ok(Value::Int(v)),
+        None if switches.overflow_checks => Err(InterpretError::IntegerOverflow {
+            expression: expression(),
+        }),
+        None => Ok(Value::Int(wrapped)),
+    }
+}
+
+fn real_result(
+    value: f32,
+    switches: &Switches,
+    expression: impl FnOnce() -> String,
+) -> InterpretResult<Value> {
+    if !value.is_finite() && switches.real_policy == RealPolicy::Error {
+        return Err(InterpretError::NonFiniteReal {
+            expression: expression(),
+            value,
+        });
+    }
+    Ok(Value::Real(value))
+}
+
+pub fn apply_unary_op(token: &Token, value: Value, switches: &Switches) -> InterpretResult<Value> {
+    match (token, value) {
+        (Token::Plus, value @ (Value::Int(_) | Value::Real(_))) => Ok(value),
+        (Token::Minus, Value::Int(v)) => {
+            integer_result(v.checked_neg(), v.wrapping_neg(), switches, || {
+                format!("-({v})")
+            })
+        }
+        (Token::Minus, Value::Real(v)) => Ok(Value::Real(-v)),
+        (Token::Not, Value::Bool(v)) => Ok(Value::Bool(!v)),
+        (Token::Plus | Token::Minus | Token::Not, value) => {
+            Err(InterpretError::InvalidOperandType {
+                token: token.clone(),
+                type_name: value.type_name(),
+            })
+        }
+        _ => Err(InterpretError::InvalidUnaryOperator {
+            token: token.clone(),
+        }),
+    }
+}
+
+// `+`, `-`, `*` and `div` on two INTEGERs give an INTEGER; with a REAL
+// operand they give a REAL, except `div`, which truncates its operands. `/`
+// always gives a REAL, and a comparison a BOOLEAN. `and` and `or` take two
+// BOOLEANs, both of which have already been evaluated.
+pub fn apply_bin_op(
+    op: &Token,
+    left: Value,
+    right: Value,
+    switches: &Switches,
+) -> InterpretResult<Value> {
+    if let Some(Precedence::Relational) = binary_precedence(op) {
+        return compare(op, &left, &right);
+    }
+    if let Token::And | Token::Or = op {
+        return logical(op, &left, &right);
+    }
+    if !matches!(
+        op,
+        Token::Plus | Token::Minus | Token::Asterisk | Token::FloatDiv | Token::IntegerDiv
+    ) {
+        return Err(InterpretError::InvalidBinaryOperator { token: op.clone() });
+    }
+
+    if let (Value::Int(l), Value::Int(r)) = (&left, &right) {
+        let (l, r) = (*l, *r);
+        let result = match op {
+            Token::Plus => Some((l.checked_add(r), l.wrapping_add(r))),
+            Token::Minus => Some((l.checked_sub(r), l.wrapping_sub(r))),
+            Token::Asterisk => Some((l.checked_mul(r), l.wrapping_mul(r))),
+            Token::IntegerDiv if r != 0 => Some((l.checked_div(r), l.wrapping_div(r))),
+            _ => None,
+        };
+        if let Some((checked, wrapped)) = result {
+            return integer_result(checked, wrapped, switches, || format!("{l} {op} {r}"));
+        }
+    }
+
+    let left_value = as_real(op, &left)?;
+    let right_value = as_real(op, &right)?;
+
+    let value = match op {
+        Token::Plus => left_value + right_value,
+        Token::Minus => left_value - right_value,
+        Token::Asterisk => left_value * right_value,
+        Token::FloatDiv => {
+            if right_value == 0.0 && switches.real_policy == RealPolicy::Error {
+                return Err(InterpretError::DivisionByZero);
+            }
+            left_value / right_value
+        }
+        _ => {
+            let divisor = right_value as i32;
+            if divisor == 0 {
+                return Err(InterpretError::DivisionByZero);
+            }
+            return Ok(Value::Int((left_value as i32).wrapping_div(divisor)));
+        }
+    };
+    real_result(value, switches, || format!("{left} {op} {right}"))
+}
+
+// The relational operators compare two numbers, an INTEGER with a REAL as
+// two REALs, or two BOOLEANs, with FALSE before TRUE. A NaN is unordered,
+// so only `<>` holds for it.
+fn compare(op: &Token, left: &Value, right: &Value) -> InterpretResult<Value> {
+    let ordering = match (left, right) {
+        (Value::Int(l), Value::Int(r)) => l.partial_cmp(r),
+        (Value::Bool(l), Value::Bool(r)) => l.partial_cmp(r),
+        _ => as_real(op, left)?.partial_cmp(&as_real(op, right)?),
+    };
+    let holds = match op {
+        Token::Equal => ordering == Some(Ordering::Equal),
+        Token::NotEqual => ordering != Some(Ordering::Equal),
+        Token::LessThan => ordering == Some(Ordering::Less),
+        Token::LessEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Token::GreaterThan => ordering == Some(Ordering::Greater),
+        Token::GreaterEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        _ => return Err(InterpretError::InvalidBinaryOperator { token: op.clone() }),
+    };
+    Ok(Value::Bool(holds))
+}
+
+fn logical(op: &Token, left: &Value, right: &Value) -> InterpretResult<Value> {
+    let (Value::Bool(l), Value::Bool(r)) = (left, right) else {
+        let operand = if let Value::Bool(_) = left {
+            right
+        } else {
+            left
+        };
+        return Err(InterpretError::InvalidOperandType {
+            token: op.clone(),
+            type_name: operand.type_name(),
+        });
+    };
+    match op {
+        Token::And => Ok(Value::Bool(*l && *r)),
+        _ => Ok(Value::Bool(*l || *r)),
+    }
+}
+
+// The operands `apply_bin_op` converts with a loss: a REAL operand of
+// `div` that has a fraction or doesn't fit in an INTEGER, and an INTEGER
+// too big for a REAL to hold exactly when the other operand is a REAL or
+// the operator is `/`. Each comes with a message naming the values.
+pub fn lossy_conversions(op: &Token, left: &Value, right: &Value) -> Vec<String> {
+    let expression = || format!("{left} {op} {right}");
+    let mut lossy = vec![];
+    if *op == Token::IntegerDiv {
+        for value in [left, right] {
+            if let Value::Real(real) = value {
+                let truncated = *real as i32;
+                if f64::from(truncated) != f64::from(*real) {
+                    lossy.push(format!(
+                        "REAL {value} truncated to INTEGER {truncated} in {}",
+                        expression()
+                    ));
+                }
+            }
+        }
+        return lossy;
+    }
+    let real =
+        *op == Token::FloatDiv || matches!(left, Value::Real(_)) || matches!(right, Value::Real(_));
+    if !real {
+        return lossy;
+    }
+    for value in [left, right] {
+        if let Value::Int(integer) = value {
+            let rounded = *integer as f32;
+            if f64::from(rounded) != f64::from(*integer) {
+                let rounded = Value::Real(rounded);
+                lossy.push(format!(
+                    "INTEGER {integer} rounded to REAL {rounded} in {}",
+                    expression()
+                ));
+            }
+        }
+    }
+    lossy
+}
+
+// What to do once a statement hook returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    // Stop the program; `interpret` fails with `InterpretError::Aborted`
+    Abort,
+}
+
+// Gets to look at the program before each statement runs, e.g. to pause it
+// in a debugger. Execution resumes when the call returns.
+pub trait StatementHook {
+    fn before_statement(&mut self, ast: &Ast, node: NodeId, call_stack: &CallStack) -> Resume;
+}
+
+// Warnings past this many are dropped, so a long run can't fill memory
+// with them
+const MAX_WARNINGS: usize = 100;
+
+pub struct Interpreter {
+    call_stack: CallStack,
+    steps: u64,
+    budget: Option<Budget>,
+    // Set while running as a `BudgetedRun`, whose coroutine this pauses
+    pauser: Option<NonNull<Pauser>>,
+    // How long the run spent paused, which doesn't count towards its time
+    paused: Duration,
+    stats: RuntimeStats,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    statement_hook: Option<Box<dyn StatementHook>>,
+    breakpoints: Breakpoints,
+    break_handler: Option<BreakHandler>,
+    recording: Option<Recording>,
+    tracer: Option<Tracer>,
+    dump_on_error: bool,
+    error_dump: Option<StateDump>,
+    // Where `write` and `writeln` print
+    output: CountedOutput,
+    wall_time: Duration,
+    warnings: Vec<RuntimeWarning>,
+    builtins: BuiltinRegistry,
+    hir: Hir,
+    switches: Switches,
+    // Warn about operands converted with a loss
+    conversion_warnings: bool,
+    declarations: Declarations,
+    heap: Heap,
+}
+
+// The program's output, counting the bytes written to it and, when
+// explaining a run, recording which statement wrote them.
+struct CountedOutput {
+    inner: Box<dyn Write>,
+    bytes: u64,
+    explanation: Option<Explanation>,
+    // The call writing to the output
+    statement: Option<NodeId>,
+}
+
+impl CountedOutput {
+    fn new(inner: Box<dyn Write>) -> Self {
+        CountedOutput {
+            inner,
+            bytes: 0,
+            explanation: None,
+            statement: None,
+        }
+    }
+}
+
+impl Write for CountedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes += written as u64;
+        if let (Some(explanation), Some(statement)) = (&mut self.explanation, self.statement) {
+            explanation.record(statement, &String::from_utf8_lossy(&buf[..written]));
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// What the blocks being run declare, innermost last. The analyzer has
+// resolved every name already; these are for the names a running program
+// needs that the call stack doesn't hold, like a procedure used as a value
+// or the methods of a class.
+#[derive(Default)]
+struct Declarations {
+    procedures: Vec<Rc<ProcedureValue>>,
+    // Name and definition of each type
+    types: Vec<(String, NodeId)>,
+    methods: Vec<Method>,
+}
+
+struct Method {
+    class_name: String,
+    kind: MethodKind,
+    procedure: Rc<ProcedureValue>,
+}
+
+// The method every class has without declaring it
+const FREE: &str = "free";
+// The instance a method was called on
+const SELF: &str = "self";
+
+impl Declarations {
+    fn len(&self) -> (usize, usize, usize) {
+        (self.procedures.len(), self.types.len(), self.methods.len())
+    }
+
+    fn truncate(&mut self, (procedures, types, methods): (usize, usize, usize)) {
+        self.procedures.truncate(procedures);
+        self.types.truncate(types);
+        self.methods.truncate(methods);
+    }
+
+    fn procedure(&self, name: &str) -> Option<&Rc<ProcedureValue>> {
+        self.procedures
+            .iter()
+            .rev()
+            .find(|procedure| procedure.name == name)
+    }
+
+    // The procedure `declaration` declares, in the innermost block running
+    // it. The analyzer resolved the call, so no name lookup is needed.
+    fn declared_at(&self, ast: &Ast, declaration: NodeId) -> Option<&Rc<ProcedureValue>> {
+        let ASTNode::ProcedureDecl { block_node, .. } = &ast[declaration] else {
+            return None;
+        };
+        self.procedures
+            .iter()
+            .rev()
+            .find(|procedure| procedure.block == *block_node)
+    }
+
+    fn method(&self, class_name: &str, name: &str) -> Option<&Method> {
+        self.methods
+            .iter()
+            .rev()
+            .find(|method| method.class_name == class_name && method.procedure.name == name)
+    }
+
+    // The definition a type name stands for, following aliases.
+    fn type_definition<'a>(&self, ast: &'a Ast, type_name: &str) -> Option<&'a ASTNode> {
+        let mut type_name = type_name;
+        for _ in 0..=self.types.len() {
+            let (_, definition) = self
+                .types
+                .iter()
+                .rev()
+                .find(|(name, _)| name == type_name)?;
+            match &ast[*definition] {
+                ASTNode::Type { value } => type_name = value,
+                definition => return Some(definition),
+            }
+        }
+        None
+    }
+
+    fn class<'a>(&self, ast: &'a Ast, type_name: &str) -> Option<&'a [NodeId]> {
+        match self.type_definition(ast, type_name)? {
+            ASTNode::ClassType { members } => Some(members),
+            _ => None,
+        }
+    }
+
+    // The reader and writer of a property of the class.
+    fn property<'a>(
+        &self,
+        ast: &'a Ast,
+        class_name: &str,
+        property: &str,
+    ) -> Option<(Option<&'a str>, Option<&'a str>)> {
+        self.class(ast, class_name)?
+            .iter()
+            .find_map(|member| match &ast[*member] {
+                ASTNode::Property {
+                    name,
+                    reader,
+                    writer,
+                    ..
+                } if name == property => Some((reader.as_deref(), writer.as_deref())),
+                _ => None,
+            })
+    }
+
+    // What a new instance's field of this type starts out as. FPC zeroes
+    // the memory of a new instance.
+    fn initial_value(&self, ast: &Ast, type_name: &str) -> Value {
+        let mut type_name = type_name;
+        for _ in 0..=self.types.len() {
+            match type_name {
+                "INTEGER" => return Value::Int(0),
+                "REAL" => return Value::Real(0.0),
+                _ => {}
+            }
+            let Some((_, definition)) = self.types.iter().rev().find(|(name, _)| name == type_name)
+            else {
+                break;
+            };
+            match &ast[*definition] {
+                ASTNode::Type { value } => type_name = value,
+                _ => break,
+            }
+        }
+        Value::Nil
+    }
+}
+
+// What a caught panic was raised with, when it's a message.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "the interpreter panicked".to_string()
+    }
+}
+
+// The names of a procedure's or method's parameters.
+fn param_names(ast: &Ast, params: &[NodeId]) -> Vec<String> {
+    params
+        .iter()
+        .filter_map(|param| match &ast[*ast.children(*param).first()?] {
+            ASTNode::Var { name } => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn param_modes(ast: &Ast, params: &[NodeId]) -> Vec<ParamMode> {
+    params
+        .iter()
+        .map(|param| match &ast[*param] {
+            ASTNode::Param { mode, .. } => *mode,
+            _ => ParamMode::Value,
+        })
+        .collect()
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            call_stack: CallStack::new(),
+            steps: 0,
+            budget: None,
+            pauser: None,
+            paused: Duration::ZERO,
+            stats: RuntimeStats::default(),
+            profiler: None,
+            coverage: None,
+            statement_hook: None,
+            breakpoints: Breakpoints::new(),
+            break_handler: None,
+            recording: None,
+            tracer: None,
+            dump_on_error: false,
+            error_dump: None,
+            output: CountedOutput::new(Box::new(io::stdout())),
+            wall_time: Duration::ZERO,
+            warnings: vec![],
+            builtins: BuiltinRegistry::new(),
+            hir: Hir::default(),
+            switches: Switches::default(),
+            conversion_warnings: false,
+            declarations: Declarations::default(),
+            heap: Heap::new(),
+        }
+    }
+
+    pub fn set_switches(&mut self, switches: Switches) {
+        self.switches = switches;
+    }
+
+    // How many frames the call stack may hold, the program's included; see
+    // `CallStack::with_depth_limit`.
+    pub fn set_call_depth_limit(&mut self, limit: usize) {
+        self.call_stack = std::mem::take(&mut self.call_stack).with_depth_limit(limit);
+    }
+
+    // Adds a runtime warning for every operand converted with a loss, as
+    // `lossy_conversions` finds them. Unlike the other warnings these never
+    // stop the program, whatever the switches.
+    pub fn enable_conversion_warnings(&mut self) {
+        self.conversion_warnings = true;
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        let explanation = self.output.explanation.take();
+        self.output = CountedOutput::new(output);
+        self.output.explanation = explanation;
+    }
+
+    // Records which statement wrote each piece of the output.
+    pub fn enable_explain(&mut self) {
+        self.output.explanation = Some(Explanation::new());
+    }
+
+    pub fn explanation(&self) -> Option<&Explanation> {
+        self.output.explanation.as_ref()
+    }
+
+    // The analyzer must know their signatures too; see
+    // `SemanticAnalyzer::with_builtins`.
+    pub fn set_builtins(&mut self, builtins: BuiltinRegistry) {
+        self.builtins = builtins;
+    }
+
+    pub fn set_statement_hook(&mut self, hook: Box<dyn StatementHook>) {
+        self.statement_hook = Some(hook);
+    }
+
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    // Breakpoints and watchpoints only trigger once a handler is set.
+    pub fn on_break(
+        &mut self,
+        handler: impl FnMut(&BreakEvent<'_>, &mut Breakpoints) -> Resume + 'static,
+    ) {
+        self.break_handler = Some(Box::new(handler));
+    }
+
+    fn trigger(&mut self, ast: &Ast, node: NodeId, reason: BreakReason) -> InterpretResult<()> {
+        let Some(handler) = &mut self.break_handler else {
+            return Ok(());
+        };
+        let event = BreakEvent {
+            reason,
+            ast,
+            node,
+            call_stack: &self.call_stack,
+        };
+        match handler(&event, &mut self.breakpoints) {
+            Resume::Continue => Ok(()),
+            Resume::Abort => Err(InterpretError::Aborted),
+        }
+    }
+
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    // Records every change to the program state, for replaying the run
+    // later.
+    pub fn enable_recording(&mut self) {
+        self.recording = Some(Recording::new());
+    }
+
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    // Writes an event to `out` for every statement, call, return and
+    // assignment as the program runs; see `TraceEvent`.
+    pub fn enable_tracing(&mut self, out: Box<dyn Write>) {
+        self.tracer = Some(Tracer::new(out));
+    }
+
+    // Flushes the trace, if tracing is on, and reports the first error
+    // writing it.
+    pub fn finish_trace(&mut self) -> Option<io::Result<()>> {
+        self.tracer.take().map(Tracer::finish)
+    }
+
+    // Captures the call stack and the heap when the program fails, before
+    // the frames are popped.
+    pub fn enable_error_dump(&mut self) {
+        self.dump_on_error = true;
+    }
+
+    pub fn error_dump(&self) -> Option<&StateDump> {
+        self.error_dump.as_ref()
+    }
+
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn profile(&self) -> Option<ProfileReport> {
+        self.profiler.as_ref().map(Profiler::report)
+    }
+
+    // Number of AST nodes evaluated so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.call_stack.max_depth()
+    }
+
+    pub fn stats(&self) -> RuntimeStats {
+        RuntimeStats {
+            max_call_depth: self.call_stack.max_depth(),
+            ..self.stats.clone()
+        }
+    }
+
+    // Runs a program the semantic analyzer accepted, with `hir` from that
+    // analysis. A panic while running it fails the run with
+    // `InterpretError::Internal` instead of unwinding into the caller; the
+    // interpreter shouldn't be used again after that.
+    pub fn interpret(&mut self, ast: &Ast, hir: &Hir) -> InterpretResult<RunReport> {
+        self.hir = hir.clone();
+        self.paused = Duration::ZERO;
+        let start = Instant::now();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| self.visit(ast, ast.root())))
+            .unwrap_or_else(|payload| {
+                Err(InterpretError::Internal {
+                    message: panic_message(payload.as_ref()),
+                })
+            });
+        self.output.flush()?;
+        self.wall_time = start.elapsed().saturating_sub(self.paused);
+        res.map(|_| self.run_report())
+    }
+
+    // Starts running a program like `interpret`, but in turns: the run
+    // pauses whenever the budget given to `BudgetedRun::run_with_budget`
+    // runs out, and goes on with the next one. The interpreter stays
+    // borrowed until the run is dropped; one dropped while paused fails
+    // with `InterpretError::Aborted`.
+    pub fn budgeted<'a>(&'a mut self, ast: &'a Ast, hir: &'a Hir) -> BudgetedRun<'a> {
+        BudgetedRun::new(self, ast, hir)
+    }
+
+    // The body of a `BudgetedRun`, on the coroutine `pauser` pauses.
+    pub(crate) fn run_budgeted(
+        &mut self,
+        ast: &Ast,
+        hir: &Hir,
+        pauser: &Pauser,
+        allowance: Allowance,
+    ) -> InterpretResult<RunReport> {
+        let Some((steps, millis)) = allowance else {
+            return Err(InterpretError::Aborted);
+        };
+        self.budget = Some(Budget::new(self.steps, steps, millis));
+        self.pauser = Some(NonNull::from(pauser));
+        let res = self.interpret(ast, hir);
+        self.budget = None;
+        self.pauser = None;
+        res
+    }
+
+    // Pauses a budgeted run whose budget has run out until the host gives
+    // it a new one. Without a way to pause, which is the case once the
+    // paused run was dropped, the run fails instead.
+    fn check_budget(&mut self) -> InterpretResult<()> {
+        let Some(exhausted) = self.budget.as_ref().and_then(|b| b.check(self.steps)) else {
+            return Ok(());
+        };
+        let Some(pauser) = self.pauser else {
+            return Err(InterpretError::Aborted);
+        };
+        self.output.flush()?;
+        let paused_at = Instant::now();
+        // SAFETY: `run_budgeted` points `pauser` at the yielder of the
+        // coroutine it runs on, which outlives it, and clears it again
+        // before returning. This only runs within it, on that coroutine.
+        let pauser = unsafe { pauser.as_ref() };
+        let allowance = visitor::keep_stack_limit(|| pauser.suspend(exhausted));
+        self.paused += paused_at.elapsed();
+        match allowance {
+            // The node that found the budget exhausted is the first of the
+            // new one
+            Some((steps, millis)) => {
+                self.budget = Some(Budget::new(self.steps - 1, steps, millis));
+                Ok(())
+            }
+            None => {
+                self.pauser = None;
+                Err(InterpretError::Aborted)
             }
         }
     }
-}
 
-impl std::error::Error for InterpretError {}
+    // Parses `expression`, e.g. `total div count`, and evaluates it with the
+    // variables of `scope`, such as the frame a paused program is in. This
+    // powers debugger watches and other tools. An expression can't assign
+    // or call procedures, so `scope` is only read. Objects live on the heap
+    // of the interpreter running the program, so their fields are out of
+    // reach.
+    pub fn eval_expression(
+        expression: &str,
+        scope: &Rc<RefCell<ActivationRecord>>,
+    ) -> InterpretResult<Value> {
+        let ast = Parser::new(Lexer::new(expression))
+            .and_then(|mut parser| parser.parse_expression())
+            .map_err(|e| InterpretError::InvalidExpression {
+                message: Diagnostic::from_parse_error(&e).to_string(),
+            })?;
+        let mut evaluator = Interpreter::new();
+        evaluator.call_stack.push(Rc::clone(scope));
+        evaluator
+            .visit(&ast, ast.root())?
+            .ok_or_else(|| InterpretError::InvalidExpression {
+                message: format!("'{expression}' has no value"),
+            })
+    }
 
-pub struct Interpreter {
-    log_call_stack: bool,
-    call_stack: CallStack,
-}
+    pub fn run_report(&self) -> RunReport {
+        RunReport {
+            steps: self.steps,
+            max_stack_depth: self.call_stack.max_depth(),
+            wall_time: self.wall_time,
+            io_bytes: self.output.bytes,
+            warnings: self.warnings.clone(),
+        }
+    }
 
-impl Interpreter {
-    pub fn new(log_call_stack: bool) -> Self {
-        Interpreter {
-            log_call_stack: log_call_stack,
-            call_stack: CallStack::new(),
+    pub fn visit(&mut self, ast: &Ast, node: NodeId) -> InterpretResult<Option<Value>> {
+        let res = ensure_stack(|| self.visit_instrumented(ast, node));
+        // The innermost failing node sees the error first
+        if let Err(e) = &res {
+            if self.dump_on_error && self.error_dump.is_none() {
+                self.error_dump = Some(StateDump::capture(
+                    e.to_string(),
+                    ast.location(node),
+                    &self.call_stack,
+                    &self.heap,
+                ));
+            }
         }
+        res
     }
 
-    pub fn interpret(&mut self, node: &ASTNode) -> InterpretResult<Option<BuiltinNumTypes>> {
-        self.visit(node)
+    fn visit_instrumented(&mut self, ast: &Ast, node: NodeId) -> InterpretResult<Option<Value>> {
+        self.steps += 1;
+        if self.budget.is_some() {
+            self.check_budget()?;
+        }
+        if ast[node].is_statement() {
+            self.stats.statements += 1;
+            if let Some(recording) = &mut self.recording {
+                recording.record_statement(node, self.call_stack.len());
+            }
+            if let Some(tracer) = &mut self.tracer {
+                let location = ast.location(node);
+                tracer.statement(node, location.line, location.column, self.call_stack.len());
+            }
+            if let Some(hook) = &mut self.statement_hook {
+                if hook.before_statement(ast, node, &self.call_stack) == Resume::Abort {
+                    return Err(InterpretError::Aborted);
+                }
+            }
+            if self.break_handler.is_some() {
+                let line = ast.location(node).line;
+                if self.breakpoints.hits_line(self.call_stack.len(), line) {
+                    self.trigger(ast, node, BreakReason::Breakpoint { line })?;
+                }
+            }
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.hit(node);
+        }
+        let Some(profiler) = &mut self.profiler else {
+            return self.visit_node(ast, node);
+        };
+
+        profiler.enter_node();
+        let res = self.visit_node(ast, node);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_node(ast.location(node).line);
+        }
+        res
     }
 
-    pub fn visit(&mut self, node: &ASTNode) -> InterpretResult<Option<BuiltinNumTypes>> {
-        match node {
+    fn visit_node(&mut self, ast: &Ast, node: NodeId) -> InterpretResult<Option<Value>> {
+        match &ast[node] {
             ASTNode::NumNode { value, .. } => {
-                let res = self.visit_num_node(*value)?;
+                let res = self.visit_num_node(value)?;
                 Ok(Some(res))
             }
             ASTNode::UnaryOpNode { expr, token } => {
-                let res = self.visit_unary_op_node(token, expr)?;
+                let res = self.visit_unary_op_node(ast, node, token, *expr)?;
                 Ok(Some(res))
             }
             ASTNode::BinOpNode { left, right, op } => {
-                let res = self.visit_bin_op_node(op, left, right)?;
+                let res = self.visit_bin_op_node(ast, node, op, *left, *right)?;
                 Ok(Some(res))
             }
             ASTNode::Assign { left, right, .. } => {
-                self.visit_assign_node(left, right)?;
+                self.visit_assign_node(ast, node, *left, *right)?;
                 Ok(None)
             }
             ASTNode::Var { name: value, .. } => {
@@ -169,256 +1262,913 @@ impl Interpreter {
                 Ok(Some(value))
             }
             ASTNode::Compound { children } => {
-                self.visit_compound_node(children)?;
+                self.visit_compound_node(ast, children)?;
+                Ok(None)
+            }
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.visit_if_node(ast, *condition, *then_branch, *else_branch)?;
+                Ok(None)
+            }
+            ASTNode::While { condition, body } => {
+                while self.condition(ast, *condition, "a WHILE")? {
+                    self.visit(ast, *body)?;
+                }
+                Ok(None)
+            }
+            ASTNode::Repeat { body, condition } => {
+                loop {
+                    self.visit_compound_node(ast, body)?;
+                    if self.condition(ast, *condition, "a REPEAT")? {
+                        break;
+                    }
+                }
+                Ok(None)
+            }
+            ASTNode::Case {
+                selector,
+                arms,
+                else_branch,
+            } => {
+                self.visit_case_node(ast, *selector, arms, else_branch.as_deref())?;
                 Ok(None)
             }
+            // Only looked at through their CASE
+            ASTNode::CaseArm { .. } | ASTNode::Range { .. } => Ok(None),
             ASTNode::NoOp => Ok(None),
             ASTNode::Program { name, block } => {
-                self.visit_program_node(name, block)?;
+                self.visit_program_node(ast, name, *block)?;
                 Ok(None)
             }
             ASTNode::Block {
                 declarations,
                 compound_statement,
             } => {
-                self.visit_block_node(declarations, compound_statement)?;
+                self.visit_block_node(ast, declarations, *compound_statement)?;
                 Ok(None)
             }
             ASTNode::VarDecl {
                 var_node,
-                type_node,
+                initializer,
+                ..
             } => {
-                self.visit_var_decl_node(var_node, type_node)?;
+                self.visit_var_decl_node(ast, node, *var_node, *initializer)?;
                 Ok(None)
             }
             ASTNode::Type { value, .. } => {
                 self.visit_type_node(value)?;
                 Ok(None)
             }
+            ASTNode::TypeDecl {
+                type_name,
+                type_node,
+            } => {
+                self.declarations
+                    .types
+                    .push((type_name.clone(), *type_node));
+                Ok(None)
+            }
+            ASTNode::ProcedureType { .. }
+            | ASTNode::ClassType { .. }
+            | ASTNode::MethodHeading { .. }
+            | ASTNode::Property { .. } => Ok(None),
+            ASTNode::MethodDecl {
+                class_name,
+                method_kind,
+                method_name,
+                params,
+                block_node,
+            } => {
+                self.declarations.methods.push(Method {
+                    class_name: class_name.clone(),
+                    kind: *method_kind,
+                    procedure: Rc::new(ProcedureValue {
+                        name: method_name.clone(),
+                        param_names: param_names(ast, params),
+                        param_modes: param_modes(ast, params),
+                        block: *block_node,
+                    }),
+                });
+                Ok(None)
+            }
+            ASTNode::FieldAccess { object, field } => {
+                let value = self.visit_field_access_node(ast, *object, field)?;
+                Ok(Some(value))
+            }
+            ASTNode::MethodCall {
+                object,
+                method,
+                arguments,
+            } => self.visit_method_call_node(ast, *object, method, arguments),
             ASTNode::ProcedureDecl {
                 proc_name,
                 params,
                 block_node,
+                ..
             } => {
-                self.visit_procedure_decl_node(proc_name, params, block_node)?;
+                self.visit_procedure_decl_node(ast, proc_name, params, *block_node)?;
                 Ok(None)
             }
             ASTNode::Param { .. } => Ok(None),
             ASTNode::ProcedureCall {
                 proc_name,
                 arguments,
-                proc_symbol,
-            } => self.visit_procedure_call_node(proc_name, arguments, proc_symbol),
+            } => self.visit_procedure_call_node(ast, node, proc_name, arguments),
         }
     }
 
-    fn log(&self) {
-        if self.log_call_stack {
-            println!("{}", self.call_stack);
-        }
+    // The whole call stack, for following a run with `--log-level=trace`.
+    // Formatting it is skipped unless a subscriber wants the event.
+    fn log(&self, event: &str) {
+        tracing::trace!(call_stack = %self.call_stack, "{}", event);
     }
 
     fn visit_program_node(
         &mut self,
-        name: &String,
-        block: &Box<ASTNode>,
-    ) -> InterpretResult<Option<BuiltinNumTypes>> {
+        ast: &Ast,
+        name: &str,
+        block: NodeId,
+    ) -> InterpretResult<Option<Value>> {
         let ar = Rc::new(RefCell::new(ActivationRecord::new(
-            &name,
+            name,
             ARType::Program,
             1,
         )));
         self.call_stack.push(ar);
-        self.log();
-        let res = self.visit(block);
+        self.log("entered program");
+        if let Some(recording) = &mut self.recording {
+            recording.record_call(name, 1, []);
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer.call(name, self.call_stack.len(), []);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_procedure(name);
+        }
+        let res = self.visit(ast, block);
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_procedure();
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer.ret(name, self.call_stack.len());
+        }
         self.call_stack.pop();
+        if let Some(recording) = &mut self.recording {
+            recording.record_return();
+        }
         res
     }
 
     fn visit_block_node(
         &mut self,
-        declarations: &Vec<Box<ASTNode>>,
-        compound_statement: &Box<ASTNode>,
-    ) -> InterpretResult<Option<BuiltinNumTypes>> {
-        for d in declarations {
-            self.visit(d)?;
-        }
-
-        self.visit(compound_statement)
+        ast: &Ast,
+        declarations: &[NodeId],
+        compound_statement: NodeId,
+    ) -> InterpretResult<Option<Value>> {
+        let declared = self.declarations.len();
+        let res = declarations
+            .iter()
+            .try_for_each(|d| self.visit(ast, *d).map(drop))
+            .and_then(|()| self.visit(ast, compound_statement));
+        self.declarations.truncate(declared);
+        res
     }
 
+    // Declarations are visited when their block is entered, so this is
+    // where an initializer runs.
     fn visit_var_decl_node(
         &mut self,
-        _var_node: &Box<ASTNode>,
-        _type_node: &Box<ASTNode>,
+        ast: &Ast,
+        node: NodeId,
+        var_node: NodeId,
+        initializer: Option<NodeId>,
     ) -> InterpretResult<()> {
-        Ok(())
+        let Some(initializer) = initializer else {
+            return Ok(());
+        };
+        let Some(value) = self.visit(ast, initializer)? else {
+            return Err(InterpretError::MissingAssignmentValue {
+                name: ast.display(var_node).to_string(),
+            });
+        };
+        self.assign(ast, node, var_node, value)
     }
 
     fn visit_procedure_decl_node(
         &mut self,
-        _procedure_name: &String,
-        _params: &Vec<Box<ASTNode>>,
-        _block: &Box<ASTNode>,
+        ast: &Ast,
+        procedure_name: &str,
+        params: &[NodeId],
+        block: NodeId,
     ) -> InterpretResult<()> {
+        self.declarations.procedures.push(Rc::new(ProcedureValue {
+            name: procedure_name.to_string(),
+            param_names: param_names(ast, params),
+            param_modes: param_modes(ast, params),
+            block,
+        }));
         Ok(())
     }
 
     fn visit_procedure_call_node(
         &mut self,
+        ast: &Ast,
+        node: NodeId,
         proc_name: &str,
-        arguments: &Vec<Box<ASTNode>>,
-        proc_symbol: &RefCell<Option<Box<Symbol>>>,
-    ) -> InterpretResult<Option<BuiltinNumTypes>> {
-        let current_nesting_level = self.call_stack.peek().unwrap().borrow().nesting_level();
-
-        let ar = Rc::new(RefCell::new(ActivationRecord::new(
-            &proc_name,
-            ARType::Procedure,
-            current_nesting_level + 1,
-        )));
-        self.call_stack.push(ar);
-
-        let Some(symbol_ptr) = proc_symbol.borrow().clone() else {
+        arguments: &[NodeId],
+    ) -> InterpretResult<Option<Value>> {
+        let Some(callee) = self.hir.callee(node) else {
             return Err(InterpretError::UndefinedFunction {
                 name: proc_name.to_string(),
             });
         };
 
-        let Symbol {
-            kind:
-                SymbolKind::Procedure {
-                    param_names,
-                    block: block_node,
-                },
-            ..
-        } = symbol_ptr.as_ref()
-        else {
+        // A procedure, or a variable holding one
+        let procedure = match callee {
+            Callee::Builtin(_) | Callee::External => {
+                return self.call_builtin(ast, node, proc_name, arguments);
+            }
+            Callee::Procedure(declaration) => {
+                self.declarations.declared_at(ast, declaration).cloned()
+            }
+            Callee::Variable => match self.visit_var_node(proc_name)? {
+                Value::Procedure(procedure) => Some(procedure),
+                _ => None,
+            },
+        };
+        let Some(procedure) = procedure else {
             return Err(InterpretError::UndefinedFunction {
                 name: proc_name.to_string(),
             });
         };
+        let values = self.arguments(ast, &procedure, arguments)?;
+        let results = self.call_procedure(ast, &procedure.name, &procedure, values, None)?;
+        self.write_back(ast, arguments, results)?;
+        Ok(None)
+    }
 
-        for (param, arg) in zip(param_names, arguments) {
-            let value = self
-                .visit(arg)?
-                .ok_or(InterpretError::AssignTargetMustBeVar)?;
-            self.call_stack
-                .peek()
-                .unwrap()
-                .borrow_mut()
-                .set(param, value);
+    // Runs the procedure with its parameters set to `arguments`, which has
+    // no value for an OUT parameter. Returns the final values of the VAR
+    // and OUT parameters, for the caller to write back.
+    fn call_procedure(
+        &mut self,
+        ast: &Ast,
+        proc_name: &str,
+        procedure: &ProcedureValue,
+        arguments: Vec<Option<Value>>,
+        // The instance a method is called on, which it sees as `self`
+        receiver: Option<ObjectRef>,
+    ) -> InterpretResult<Vec<Option<Value>>> {
+        let _span = tracing::debug_span!("call", procedure = proc_name).entered();
+        let param_names = &procedure.param_names;
+        if param_names.len() != arguments.len() {
+            return Err(InterpretError::ProcCallMissingArgs {
+                proc_name: proc_name.to_string(),
+                expected: param_names.len(),
+                got: arguments.len(),
+            });
+        }
+        let limit = self.call_stack.depth_limit();
+        if self.call_stack.len() >= limit {
+            return Err(InterpretError::CallDepthExceeded { limit });
+        }
+
+        let current_nesting_level = self.frame()?.borrow().nesting_level();
+        let mut ar = ActivationRecord::new(proc_name, ARType::Procedure, current_nesting_level + 1);
+
+        for (param, value) in zip(param_names, arguments) {
+            if let Some(value) = value {
+                ar.set(param, value);
+            }
+        }
+        if let Some(receiver) = receiver {
+            ar.set(SELF, Value::Object(receiver));
+        }
+
+        self.stats.procedure_calls += 1;
+        if let Some(recording) = &mut self.recording {
+            recording.record_call(proc_name, ar.nesting_level(), ar.members());
+        }
+        if let Some(tracer) = &mut self.tracer {
+            let arguments = param_names
+                .iter()
+                .filter_map(|param| Some((param.as_str(), ar.get(param)?)));
+            tracer.call(proc_name, self.call_stack.len() + 1, arguments);
+        }
+        self.call_stack.push(Rc::new(RefCell::new(ar)));
+        self.log("entered procedure");
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_procedure(proc_name);
         }
 
-        let res = self.visit(&block_node);
+        let res = self.visit(ast, procedure.block).and_then(|_| {
+            let frame = self.frame()?;
+            let frame = frame.borrow();
+            Ok(zip(param_names, &procedure.param_modes)
+                .map(|(param, mode)| {
+                    mode.by_reference()
+                        .then(|| frame.get(param).cloned())
+                        .flatten()
+                })
+                .collect())
+        });
 
-        self.log();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_procedure();
+        }
+        self.log("leaving procedure");
 
+        if let Some(tracer) = &mut self.tracer {
+            tracer.ret(proc_name, self.call_stack.len());
+        }
         self.call_stack.pop();
+        if let Some(recording) = &mut self.recording {
+            recording.record_return();
+        }
 
         res
     }
 
-    fn visit_type_node(&self, _value: &String) -> InterpretResult<()> {
+    // Arguments are evaluated in the caller's frame, before the callee's is
+    // pushed. An OUT parameter's argument isn't read at all, and a VAR
+    // parameter's may still be unassigned.
+    fn arguments(
+        &mut self,
+        ast: &Ast,
+        procedure: &ProcedureValue,
+        arguments: &[NodeId],
+    ) -> InterpretResult<Vec<Option<Value>>> {
+        let modes = procedure
+            .param_modes
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(ParamMode::Value));
+        zip(arguments, modes)
+            .map(|(arg, mode)| {
+                if mode == ParamMode::Out {
+                    return Ok(None);
+                }
+                match self.visit(ast, *arg) {
+                    Err(InterpretError::UninitializedVariable { .. }) if mode == ParamMode::Var => {
+                        Ok(None)
+                    }
+                    value => value?
+                        .map(Some)
+                        .ok_or(InterpretError::AssignTargetMustBeVar),
+                }
+            })
+            .collect()
+    }
+
+    // Assigns the final values of VAR and OUT parameters to the variables
+    // passed for them.
+    fn write_back(
+        &mut self,
+        ast: &Ast,
+        arguments: &[NodeId],
+        results: Vec<Option<Value>>,
+    ) -> InterpretResult<()> {
+        for (arg, result) in zip(arguments, results) {
+            if let Some(value) = result {
+                self.assign(ast, *arg, *arg, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The class `node` names, when it is the name of a class rather than a
+    // variable holding an instance.
+    fn class_reference<'a>(&self, ast: &'a Ast, node: NodeId) -> Option<&'a str> {
+        let ASTNode::Var { name } = &ast[node] else {
+            return None;
+        };
+        if self.call_stack.peek()?.borrow().get(name).is_some() {
+            return None;
+        }
+        self.declarations.class(ast, name).map(|_| name.as_str())
+    }
+
+    // The live instance an expression evaluates to.
+    fn object(&mut self, ast: &Ast, node: NodeId) -> InterpretResult<ObjectRef> {
+        let value = self.visit(ast, node)?;
+        self.live_object(ast, node, value)
+    }
+
+    fn live_object(
+        &self,
+        ast: &Ast,
+        node: NodeId,
+        value: Option<Value>,
+    ) -> InterpretResult<ObjectRef> {
+        match value {
+            Some(Value::Object(object)) if self.heap.get(&object).is_some() => Ok(object),
+            Some(Value::Object(object)) => Err(InterpretError::FreedObject {
+                class_name: object.class_name.to_string(),
+            }),
+            Some(Value::Nil) => Err(InterpretError::NilObject),
+            _ => Err(InterpretError::NotAnObject {
+                expression: ast.display(node).to_string(),
+            }),
+        }
+    }
+
+    fn visit_field_access_node(
+        &mut self,
+        ast: &Ast,
+        object: NodeId,
+        field: &str,
+    ) -> InterpretResult<Value> {
+        if let Some(class_name) = self.class_reference(ast, object) {
+            return self.construct(ast, class_name, field, &[]);
+        }
+        let object = self.object(ast, object)?;
+        let field = match self.declarations.property(ast, &object.class_name, field) {
+            Some((Some(reader), _)) => reader,
+            Some((None, _)) => {
+                return Err(InterpretError::WriteOnlyProperty {
+                    class_name: object.class_name.to_string(),
+                    property: field.to_string(),
+                })
+            }
+            None => field,
+        };
+        self.heap
+            .get(&object)
+            .and_then(|fields| fields.field(field))
+            .cloned()
+            .ok_or_else(|| InterpretError::UnknownMember {
+                class_name: object.class_name.to_string(),
+                member: field.to_string(),
+            })
+    }
+
+    fn visit_method_call_node(
+        &mut self,
+        ast: &Ast,
+        object: NodeId,
+        method: &str,
+        arguments: &[NodeId],
+    ) -> InterpretResult<Option<Value>> {
+        if let Some(class_name) = self.class_reference(ast, object) {
+            return self.construct(ast, class_name, method, arguments).map(Some);
+        }
+
+        let value = self.visit(ast, object)?;
+        // Like in FPC, freeing NIL does nothing
+        if method == FREE && value == Some(Value::Nil) {
+            return Ok(None);
+        }
+        let object = self.live_object(ast, object, value)?;
+
+        // `Free` runs the destructor `Destroy` if the class has one
+        let method =
+            if method == FREE && self.declarations.method(&object.class_name, FREE).is_none() {
+                if self
+                    .declarations
+                    .method(&object.class_name, "destroy")
+                    .is_none()
+                {
+                    self.heap.free(&object);
+                    return Ok(None);
+                }
+                "destroy"
+            } else {
+                method
+            };
+        let procedure = self.method(&object.class_name, method)?;
+        let values = self.arguments(ast, &procedure, arguments)?;
+        let results = self.call_method(ast, &object, method, values)?;
+        self.write_back(ast, arguments, results)?;
+        Ok(None)
+    }
+
+    fn method(&self, class_name: &str, method: &str) -> InterpretResult<Rc<ProcedureValue>> {
+        self.declarations
+            .method(class_name, method)
+            .map(|method| Rc::clone(&method.procedure))
+            .ok_or_else(|| InterpretError::UndefinedFunction {
+                name: format!("{class_name}.{method}"),
+            })
+    }
+
+    fn call_method(
+        &mut self,
+        ast: &Ast,
+        object: &ObjectRef,
+        method: &str,
+        arguments: Vec<Option<Value>>,
+    ) -> InterpretResult<Vec<Option<Value>>> {
+        let Some(Method {
+            kind, procedure, ..
+        }) = self.declarations.method(&object.class_name, method)
+        else {
+            return Err(InterpretError::UndefinedFunction {
+                name: format!("{}.{}", object.class_name, method),
+            });
+        };
+        let (kind, procedure) = (*kind, Rc::clone(procedure));
+        let results = self.call_procedure(
+            ast,
+            &format!("{}.{}", object.class_name, method),
+            &procedure,
+            arguments,
+            Some(object.clone()),
+        )?;
+        if kind == MethodKind::Destructor {
+            self.heap.free(object);
+        }
+        Ok(results)
+    }
+
+    // A new instance of the class, set up by the constructor.
+    fn construct(
+        &mut self,
+        ast: &Ast,
+        class_name: &str,
+        constructor: &str,
+        arguments: &[NodeId],
+    ) -> InterpretResult<Value> {
+        let is_constructor = self
+            .declarations
+            .method(class_name, constructor)
+            .is_some_and(|method| method.kind == MethodKind::Constructor);
+        if !is_constructor {
+            return Err(InterpretError::UnknownMember {
+                class_name: class_name.to_string(),
+                member: constructor.to_string(),
+            });
+        }
+        let members = self.declarations.class(ast, class_name).unwrap_or_default();
+        let fields = members
+            .iter()
+            .filter_map(|member| match &ast[*member] {
+                ASTNode::VarDecl {
+                    var_node,
+                    type_node,
+                    ..
+                } => match (&ast[*var_node], &ast[*type_node]) {
+                    (ASTNode::Var { name }, ASTNode::Type { value }) => {
+                        Some((name.clone(), self.declarations.initial_value(ast, value)))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        let procedure = self.method(class_name, constructor)?;
+        let values = self.arguments(ast, &procedure, arguments)?;
+        let object = self
+            .heap
+            .allocate(class_name, Value::Record(Rc::new(fields)));
+        let results = self.call_method(ast, &object, constructor, values)?;
+        self.write_back(ast, arguments, results)?;
+        Ok(Value::Object(object))
+    }
+
+    // A field of the instance the current method was called on.
+    // The frame of the running routine. There always is one while a
+    // program runs.
+    fn frame(&self) -> InterpretResult<Rc<RefCell<ActivationRecord>>> {
+        self.call_stack
+            .peek()
+            .cloned()
+            .ok_or_else(|| InterpretError::Internal {
+                message: "no activation record on the call stack".to_string(),
+            })
+    }
+
+    fn self_field(&self, name: &str) -> Option<(ObjectRef, Value)> {
+        let frame = self.call_stack.peek()?;
+        let Some(Value::Object(object)) = frame.borrow().get(SELF).cloned() else {
+            return None;
+        };
+        let value = self.heap.get(&object)?.field(name)?.clone();
+        Some((object, value))
+    }
+
+    // Assigns a field, or a property through its writer.
+    fn set_member(
+        &mut self,
+        ast: &Ast,
+        object: &ObjectRef,
+        field: &str,
+        value: Value,
+    ) -> InterpretResult<()> {
+        let writer = match self.declarations.property(ast, &object.class_name, field) {
+            Some((_, Some(writer))) => writer,
+            Some((_, None)) => {
+                return Err(InterpretError::ReadOnlyProperty {
+                    class_name: object.class_name.to_string(),
+                    property: field.to_string(),
+                })
+            }
+            None => return self.set_field(object, field, value),
+        };
+        if self
+            .declarations
+            .method(&object.class_name, writer)
+            .is_some()
+        {
+            self.call_method(ast, object, writer, vec![Some(value)])?;
+            return Ok(());
+        }
+        self.set_field(object, writer, value)
+    }
+
+    fn set_field(&mut self, object: &ObjectRef, field: &str, value: Value) -> InterpretResult<()> {
+        let Some(fields) = self.heap.get_mut(object) else {
+            return Err(InterpretError::FreedObject {
+                class_name: object.class_name.to_string(),
+            });
+        };
+        fields
+            .set_field(field, value)
+            .ok_or_else(|| InterpretError::UnknownMember {
+                class_name: object.class_name.to_string(),
+                member: field.to_string(),
+            })
+    }
+
+    fn visit_type_node(&self, _value: &str) -> InterpretResult<()> {
         Ok(())
     }
 
-    fn visit_num_node(&self, value: BuiltinNumTypes) -> InterpretResult<BuiltinNumTypes> {
-        Ok(value)
+    fn visit_num_node(&self, value: &Value) -> InterpretResult<Value> {
+        Ok(value.clone())
     }
 
     fn visit_unary_op_node(
         &mut self,
+        ast: &Ast,
+        node: NodeId,
         token: &Token,
-        expr: &ASTNode,
-    ) -> InterpretResult<BuiltinNumTypes> {
-        let value = match self.visit(expr)? {
-            Some(BuiltinNumTypes::F32(v)) => v,
-            Some(BuiltinNumTypes::I32(v)) => v as f32,
-            None => return Err(InterpretError::MissingUnaryOperand),
+        expr: NodeId,
+    ) -> InterpretResult<Value> {
+        let Some(value) = self.visit(ast, expr)? else {
+            return Err(InterpretError::MissingUnaryOperand);
         };
 
-        match token {
-            Token::Plus => Ok(BuiltinNumTypes::F32(value)),
-            Token::Minus => Ok(BuiltinNumTypes::F32(-value)),
-            _ => Err(InterpretError::InvalidUnaryOperator {
-                token: token.clone(),
-            }),
+        self.stats.arithmetic.count_unary(token);
+        let result = apply_unary_op(token, value.clone(), &self.switches)?;
+        if let Err(e) = apply_unary_op(token, value, &Switches::STRICT) {
+            self.warn(ast, node, e.to_string());
         }
+        Ok(result)
     }
 
     fn visit_bin_op_node(
         &mut self,
+        ast: &Ast,
+        node: NodeId,
         op: &Token,
-        left: &ASTNode,
-        right: &ASTNode,
-    ) -> InterpretResult<BuiltinNumTypes> {
-        let left_value = match self.visit(left)? {
-            Some(BuiltinNumTypes::F32(v)) => v,
-            Some(BuiltinNumTypes::I32(v)) => v as f32,
-            None => {
-                return Err(InterpretError::MissingBinaryOperand {
-                    side: BinaryOperandSide::Left,
-                })
-            }
+        left: NodeId,
+        right: NodeId,
+    ) -> InterpretResult<Value> {
+        let Some(left_value) = self.visit(ast, left)? else {
+            return Err(InterpretError::MissingBinaryOperand {
+                side: BinaryOperandSide::Left,
+            });
         };
 
-        let right_value = match self.visit(right)? {
-            Some(BuiltinNumTypes::F32(v)) => v,
-            Some(BuiltinNumTypes::I32(v)) => v as f32,
-            None => {
-                return Err(InterpretError::MissingBinaryOperand {
-                    side: BinaryOperandSide::Right,
-                })
-            }
+        let Some(right_value) = self.visit(ast, right)? else {
+            return Err(InterpretError::MissingBinaryOperand {
+                side: BinaryOperandSide::Right,
+            });
         };
 
-        match op {
-            Token::Plus => Ok(BuiltinNumTypes::F32(left_value + right_value)),
-            Token::Minus => Ok(BuiltinNumTypes::F32(left_value - right_value)),
-            Token::Asterisk => Ok(BuiltinNumTypes::F32(left_value * right_value)),
-            Token::FloatDiv => Ok(BuiltinNumTypes::F32(left_value / right_value)),
-            Token::IntegerDiv => Ok(BuiltinNumTypes::F32(
-                ((left_value as i32) / (right_value as i32)) as f32,
-            )),
-            _ => Err(InterpretError::InvalidBinaryOperator { token: op.clone() }),
+        self.stats.arithmetic.count_binary(op);
+        if self.conversion_warnings {
+            for message in lossy_conversions(op, &left_value, &right_value) {
+                self.warn(ast, node, message);
+            }
+        }
+        let result = apply_bin_op(op, left_value.clone(), right_value.clone(), &self.switches)?;
+        if let Err(e) = apply_bin_op(op, left_value, right_value, &Switches::STRICT) {
+            self.warn(ast, node, e.to_string());
+        }
+        Ok(result)
+    }
+
+    // An operation that succeeded only because a check was off would have
+    // failed with the error in `message` under the strictest switches, or
+    // lost part of an operand converting it.
+    fn warn(&mut self, ast: &Ast, node: NodeId, message: String) {
+        if self.warnings.len() < MAX_WARNINGS {
+            self.warnings.push(RuntimeWarning {
+                location: ast.location(node),
+                message,
+            });
+        }
+    }
+
+    // Builtins run through the registry, which checks the arguments
+    // against their signature.
+    fn call_builtin(
+        &mut self,
+        ast: &Ast,
+        node: NodeId,
+        proc_name: &str,
+        arguments: &[NodeId],
+    ) -> InterpretResult<Option<Value>> {
+        let mut values = Vec::with_capacity(arguments.len());
+        for arg in arguments {
+            let value = self
+                .visit(ast, *arg)?
+                .ok_or(InterpretError::AssignTargetMustBeVar)?;
+            values.push(value);
         }
+        self.output.statement = Some(node);
+        let result = self.builtins.call(
+            proc_name,
+            &mut values,
+            &mut self.output,
+            self.switches.real_format,
+        )?;
+        let signature = self.builtins.signature(proc_name);
+        let results = values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let by_reference = signature
+                    .and_then(|signature| signature.param(index))
+                    .is_some_and(|(_, mode)| mode.by_reference());
+                by_reference.then_some(value)
+            })
+            .collect();
+        self.write_back(ast, arguments, results)?;
+        Ok(result)
     }
 
-    fn visit_assign_node(&mut self, left: &ASTNode, right: &ASTNode) -> InterpretResult<()> {
-        let ASTNode::Var { name, .. } = left else {
+    fn visit_assign_node(
+        &mut self,
+        ast: &Ast,
+        node: NodeId,
+        left: NodeId,
+        right: NodeId,
+    ) -> InterpretResult<()> {
+        if !matches!(ast[left], ASTNode::Var { .. } | ASTNode::FieldAccess { .. }) {
             return Err(InterpretError::AssignTargetMustBeVar);
-        };
+        }
 
-        let res = self.visit(right)?;
+        let res = self.visit(ast, right)?;
 
         let Some(right_hand_value) = res else {
-            return Err(InterpretError::MissingAssignmentValue { name: name.clone() });
+            return Err(InterpretError::MissingAssignmentValue {
+                name: ast.display(left).to_string(),
+            });
         };
+        self.assign(ast, node, left, right_hand_value)
+    }
 
-        self.call_stack
-            .peek()
-            .unwrap()
-            .borrow_mut()
-            .set(name, right_hand_value);
+    // Stores a value in a variable or field, for an assignment or when a
+    // call writes back a VAR or OUT parameter.
+    fn assign(
+        &mut self,
+        ast: &Ast,
+        node: NodeId,
+        target: NodeId,
+        right_hand_value: Value,
+    ) -> InterpretResult<()> {
+        let name = match &ast[target] {
+            ASTNode::Var { name, .. } => name,
+            ASTNode::FieldAccess { object, field } => {
+                let object = self.object(ast, *object)?;
+                return self.set_member(ast, &object, field, right_hand_value);
+            }
+            _ => return Err(InterpretError::AssignTargetMustBeVar),
+        };
+
+        // A field of `self`, unless a variable of the method has its name
+        let in_frame = self.frame()?.borrow().get(name).is_some();
+        if !in_frame {
+            if let Some((object, _)) = self.self_field(name) {
+                return self.set_field(&object, name, right_hand_value);
+            }
+        }
 
+        let frame = self.frame()?;
+        let watched = self.break_handler.is_some() && self.breakpoints.is_watched(name);
+        let old = watched.then(|| frame.borrow().get(name).cloned()).flatten();
+        frame.borrow_mut().set(name, right_hand_value.clone());
+        if let Some(recording) = &mut self.recording {
+            recording.record_assign(name, right_hand_value.clone());
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer.assign(name, &right_hand_value, self.call_stack.len());
+        }
+
+        if watched && old.as_ref() != Some(&right_hand_value) {
+            let reason = BreakReason::Watchpoint {
+                name: name.clone(),
+                old,
+                new: right_hand_value,
+            };
+            self.trigger(ast, node, reason)?;
+        }
         Ok(())
     }
 
-    fn visit_var_node(&mut self, name: &String) -> InterpretResult<BuiltinNumTypes> {
-        self.call_stack
-            .peek()
-            .unwrap()
-            .borrow()
-            .get(name)
-            .cloned()
-            .ok_or_else(|| InterpretError::UninitializedVariable { name: name.clone() })
+    // A variable of the current frame, a field of `self` in a method, or a
+    // procedure used as a value.
+    fn visit_var_node(&mut self, name: &str) -> InterpretResult<Value> {
+        if let Some(value) = self.frame()?.borrow().get(name) {
+            return Ok(value.clone());
+        }
+        if let Some((_, value)) = self.self_field(name) {
+            return Ok(value);
+        }
+        self.declarations
+            .procedure(name)
+            .map(|procedure| Value::Procedure(Rc::clone(procedure)))
+            .ok_or_else(|| InterpretError::UninitializedVariable {
+                name: name.to_string(),
+            })
     }
 
-    fn visit_compound_node(&mut self, children: &Vec<Box<ASTNode>>) -> InterpretResult<()> {
+    fn visit_compound_node(&mut self, ast: &Ast, children: &[NodeId]) -> InterpretResult<()> {
         for child in children {
-            self.visit(child)?;
+            self.visit(ast, *child)?;
+        }
+        Ok(())
+    }
+
+    // Evaluates the condition of `statement`, which must be a BOOLEAN.
+    fn condition(
+        &mut self,
+        ast: &Ast,
+        condition: NodeId,
+        statement: &'static str,
+    ) -> InterpretResult<bool> {
+        match self.visit(ast, condition)? {
+            Some(Value::Bool(holds)) => Ok(holds),
+            Some(value) => Err(InterpretError::ConditionNotBoolean {
+                statement,
+                type_name: value.type_name(),
+            }),
+            None => Err(InterpretError::NoValue {
+                call: ast.display(condition).to_string(),
+            }),
+        }
+    }
+
+    fn visit_if_node(
+        &mut self,
+        ast: &Ast,
+        condition: NodeId,
+        then_branch: NodeId,
+        else_branch: Option<NodeId>,
+    ) -> InterpretResult<()> {
+        let branch = match self.condition(ast, condition, "an IF")? {
+            true => Some(then_branch),
+            false => else_branch,
+        };
+        if let Some(branch) = branch {
+            self.visit(ast, branch)?;
+        }
+        Ok(())
+    }
+
+    // Runs the first arm with a label holding the selector, or else the
+    // ELSE part; with neither, nothing runs.
+    fn visit_case_node(
+        &mut self,
+        ast: &Ast,
+        selector: NodeId,
+        arms: &[NodeId],
+        else_branch: Option<&[NodeId]>,
+    ) -> InterpretResult<()> {
+        let value = match self.visit(ast, selector)? {
+            Some(Value::Int(value)) => value,
+            Some(value) => {
+                return Err(InterpretError::CaseSelectorNotInteger {
+                    type_name: value.type_name(),
+                })
+            }
+            None => {
+                return Err(InterpretError::NoValue {
+                    call: ast.display(selector).to_string(),
+                })
+            }
+        };
+        for &arm in arms {
+            let ASTNode::CaseArm { labels, statement } = &ast[arm] else {
+                continue;
+            };
+            let matches = labels
+                .iter()
+                .filter_map(|&label| ast.case_label_bounds(label))
+                .any(|(low, high)| (low..=high).contains(&value));
+            if matches {
+                self.visit(ast, *statement)?;
+                return Ok(());
+            }
+        }
+        if let Some(else_branch) = else_branch {
+            self.visit_compound_node(ast, else_branch)?;
         }
         Ok(())
     }
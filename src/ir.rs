@@ -0,0 +1,545 @@
+use std::fmt;
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::token::Token;
+use crate::value::Value;
+use crate::visitor::ensure_stack;
+
+// Three-address code: every instruction applies at most one operator, with
+// intermediate results held in numbered temporaries local to a function.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Const(Value),
+    Var(String),
+    Temp(Temp),
+}
+
+// Temporaries and branch targets are numbered per function and print as
+// `%t1` and `%L1`. No identifier starts with `%`, so they can't collide with
+// a variable or procedure of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Temp(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(pub u32);
+
+// Hands out the temporaries and labels of one function. Each name is new.
+#[derive(Debug, Default)]
+pub struct Names {
+    temps: u32,
+    labels: u32,
+}
+
+impl Names {
+    pub fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        Temp(self.temps)
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels += 1;
+        Label(self.labels)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    // The start of a function, named after it
+    Entry(String),
+    Label(Label),
+    Goto(Label),
+    IfFalse {
+        condition: Operand,
+        label: Label,
+    },
+    Copy {
+        dest: Operand,
+        src: Operand,
+    },
+    Unary {
+        dest: Operand,
+        op: Token,
+        src: Operand,
+    },
+    Binary {
+        dest: Operand,
+        op: Token,
+        left: Operand,
+        right: Operand,
+    },
+    // Reads and writes a field of the object in `object`
+    Field {
+        dest: Operand,
+        object: Operand,
+        field: String,
+    },
+    SetField {
+        object: Operand,
+        field: String,
+        src: Operand,
+    },
+    // Arguments are passed with `param`, in order, before the `call`. A
+    // method gets its receiver as the first one. A constructor call in an
+    // expression stores the new object in `dest`.
+    Param(Operand),
+    Call {
+        dest: Option<Operand>,
+        label: String,
+        arguments: usize,
+    },
+    Return,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Instruction>,
+}
+
+// The main program comes first, followed by every procedure, nested ones
+// included, in the order they are declared. A method is a function named
+// `class.method` whose first parameter is `self`.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+}
+
+// Lowers an analyzed program to three-address code.
+pub fn lower(ast: &Ast) -> Program {
+    let mut program = Program::default();
+    let ASTNode::Program { name, block } = &ast[ast.root()] else {
+        return program;
+    };
+    Lowering::function(ast, name, vec![], *block, &mut program);
+    program
+}
+
+struct Lowering<'a> {
+    ast: &'a Ast,
+    body: Vec<Instruction>,
+    names: Names,
+}
+
+impl<'a> Lowering<'a> {
+    // Lowers a block into a new function, then the procedures declared in it.
+    fn function(
+        ast: &'a Ast,
+        name: &str,
+        params: Vec<String>,
+        block: NodeId,
+        program: &mut Program,
+    ) {
+        let ASTNode::Block {
+            declarations,
+            compound_statement,
+        } = &ast[block]
+        else {
+            return;
+        };
+
+        let mut lowering = Lowering {
+            ast,
+            body: vec![Instruction::Entry(name.to_string())],
+            names: Names::default(),
+        };
+        for declaration in declarations {
+            if let ASTNode::VarDecl {
+                var_node,
+                initializer: Some(initializer),
+                ..
+            } = &ast[*declaration]
+            {
+                if let ASTNode::Var { name } = &ast[*var_node] {
+                    lowering.expression_into(*initializer, Operand::Var(name.clone()));
+                }
+            }
+        }
+        lowering.statement(*compound_statement);
+        lowering.body.push(Instruction::Return);
+        program.functions.push(Function {
+            name: name.to_string(),
+            params,
+            body: lowering.body,
+        });
+
+        for declaration in declarations {
+            match &ast[*declaration] {
+                ASTNode::ProcedureDecl {
+                    proc_name,
+                    params,
+                    block_node,
+                    ..
+                } => {
+                    let params = param_names(ast, params).collect();
+                    Lowering::function(ast, proc_name, params, *block_node, program);
+                }
+                ASTNode::MethodDecl {
+                    class_name,
+                    method_name,
+                    params,
+                    block_node,
+                    ..
+                } => {
+                    let name = format!("{}.{}", class_name, method_name);
+                    let params = std::iter::once("self".to_string())
+                        .chain(param_names(ast, params))
+                        .collect();
+                    Lowering::function(ast, &name, params, *block_node, program);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn temp(&mut self) -> Operand {
+        Operand::Temp(self.names.temp())
+    }
+
+    fn label(&mut self) -> Label {
+        self.names.label()
+    }
+
+    fn statement(&mut self, node: NodeId) {
+        match &self.ast[node] {
+            ASTNode::Compound { children } => {
+                for child in children {
+                    self.statement(*child);
+                }
+            }
+            ASTNode::Assign { left, right, .. } => match &self.ast[*left] {
+                ASTNode::Var { name } => {
+                    self.expression_into(*right, Operand::Var(name.clone()));
+                }
+                ASTNode::FieldAccess { object, field } => {
+                    let object = self.expression(*object);
+                    let src = self.expression(*right);
+                    self.body.push(Instruction::SetField {
+                        object,
+                        field: field.clone(),
+                        src,
+                    });
+                }
+                _ => {}
+            },
+            ASTNode::ProcedureCall {
+                proc_name,
+                arguments,
+                ..
+            } => {
+                let operands = arguments
+                    .iter()
+                    .map(|argument| self.expression(*argument))
+                    .collect::<Vec<_>>();
+                for operand in operands {
+                    self.body.push(Instruction::Param(operand));
+                }
+                self.body.push(Instruction::Call {
+                    dest: None,
+                    label: proc_name.clone(),
+                    arguments: arguments.len(),
+                });
+            }
+            ASTNode::MethodCall { .. } => self.method_call(node, None),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.expression(*condition);
+                let skip_then = self.label();
+                self.body.push(Instruction::IfFalse {
+                    condition,
+                    label: skip_then,
+                });
+                self.statement(*then_branch);
+                let Some(else_branch) = else_branch else {
+                    self.body.push(Instruction::Label(skip_then));
+                    return;
+                };
+                let end = self.label();
+                self.body.push(Instruction::Goto(end));
+                self.body.push(Instruction::Label(skip_then));
+                self.statement(*else_branch);
+                self.body.push(Instruction::Label(end));
+            }
+            ASTNode::While { condition, body } => {
+                let top = self.label();
+                self.body.push(Instruction::Label(top));
+                let condition = self.expression(*condition);
+                let end = self.label();
+                self.body.push(Instruction::IfFalse {
+                    condition,
+                    label: end,
+                });
+                self.statement(*body);
+                self.body.push(Instruction::Goto(top));
+                self.body.push(Instruction::Label(end));
+            }
+            ASTNode::Repeat { body, condition } => {
+                let top = self.label();
+                self.body.push(Instruction::Label(top));
+                for statement in body {
+                    self.statement(*statement);
+                }
+                let condition = self.expression(*condition);
+                self.body.push(Instruction::IfFalse {
+                    condition,
+                    label: top,
+                });
+            }
+            // Each arm tests its labels in turn: a match jumps to the arm's
+            // statement, and when the last one fails too, on to the next arm
+            ASTNode::Case {
+                selector,
+                arms,
+                else_branch,
+            } => {
+                let selector = self.expression(*selector);
+                let end = self.label();
+                for arm in arms {
+                    let ASTNode::CaseArm { labels, statement } = &self.ast[*arm] else {
+                        continue;
+                    };
+                    let body = self.label();
+                    let next = self.label();
+                    for (i, label) in labels.iter().enumerate() {
+                        if i + 1 == labels.len() {
+                            self.case_label(&selector, *label, next);
+                            break;
+                        }
+                        let miss = self.label();
+                        self.case_label(&selector, *label, miss);
+                        self.body.push(Instruction::Goto(body));
+                        self.body.push(Instruction::Label(miss));
+                    }
+                    self.body.push(Instruction::Label(body));
+                    self.statement(*statement);
+                    self.body.push(Instruction::Goto(end));
+                    self.body.push(Instruction::Label(next));
+                }
+                for statement in else_branch.iter().flatten() {
+                    self.statement(*statement);
+                }
+                self.body.push(Instruction::Label(end));
+            }
+            _ => {}
+        }
+    }
+
+    // Falls through if `selector` matches the case label, or else jumps to
+    // `miss`.
+    fn case_label(&mut self, selector: &Operand, label: NodeId, miss: Label) {
+        let Some((low, high)) = self.ast.case_label_bounds(label) else {
+            return;
+        };
+        let tests = match low == high {
+            true => vec![(Token::Equal, low)],
+            false => vec![(Token::GreaterEqual, low), (Token::LessEqual, high)],
+        };
+        for (op, bound) in tests {
+            let condition = self.temp();
+            self.body.push(Instruction::Binary {
+                dest: condition.clone(),
+                op,
+                left: selector.clone(),
+                right: Operand::Const(Value::Int(bound)),
+            });
+            self.body.push(Instruction::IfFalse {
+                condition,
+                label: miss,
+            });
+        }
+    }
+
+    // Passes the receiver and the arguments, then calls the method by name;
+    // which class it belongs to is only known at runtime.
+    fn method_call(&mut self, node: NodeId, dest: Option<Operand>) {
+        let ASTNode::MethodCall {
+            object,
+            method,
+            arguments,
+        } = &self.ast[node]
+        else {
+            return;
+        };
+        let receiver = self.expression(*object);
+        let operands = arguments
+            .iter()
+            .map(|argument| self.expression(*argument))
+            .collect::<Vec<_>>();
+        self.body.push(Instruction::Param(receiver));
+        for operand in operands {
+            self.body.push(Instruction::Param(operand));
+        }
+        self.body.push(Instruction::Call {
+            dest,
+            label: method.clone(),
+            arguments: arguments.len() + 1,
+        });
+    }
+
+    // The operand holding the expression's value, computing it into a new
+    // temporary unless it is a constant or a variable.
+    fn expression(&mut self, node: NodeId) -> Operand {
+        ensure_stack(|| match &self.ast[node] {
+            ASTNode::NumNode { value } => Operand::Const(value.clone()),
+            ASTNode::Var { name } => Operand::Var(name.clone()),
+            ASTNode::FieldAccess { object, field } => {
+                let object = self.expression(*object);
+                let dest = self.temp();
+                self.body.push(Instruction::Field {
+                    dest: dest.clone(),
+                    object,
+                    field: field.clone(),
+                });
+                dest
+            }
+            ASTNode::MethodCall { .. } => {
+                let dest = self.temp();
+                self.method_call(node, Some(dest.clone()));
+                dest
+            }
+            _ => self.operation(node, None),
+        })
+    }
+
+    // Computes the expression straight into `dest`, so `x := a + b` needs no
+    // temporary.
+    fn expression_into(&mut self, node: NodeId, dest: Operand) {
+        match &self.ast[node] {
+            ASTNode::UnaryOpNode { .. } | ASTNode::BinOpNode { .. } => {
+                self.operation(node, Some(dest));
+            }
+            _ => {
+                let src = self.expression(node);
+                self.body.push(Instruction::Copy { dest, src });
+            }
+        }
+    }
+
+    // Lowers the operands of an operator node, then the operator itself into
+    // `dest` or, failing that, the next temporary.
+    fn operation(&mut self, node: NodeId, dest: Option<Operand>) -> Operand {
+        ensure_stack(|| match &self.ast[node] {
+            ASTNode::UnaryOpNode { expr, token } => {
+                let src = self.expression(*expr);
+                let dest = dest.unwrap_or_else(|| self.temp());
+                self.body.push(Instruction::Unary {
+                    dest: dest.clone(),
+                    op: token.clone(),
+                    src,
+                });
+                dest
+            }
+            ASTNode::BinOpNode { left, right, op } => {
+                let left = self.expression(*left);
+                let right = self.expression(*right);
+                let dest = dest.unwrap_or_else(|| self.temp());
+                self.body.push(Instruction::Binary {
+                    dest: dest.clone(),
+                    op: op.clone(),
+                    left,
+                    right,
+                });
+                dest
+            }
+            _ => self.expression(node),
+        })
+    }
+}
+
+fn param_names<'a>(ast: &'a Ast, params: &'a [NodeId]) -> impl Iterator<Item = String> + 'a {
+    params.iter().filter_map(|param| match &ast[*param] {
+        ASTNode::Param { var_node, .. } => match &ast[*var_node] {
+            ASTNode::Var { name } => Some(name.clone()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Const(value) => write!(f, "{}", value),
+            Operand::Var(name) => write!(f, "{}", name),
+            Operand::Temp(temp) => write!(f, "{}", temp),
+        }
+    }
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%t{}", self.0)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%L{}", self.0)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Entry(name) => write!(f, "{}:", name),
+            Instruction::Label(label) => write!(f, "{}:", label),
+            Instruction::Goto(label) => write!(f, "    goto {}", label),
+            Instruction::IfFalse { condition, label } => {
+                write!(f, "    if_false {} goto {}", condition, label)
+            }
+            Instruction::Copy { dest, src } => write!(f, "    {} = {}", dest, src),
+            Instruction::Unary { dest, op, src } => write!(f, "    {} = {}{}", dest, op, src),
+            Instruction::Binary {
+                dest,
+                op,
+                left,
+                right,
+            } => write!(f, "    {} = {} {} {}", dest, left, op, right),
+            Instruction::Field {
+                dest,
+                object,
+                field,
+            } => write!(f, "    {} = {}.{}", dest, object, field),
+            Instruction::SetField { object, field, src } => {
+                write!(f, "    {}.{} = {}", object, field, src)
+            }
+            Instruction::Param(operand) => write!(f, "    param {}", operand),
+            Instruction::Call {
+                dest: None,
+                label,
+                arguments,
+            } => write!(f, "    call {}, {}", label, arguments),
+            Instruction::Call {
+                dest: Some(dest),
+                label,
+                arguments,
+            } => write!(f, "    {} = call {}, {}", dest, label, arguments),
+            Instruction::Return => write!(f, "    return"),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "; {}({})", self.name, self.params.join(", "))?;
+        for instruction in &self.body {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, InstBuilder, MemFlagsData, Signature, UserFuncName, Value,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::call_stack::{ARType, ActivationRecord};
+use crate::interpreter::{InterpretError, InterpretResult};
+use crate::token::Token;
+use crate::value::Value as RuntimeValue;
+use crate::visitor::ensure_stack;
+
+// Status codes returned by every compiled function.
+const STATUS_OK: i64 = 0;
+const STATUS_DIVISION_BY_ZERO: i64 = 1;
+
+#[derive(Debug, Clone)]
+pub enum JitError {
+    Unsupported { construct: String },
+    Codegen { message: String },
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JitError::Unsupported { construct } => {
+                write!(f, "JIT does not support {construct}")
+            }
+            JitError::Codegen { message } => write!(f, "JIT code generation failed: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for JitError {}
+
+pub type JitResult<T> = std::result::Result<T, JitError>;
+
+fn unsupported<T>(construct: impl Into<String>) -> JitResult<T> {
+    Err(JitError::Unsupported {
+        construct: construct.into(),
+    })
+}
+
+fn codegen_error(err: impl fmt::Display) -> JitError {
+    JitError::Codegen {
+        message: err.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Kind {
+    I32,
+    F32,
+}
+
+struct ProcedurePlan {
+    func_id: FuncId,
+    params: Vec<String>,
+    body: NodeId,
+}
+
+pub struct JitProgram {
+    module: JITModule,
+    entry: FuncId,
+    program_name: String,
+    globals: Vec<(String, Kind)>,
+}
+
+impl JitProgram {
+    pub fn run(&self) -> InterpretResult<ActivationRecord> {
+        let mut out = vec![0u32; self.globals.len()];
+        let code = self.module.get_finalized_function(self.entry);
+        // SAFETY: the entry function was compiled with exactly this signature and
+        // writes at most `globals.len()` 4-byte slots into `out`.
+        let entry =
+            unsafe { std::mem::transmute::<*const u8, extern "C" fn(*mut u32) -> i32>(code) };
+        match entry(out.as_mut_ptr()) as i64 {
+            STATUS_OK => {}
+            STATUS_DIVISION_BY_ZERO => return Err(InterpretError::DivisionByZero),
+            status => unreachable!("unknown JIT status code {status}"),
+        }
+
+        let mut ar = ActivationRecord::new(&self.program_name, ARType::Program, 1);
+        for ((name, kind), bits) in self.globals.iter().zip(out) {
+            let value = match kind {
+                Kind::I32 => RuntimeValue::Int(bits as i32),
+                Kind::F32 => RuntimeValue::Real(f32::from_bits(bits)),
+            };
+            ar.set(name, value);
+        }
+        Ok(ar)
+    }
+}
+
+pub fn compile(ast: &Ast) -> JitResult<JitProgram> {
+    let ASTNode::Program { name, block } = &ast[ast.root()] else {
+        return unsupported("a tree without a PROGRAM root");
+    };
+    let ASTNode::Block {
+        compound_statement, ..
+    } = &ast[*block]
+    else {
+        return unsupported("a program without a block");
+    };
+
+    let mut flag_builder = settings::builder();
+    flag_builder
+        .set("opt_level", "speed")
+        .map_err(codegen_error)?;
+    let isa = cranelift_native::builder()
+        .map_err(codegen_error)?
+        .finish(settings::Flags::new(flag_builder))
+        .map_err(codegen_error)?;
+    let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+    let mut compiler = Compiler {
+        ast,
+        module,
+        plans: vec![],
+        calls: HashMap::new(),
+        scopes: vec![],
+    };
+    compiler.collect_block(*block)?;
+
+    let plans = std::mem::take(&mut compiler.plans);
+    for plan in &plans {
+        compiler.define_procedure(plan)?;
+    }
+    let (entry, globals) = compiler.define_entry(*compound_statement)?;
+
+    compiler
+        .module
+        .finalize_definitions()
+        .map_err(codegen_error)?;
+
+    Ok(JitProgram {
+        module: compiler.module,
+        entry,
+        program_name: name.clone(),
+        globals,
+    })
+}
+
+struct Compiler<'a> {
+    ast: &'a Ast,
+    module: JITModule,
+    plans: Vec<ProcedurePlan>,
+    // Call sites are resolved up front, keyed by node id, so that
+    // shadowed procedure names bind exactly like the semantic analyzer binds them.
+    calls: HashMap<NodeId, FuncId>,
+    scopes: Vec<HashMap<String, FuncId>>,
+}
+
+impl<'a> Compiler<'a> {
+    fn procedure_signature(&self, param_count: usize) -> Signature {
+        let mut sig = self.module.make_signature();
+        for _ in 0..param_count {
+            sig.params.push(AbiParam::new(types::F32));
+        }
+        sig.returns.push(AbiParam::new(types::I32));
+        sig
+    }
+
+    fn collect_block(&mut self, block: NodeId) -> JitResult<()> {
+        let ast = self.ast;
+        let ASTNode::Block {
+            declarations,
+            compound_statement,
+        } = &ast[block]
+        else {
+            return unsupported(format!("'{}' in place of a block", ast.display(block)));
+        };
+
+        self.scopes.push(HashMap::new());
+        for declaration in declarations {
+            match &ast[*declaration] {
+                ASTNode::VarDecl {
+                    var_node,
+                    initializer: Some(_),
+                    ..
+                } => {
+                    return unsupported(format!("the initializer of '{}'", ast.display(*var_node)));
+                }
+                ASTNode::VarDecl { .. } => {}
+                ASTNode::ProcedureDecl {
+                    proc_name,
+                    params,
+                    block_node,
+                    ..
+                } => {
+                    let params = params
+                        .iter()
+                        .map(|param| match &ast[*param] {
+                            // VAR and OUT parameters would need the caller's
+                            // variable written back
+                            ASTNode::Param { var_node, mode, .. } if !mode.by_reference() => {
+                                match &ast[*var_node] {
+                                    ASTNode::Var { name } => Ok(name.clone()),
+                                    _ => {
+                                        unsupported(format!("parameter '{}'", ast.display(*param)))
+                                    }
+                                }
+                            }
+                            _ => unsupported(format!("parameter '{}'", ast.display(*param))),
+                        })
+                        .collect::<JitResult<Vec<_>>>()?;
+
+                    let sig = self.procedure_signature(params.len());
+                    let func_id = self
+                        .module
+                        .declare_function(
+                            &format!("proc{}_{}", self.plans.len(), proc_name),
+                            Linkage::Local,
+                            &sig,
+                        )
+                        .map_err(codegen_error)?;
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert(proc_name.clone(), func_id);
+                    }
+
+                    let ASTNode::Block {
+                        compound_statement: body,
+                        ..
+                    } = &ast[*block_node]
+                    else {
+                        return unsupported(format!("procedure '{proc_name}' without a block"));
+                    };
+                    self.plans.push(ProcedurePlan {
+                        func_id,
+                        params,
+                        body: *body,
+                    });
+                    self.collect_block(*block_node)?;
+                }
+                _ => return unsupported(format!("declaration '{}'", ast.display(*declaration))),
+            }
+        }
+        self.resolve_calls(*compound_statement)?;
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn resolve_calls(&mut self, statement: NodeId) -> JitResult<()> {
+        let ast = self.ast;
+        match &ast[statement] {
+            ASTNode::Compound { children } => {
+                for child in children {
+                    self.resolve_calls(*child)?;
+                }
+            }
+            ASTNode::ProcedureCall { proc_name, .. } => {
+                let Some(func_id) = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(proc_name))
+                else {
+                    return unsupported(format!("call to unknown procedure '{proc_name}'"));
+                };
+                self.calls.insert(statement, *func_id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn define_procedure(&mut self, plan: &ProcedurePlan) -> JitResult<()> {
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = self.procedure_signature(plan.params.len());
+        ctx.func.name = UserFuncName::user(0, plan.func_id.as_u32());
+
+        let mut builder_ctx = FunctionBuilderContext::new();
+        {
+            let builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+            let mut function =
+                FunctionTranslator::new(builder, &mut self.module, self.ast, &self.calls);
+            let entry = function
+                .builder
+                .current_block()
+                .ok_or_else(|| codegen_error("the function has no entry block"))?;
+            let args = function.builder.block_params(entry).to_vec();
+            for (name, arg) in plan.params.iter().zip(args) {
+                function.define(name, Kind::F32, arg);
+            }
+            function.statement(plan.body)?;
+            function.finish();
+        }
+
+        self.module
+            .define_function(plan.func_id, &mut ctx)
+            .map_err(codegen_error)?;
+        Ok(())
+    }
+
+    fn define_entry(
+        &mut self,
+        compound_statement: NodeId,
+    ) -> JitResult<(FuncId, Vec<(String, Kind)>)> {
+        let pointer_type = self.module.target_config().pointer_type();
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(pointer_type));
+        sig.returns.push(AbiParam::new(types::I32));
+        let func_id = self
+            .module
+            .declare_function("main", Linkage::Local, &sig)
+            .map_err(codegen_error)?;
+
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = sig;
+        ctx.func.name = UserFuncName::user(0, func_id.as_u32());
+
+        let mut builder_ctx = FunctionBuilderContext::new();
+        let globals = {
+            let builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+            let mut function =
+                FunctionTranslator::new(builder, &mut self.module, self.ast, &self.calls);
+            let entry = function
+                .builder
+                .current_block()
+                .ok_or_else(|| codegen_error("the function has no entry block"))?;
+            let out = function.builder.block_params(entry)[0];
+            function.statement(compound_statement)?;
+
+            // Copy the final program variables out so the caller can rebuild
+            // the program's activation record.
+            let mut globals: Vec<(String, Kind)> = function
+                .kinds
+                .iter()
+                .map(|(name, kind)| (name.clone(), *kind))
+                .collect();
+            globals.sort();
+            for (index, (name, _)) in globals.iter().enumerate() {
+                let value = function.use_var(name)?.0;
+                function.builder.ins().store(
+                    MemFlagsData::trusted(),
+                    value,
+                    out,
+                    (index * 4) as i32,
+                );
+            }
+            function.finish();
+            globals
+        };
+
+        self.module
+            .define_function(func_id, &mut ctx)
+            .map_err(codegen_error)?;
+        Ok((func_id, globals))
+    }
+}
+
+struct FunctionTranslator<'b> {
+    builder: FunctionBuilder<'b>,
+    module: &'b mut JITModule,
+    ast: &'b Ast,
+    calls: &'b HashMap<NodeId, FuncId>,
+    exit_block: Block,
+    variables: HashMap<(String, Kind), Variable>,
+    kinds: HashMap<String, Kind>,
+}
+
+impl<'b> FunctionTranslator<'b> {
+    fn new(
+        mut builder: FunctionBuilder<'b>,
+        module: &'b mut JITModule,
+        ast: &'b Ast,
+        calls: &'b HashMap<NodeId, FuncId>,
+    ) -> Self {
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+
+        let exit_block = builder.create_block();
+        builder.append_block_param(exit_block, types::I32);
+
+        FunctionTranslator {
+            builder,
+            module,
+            ast,
+            calls,
+            exit_block,
+            variables: HashMap::new(),
+            kinds: HashMap::new(),
+        }
+    }
+
+    fn finish(mut self) {
+        let ok = self.builder.ins().iconst(types::I32, STATUS_OK);
+        self.builder.ins().jump(self.exit_block, &[ok.into()]);
+
+        self.builder.switch_to_block(self.exit_block);
+        let status = self.builder.block_params(self.exit_block)[0];
+        self.builder.ins().return_(&[status]);
+
+        self.builder.seal_all_blocks();
+        let config = self.module.target_config();
+        self.builder.finalize(config);
+    }
+
+    fn define(&mut self, name: &str, kind: Kind, value: Value) {
+        let ty = match kind {
+            Kind::I32 => types::I32,
+            Kind::F32 => types::F32,
+        };
+        let builder = &mut self.builder;
+        let var = *self
+            .variables
+            .entry((name.to_string(), kind))
+            .or_insert_with(|| builder.declare_var(ty));
+        self.builder.def_var(var, value);
+        self.kinds.insert(name.to_string(), kind);
+    }
+
+    fn use_var(&mut self, name: &str) -> JitResult<(Value, Kind)> {
+        // Control flow is straight-line, so whether a variable holds a value
+        // (and of which kind) is known statically. Reads of unassigned
+        // variables are left to the interpreter, which reports them.
+        let Some(kind) = self.kinds.get(name).copied() else {
+            return unsupported(format!("reading '{name}' before it is assigned"));
+        };
+        let var = self.variables[&(name.to_string(), kind)];
+        Ok((self.builder.use_var(var), kind))
+    }
+
+    fn exit_if_nonzero(&mut self, status: Value) {
+        let continue_block = self.builder.create_block();
+        self.builder.ins().brif(
+            status,
+            self.exit_block,
+            &[status.into()],
+            continue_block,
+            &[],
+        );
+        self.builder.switch_to_block(continue_block);
+    }
+
+    fn statement(&mut self, node: NodeId) -> JitResult<()> {
+        let ast = self.ast;
+        match &ast[node] {
+            ASTNode::Compound { children } => {
+                for child in children {
+                    self.statement(*child)?;
+                }
+                Ok(())
+            }
+            ASTNode::NoOp => Ok(()),
+            ASTNode::Assign { left, right, .. } => {
+                let ASTNode::Var { name } = &ast[*left] else {
+                    return Err(JitError::Unsupported {
+                        construct: format!("assignment to '{}'", ast.display(*left)),
+                    });
+                };
+                let (value, kind) = self.expr(*right)?;
+                self.define(name, kind, value);
+                Ok(())
+            }
+            ASTNode::ProcedureCall {
+                proc_name,
+                arguments,
+                ..
+            } => {
+                let Some(func_id) = self.calls.get(&node).copied() else {
+                    return unsupported(format!("call to unknown procedure '{proc_name}'"));
+                };
+                let mut args = vec![];
+                for argument in arguments {
+                    let value = self.expr(*argument)?;
+                    args.push(self.coerce_to_f32(value));
+                }
+                let callee = self.module.declare_func_in_func(func_id, self.builder.func);
+                let call = self.builder.ins().call(callee, &args);
+                let status = self.builder.inst_results(call)[0];
+                self.exit_if_nonzero(status);
+                Ok(())
+            }
+            _ => unsupported(format!("statement '{}'", ast.display(node))),
+        }
+    }
+
+    fn coerce_to_f32(&mut self, (value, kind): (Value, Kind)) -> Value {
+        match kind {
+            Kind::F32 => value,
+            Kind::I32 => self.builder.ins().fcvt_from_sint(types::F32, value),
+        }
+    }
+
+    fn expr(&mut self, node: NodeId) -> JitResult<(Value, Kind)> {
+        ensure_stack(|| {
+            let ast = self.ast;
+            match &ast[node] {
+                ASTNode::NumNode {
+                    value: RuntimeValue::Int(v),
+                } => Ok((self.builder.ins().iconst(types::I32, *v as i64), Kind::I32)),
+                ASTNode::NumNode {
+                    value: RuntimeValue::Real(v),
+                } => Ok((self.builder.ins().f32const(*v), Kind::F32)),
+                ASTNode::Var { name } => self.use_var(name),
+                ASTNode::UnaryOpNode { expr, token } => {
+                    let (operand, kind) = self.expr(*expr)?;
+                    let value = match (token, kind) {
+                        (Token::Plus, _) => operand,
+                        (Token::Minus, Kind::I32) => self.builder.ins().ineg(operand),
+                        (Token::Minus, Kind::F32) => self.builder.ins().fneg(operand),
+                        _ => return unsupported(format!("unary operator '{token}'")),
+                    };
+                    Ok((value, kind))
+                }
+                ASTNode::BinOpNode { left, right, op } => {
+                    let left = self.expr(*left)?;
+                    let right = self.expr(*right)?;
+                    if *op == Token::IntegerDiv {
+                        return Ok((self.integer_div(left, right), Kind::I32));
+                    }
+                    // Like the interpreter, two INTEGERs give an INTEGER, wrapping
+                    // around on overflow; `/` always gives a REAL
+                    if left.1 == Kind::I32 && right.1 == Kind::I32 && *op != Token::FloatDiv {
+                        let ins = self.builder.ins();
+                        let value = match op {
+                            Token::Plus => ins.iadd(left.0, right.0),
+                            Token::Minus => ins.isub(left.0, right.0),
+                            Token::Asterisk => ins.imul(left.0, right.0),
+                            _ => return unsupported(format!("binary operator '{op}'")),
+                        };
+                        return Ok((value, Kind::I32));
+                    }
+                    let left = self.coerce_to_f32(left);
+                    let right = self.coerce_to_f32(right);
+                    let ins = self.builder.ins();
+                    let value = match op {
+                        Token::Plus => ins.fadd(left, right),
+                        Token::Minus => ins.fsub(left, right),
+                        Token::Asterisk => ins.fmul(left, right),
+                        Token::FloatDiv => ins.fdiv(left, right),
+                        _ => return unsupported(format!("binary operator '{op}'")),
+                    };
+                    Ok((value, Kind::F32))
+                }
+                _ => unsupported(format!("expression '{}'", ast.display(node))),
+            }
+        })
+    }
+
+    fn coerce_to_i32(&mut self, (value, kind): (Value, Kind)) -> Value {
+        match kind {
+            Kind::I32 => value,
+            Kind::F32 => self.builder.ins().fcvt_to_sint_sat(types::I32, value),
+        }
+    }
+
+    // Mirrors the interpreter: truncate REAL sides with `as i32` semantics,
+    // report division by zero and wrap on `i32::MIN div -1`.
+    fn integer_div(&mut self, left: (Value, Kind), right: (Value, Kind)) -> Value {
+        let dividend = self.coerce_to_i32(left);
+        let divisor = self.coerce_to_i32(right);
+
+        let is_zero = self.builder.ins().icmp_imm_s(IntCC::Equal, divisor, 0);
+        let status = self.builder.ins().uextend(types::I32, is_zero);
+        let division_by_zero = self
+            .builder
+            .ins()
+            .imul_imm_s(status, STATUS_DIVISION_BY_ZERO);
+        self.exit_if_nonzero(division_by_zero);
+
+        let is_minus_one = self.builder.ins().icmp_imm_s(IntCC::Equal, divisor, -1);
+        let one = self.builder.ins().iconst(types::I32, 1);
+        let safe_divisor = self.builder.ins().select(is_minus_one, one, divisor);
+        let quotient = self.builder.ins().sdiv(dividend, safe_divisor);
+        let negated = self.builder.ins().ineg(dividend);
+        self.builder.ins().select(is_minus_one, negated, quotient)
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use crate::dialect::Dialect;
+use crate::lexer::{is_identifier_char, is_identifier_start};
+use crate::token::{Token, RESERVER_KEYWORDS};
+
+// The words the lexer reads as keywords rather than identifiers. Each
+// dialect has its own set; an embedder can start from one, spell existing
+// keywords differently (`fin` for END) or free a keyword up for use as an
+// identifier. The parser only sees tokens, so a word added here works
+// wherever its keyword does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keywords {
+    words: HashMap<String, Token>,
+}
+
+impl Keywords {
+    pub fn for_dialect(dialect: Dialect) -> Self {
+        let words = RESERVER_KEYWORDS
+            .entries()
+            .filter(|(_, token)| reserved_in(dialect, token))
+            .map(|(word, token)| (word.to_string(), token.clone()))
+            .collect();
+        Keywords { words }
+    }
+
+    // The keyword `word` is in `dialect`, without building the whole set.
+    pub fn lookup(dialect: Dialect, word: &str) -> Option<&'static Token> {
+        RESERVER_KEYWORDS
+            .get(word)
+            .filter(|token| reserved_in(dialect, token))
+    }
+
+    pub fn get(&self, word: &str) -> Option<&Token> {
+        self.words.get(word)
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = (&str, &Token)> {
+        self.words
+            .iter()
+            .map(|(word, token)| (word.as_str(), token))
+    }
+
+    // Reads `word`, in any letter case, as `token`, which has to be one of
+    // the keyword tokens.
+    pub fn add(&mut self, word: &str, token: Token) -> Result<(), String> {
+        let mut chars = word.chars();
+        if !chars.next().is_some_and(is_identifier_start) || !chars.all(is_identifier_char) {
+            return Err(format!("'{word}' is not a valid keyword"));
+        }
+        if !RESERVER_KEYWORDS.values().any(|keyword| *keyword == token) {
+            return Err(format!("{token} is not a keyword token"));
+        }
+        self.words.insert(word.to_ascii_lowercase(), token);
+        Ok(())
+    }
+
+    // Makes `word` an ordinary identifier again.
+    pub fn remove(&mut self, word: &str) -> Option<Token> {
+        self.words.remove(&word.to_ascii_lowercase())
+    }
+}
+
+// Classes and everything that goes with them are only keywords where the
+// dialect has classes, so ISO and Turbo Pascal programs may use the words
+// as names.
+fn reserved_in(dialect: Dialect, token: &Token) -> bool {
+    match token {
+        Token::Class | Token::Constructor | Token::Destructor | Token::Property => {
+            dialect.classes()
+        }
+        _ => true,
+    }
+}
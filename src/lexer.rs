@@ -1,7 +1,11 @@
-use crate::token::{LocatedToken, Token, RESERVER_KEYWORDS};
+use crate::dialect::Dialect;
+use crate::keywords::Keywords;
+use crate::token::{LocatedToken, Location, Span, Token, Trivia, TriviaKind};
+use crate::value::parse_real;
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fmt;
-use std::iter::Peekable;
-use std::str::Chars;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub struct LexerError {
@@ -26,28 +30,90 @@ impl fmt::Display for LexerError {
 impl std::error::Error for LexerError {}
 
 pub struct Lexer<'a> {
-    chars: Peekable<Chars<'a>>,
     input: &'a str,
     pos: usize,
     line: usize,
     column: usize,
-    lookahead: Option<LocatedToken>,
+    // Tokens read ahead of the consumer, oldest first
+    lookahead: VecDeque<LocatedToken>,
+    elapsed: Duration,
+    // Set once iteration has yielded `Eof` or an error
+    finished: bool,
+    keep_trivia: bool,
+    dialect: Dialect,
+    // Replaces the dialect's keywords
+    keywords: Option<Keywords>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(text: &'a str) -> Self {
         Lexer {
-            chars: text.chars().peekable(),
             input: text,
             pos: 0,
             line: 1,
             column: 1,
-            lookahead: None,
+            lookahead: VecDeque::new(),
+            elapsed: Duration::ZERO,
+            finished: false,
+            keep_trivia: false,
+            dialect: Dialect::default(),
+            keywords: None,
+        }
+    }
+
+    // A lexer that records comments and whitespace on the tokens as
+    // leading/trailing trivia, for tools that need to reproduce the source.
+    pub fn with_trivia(text: &'a str) -> Self {
+        Lexer {
+            keep_trivia: true,
+            ..Lexer::new(text)
+        }
+    }
+
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    // Reads `keywords` as the keywords instead of those of the dialect.
+    pub fn with_keywords(mut self, keywords: Keywords) -> Self {
+        self.keywords = Some(keywords);
+        self
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    // Starts lexing at byte offset `pos` of the text, which is at
+    // `location`. Spans and positions stay relative to the whole text.
+    pub fn starting_at(mut self, pos: usize, location: Location) -> Self {
+        self.pos = pos;
+        self.line = location.line;
+        self.column = location.column;
+        self
+    }
+
+    // Time spent producing tokens so far.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.input
+    }
+
+    // Nearly all Pascal source is ASCII, which needs no UTF-8 decoding.
+    fn peek(&self) -> Option<char> {
+        match self.input.as_bytes().get(self.pos) {
+            Some(&byte) if byte.is_ascii() => Some(char::from(byte)),
+            Some(_) => self.input[self.pos..].chars().next(),
+            None => None,
         }
     }
 
     fn consume(&mut self) -> Option<char> {
-        let ch = self.chars.next();
+        let ch = self.peek();
         if let Some(ch) = ch {
             self.pos += ch.len_utf8();
             if ch == '\n' {
@@ -60,137 +126,197 @@ impl<'a> Lexer<'a> {
         ch
     }
 
-    fn snippet_at(&self, pos: usize) -> String {
-        let start = self.input[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
-        let end = self.input[pos..]
-            .find('\n')
-            .map(|i| pos + i)
-            .unwrap_or(self.input.len());
-        self.input[start..end].to_string()
+    fn consume_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(&predicate) {
+            self.consume();
+        }
+        &self.input[start..self.pos]
     }
 
-    fn get_snippet(&self) -> String {
-        self.snippet_at(self.pos)
+    fn error(&self, message: String) -> LexerError {
+        LexerError {
+            message,
+            line: self.line,
+            column: self.column,
+            snippet: Span::new(self.pos, self.pos)
+                .snippet(self.input)
+                .to_string(),
+        }
     }
 
     fn number(&mut self) -> Result<Token, LexerError> {
-        let mut number_str = String::new();
-
-        while let Some(&ch) = self.chars.peek() {
-            if ch.is_ascii_digit() {
-                number_str.push(ch);
-                self.consume();
-            } else {
-                break;
-            }
-        }
-
-        if number_str.is_empty() {
-            return Err(LexerError {
-                message: "Expected integer but found none".to_string(),
-                line: self.line,
-                column: self.column,
-                snippet: self.get_snippet(),
-            });
+        let start = self.pos;
+        let (line, column) = (self.line, self.column);
+        if self.consume_while(|ch| ch.is_ascii_digit()).is_empty() {
+            return Err(self.error("Expected integer but found none".to_string()));
         }
 
-        if let Some('.') = self.chars.peek() {
-            number_str.push('.');
+        // `1..5` is a range, not the real `1.` followed by `.5`
+        if self.peek() == Some('.') && !self.input[self.pos..].starts_with("..") {
             self.consume();
+            self.consume_while(|ch| ch.is_ascii_digit());
+            self.reject_letters_after_number(start, line, column)?;
 
-            while let Some(&ch) = self.chars.peek() {
-                if ch.is_ascii_digit() {
-                    number_str.push(ch);
-                    self.consume();
-                } else {
-                    break;
-                }
-            }
-
-            let float_val = number_str.parse::<f32>().map_err(|e| LexerError {
-                message: format!("Parse error: {}", e),
-                line: self.line,
-                column: self.column,
-                snippet: self.get_snippet(),
-            })?;
+            let text = &self.input[start..self.pos];
+            let float_val = parse_real(text)
+                .ok_or_else(|| self.error(format!("Invalid real literal '{}'", text)))?;
             return Ok(Token::RealConst(float_val));
         }
 
-        let int_val = number_str.parse::<i32>().map_err(|e| LexerError {
-            message: format!("Parse error: {}", e),
-            line: self.line,
-            column: self.column,
-            snippet: self.get_snippet(),
-        })?;
+        self.reject_letters_after_number(start, line, column)?;
+        let int_val = self.input[start..self.pos]
+            .parse::<i32>()
+            .map_err(|e| self.error(format!("Parse error: {}", e)))?;
         Ok(Token::IntegerConst(int_val))
     }
 
-    fn skip_whitespace(&mut self) {
-        while let Some(&ch) = self.chars.peek() {
-            if ch.is_whitespace() {
-                self.consume();
-            } else {
-                break;
-            }
+    // `123abc` is neither a number nor an identifier, so rather than lexing
+    // it as two tokens and leaving the parser to complain about the second,
+    // the whole word is reported where it starts.
+    fn reject_letters_after_number(
+        &mut self,
+        start: usize,
+        line: usize,
+        column: usize,
+    ) -> Result<(), LexerError> {
+        if !self.peek().is_some_and(is_identifier_char) {
+            return Ok(());
         }
+        self.consume_while(is_identifier_char);
+        let text = &self.input[start..self.pos];
+        Err(LexerError {
+            message: format!(
+                "Invalid number '{}': identifiers must start with a letter or underscore",
+                text
+            ),
+            line,
+            column,
+            snippet: Span::new(start, start).snippet(self.input).to_string(),
+        })
     }
 
     fn skip_comment(&mut self) {
+        let mut depth = 1;
         while let Some(ch) = self.consume() {
-            if ch == '}' {
+            match ch {
+                '{' if self.dialect.nested_comments() => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Skips whitespace and comments, recording them when trivia is kept.
+    // With `to_end_of_line` it stops after the first newline, which is how
+    // a token's trailing trivia is delimited.
+    fn trivia(&mut self, to_end_of_line: bool) -> Vec<Trivia> {
+        let mut trivia = vec![];
+        loop {
+            let start = self.pos;
+            let kind = match self.peek() {
+                Some('\n') => {
+                    self.consume();
+                    TriviaKind::Newline
+                }
+                Some(ch) if ch.is_whitespace() => {
+                    self.consume_while(|ch| ch.is_whitespace() && ch != '\n');
+                    TriviaKind::Whitespace
+                }
+                Some('{') => {
+                    self.consume();
+                    self.skip_comment();
+                    TriviaKind::Comment
+                }
+                Some('/')
+                    if self.dialect.line_comments() && self.input[self.pos..].starts_with("//") =>
+                {
+                    self.consume_while(|ch| ch != '\n');
+                    TriviaKind::Comment
+                }
+                _ => break,
+            };
+            if self.keep_trivia {
+                trivia.push(Trivia {
+                    kind,
+                    span: Span::new(start, self.pos),
+                });
+            }
+            if to_end_of_line && kind == TriviaKind::Newline {
                 break;
             }
         }
+        trivia
     }
 
     fn _id(&mut self) -> Result<Token, LexerError> {
-        let mut result = String::new();
-        while self.chars.peek().map_or(false, |c| c.is_alphanumeric()) {
-            result.push(self.consume().unwrap().to_ascii_lowercase());
-        }
+        let text = self.consume_while(is_identifier_char);
 
-        let v = RESERVER_KEYWORDS
-            .get(&result)
-            .map_or(Token::Id(result), |v| v.clone());
+        // Identifiers are case-insensitive; only allocate a lowered copy when
+        // the source spelling actually has uppercase letters in it.
+        let name = if text.bytes().any(|b| b.is_ascii_uppercase()) {
+            Cow::Owned(text.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(text)
+        };
+
+        let keyword = match &self.keywords {
+            Some(keywords) => keywords.get(&name),
+            None => Keywords::lookup(self.dialect, &name),
+        };
+        let v = keyword.map_or_else(|| Token::Id(name.into_owned()), |v| v.clone());
         Ok(v)
     }
 
     pub fn next_token(&mut self) -> Result<LocatedToken, LexerError> {
-        if let Some(token) = self.lookahead.take() {
+        if let Some(token) = self.lookahead.pop_front() {
             return Ok(token);
         }
-        self.read_token()
+        self.timed_read_token()
     }
 
-    pub fn peek_token(&mut self) -> Result<LocatedToken, LexerError> {
-        if self.lookahead.is_none() {
-            let token = self.read_token()?;
-            self.lookahead = Some(token);
+    pub fn peek_token(&mut self) -> Result<&LocatedToken, LexerError> {
+        self.peek_nth(0)
+    }
+
+    // The token `k` positions ahead of the next one `next_token` returns;
+    // `peek_nth(0)` is that next token. Past the end of input this keeps
+    // returning `Eof`.
+    pub fn peek_nth(&mut self, k: usize) -> Result<&LocatedToken, LexerError> {
+        while self.lookahead.len() <= k {
+            let token = self.timed_read_token()?;
+            self.lookahead.push_back(token);
         }
+        Ok(&self.lookahead[k])
+    }
 
-        Ok(self.lookahead.as_ref().unwrap().clone())
+    fn timed_read_token(&mut self) -> Result<LocatedToken, LexerError> {
+        let start = Instant::now();
+        let token = self.read_token();
+        self.elapsed += start.elapsed();
+        token
     }
 
     fn read_token(&mut self) -> Result<LocatedToken, LexerError> {
-        self.skip_whitespace();
+        let leading = self.trivia(false);
 
         let start_line = self.line;
         let start_column = self.column;
         let start_pos = self.pos;
 
-        let token = match self.chars.peek().copied() {
+        let token = match self.peek() {
             None => Token::Eof,
             Some(ch) if ch.is_ascii_digit() => self.number()?,
-            Some(ch) if ch.is_alphanumeric() => self._id()?,
-            Some('{') => {
+            Some(ch) if is_identifier_start(ch) => self._id()?,
+            Some(c) => {
                 self.consume();
-                self.skip_comment();
-                return self.next_token();
-            }
-            _ => {
-                let c = self.consume().unwrap();
                 match c {
-                    ':' if self.chars.peek() == Some(&'=') => {
+                    ':' if self.peek() == Some('=') => {
                         self.consume();
                         Token::Assign
                     }
@@ -200,27 +326,178 @@ impl<'a> Lexer<'a> {
                     '/' => Token::FloatDiv,
                     '(' => Token::LParenthesis,
                     ')' => Token::RParenthesis,
+                    '.' if self.peek() == Some('.') => {
+                        self.consume();
+                        Token::Range
+                    }
                     '.' => Token::Dot,
                     ';' => Token::Semi,
                     ':' => Token::Colon,
                     ',' => Token::Comma,
-                    _ => {
-                        return Err(LexerError {
-                            message: format!("Unexpected character '{}'", c),
-                            line: self.line,
-                            column: self.column,
-                            snippet: self.get_snippet(),
-                        })
+                    '=' => Token::Equal,
+                    '<' if self.peek() == Some('>') => {
+                        self.consume();
+                        Token::NotEqual
+                    }
+                    '<' if self.peek() == Some('=') => {
+                        self.consume();
+                        Token::LessEqual
+                    }
+                    '>' if self.peek() == Some('=') => {
+                        self.consume();
+                        Token::GreaterEqual
                     }
+                    '<' => Token::LessThan,
+                    '>' => Token::GreaterThan,
+                    _ => return Err(self.error(format!("Unexpected character '{}'", c))),
                 }
             }
         };
 
-        Ok(LocatedToken::new(
+        let mut located = LocatedToken::new(
             token,
             start_line,
             start_column,
-            self.snippet_at(start_pos),
-        ))
+            Span::new(start_pos, self.pos),
+        );
+        if self.keep_trivia {
+            located.leading = leading;
+            located.trailing = self.trivia(true);
+        }
+        Ok(located)
+    }
+}
+
+// Yields every token up to and including `Eof`, then stops. A lexing error
+// is yielded once and also ends the iteration.
+impl Iterator for Lexer<'_> {
+    type Item = Result<LocatedToken, LexerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_token();
+        self.finished = match &token {
+            Ok(located) => located.token == Token::Eof,
+            Err(_) => true,
+        };
+        Some(token)
+    }
+}
+
+// Identifiers start with a letter or an underscore and go on with letters,
+// digits and underscores. Anything starting with a digit is a number.
+pub fn is_identifier_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+pub fn is_identifier_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+pub fn tokenize(text: &str) -> Result<Vec<LocatedToken>, LexerError> {
+    Lexer::new(text).collect()
+}
+
+// Anything the parser can pull tokens from.
+pub trait TokenSource {
+    fn next_token(&mut self) -> Result<LocatedToken, LexerError>;
+
+    // See `Lexer::peek_nth`.
+    fn peek_nth(&mut self, k: usize) -> Result<&LocatedToken, LexerError>;
+
+    // The text the tokens' spans point into, used for diagnostics.
+    fn source(&self) -> &str;
+
+    // Time spent producing tokens, for sources that lex on demand.
+    fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    // Whether tokens carry trivia, in which case the parser keeps them all
+    // in the AST.
+    fn keeps_trivia(&self) -> bool {
+        false
+    }
+
+    // Which language features the parser accepts.
+    fn dialect(&self) -> Dialect {
+        Dialect::default()
+    }
+}
+
+impl TokenSource for Lexer<'_> {
+    fn next_token(&mut self) -> Result<LocatedToken, LexerError> {
+        Lexer::next_token(self)
+    }
+
+    fn peek_nth(&mut self, k: usize) -> Result<&LocatedToken, LexerError> {
+        Lexer::peek_nth(self, k)
+    }
+
+    fn source(&self) -> &str {
+        self.input
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    fn keeps_trivia(&self) -> bool {
+        self.keep_trivia
+    }
+
+    fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+}
+
+// An already-lexed token list, e.g. from `tokenize`, as a token source.
+pub struct TokenBuffer<'a> {
+    source: &'a str,
+    tokens: Vec<LocatedToken>,
+    pos: usize,
+}
+
+impl<'a> TokenBuffer<'a> {
+    pub fn new(source: &'a str, mut tokens: Vec<LocatedToken>) -> Self {
+        if !matches!(tokens.last(), Some(t) if t.token == Token::Eof) {
+            let line = source.matches('\n').count() + 1;
+            let column = source.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+            tokens.push(LocatedToken::new(
+                Token::Eof,
+                line,
+                column,
+                Span::new(source.len(), source.len()),
+            ));
+        }
+        TokenBuffer {
+            source,
+            tokens,
+            pos: 0,
+        }
+    }
+}
+
+impl TokenSource for TokenBuffer<'_> {
+    fn next_token(&mut self) -> Result<LocatedToken, LexerError> {
+        let token = self.tokens[self.pos.min(self.tokens.len() - 1)].clone();
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn peek_nth(&mut self, k: usize) -> Result<&LocatedToken, LexerError> {
+        Ok(&self.tokens[(self.pos + k).min(self.tokens.len() - 1)])
+    }
+
+    fn source(&self) -> &str {
+        self.source
+    }
+
+    fn keeps_trivia(&self) -> bool {
+        self.tokens
+            .iter()
+            .any(|t| !t.leading.is_empty() || !t.trailing.is_empty())
     }
 }
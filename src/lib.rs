@@ -0,0 +1,53 @@
+pub mod artifacts;
+pub mod ast;
+pub mod bench;
+pub mod breakpoints;
+pub mod budget;
+pub mod builtins;
+pub mod call_stack;
+pub mod check;
+pub mod coverage;
+pub mod cst;
+pub mod debugger;
+pub mod dialect;
+#[cfg(feature = "fpc")]
+pub mod differential;
+pub mod directives;
+pub mod driver;
+pub mod dump;
+pub mod explain;
+pub mod formatter;
+pub mod harness;
+pub mod heap;
+pub mod hir;
+pub mod incremental;
+pub mod interpreter;
+pub mod ir;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod keywords;
+pub mod lexer;
+pub mod linter;
+pub mod lsp;
+pub mod manifest;
+pub mod metrics;
+pub mod obfuscator;
+pub mod optimizer;
+pub mod parser;
+pub mod passes;
+pub mod postfix;
+pub mod profiler;
+pub mod replay;
+pub mod semantic_analyzer;
+pub mod source;
+pub mod stats;
+pub mod symbols;
+pub mod token;
+pub mod trace;
+#[cfg(feature = "turtle")]
+pub mod turtle;
+pub mod value;
+pub mod visitor;
+pub mod visualizer;
+pub mod wasm;
+pub mod xref;
@@ -0,0 +1,377 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::token::{Location, Token};
+use crate::value::Value;
+use crate::visitor::ensure_stack;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Rule {
+    // Procedure and variable names follow the configured casing
+    Naming,
+    // Numbers in expressions other than the allowed ones
+    MagicNumber,
+    // Statements nested more deeply than allowed
+    DeepNesting,
+    // A stray `;` that makes an empty statement
+    EmptyStatement,
+    // Variables, parameters and procedures with single-letter names. Loop
+    // counters are the usual exception, but the language has no FOR loops yet.
+    ShortName,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 5] = [
+        Rule::Naming,
+        Rule::MagicNumber,
+        Rule::DeepNesting,
+        Rule::EmptyStatement,
+        Rule::ShortName,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::Naming => "naming",
+            Rule::MagicNumber => "magic-number",
+            Rule::DeepNesting => "deep-nesting",
+            Rule::EmptyStatement => "empty-statement",
+            Rule::ShortName => "short-name",
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.name() == s)
+            .ok_or_else(|| format!("Unknown lint rule '{s}'"))
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    // The rule is off
+    Allow,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Allow => write!(f, "allow"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamingStyle {
+    // `SumOfSquares`
+    Pascal,
+    // `sumOfSquares`
+    Camel,
+    // `sumofsquares`
+    Lower,
+    // `SUMOFSQUARES`
+    Upper,
+}
+
+impl NamingStyle {
+    fn accepts(self, name: &str) -> bool {
+        let Some(first) = name.chars().next() else {
+            return true;
+        };
+        match self {
+            NamingStyle::Pascal => !first.is_lowercase(),
+            NamingStyle::Camel => !first.is_uppercase(),
+            NamingStyle::Lower => !name.chars().any(char::is_uppercase),
+            NamingStyle::Upper => !name.chars().any(char::is_lowercase),
+        }
+    }
+}
+
+impl FromStr for NamingStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pascal" => Ok(NamingStyle::Pascal),
+            "camel" => Ok(NamingStyle::Camel),
+            "lower" => Ok(NamingStyle::Lower),
+            "upper" => Ok(NamingStyle::Upper),
+            _ => Err(format!("Unknown naming style '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for NamingStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NamingStyle::Pascal => write!(f, "PascalCase"),
+            NamingStyle::Camel => write!(f, "camelCase"),
+            NamingStyle::Lower => write!(f, "lowercase"),
+            NamingStyle::Upper => write!(f, "UPPERCASE"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintConfig {
+    severities: [Severity; Rule::ALL.len()],
+    // Also used for the program name
+    pub procedure_naming: NamingStyle,
+    pub variable_naming: NamingStyle,
+    // How many procedures and BEGIN blocks may enclose a statement
+    pub max_nesting: usize,
+    pub allowed_numbers: Vec<f32>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            severities: [Severity::Warning; Rule::ALL.len()],
+            procedure_naming: NamingStyle::Pascal,
+            variable_naming: NamingStyle::Camel,
+            max_nesting: 4,
+            allowed_numbers: vec![0.0, 1.0],
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn with_severity(mut self, rule: Rule, severity: Severity) -> Self {
+        self.set_severity(rule, severity);
+        self
+    }
+
+    pub fn set_severity(&mut self, rule: Rule, severity: Severity) {
+        self.severities[rule as usize] = severity;
+    }
+
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.severities[rule as usize]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub location: Location,
+    pub message: String,
+}
+
+// `line:column: severity: message [rule]`, the usual compiler format minus
+// the file name.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {} [{}]",
+            self.location.line, self.location.column, self.severity, self.message, self.rule
+        )
+    }
+}
+
+// Checks a parsed program against the enabled rules, returning diagnostics
+// in source order. Names are checked as spelled in the source, which needs
+// an AST parsed with trivia; otherwise they are seen lowercased.
+pub fn lint(ast: &Ast, source: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        ast,
+        source,
+        config,
+        diagnostics: vec![],
+    };
+    linter.visit(ast.root(), 0);
+    linter
+        .diagnostics
+        .sort_by_key(|diagnostic| diagnostic.location);
+    linter.diagnostics
+}
+
+struct Linter<'a> {
+    ast: &'a Ast,
+    source: &'a str,
+    config: &'a LintConfig,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: Rule, node: NodeId, message: String) {
+        let severity = self.config.severity(rule);
+        if severity == Severity::Allow {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            rule,
+            severity,
+            location: self.ast.location(node),
+            message,
+        });
+    }
+
+    // `depth` counts the procedures and BEGIN blocks around `node`.
+    fn visit(&mut self, node: NodeId, depth: usize) {
+        ensure_stack(|| {
+            let ast = self.ast;
+            let depth = match &ast[node] {
+                ASTNode::Program { name, .. } => {
+                    self.check_name(node, name, "Program", self.config.procedure_naming);
+                    depth
+                }
+                ASTNode::ProcedureDecl { proc_name, .. } => {
+                    self.check_name(node, proc_name, "Procedure", self.config.procedure_naming);
+                    self.check_length(node, proc_name, "Procedure");
+                    depth + 1
+                }
+                ASTNode::MethodDecl { method_name, .. } => {
+                    self.check_name(node, method_name, "Method", self.config.procedure_naming);
+                    self.check_length(node, method_name, "Method");
+                    depth + 1
+                }
+                ASTNode::VarDecl { var_node, .. } | ASTNode::Param { var_node, .. } => {
+                    if let ASTNode::Var { name } = &ast[*var_node] {
+                        let kind = match &ast[node] {
+                            ASTNode::Param { .. } => "Parameter",
+                            _ => "Variable",
+                        };
+                        self.check_name(*var_node, name, kind, self.config.variable_naming);
+                        self.check_length(*var_node, name, kind);
+                    }
+                    // Numbers in an initializer have the variable as their
+                    // name, so they aren't magic
+                    return;
+                }
+                ASTNode::Compound { children } => {
+                    self.check_empty_statements(ast, children);
+                    depth + 1
+                }
+                ASTNode::Repeat { body, .. }
+                | ASTNode::Case {
+                    else_branch: Some(body),
+                    ..
+                } => {
+                    self.check_empty_statements(ast, body);
+                    depth
+                }
+                // Case labels can't be named, so they aren't magic
+                ASTNode::CaseArm { statement, .. } => {
+                    self.visit(*statement, depth);
+                    return;
+                }
+                ASTNode::Assign { right, .. } => {
+                    // Assigning a bare number is how a program names it
+                    if matches!(ast[*right], ASTNode::NumNode { .. }) {
+                        return;
+                    }
+                    depth
+                }
+                ASTNode::NumNode { value } => {
+                    self.check_number(node, value);
+                    depth
+                }
+                _ => depth,
+            };
+
+            if depth > self.config.max_nesting
+                && matches!(
+                    ast[node],
+                    ASTNode::ProcedureDecl { .. }
+                        | ASTNode::MethodDecl { .. }
+                        | ASTNode::Compound { .. }
+                )
+            {
+                self.report(
+                    Rule::DeepNesting,
+                    node,
+                    format!(
+                        "Nested {} levels deep, more than the allowed {}",
+                        depth, self.config.max_nesting
+                    ),
+                );
+                // One report per too-deep subtree is enough
+                return;
+            }
+            for child in ast.children(node) {
+                self.visit(child, depth);
+            }
+        })
+    }
+
+    // The name as written in the source, when the AST has its tokens.
+    fn spelling(&self, node: NodeId, name: &str) -> String {
+        let ast = self.ast;
+        ast.token_range(node)
+            .and_then(|range| {
+                ast.tokens()[range]
+                    .iter()
+                    .find(|token| matches!(&token.token, Token::Id(id) if id == name))
+            })
+            .map_or_else(
+                || name.to_string(),
+                |token| self.source[token.span.start..token.span.end].to_string(),
+            )
+    }
+
+    fn check_name(&mut self, node: NodeId, name: &str, kind: &str, style: NamingStyle) {
+        let spelling = self.spelling(node, name);
+        if !style.accepts(&spelling) {
+            self.report(
+                Rule::Naming,
+                node,
+                format!("{kind} name '{spelling}' is not {style}"),
+            );
+        }
+    }
+
+    fn check_length(&mut self, node: NodeId, name: &str, kind: &str) {
+        if name.chars().count() == 1 {
+            let spelling = self.spelling(node, name);
+            self.report(
+                Rule::ShortName,
+                node,
+                format!("{kind} name '{spelling}' is a single letter; use a descriptive name"),
+            );
+        }
+    }
+
+    // A `;` at the end of a statement list is allowed, so only empty
+    // statements before the last one are reported.
+    fn check_empty_statements(&mut self, ast: &Ast, statements: &[NodeId]) {
+        for (index, statement) in statements.iter().enumerate() {
+            if matches!(ast[*statement], ASTNode::NoOp) && index + 1 < statements.len() {
+                self.report(
+                    Rule::EmptyStatement,
+                    *statement,
+                    "Empty statement; remove the extra ';'".to_string(),
+                );
+            }
+        }
+    }
+
+    fn check_number(&mut self, node: NodeId, value: &Value) {
+        let Some(number) = value.as_real() else {
+            return;
+        };
+        if !self.config.allowed_numbers.contains(&number) {
+            self.report(
+                Rule::MagicNumber,
+                node,
+                format!("Magic number {value}; assign it to a descriptively named variable"),
+            );
+        }
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value as Json};
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::check::{Diagnostic, Suggestion};
+use crate::dialect::Dialect;
+use crate::driver::{Driver, StageError};
+use crate::semantic_analyzer::CrossReferences;
+use crate::symbols::SymbolKind;
+use crate::token::{Location, Token};
+
+// LSP error code for requests the server does not implement.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// Serves the Language Server Protocol over `input` and `output` until the
+// client sends `exit` or closes the stream. Documents are synced in full;
+// every change re-parses and re-analyzes the document and publishes its
+// diagnostics.
+pub fn run(input: impl BufRead, output: impl Write, dialect: Dialect) -> io::Result<()> {
+    let mut server = Server {
+        input,
+        output,
+        dialect,
+        documents: HashMap::new(),
+    };
+    while let Some(message) = server.read_message()? {
+        if message["method"] == "exit" {
+            break;
+        }
+        server.handle(&message)?;
+    }
+    Ok(())
+}
+
+struct Server<R, W> {
+    input: R,
+    output: W,
+    dialect: Dialect,
+    documents: HashMap<String, Analysis>,
+}
+
+// What the parser and analyzer made of a document.
+struct Analysis {
+    ast: Option<Ast>,
+    cross_references: CrossReferences,
+    diagnostics: Vec<Json>,
+    // Suggested edits with the diagnostic each one fixes
+    fixes: Vec<(Json, Suggestion)>,
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    fn read_message(&mut self) -> io::Result<Option<Json>> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(length) = length else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message without Content-Length",
+            ));
+        };
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body)?;
+        serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+
+    fn handle(&mut self, message: &Json) -> io::Result<()> {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "codeActionProvider": true,
+                },
+                "serverInfo": { "name": "simple-interpreter" },
+            }),
+            "shutdown" => Json::Null,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                return self.update(uri, text);
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                let Some(text) = changes
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                else {
+                    return Ok(());
+                };
+                return self.update(uri, text);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return Ok(());
+            }
+            "textDocument/hover" => self.with_document(uri, |document| {
+                document.hover(position(&params["position"]))
+            }),
+            "textDocument/definition" => self.with_document(uri, |document| {
+                document
+                    .definition(position(&params["position"]))
+                    .map(|range| json!({ "uri": uri, "range": range }))
+            }),
+            "textDocument/documentSymbol" => self.with_document(uri, |document| document.symbols()),
+            "textDocument/codeAction" => self.with_document(uri, |document| {
+                let start = position(&params["range"]["start"]);
+                let end = position(&params["range"]["end"]);
+                Some(document.code_actions(uri, start, end))
+            }),
+            method => {
+                // Notifications the server has no use for need no answer
+                if message.get("id").is_none() {
+                    return Ok(());
+                }
+                return self.send(json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("unsupported method '{method}'"),
+                    },
+                }));
+            }
+        };
+        self.send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }))
+    }
+
+    fn with_document(&self, uri: &str, f: impl FnOnce(&Analysis) -> Option<Json>) -> Json {
+        self.documents.get(uri).and_then(f).unwrap_or(Json::Null)
+    }
+
+    fn update(&mut self, uri: &str, text: &str) -> io::Result<()> {
+        let analysis = Analysis::new(text, self.dialect);
+        let diagnostics = analysis.diagnostics.clone();
+        self.documents.insert(uri.to_string(), analysis);
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }))
+    }
+}
+
+impl Analysis {
+    fn new(source: &str, dialect: Dialect) -> Self {
+        let mut driver = Driver::new(source).with_dialect(dialect).with_trivia();
+        let result = driver.analyze();
+        if let (Err(e), None) = (&result, driver.ast()) {
+            let Diagnostic {
+                location,
+                message,
+                suggestions,
+            } = Diagnostic::from_stage_error(e, &driver);
+            let error = diagnostic(range(location, location), ERROR, &message);
+            return Analysis {
+                ast: None,
+                cross_references: CrossReferences::default(),
+                diagnostics: vec![error.clone()],
+                fixes: suggestions
+                    .into_iter()
+                    .map(|suggestion| (error.clone(), suggestion))
+                    .collect(),
+            };
+        }
+
+        let mut diagnostics = vec![];
+        let mut fixes = vec![];
+        for recovery in driver.recoveries() {
+            let location = recovery.location;
+            let message = format!("{} ({})", recovery.message, recovery.fix);
+            let warning = diagnostic(range(location, location), WARNING, &message);
+            diagnostics.push(warning.clone());
+            fixes.push((warning, recovery.fix.clone()));
+        }
+        let ast = driver.ast().expect("parsed");
+        if let Err(e) = &result {
+            let (node, message) = match e {
+                StageError::Analyze { error, node } => (*node, error.to_string()),
+                e => (ast.root(), e.to_string()),
+            };
+            let range = name_range(ast, node).unwrap_or_else(|| node_range(ast, node));
+            diagnostics.push(diagnostic(range, ERROR, &message));
+        }
+        Analysis {
+            cross_references: driver
+                .analyzer()
+                .map(|analyzer| analyzer.cross_references().clone())
+                .unwrap_or_default(),
+            ast: driver.into_ast(),
+            diagnostics,
+            fixes,
+        }
+    }
+
+    // A quick fix for every suggested edit on the lines from `start` to
+    // `end`.
+    fn code_actions(&self, uri: &str, start: Location, end: Location) -> Json {
+        let actions = self
+            .fixes
+            .iter()
+            .filter(|(_, suggestion)| {
+                suggestion.end.line >= start.line && suggestion.start.line <= end.line
+            })
+            .map(|(diagnostic, suggestion)| {
+                json!({
+                    "title": suggestion.message,
+                    "kind": "quickfix",
+                    "diagnostics": [diagnostic],
+                    "isPreferred": true,
+                    "edit": {
+                        "changes": {
+                            uri: [{
+                                "range": range(suggestion.start, suggestion.end),
+                                "newText": suggestion.replacement,
+                            }],
+                        },
+                    },
+                })
+            })
+            .collect();
+        Json::Array(actions)
+    }
+
+    fn hover(&self, at: Location) -> Option<Json> {
+        let ast = self.ast.as_ref()?;
+        let (node, declaration) = self.occurrence(at)?;
+        let symbol = self.cross_references.declarations.get(&declaration)?;
+        let signature = match &symbol.kind {
+            SymbolKind::Variable { type_name } => {
+                let role = if is_param(ast, declaration) {
+                    "parameter"
+                } else {
+                    "variable"
+                };
+                format!("({role}) {} : {type_name}", symbol.name)
+            }
+            SymbolKind::Procedure { .. } => {
+                let ASTNode::ProcedureDecl {
+                    type_params,
+                    params,
+                    ..
+                } = &ast[declaration]
+                else {
+                    return None;
+                };
+                let params = params
+                    .iter()
+                    .map(|param| ast.display(*param).to_string())
+                    .collect::<Vec<_>>();
+                if type_params.is_empty() {
+                    format!("procedure {}({})", symbol.name, params.join("; "))
+                } else {
+                    format!(
+                        "generic procedure {}<{}>({})",
+                        symbol.name,
+                        type_params.join(", "),
+                        params.join("; ")
+                    )
+                }
+            }
+            SymbolKind::BuiltinType(builtin) => builtin.to_string(),
+            SymbolKind::BuiltinProcedure(builtin) => format!("procedure {}", builtin.signature()),
+            SymbolKind::ExternalProcedure(signature) => format!("procedure {signature}"),
+            SymbolKind::Type { definition } => {
+                format!("type {} = {}", symbol.name, ast.display(*definition))
+            }
+            SymbolKind::TypeParam => format!("type {}", symbol.name),
+        };
+        let specialized = match self.cross_references.specializations.get(&node) {
+            Some(types) => format!("\nSpecialized as `{}<{}>`", symbol.name, types.join(", ")),
+            None => String::new(),
+        };
+        let line = name_range(ast, declaration)
+            .and_then(|range| range["start"]["line"].as_u64())
+            .map_or(0, |line| line + 1);
+        Some(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!(
+                    "```pascal\n{signature}\n```\nDeclared on line {line}{specialized}"
+                ),
+            },
+            "range": name_range(ast, node),
+        }))
+    }
+
+    fn definition(&self, at: Location) -> Option<Json> {
+        let (_, declaration) = self.occurrence(at)?;
+        name_range(self.ast.as_ref()?, declaration)
+    }
+
+    // The name under the cursor, and the node that declares it.
+    fn occurrence(&self, at: Location) -> Option<(NodeId, NodeId)> {
+        let ast = self.ast.as_ref()?;
+        let references = self.cross_references.references.iter();
+        let declarations = self.cross_references.declarations.keys().map(|d| (d, d));
+        references
+            .chain(declarations)
+            .find(|(node, _)| {
+                name_location(ast, **node).is_some_and(|(start, length)| {
+                    start.line == at.line
+                        && start.column <= at.column
+                        && at.column <= start.column + length
+                })
+            })
+            .map(|(node, declaration)| (*node, *declaration))
+    }
+
+    fn symbols(&self) -> Option<Json> {
+        let ast = self.ast.as_ref()?;
+        Some(Json::Array(document_symbols(ast, ast.root())))
+    }
+}
+
+// LSP `SymbolKind`s
+const MODULE: u32 = 2;
+const METHOD: u32 = 6;
+const FUNCTION: u32 = 12;
+const VARIABLE: u32 = 13;
+
+// The outline below `node`: the program, its procedures and, inside each,
+// their parameters and variables.
+fn document_symbols(ast: &Ast, node: NodeId) -> Vec<Json> {
+    let symbol = |node: NodeId, name: &str, kind: u32, detail: Option<&str>, children| {
+        json!({
+            "name": name,
+            "detail": detail,
+            "kind": kind,
+            "range": node_range(ast, node),
+            "selectionRange": name_range(ast, node).unwrap_or_else(|| node_range(ast, node)),
+            "children": children,
+        })
+    };
+    let typed = |declaration: NodeId| {
+        let children = ast.children(declaration);
+        let (var_node, type_node) = (*children.first()?, *children.get(1)?);
+        let (ASTNode::Var { name }, ASTNode::Type { value }) = (&ast[var_node], &ast[type_node])
+        else {
+            return None;
+        };
+        Some(symbol(var_node, name, VARIABLE, Some(value), vec![]))
+    };
+    let declarations = |block: NodeId| match &ast[block] {
+        ASTNode::Block { declarations, .. } => declarations
+            .iter()
+            .flat_map(|declaration| match &ast[*declaration] {
+                ASTNode::VarDecl { .. } => typed(*declaration).into_iter().collect(),
+                _ => document_symbols(ast, *declaration),
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    match &ast[node] {
+        ASTNode::Program { name, block } => {
+            vec![symbol(node, name, MODULE, None, declarations(*block))]
+        }
+        ASTNode::ProcedureDecl {
+            proc_name,
+            params,
+            block_node,
+            ..
+        } => {
+            let children = params
+                .iter()
+                .filter_map(|param| typed(*param))
+                .chain(declarations(*block_node))
+                .collect();
+            vec![symbol(node, proc_name, FUNCTION, None, children)]
+        }
+        ASTNode::MethodDecl {
+            class_name,
+            method_name,
+            params,
+            block_node,
+            ..
+        } => {
+            let children = params
+                .iter()
+                .filter_map(|param| typed(*param))
+                .chain(declarations(*block_node))
+                .collect();
+            let name = format!("{class_name}.{method_name}");
+            vec![symbol(node, &name, METHOD, None, children)]
+        }
+        _ => vec![],
+    }
+}
+
+fn is_param(ast: &Ast, var_node: NodeId) -> bool {
+    ast.ids()
+        .any(|id| matches!(&ast[id], ASTNode::Param { var_node: param, .. } if *param == var_node))
+}
+
+// Where the name a node declares or refers to starts, and its length.
+fn name_location(ast: &Ast, node: NodeId) -> Option<(Location, usize)> {
+    match &ast[node] {
+        ASTNode::Var { name }
+        | ASTNode::ProcedureCall {
+            proc_name: name, ..
+        } => Some((ast.location(node), name.chars().count())),
+        ASTNode::ProcedureDecl {
+            proc_name: name, ..
+        }
+        | ASTNode::TypeDecl {
+            type_name: name, ..
+        }
+        | ASTNode::MethodDecl {
+            method_name: name, ..
+        }
+        | ASTNode::Program { name, .. } => {
+            let range = ast.token_range(node)?;
+            let token = ast.tokens()[range]
+                .iter()
+                .find(|token| matches!(&token.token, Token::Id(id) if id == name))?;
+            Some((token.location(), name.chars().count()))
+        }
+        _ => None,
+    }
+}
+
+fn name_range(ast: &Ast, node: NodeId) -> Option<Json> {
+    let (start, length) = name_location(ast, node)?;
+    let end = Location {
+        line: start.line,
+        column: start.column + length,
+    };
+    Some(range(start, end))
+}
+
+// From the node's first token to the end of its last one.
+fn node_range(ast: &Ast, node: NodeId) -> Json {
+    let start = ast.location(node);
+    let Some(tokens) = ast.token_range(node).filter(|tokens| !tokens.is_empty()) else {
+        return range(start, start);
+    };
+    let first = &ast.tokens()[tokens.start];
+    let last = &ast.tokens()[tokens.end - 1];
+    let end = Location {
+        line: last.line,
+        column: last.column + (last.span.end - last.span.start),
+    };
+    range(first.location(), end)
+}
+
+// LSP `DiagnosticSeverity`s
+const ERROR: u32 = 1;
+const WARNING: u32 = 2;
+
+fn diagnostic(range: Json, severity: u32, message: &str) -> Json {
+    json!({
+        "range": range,
+        "severity": severity,
+        "source": "simple-interpreter",
+        "message": message.trim_end(),
+    })
+}
+
+// LSP positions count lines and characters from zero.
+fn range(start: Location, end: Location) -> Json {
+    let position = |location: Location| {
+        json!({
+            "line": location.line.saturating_sub(1),
+            "character": location.column.saturating_sub(1),
+        })
+    };
+    json!({ "start": position(start), "end": position(end) })
+}
+
+fn position(position: &Json) -> Location {
+    let field = |name: &str| position[name].as_u64().unwrap_or(0) as usize;
+    Location {
+        line: field("line") + 1,
+        column: field("character") + 1,
+    }
+}
@@ -1,73 +1,1293 @@
+use std::cell::RefCell;
 use std::env;
 use std::fs;
-use std::io;
-
-mod ast;
-mod call_stack;
-mod interpreter;
-mod lexer;
-mod parser;
-mod semantic_analyzer;
-mod symbols;
-mod token;
-mod visualizer;
-
-use interpreter::Interpreter;
-use lexer::Lexer;
-use parser::{Parser, SyntaxError};
-use semantic_analyzer::SemanticAnalyzer;
-use visualizer::Visualizer;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::rc::Rc;
+#[cfg(feature = "jit")]
+use std::time::Instant;
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+use simple_interpreter::artifacts::{Artifact, ArtifactWriter, PendingArtifact};
+use simple_interpreter::bench;
+use simple_interpreter::builtins::{self, BuiltinRegistry};
+use simple_interpreter::check::Diagnostic;
+use simple_interpreter::cst::Cst;
+use simple_interpreter::debugger::TerminalDebugger;
+use simple_interpreter::dialect::Dialect;
+#[cfg(feature = "fpc")]
+use simple_interpreter::differential::{self, Comparison};
+use simple_interpreter::directives::{RealPolicy, Switches};
+use simple_interpreter::driver::{Driver, Stage, StageError};
+use simple_interpreter::formatter::{self, FormatOptions, KeywordCase};
+use simple_interpreter::harness::{self, Outcome};
+use simple_interpreter::interpreter::Interpreter;
+use simple_interpreter::ir;
+#[cfg(feature = "jit")]
+use simple_interpreter::jit;
+use simple_interpreter::lexer::Lexer;
+use simple_interpreter::linter::{self, LintConfig, Severity};
+use simple_interpreter::lsp;
+use simple_interpreter::manifest::{self, Manifest};
+use simple_interpreter::metrics;
+use simple_interpreter::obfuscator;
+use simple_interpreter::optimizer::OptLevel;
+use simple_interpreter::postfix::PostfixTranslator;
+use simple_interpreter::source::{self, Encoding, InvalidUtf8, SourceFile};
+use simple_interpreter::stats::{RuntimeStats, StageTimes};
+#[cfg(feature = "turtle")]
+use simple_interpreter::turtle::{Drawing, Turtle};
+use simple_interpreter::value::RealFormat;
+use simple_interpreter::visualizer::Visualizer;
+use simple_interpreter::wasm;
+use simple_interpreter::xref;
+use tracing::level_filters::LevelFilter;
 
-    if args.len() != 2 {
-        eprintln!("Usage: {} <filename>", args[0]);
-        std::process::exit(1);
+// The program's output when it is compared instead of printed.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
     }
 
-    let filename = &args[1];
-    let content = fs::read_to_string(filename)?;
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
-    let lexer = Lexer::new(&content);
-    let mut parser = match Parser::new(lexer) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
+// Without the `turtle` feature nothing is ever drawn
+#[cfg(not(feature = "turtle"))]
+enum Drawing {}
+
+#[cfg(not(feature = "turtle"))]
+impl Drawing {
+    fn to_svg(&self) -> String {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static GLOBAL: bench::CountingAllocator = bench::CountingAllocator;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    Interpreter,
+    Jit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Emit {
+    Ast,
+    AstJson,
+    Postfix,
+    Ir,
+    Wat,
+    Metrics,
+    MetricsJson,
+    XrefJson,
+    DiagnosticsJson,
+    TokensJson,
+    Cst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProfileFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DumpFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Run,
+    Bench { warmup: u32, iterations: u32 },
+    Fmt(FormatOptions),
+    Obfuscate,
+    Lint(LintConfig),
+    Lsp,
+    Debug { replay: bool },
+    // Runs the conformance cases in a directory; `run_args` are passed on to
+    // every run
+    Test { run_args: Vec<String>, bless: bool },
+    // Compares the conformance cases in a directory with Free Pascal
+    CompareFpc,
+}
+
+struct Options {
+    command: Command,
+    filename: String,
+    backend: Backend,
+    opt_level: OptLevel,
+    emit: Option<Emit>,
+    profile: Option<ProfileFormat>,
+    coverage: bool,
+    // Where to write the execution trace, as JSON Lines
+    trace_file: Option<String>,
+    // Write the program state to a file if the run fails
+    dump_on_error: Option<DumpFormat>,
+    // Shared libraries providing builtin procedures
+    plugins: Vec<String>,
+    // Where to write what the turtle builtins drew
+    turtle: Option<String>,
+    // Where artifacts such as ast.svg go, and names replacing their defaults
+    out_dir: Option<String>,
+    artifact_names: Vec<(Artifact, String)>,
+    // What the program must print, compared instead of printing it
+    expect_output: Option<String>,
+    // Print each output line with the statement that wrote it
+    explain_run: bool,
+    // Run the program this many steps at a time, resuming it after each
+    step_budget: Option<u64>,
+    // How deep procedure calls may nest, instead of the interpreter's default
+    max_call_depth: Option<usize>,
+    stats: bool,
+    // Warn about operands converted with a loss
+    warn_conversions: bool,
+    // Of the interpreter's own events, printed on stderr
+    log_level: LevelFilter,
+    // Warnings are errors and only ISO Pascal is accepted
+    strict: bool,
+    dialect: Dialect,
+    // Of the source file
+    encoding: Encoding,
+    overflow_checks: bool,
+    real_policy: RealPolicy,
+    // Wins over `{$REALFORMAT}` in the source
+    real_format: Option<RealFormat>,
+}
+
+impl Options {
+    // `--turtle=PATH` and `--trace-file=PATH` name their artifact too.
+    fn artifacts(&self) -> ArtifactWriter {
+        let mut artifacts = ArtifactWriter::new();
+        if let Some(dir) = &self.out_dir {
+            artifacts = artifacts.with_dir(dir);
+        }
+        if let Some(path) = &self.turtle {
+            artifacts = artifacts.with_name(Artifact::Turtle, path);
+        }
+        if let Some(path) = &self.trace_file {
+            artifacts = artifacts.with_name(Artifact::Trace, path);
+        }
+        for (artifact, name) in &self.artifact_names {
+            artifacts = artifacts.with_name(*artifact, name);
+        }
+        artifacts
+    }
+
+    // The command line only changes a switch from its default, and then
+    // wins over the source: `{$Q-}` doesn't undo `--overflow-checks`.
+    fn switches(&self, source: &str) -> Switches {
+        let mut switches = Switches::default().with_directives(source, self.dialect);
+        if let Some(real_format) = self.real_format {
+            switches.real_format = real_format;
         }
+        // What would only be a runtime warning otherwise fails the run
+        if self.strict {
+            return Switches {
+                real_format: switches.real_format,
+                ..Switches::STRICT
+            };
+        }
+        switches.overflow_checks |= self.overflow_checks;
+        switches.real_policy = self.real_policy;
+        switches
+    }
+}
+
+fn parse_args(args: &[String], manifest: &Manifest) -> Result<Options, String> {
+    let mut filename = None;
+    let mut backend = Backend::Interpreter;
+    let mut opt_level = OptLevel::O0;
+    let mut emit = None;
+    let mut profile = None;
+    let mut coverage = false;
+    let mut trace_file = None;
+    let mut expect_output = None;
+    let mut explain_run = false;
+    let mut step_budget = None;
+    let mut max_call_depth = None;
+    let mut dump_on_error = None;
+    let mut plugins = vec![];
+    let mut turtle = None;
+    let mut out_dir = None;
+    let mut artifact_names = vec![];
+    let mut stats = false;
+    let mut warn_conversions = false;
+    let mut log_level = LevelFilter::OFF;
+    let mut strict = false;
+    let mut dialect = manifest.dialect.unwrap_or_default();
+    let mut dialect_arg = None;
+    let mut encoding = manifest.encoding.unwrap_or_default();
+    let mut overflow_checks = manifest.switches.overflow_checks.unwrap_or(false);
+    let mut real_policy = manifest.switches.real_policy.unwrap_or_default();
+    let mut real_format = manifest.switches.real_format;
+
+    let mut command = Command::Run;
+    let mut rest = &args[1..];
+    if rest.first().map(String::as_str) == Some("bench") {
+        command = Command::Bench {
+            warmup: 3,
+            iterations: 10,
+        };
+        rest = &rest[1..];
+    } else if rest.first().map(String::as_str) == Some("fmt") {
+        command = Command::Fmt(FormatOptions::default());
+        rest = &rest[1..];
+    } else if rest.first().map(String::as_str) == Some("obfuscate") {
+        command = Command::Obfuscate;
+        rest = &rest[1..];
+    } else if rest.first().map(String::as_str) == Some("lint") {
+        command = Command::Lint(LintConfig::default());
+        rest = &rest[1..];
+    } else if rest.first().map(String::as_str) == Some("test") {
+        command = Command::Test {
+            run_args: vec![],
+            bless: false,
+        };
+        rest = &rest[1..];
+    } else if rest.first().map(String::as_str) == Some("compare-fpc") {
+        command = Command::CompareFpc;
+        rest = &rest[1..];
+    } else if rest.first().map(String::as_str) == Some("lsp") {
+        command = Command::Lsp;
+        rest = &rest[1..];
+    } else if rest.first().map(String::as_str) == Some("debug") {
+        command = Command::Debug { replay: false };
+        rest = &rest[1..];
+    }
+
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+        if let Command::Bench { warmup, iterations } = &mut command {
+            if let Some(value) = arg.strip_prefix("--iterations=") {
+                *iterations = parse_count(arg, value)?;
+                continue;
+            }
+            if let Some(value) = arg.strip_prefix("--warmup=") {
+                *warmup = parse_count(arg, value)?;
+                continue;
+            }
+        }
+        if let Command::Fmt(format) = &mut command {
+            if let Some(value) = arg.strip_prefix("--indent=") {
+                format.indent_width = parse_count(arg, value)? as usize;
+                continue;
+            }
+            if let Some(value) = arg.strip_prefix("--keyword-case=") {
+                format.keyword_case = match value {
+                    "lower" => KeywordCase::Lower,
+                    "upper" => KeywordCase::Upper,
+                    _ => return Err(format!("Unknown keyword case '{value}'")),
+                };
+                continue;
+            }
+            if arg == "--compact-operators" {
+                format.space_around_operators = false;
+                continue;
+            }
+        }
+        if let Command::Lint(config) = &mut command {
+            if let Some((option, value)) = arg.split_once('=') {
+                let severity = match option {
+                    "--allow" => Some(Severity::Allow),
+                    "--warn" => Some(Severity::Warning),
+                    "--deny" => Some(Severity::Error),
+                    _ => None,
+                };
+                if let Some(severity) = severity {
+                    for rule in value.split(',') {
+                        config.set_severity(rule.parse()?, severity);
+                    }
+                    continue;
+                }
+                match option {
+                    "--max-nesting" => {
+                        config.max_nesting = parse_count(arg, value)? as usize;
+                        continue;
+                    }
+                    "--allowed-numbers" => {
+                        config.allowed_numbers = value
+                            .split(',')
+                            .filter(|number| !number.is_empty())
+                            .map(|number| {
+                                number
+                                    .parse()
+                                    .map_err(|_| format!("Invalid number '{number}' in {arg}"))
+                            })
+                            .collect::<Result<_, _>>()?;
+                        continue;
+                    }
+                    "--procedure-naming" => {
+                        config.procedure_naming = value.parse()?;
+                        continue;
+                    }
+                    "--variable-naming" => {
+                        config.variable_naming = value.parse()?;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Command::Debug { replay } = &mut command {
+            if arg == "--replay" {
+                *replay = true;
+                continue;
+            }
+        }
+        if let Command::Test { run_args, bless } = &mut command {
+            if arg == "--bless" {
+                *bless = true;
+                continue;
+            }
+            // Still parsed below, so typos are caught before any case runs
+            if [
+                "--backend=",
+                "--dialect=",
+                "--encoding=",
+                "-O",
+                "--overflow-checks",
+                "--real-policy=",
+                "--real-format=",
+                "--strict",
+            ]
+            .iter()
+            .any(|prefix| arg.starts_with(prefix))
+            {
+                run_args.push(arg.clone());
+            }
+        }
+
+        if let Some(value) = arg.strip_prefix("--backend=") {
+            backend = match value {
+                "interpreter" => Backend::Interpreter,
+                "jit" => Backend::Jit,
+                _ => return Err(format!("Unknown backend '{value}'")),
+            };
+        } else if arg == "--emit" || arg.starts_with("--emit=") {
+            let value = match arg.strip_prefix("--emit=") {
+                Some(value) => value,
+                None => iter.next().ok_or("Missing value for --emit")?,
+            };
+            emit = match value {
+                "ast" => Some(Emit::Ast),
+                "ast-json" => Some(Emit::AstJson),
+                "postfix" => Some(Emit::Postfix),
+                "ir" => Some(Emit::Ir),
+                "wat" => Some(Emit::Wat),
+                "metrics" => Some(Emit::Metrics),
+                "metrics-json" => Some(Emit::MetricsJson),
+                "xref-json" => Some(Emit::XrefJson),
+                "diagnostics-json" => Some(Emit::DiagnosticsJson),
+                "tokens-json" => Some(Emit::TokensJson),
+                "cst" => Some(Emit::Cst),
+                _ => return Err(format!("Unknown --emit kind '{value}'")),
+            };
+        } else if arg == "--profile" || arg.starts_with("--profile=") {
+            profile = match arg.strip_prefix("--profile=").unwrap_or("text") {
+                "text" => Some(ProfileFormat::Text),
+                "json" => Some(ProfileFormat::Json),
+                value => return Err(format!("Unknown profile format '{value}'")),
+            };
+        } else if arg == "--coverage" {
+            coverage = true;
+        } else if arg == "--trace-file" || arg.starts_with("--trace-file=") {
+            let value = match arg.strip_prefix("--trace-file=") {
+                Some(value) => value,
+                None => iter.next().ok_or("Missing value for --trace-file")?,
+            };
+            trace_file = Some(value.to_string());
+        } else if arg == "--dump-on-error" || arg.starts_with("--dump-on-error=") {
+            dump_on_error = match arg.strip_prefix("--dump-on-error=").unwrap_or("text") {
+                "text" => Some(DumpFormat::Text),
+                "json" => Some(DumpFormat::Json),
+                value => return Err(format!("Unknown dump format '{value}'")),
+            };
+        } else if arg == "--plugin" || arg.starts_with("--plugin=") {
+            let value = match arg.strip_prefix("--plugin=") {
+                Some(value) => value,
+                None => iter.next().ok_or("Missing value for --plugin")?,
+            };
+            plugins.push(value.to_string());
+        } else if arg == "--turtle" || arg.starts_with("--turtle=") {
+            if cfg!(not(feature = "turtle")) {
+                return Err("--turtle needs the `turtle` feature".to_string());
+            }
+            turtle = Some(
+                arg.strip_prefix("--turtle=")
+                    .unwrap_or("turtle.svg")
+                    .to_string(),
+            );
+        } else if arg == "-o" || arg == "--out-dir" || arg.starts_with("--out-dir=") {
+            let value = match arg.strip_prefix("--out-dir=") {
+                Some(value) => value,
+                None => iter.next().ok_or(format!("Missing value for {arg}"))?,
+            };
+            out_dir = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--artifact-name=") {
+            let (artifact, name) = value.split_once('=').ok_or(format!(
+                "Expected --artifact-name=ARTIFACT=NAME, got '{arg}'"
+            ))?;
+            if name.is_empty() {
+                return Err(format!("Missing file name in {arg}"));
+            }
+            artifact_names.push((artifact.parse()?, name.to_string()));
+        } else if arg == "--expect-output" || arg.starts_with("--expect-output=") {
+            let value = match arg.strip_prefix("--expect-output=") {
+                Some(value) => value,
+                None => iter.next().ok_or("Missing value for --expect-output")?,
+            };
+            expect_output = Some(value.to_string());
+        } else if arg == "--explain-run" {
+            explain_run = true;
+        } else if let Some(value) = arg.strip_prefix("--step-budget=") {
+            step_budget = Some(parse_count(arg, value)?.into());
+        } else if let Some(value) = arg.strip_prefix("--max-call-depth=") {
+            max_call_depth = Some(parse_count(arg, value)? as usize);
+        } else if arg == "--stdin-file" || arg.starts_with("--stdin-file=") {
+            return Err(
+                "--stdin-file is not supported yet, as programs can't read input".to_string(),
+            );
+        } else if arg == "--stats" {
+            stats = true;
+        } else if arg == "--warn-conversions" {
+            warn_conversions = true;
+        } else if let Some(value) = arg.strip_prefix("--log-level=") {
+            log_level = value
+                .parse()
+                .map_err(|_| format!("Unknown log level '{value}'"))?;
+        } else if arg == "--strict" {
+            strict = true;
+        } else if arg == "--overflow-checks" {
+            overflow_checks = true;
+        } else if let Some(value) = arg.strip_prefix("--real-policy=") {
+            real_policy = value.parse()?;
+        } else if let Some(value) = arg.strip_prefix("--real-format=") {
+            real_format = Some(value.parse()?);
+        } else if let Some(value) = arg.strip_prefix("--dialect=") {
+            dialect = value.parse()?;
+            dialect_arg = Some(dialect);
+        } else if let Some(value) = arg.strip_prefix("--encoding=") {
+            encoding = value.parse()?;
+        } else if let Some(value) = arg.strip_prefix("-O") {
+            opt_level = match value {
+                "0" => OptLevel::O0,
+                "1" => OptLevel::O1,
+                "2" => OptLevel::O2,
+                _ => return Err(format!("Unknown optimization level '{arg}'")),
+            };
+        } else if arg.starts_with('-') {
+            return Err(format!("Unknown option '{arg}'"));
+        } else if filename.replace(arg.clone()).is_some() {
+            return Err("Expected a single source file".to_string());
+        }
+    }
+
+    // The language server gets its documents from the client
+    let default_filename = match command {
+        Command::Lsp => Some(String::new()),
+        Command::Test { .. } | Command::CompareFpc => Some("tests/cases".to_string()),
+        _ => manifest.main.clone(),
+    };
+    let Some(filename) = filename.or(default_filename) else {
+        return Err(format!(
+            "Missing source file, and there is no {} naming one",
+            manifest::FILE_NAME
+        ));
     };
+    if matches!(command, Command::Bench { .. }) && backend != Backend::Interpreter {
+        return Err("bench only supports the interpreter backend".to_string());
+    }
+    if matches!(command, Command::Debug { .. }) && backend != Backend::Interpreter {
+        return Err("debug only supports the interpreter backend".to_string());
+    }
+    if profile.is_some() && backend != Backend::Interpreter {
+        return Err("--profile only supports the interpreter backend".to_string());
+    }
+    if coverage && backend != Backend::Interpreter {
+        return Err("--coverage only supports the interpreter backend".to_string());
+    }
+    if trace_file.is_some() && backend != Backend::Interpreter {
+        return Err("--trace-file only supports the interpreter backend".to_string());
+    }
+    if dump_on_error.is_some() && backend != Backend::Interpreter {
+        return Err("--dump-on-error only supports the interpreter backend".to_string());
+    }
+    if !plugins.is_empty() && backend != Backend::Interpreter {
+        return Err("--plugin only supports the interpreter backend".to_string());
+    }
+    if !plugins.is_empty() && matches!(command, Command::Bench { .. }) {
+        return Err("bench doesn't support --plugin".to_string());
+    }
+    if turtle.is_some() && backend != Backend::Interpreter {
+        return Err("--turtle only supports the interpreter backend".to_string());
+    }
+    if turtle.is_some() && command != Command::Run {
+        return Err("--turtle only works when running a program".to_string());
+    }
+    if expect_output.is_some() && backend != Backend::Interpreter {
+        return Err("--expect-output only supports the interpreter backend".to_string());
+    }
+    if expect_output.is_some() && command != Command::Run {
+        return Err("--expect-output only works when running a program".to_string());
+    }
+    if explain_run && backend != Backend::Interpreter {
+        return Err("--explain-run only supports the interpreter backend".to_string());
+    }
+    if explain_run && command != Command::Run {
+        return Err("--explain-run only works when running a program".to_string());
+    }
+    if step_budget.is_some() && backend != Backend::Interpreter {
+        return Err("--step-budget only supports the interpreter backend".to_string());
+    }
+    if step_budget.is_some() && command != Command::Run {
+        return Err("--step-budget only works when running a program".to_string());
+    }
+    if max_call_depth.is_some() && backend != Backend::Interpreter {
+        return Err("--max-call-depth only supports the interpreter backend".to_string());
+    }
+    if max_call_depth.is_some() && command != Command::Run {
+        return Err("--max-call-depth only works when running a program".to_string());
+    }
+    if command == Command::Run && backend == Backend::Interpreter {
+        step_budget = step_budget.or(manifest.limits.step_budget);
+        max_call_depth = max_call_depth.or(manifest.limits.max_call_depth);
+    }
+    if warn_conversions && backend != Backend::Interpreter {
+        return Err("--warn-conversions only supports the interpreter backend".to_string());
+    }
+    if warn_conversions && command != Command::Run {
+        return Err("--warn-conversions only works when running a program".to_string());
+    }
+    if strict {
+        if dialect_arg.is_some_and(|dialect| dialect != Dialect::Iso) {
+            return Err("--strict only accepts ISO Pascal, without dialect extensions".to_string());
+        }
+        dialect = Dialect::Iso;
+    }
+    Ok(Options {
+        command,
+        filename,
+        backend,
+        opt_level,
+        emit,
+        profile,
+        coverage,
+        trace_file,
+        dump_on_error,
+        plugins,
+        turtle,
+        out_dir,
+        artifact_names,
+        expect_output,
+        explain_run,
+        step_budget,
+        max_call_depth,
+        stats,
+        warn_conversions,
+        log_level,
+        strict,
+        dialect,
+        encoding,
+        overflow_checks,
+        real_policy,
+        real_format,
+    })
+}
 
-    let ast = match parser.parse() {
-        Ok(ast) => ast,
-        Err(e) => {
-            if let Some(syntax_error) = e.downcast_ref::<SyntaxError>() {
-                eprintln!("{}", syntax_error);
-            } else {
+fn parse_count(arg: &str, value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Expected a number in '{arg}'"))
+}
+
+// The text of the source file, or exits with why it can't be read.
+fn read_source(options: &Options) -> SourceFile {
+    let file = source::read(Path::new(&options.filename), options.encoding);
+    let file = file.unwrap_or_else(|e| {
+        match e.get_ref().and_then(|e| e.downcast_ref::<InvalidUtf8>()) {
+            Some(invalid) => eprintln!(
+                "Error: {}: {} (use --encoding=latin1 for a Latin-1 file)",
+                options.filename, invalid
+            ),
+            None => eprintln!("Error reading {}: {}", options.filename, e),
+        }
+        std::process::exit(1);
+    });
+    if let Some(invalid) = &file.fallback {
+        eprintln!(
+            "Warning: {}: {}, reading the file as Latin-1",
+            options.filename, invalid
+        );
+    }
+    file
+}
+
+// The pipeline for `content` as the options set it up, printing what the
+// parser recovered from as warnings.
+fn driver<'a>(content: &'a str, options: &Options, builtins: &BuiltinRegistry) -> Driver<'a> {
+    Driver::new(content)
+        .with_dialect(options.dialect)
+        .with_strict(options.strict)
+        .with_builtins(builtins.signatures())
+        .after_stage(|stage, driver| {
+            if stage == Stage::Parse {
+                for recovery in driver.recoveries() {
+                    eprintln!("Warning: {}", recovery);
+                }
+            }
+        })
+}
+
+fn or_exit<T>(result: Result<T, StageError>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+// Every token the lexer reads, up to and including EOF, as JSON. Offsets are
+// in bytes, `end` exclusive.
+fn emit_tokens_json(content: &str, options: &Options) {
+    let mut tokens = vec![];
+    for token in Lexer::new(content).with_dialect(options.dialect) {
+        match token {
+            Ok(token) => tokens.push(serde_json::json!({
+                "kind": token.token.kind(),
+                "text": &content[token.span.start..token.span.end],
+                "line": token.line,
+                "column": token.column,
+                "start": token.span.start,
+                "end": token.span.end,
+            })),
+            Err(e) => {
                 eprintln!("Error: {}", e);
+                std::process::exit(1);
             }
+        }
+    }
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&tokens).expect("tokens are always serializable")
+    );
+}
+
+// What is wrong with the program, with suggested fixes, as JSON rather than
+// text. Exits with status 1 if there are errors.
+fn emit_diagnostics_json(content: &str, options: &Options, builtins: &BuiltinRegistry) {
+    let mut driver = Driver::new(content)
+        .with_dialect(options.dialect)
+        .with_strict(options.strict)
+        .with_builtins(builtins.signatures());
+    let mut errors = vec![];
+    if let Err(e) = driver.analyze() {
+        errors.push(Diagnostic::from_stage_error(&e, &driver));
+    }
+    let json = serde_json::json!({ "errors": errors, "warnings": driver.recoveries() });
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json).expect("diagnostics are always serializable")
+    );
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+// Runs every case in `dir` through this executable and exits with status 1
+// if any of them fails. With `bless`, records the actual behavior as the
+// expectation instead, except for diagnostics cases, which are checked.
+fn run_tests(dir: &str, run_args: &[String], bless: bool) -> io::Result<()> {
+    let program = env::current_exe()?;
+    let cases = harness::discover(Path::new(dir))?;
+    // Keeps ast.svg and other artifacts out of the working directory
+    let work_dir = env::temp_dir().join(format!("pascal-conformance-{}", std::process::id()));
+    fs::create_dir_all(&work_dir)?;
+
+    let mut failed = 0;
+    for case in &cases {
+        // Annotations are written by hand, so there is nothing to record
+        let annotated = !case
+            .annotations()
+            .is_ok_and(|annotations| annotations.is_empty());
+        if bless && !annotated {
+            let run = harness::run(&program, run_args, case, &work_dir)?;
+            case.bless(&run)?;
+            println!("{} ... blessed", case.name);
+            continue;
+        }
+        let outcome = harness::check(&program, run_args, case, &work_dir);
+        if outcome != Outcome::Pass {
+            failed += 1;
+        }
+        println!("{} ... {}", case.name, outcome);
+    }
+    fs::remove_dir_all(&work_dir)?;
+
+    if !bless {
+        println!(
+            "\n{} passed, {} failed, {} total",
+            cases.len() - failed,
+            failed,
+            cases.len()
+        );
+        if failed > 0 {
             std::process::exit(1);
         }
-    };
+    }
+    Ok(())
+}
 
-    let mut visualizer = Visualizer::new();
-    let svg_content = visualizer.generate_svg(&ast);
-    if let Err(e) = std::fs::write("ast.svg", svg_content) {
-        eprintln!("Error writing SVG: {}", e);
+#[cfg(feature = "fpc")]
+fn compare_fpc(path: &str) -> io::Result<()> {
+    let Some(fpc) = differential::find_fpc() else {
+        eprintln!("Error: fpc not found on PATH (set FPC to the compiler)");
+        std::process::exit(1);
+    };
+    let path = Path::new(path);
+    let cases = if path.is_dir() {
+        harness::discover(path)?
     } else {
-        println!("AST visualization saved to ast.svg");
+        let name = path.file_stem().unwrap_or_default();
+        vec![harness::Case {
+            name: name.to_string_lossy().into_owned(),
+            source: path.to_path_buf(),
+        }]
+    };
+    // FPC's object files and executables are left here
+    let work_dir = env::temp_dir().join(format!("pascal-fpc-{}", std::process::id()));
+    fs::create_dir_all(&work_dir)?;
+
+    let (mut differ, mut skipped) = (0, 0);
+    for case in &cases {
+        let comparison = differential::compare(&fpc, case, &work_dir);
+        match comparison {
+            Comparison::Same => {}
+            Comparison::Skipped(_) => skipped += 1,
+            Comparison::Differs { .. } | Comparison::Error(_) => differ += 1,
+        }
+        println!("{} ... {}", case.name, comparison);
     }
+    fs::remove_dir_all(&work_dir)?;
 
-    let mut semantic_analyzer = SemanticAnalyzer::new();
-    if let Err(e) = semantic_analyzer.analyze(&ast) {
-        eprintln!("Error: {}", e);
+    println!(
+        "\n{} same, {} differ, {} skipped, {} total",
+        cases.len() - differ - skipped,
+        differ,
+        skipped,
+        cases.len()
+    );
+    if differ > 0 {
         std::process::exit(1);
     }
+    Ok(())
+}
+
+#[cfg(not(feature = "fpc"))]
+fn compare_fpc(_path: &str) -> io::Result<()> {
+    eprintln!("Error: this build does not include FPC comparison (enable the `fpc` feature)");
+    std::process::exit(1);
+}
+
+// Writes an artifact and says where it went, or why it couldn't be written.
+fn save(artifacts: &ArtifactWriter, artifact: Artifact, contents: String, description: &str) {
+    match artifacts.write(artifact, contents) {
+        Ok(path) => println!("{} saved to {}", description, path.display()),
+        Err(e) => eprintln!(
+            "Error writing {}: {}",
+            artifacts.path(artifact).display(),
+            e
+        ),
+    }
+}
+
+// Puts the trace in place once the interpreter has flushed it. A trace that
+// couldn't be written completely is removed.
+fn finish_trace(pending: PendingArtifact, result: io::Result<()>) {
+    match result.and_then(|()| pending.commit()) {
+        Ok(path) => println!("Trace saved to {}", path.display()),
+        Err(e) => eprintln!("Error writing trace: {}", e),
+    }
+}
 
-    let mut interpreter = Interpreter::new(false);
-    match interpreter.interpret(&ast) {
+fn run_interpreter(
+    driver: &mut Driver,
+    options: &Options,
+    source: &str,
+    builtins: BuiltinRegistry,
+    drawing: Option<Rc<RefCell<Drawing>>>,
+) -> Option<RuntimeStats> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_switches(options.switches(source));
+    interpreter.set_builtins(builtins);
+    if let Some(limit) = options.max_call_depth {
+        interpreter.set_call_depth_limit(limit);
+    }
+    let expected_output = options.expect_output.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Error reading {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+    let output = SharedBuffer::default();
+    if expected_output.is_some() {
+        interpreter.set_output(Box::new(output.clone()));
+    } else if options.explain_run {
+        // Printed with the annotations instead
+        interpreter.set_output(Box::new(io::sink()));
+    }
+    if options.explain_run {
+        interpreter.enable_explain();
+    }
+    if options.warn_conversions {
+        interpreter.enable_conversion_warnings();
+    }
+    if options.profile.is_some() {
+        interpreter.enable_profiling();
+    }
+    if options.coverage {
+        interpreter.enable_coverage();
+    }
+    let artifacts = options.artifacts();
+    let mut trace = None;
+    if options.trace_file.is_some() {
+        match artifacts.create(Artifact::Trace) {
+            Ok((file, pending)) => {
+                interpreter.enable_tracing(Box::new(io::BufWriter::new(file)));
+                trace = Some(pending);
+            }
+            Err(e) => {
+                let path = artifacts.path(Artifact::Trace);
+                eprintln!("Error creating {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if options.dump_on_error.is_some() {
+        interpreter.enable_error_dump();
+    }
+    let result = match options.step_budget {
+        Some(steps) => driver.execute_in_turns(&mut interpreter, steps, |exhausted| {
+            println!("{}, resuming", exhausted);
+        }),
+        None => driver.execute(&mut interpreter),
+    };
+    let ast = driver.ast().expect("analyzed");
+    if let Some(explanation) = interpreter.explanation() {
+        print!("{}", explanation.annotate(ast));
+    }
+    // A failed run still reports what it got through
+    let report = match &result {
+        Ok(report) => report.clone(),
+        Err(_) => interpreter.run_report(),
+    };
+    for warning in report.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    match &result {
         Ok(_) => println!("program done"),
-        Err(e) => eprintln!("Error: {}", e),
+        Err(e) => eprintln!("{}", e),
+    }
+
+    if let (Some(format), Some(dump)) = (options.dump_on_error, interpreter.error_dump()) {
+        let (artifact, content) = match format {
+            DumpFormat::Text => (Artifact::DumpText, dump.to_text()),
+            DumpFormat::Json => (Artifact::DumpJson, dump.to_json()),
+        };
+        save(&artifacts, artifact, content, "State dump");
+    }
+
+    if let Some(drawing) = drawing {
+        save(
+            &artifacts,
+            Artifact::Turtle,
+            drawing.borrow().to_svg(),
+            "Turtle drawing",
+        );
+    }
+
+    if let (Some(pending), Some(result)) = (trace, interpreter.finish_trace()) {
+        finish_trace(pending, result);
+    }
+
+    if let Some(coverage) = interpreter.coverage() {
+        let report = coverage.report(ast);
+        print!("{}", report.annotate(source));
+        let lcov = report.to_lcov(&options.filename);
+        save(&artifacts, Artifact::Coverage, lcov, "Coverage");
+    }
+
+    if let (Some(format), Some(report)) = (options.profile, interpreter.profile()) {
+        match format {
+            ProfileFormat::Text => print!("{}", report.to_text(source)),
+            ProfileFormat::Json => save(&artifacts, Artifact::Profile, report.to_json(), "Profile"),
+        }
+    }
+
+    let mut matches = true;
+    if let (Some(path), Some(expected)) = (&options.expect_output, expected_output) {
+        let actual = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+        matches = actual == expected;
+        if matches {
+            println!("Output matches {}", path);
+        } else {
+            println!("Output differs from {} (-expected +actual):", path);
+            print!("{}", harness::diff(&expected, &actual));
+        }
+    }
+
+    // The reports above still cover the part of the program that ran
+    if result.is_err() || !matches {
+        std::process::exit(1);
+    }
+    Some(interpreter.stats())
+}
+
+// Compiled code runs outside the driver, so this times it itself
+#[cfg(feature = "jit")]
+fn run_jit(
+    driver: &mut Driver,
+    options: &Options,
+    source: &str,
+) -> (Option<RuntimeStats>, StageTimes) {
+    let fall_back = |driver: &mut Driver| {
+        let stats = run_interpreter(driver, options, source, BuiltinRegistry::new(), None);
+        (stats, driver.times())
+    };
+    // Compiled code wraps around on INTEGER overflow and lets infinities and
+    // NaN through
+    let switches = options.switches(source);
+    if switches.overflow_checks || switches.real_policy == RealPolicy::Error {
+        eprintln!("Note: JIT does not support arithmetic checks, falling back to the interpreter");
+        return fall_back(driver);
+    }
+    match jit::compile(driver.ast().expect("analyzed")) {
+        Ok(program) => {
+            let mut times = driver.times();
+            let start = Instant::now();
+            let span = tracing::info_span!("stage", stage = %Stage::Execute).entered();
+            let result = program.run();
+            drop(span);
+            times.execute = start.elapsed();
+            match result {
+                Ok(_) => println!("program done"),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            // Compiled code does not keep runtime counters
+            (None, times)
+        }
+        Err(e) => {
+            eprintln!("Note: {}, falling back to the interpreter", e);
+            fall_back(driver)
+        }
+    }
+}
+
+#[cfg(not(feature = "jit"))]
+fn run_jit(
+    _driver: &mut Driver,
+    _options: &Options,
+    _source: &str,
+) -> (Option<RuntimeStats>, StageTimes) {
+    eprintln!("Error: this build does not include the JIT backend (enable the `jit` feature)");
+    std::process::exit(1);
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+
+    let manifest = match Manifest::find() {
+        Ok(manifest) => manifest.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let options = match parse_args(&args, &manifest) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!(
+                "Usage: {} [--backend=interpreter|jit] [-O0|-O1|-O2] [--emit ast|ast-json|postfix|ir|wat|metrics|metrics-json|xref-json|diagnostics-json|tokens-json|cst] [--profile[=text|json]] [--coverage] [--trace-file=PATH] [--dump-on-error[=text|json]] [--plugin=PATH] [--turtle[=PATH]] [-o DIR] [--artifact-name=ARTIFACT=NAME] [--expect-output=PATH] [--explain-run] [--step-budget=N] [--max-call-depth=N] [--stats] [--warn-conversions] [--log-level=off|error|warn|info|debug|trace] [--overflow-checks] [--real-policy=propagate|error] [--real-format=scientific[:N]|fixed:N] [--strict] [--dialect=iso|tp|fpc] [--encoding=auto|utf-8|latin1] <filename>",
+                args[0]
+            );
+            eprintln!(
+                "       {} bench [--iterations=N] [--warmup=N] [-O0|-O1|-O2] [--overflow-checks] [--real-policy=propagate|error] [--real-format=scientific[:N]|fixed:N] [--strict] [--dialect=iso|tp|fpc] [--encoding=auto|utf-8|latin1] <filename>",
+                args[0]
+            );
+            eprintln!(
+                "       {} fmt [--indent=N] [--keyword-case=lower|upper] [--compact-operators] [--strict] [--dialect=iso|tp|fpc] [--encoding=auto|utf-8|latin1] <filename>",
+                args[0]
+            );
+            eprintln!(
+                "       {} obfuscate [--strict] [--dialect=iso|tp|fpc] [--encoding=auto|utf-8|latin1] <filename>",
+                args[0]
+            );
+            eprintln!(
+                "       {} lint [--allow|--warn|--deny=RULE,...] [--max-nesting=N] [--allowed-numbers=N,...] [--procedure-naming=STYLE] [--variable-naming=STYLE] [--strict] [--dialect=iso|tp|fpc] [--encoding=auto|utf-8|latin1] <filename>",
+                args[0]
+            );
+            eprintln!(
+                "       {} test [--bless] [--backend=interpreter|jit] [-O0|-O1|-O2] [--overflow-checks] [--real-policy=propagate|error] [--real-format=scientific[:N]|fixed:N] [--strict] [--dialect=iso|tp|fpc] [--encoding=auto|utf-8|latin1] [directory]",
+                args[0]
+            );
+            eprintln!("       {} compare-fpc [directory|file]", args[0]);
+            eprintln!("       {} lsp [--dialect=iso|tp|fpc]", args[0]);
+            eprintln!(
+                "       {} debug [--replay] [--plugin=PATH] [-O0|-O1|-O2] [--overflow-checks] [--real-policy=propagate|error] [--real-format=scientific[:N]|fixed:N] [--strict] [--dialect=iso|tp|fpc] [--encoding=auto|utf-8|latin1] <filename>",
+                args[0]
+            );
+            std::process::exit(1);
+        }
+    };
+
+    if options.log_level != LevelFilter::OFF {
+        tracing_subscriber::fmt()
+            .with_max_level(options.log_level)
+            .with_writer(io::stderr)
+            .with_ansi(io::stderr().is_terminal())
+            .init();
+    }
+
+    if options.command == Command::Lsp {
+        return lsp::run(io::stdin().lock(), io::stdout().lock(), options.dialect);
+    }
+
+    if let Command::Test { run_args, bless } = &options.command {
+        run_tests(&options.filename, run_args, *bless)?;
+        return Ok(());
+    }
+
+    if options.command == Command::CompareFpc {
+        compare_fpc(&options.filename)?;
+        return Ok(());
+    }
+
+    let content = read_source(&options);
+
+    let mut builtins = BuiltinRegistry::new();
+    for path in &options.plugins {
+        match builtins::load_plugin(path) {
+            Ok(provider) => builtins.add(provider),
+            Err(e) => {
+                eprintln!("Error loading plugin: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    #[cfg(feature = "turtle")]
+    let drawing = options.turtle.as_ref().map(|_| {
+        let turtle = Turtle::new();
+        let drawing = turtle.drawing();
+        builtins.add(Box::new(turtle));
+        drawing
+    });
+    #[cfg(not(feature = "turtle"))]
+    let drawing = None;
+
+    match &options.command {
+        Command::Fmt(format) => {
+            let mut driver = driver(&content, &options, &builtins).with_trivia();
+            or_exit(driver.parse());
+            let ast = driver.ast().expect("parsed");
+            print!("{}", formatter::format(ast, &content, format));
+            return Ok(());
+        }
+        Command::Obfuscate => {
+            let mut driver = driver(&content, &options, &builtins).with_trivia();
+            or_exit(driver.analyze());
+            let ast = driver.ast().expect("analyzed");
+            print!("{}", obfuscator::obfuscate(ast, &content));
+            return Ok(());
+        }
+        Command::Lint(config) => {
+            let mut driver = driver(&content, &options, &builtins).with_trivia();
+            or_exit(driver.parse());
+            let ast = driver.ast().expect("parsed");
+            let mut diagnostics = linter::lint(ast, &content, config);
+            if options.strict {
+                for diagnostic in &mut diagnostics {
+                    if diagnostic.severity == Severity::Warning {
+                        diagnostic.severity = Severity::Error;
+                    }
+                }
+            }
+            for diagnostic in &diagnostics {
+                println!("{}:{}", options.filename, diagnostic);
+            }
+            if diagnostics
+                .iter()
+                .any(|diagnostic| diagnostic.severity == Severity::Error)
+            {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Command::Run
+        | Command::Bench { .. }
+        | Command::Lsp
+        | Command::Debug { .. }
+        | Command::Test { .. }
+        | Command::CompareFpc => {}
+    }
+
+    if options.emit == Some(Emit::DiagnosticsJson) {
+        emit_diagnostics_json(&content, &options, &builtins);
+        return Ok(());
+    }
+    if options.emit == Some(Emit::TokensJson) {
+        emit_tokens_json(&content, &options);
+        return Ok(());
+    }
+    if options.emit == Some(Emit::Cst) {
+        let mut driver = driver(&content, &options, &builtins).with_trivia();
+        or_exit(driver.parse());
+        let ast = driver.ast().expect("parsed");
+        let cst = Cst::new(ast).expect("parsed with trivia");
+        print!("{}", cst.display(ast, &content));
+        return Ok(());
+    }
+
+    let mut driver = driver(&content, &options, &builtins).with_opt_level(options.opt_level);
+    or_exit(driver.optimize());
+    let ast = driver.ast().expect("optimized");
+
+    match options.emit {
+        Some(Emit::Ast) => {
+            println!("{}", ast);
+            return Ok(());
+        }
+        Some(Emit::AstJson) => {
+            match serde_json::to_string_pretty(ast) {
+                Ok(json) => println!("{}", json),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Some(Emit::Postfix) => {
+            print!("{}", PostfixTranslator::new().translate(ast));
+            return Ok(());
+        }
+        Some(Emit::Ir) => {
+            or_exit(driver.analyze());
+            for remark in driver.remarks() {
+                println!("; {}", remark);
+            }
+            print!("{}", ir::lower(driver.ast().expect("analyzed")));
+            return Ok(());
+        }
+        Some(Emit::Wat) => {
+            or_exit(driver.analyze());
+            match wasm::emit_wat(driver.ast().expect("analyzed")) {
+                Ok(module) => print!("{}", module),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Some(Emit::Metrics) => {
+            print!("{}", metrics::measure(ast).to_text());
+            return Ok(());
+        }
+        Some(Emit::MetricsJson) => {
+            println!("{}", metrics::measure(ast).to_json());
+            return Ok(());
+        }
+        Some(Emit::XrefJson) => {
+            or_exit(driver.analyze());
+            let cross_references = driver.analyzer().expect("analyzed").cross_references();
+            println!(
+                "{}",
+                xref::export(driver.ast().expect("analyzed"), cross_references).to_json()
+            );
+            return Ok(());
+        }
+        Some(Emit::DiagnosticsJson | Emit::TokensJson | Emit::Cst) | None => {}
+    }
+
+    if let Command::Bench { .. } | Command::Debug { .. } = options.command {
+        or_exit(driver.analyze());
+    }
+
+    if let &Command::Debug { replay } = &options.command {
+        let mut debugger = TerminalDebugger::new(&content, io::stdin().lock(), io::stdout());
+        let mut interpreter = Interpreter::new();
+        interpreter.set_switches(options.switches(&content));
+        interpreter.set_builtins(builtins);
+        // A replay runs the whole program first, then lets the user move
+        // through the recording in both directions
+        let result = if replay {
+            interpreter.enable_recording();
+            let result = driver.execute(&mut interpreter);
+            if let Some(recording) = interpreter.recording() {
+                debugger.replay(driver.ast().expect("analyzed"), recording);
+            }
+            result
+        } else {
+            interpreter.set_statement_hook(Box::new(debugger));
+            driver.execute(&mut interpreter)
+        };
+        match result {
+            Ok(_) => println!("\nprogram done"),
+            Err(e) => eprintln!("\n{}", e),
+        }
+        return Ok(());
+    }
+
+    let ast = driver.ast().expect("optimized");
+
+    if let Command::Bench { warmup, iterations } = options.command {
+        let hir = driver.hir().expect("analyzed");
+        match bench::run(ast, hir, options.switches(&content), warmup, iterations) {
+            Ok(report) => println!("{}", report),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    // Only reached once the program parsed
+    let mut visualizer = Visualizer::new();
+    let svg_content = visualizer.generate_svg(ast);
+    save(
+        &options.artifacts(),
+        Artifact::AstSvg,
+        svg_content,
+        "AST visualization",
+    );
+
+    or_exit(driver.analyze());
+    let (runtime_stats, times) = match options.backend {
+        Backend::Interpreter => {
+            let stats = run_interpreter(&mut driver, &options, &content, builtins, drawing);
+            (stats, driver.times())
+        }
+        Backend::Jit => run_jit(&mut driver, &options, &content),
+    };
+
+    if options.stats {
+        println!("\nStage times:\n{}", times);
+        if let Some(runtime_stats) = runtime_stats {
+            println!("\nRuntime statistics:\n{}", runtime_stats);
+        }
     }
 
     Ok(())
@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer};
+
+use crate::dialect::Dialect;
+use crate::directives::RealPolicy;
+use crate::source::Encoding;
+use crate::value::RealFormat;
+
+pub const FILE_NAME: &str = "pascal.toml";
+
+// Project settings from a `pascal.toml`, which the command line picks up
+// from the current directory. Every field is optional, and an option given
+// on the command line is applied on top of the manifest's.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Manifest {
+    // The program to use when the command line names none
+    pub main: Option<String>,
+    #[serde(deserialize_with = "parsed")]
+    pub dialect: Option<Dialect>,
+    #[serde(deserialize_with = "parsed")]
+    pub encoding: Option<Encoding>,
+    pub switches: SwitchSettings,
+    pub limits: LimitSettings,
+}
+
+// The `[switches]` table, with the same meaning as the command-line options
+// of the same names.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SwitchSettings {
+    pub overflow_checks: Option<bool>,
+    #[serde(deserialize_with = "parsed")]
+    pub real_policy: Option<RealPolicy>,
+    #[serde(deserialize_with = "parsed")]
+    pub real_format: Option<RealFormat>,
+}
+
+// The `[limits]` table, with the same meaning as the command-line options
+// of the same names. They only apply where those options would, when a
+// program is run with the interpreter.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LimitSettings {
+    pub max_call_depth: Option<usize>,
+    pub step_budget: Option<u64>,
+}
+
+impl FromStr for Manifest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| e.to_string().trim_end().to_string())
+    }
+}
+
+impl Manifest {
+    // The manifest in the current directory, or `None` if there is none.
+    pub fn find() -> Result<Option<Self>, String> {
+        match fs::read_to_string(FILE_NAME) {
+            Ok(text) => text
+                .parse()
+                .map(Some)
+                .map_err(|e| format!("{}: {}", FILE_NAME, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("{}: {}", FILE_NAME, e)),
+        }
+    }
+}
+
+// A string field holding anything the command line would accept for the
+// option, e.g. `dialect = "fpc"`.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(de::Error::custom)
+}
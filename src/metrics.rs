@@ -0,0 +1,164 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::visitor::ensure_stack;
+
+// Size and complexity figures for every routine of a program, the main
+// program first and then its procedures and methods in source order. The
+// figures come from the AST alone, so they cover routines that never run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Metrics {
+    pub routines: Vec<RoutineMetrics>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoutineMetrics {
+    // `Class.Method` for a method
+    pub name: String,
+    pub line: usize,
+    // One more than the number of places the code can take different
+    // paths, which so far are its IF statements
+    pub cyclomatic_complexity: usize,
+    // Assignments and calls in the body, not counting nested procedures
+    pub statements: usize,
+    // Deepest nesting of BEGIN blocks, 1 for a body without inner blocks
+    pub max_nesting: usize,
+    // Declared in the routine's VAR section
+    pub variables: usize,
+    pub parameters: usize,
+}
+
+pub fn measure(ast: &Ast) -> Metrics {
+    let mut metrics = Metrics { routines: vec![] };
+    if let ASTNode::Program { name, block } = &ast[ast.root()] {
+        metrics.routine(ast, ast.root(), name.clone(), 0, *block);
+    }
+    metrics
+}
+
+impl Metrics {
+    fn routine(&mut self, ast: &Ast, node: NodeId, name: String, parameters: usize, block: NodeId) {
+        let ASTNode::Block {
+            declarations,
+            compound_statement,
+        } = &ast[block]
+        else {
+            return;
+        };
+        let mut body = Body::default();
+        body.visit(ast, *compound_statement, 0);
+        let variables = declarations
+            .iter()
+            .filter(|declaration| matches!(ast[**declaration], ASTNode::VarDecl { .. }))
+            .count();
+        self.routines.push(RoutineMetrics {
+            name,
+            line: ast.location(node).line,
+            cyclomatic_complexity: 1 + body.decisions,
+            statements: body.statements,
+            max_nesting: body.max_nesting,
+            variables,
+            parameters,
+        });
+
+        for declaration in declarations {
+            match &ast[*declaration] {
+                ASTNode::ProcedureDecl {
+                    proc_name,
+                    params,
+                    block_node,
+                    ..
+                } => self.routine(
+                    ast,
+                    *declaration,
+                    proc_name.clone(),
+                    params.len(),
+                    *block_node,
+                ),
+                ASTNode::MethodDecl {
+                    class_name,
+                    method_name,
+                    params,
+                    block_node,
+                    ..
+                } => self.routine(
+                    ast,
+                    *declaration,
+                    format!("{class_name}.{method_name}"),
+                    params.len(),
+                    *block_node,
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("metrics are always serializable")
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{:<24} {:>5} {:>10} {:>10} {:>7} {:>9} {:>10}",
+            "routine", "line", "complexity", "statements", "nesting", "variables", "parameters"
+        );
+        for routine in &self.routines {
+            let _ = writeln!(
+                out,
+                "{:<24} {:>5} {:>10} {:>10} {:>7} {:>9} {:>10}",
+                routine.name,
+                routine.line,
+                routine.cyclomatic_complexity,
+                routine.statements,
+                routine.max_nesting,
+                routine.variables,
+                routine.parameters
+            );
+        }
+        out
+    }
+}
+
+// Counts for the statements of one routine body.
+#[derive(Default)]
+struct Body {
+    decisions: usize,
+    statements: usize,
+    max_nesting: usize,
+}
+
+impl Body {
+    // `depth` counts the BEGIN blocks around `node`.
+    fn visit(&mut self, ast: &Ast, node: NodeId, depth: usize) {
+        ensure_stack(|| {
+            let depth = match &ast[node] {
+                ASTNode::Compound { .. } => {
+                    self.max_nesting = self.max_nesting.max(depth + 1);
+                    depth + 1
+                }
+                // Each arm of a CASE is a way through it
+                ASTNode::CaseArm { .. } => {
+                    self.decisions += 1;
+                    depth
+                }
+                node if node.is_statement() => {
+                    if let ASTNode::If { .. } | ASTNode::While { .. } | ASTNode::Repeat { .. } =
+                        node
+                    {
+                        self.decisions += 1;
+                    }
+                    self.statements += 1;
+                    depth
+                }
+                _ => depth,
+            };
+            for child in ast.children(node) {
+                self.visit(ast, child, depth);
+            }
+        })
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::token::{Token, RESERVER_KEYWORDS};
+use crate::visitor::ensure_stack;
+
+// Prints the program on a single line without comments and with every
+// identifier the program declares renamed to a short meaningless one. Needs
+// an AST parsed with trivia, since it re-emits the source tokens; anything
+// else yields an empty string.
+//
+// Names are resolved scope by scope like the semantic analyzer does, so a
+// local that shadows a global gets a name of its own. Every declaration gets
+// a fresh name, which keeps the renaming correct without having to check
+// what an inner scope can see. Class members keep their names, since which
+// class a member belongs to is only known from the types.
+pub fn obfuscate(ast: &Ast, source: &str) -> String {
+    let mut renamer = Renamer {
+        ast,
+        scopes: vec![HashMap::new()],
+        fields: HashMap::new(),
+        renamed: HashMap::new(),
+        next_name: 0,
+    };
+    if !ast.tokens().is_empty() {
+        renamer.visit(ast.root());
+    }
+
+    let mut out = String::new();
+    for (index, token) in ast.tokens().iter().enumerate() {
+        if token.token == Token::Eof {
+            break;
+        }
+        let text = match renamer.renamed.get(&index) {
+            Some(name) => name.as_str(),
+            None => &source[token.span.start..token.span.end],
+        };
+        if needs_space(&out, text) {
+            out.push(' ');
+        }
+        out.push_str(text);
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+// Two tokens written back to back must not read as one, nor start a comment.
+fn needs_space(before: &str, text: &str) -> bool {
+    let (Some(last), Some(first)) = (before.chars().last(), text.chars().next()) else {
+        return false;
+    };
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+    (word(last) && word(first)) || matches!((last, first), ('/', '/') | ('(', '*'))
+}
+
+struct Renamer<'a> {
+    ast: &'a Ast,
+    // Innermost scope last: source name to new name
+    scopes: Vec<HashMap<String, String>>,
+    // Class name to its field names
+    fields: HashMap<String, Vec<String>>,
+    // Token index to the name printed in its place
+    renamed: HashMap<usize, String>,
+    next_name: usize,
+}
+
+impl Renamer<'_> {
+    fn visit(&mut self, node: NodeId) {
+        ensure_stack(|| {
+            let ast = self.ast;
+            match &ast[node] {
+                ASTNode::Program { name, block } => {
+                    self.declare(name, node);
+                    self.scopes.push(HashMap::new());
+                    self.visit(*block);
+                    self.scopes.pop();
+                }
+                ASTNode::ProcedureDecl {
+                    proc_name,
+                    params,
+                    block_node,
+                    ..
+                } => {
+                    self.declare(proc_name, node);
+                    self.scopes.push(HashMap::new());
+                    for param in params {
+                        self.visit(*param);
+                    }
+                    self.visit(*block_node);
+                    self.scopes.pop();
+                }
+                ASTNode::Param {
+                    var_node,
+                    type_node,
+                    ..
+                }
+                | ASTNode::VarDecl {
+                    var_node,
+                    type_node,
+                    initializer: None,
+                } => {
+                    if let ASTNode::Var { name } = &ast[*var_node] {
+                        self.declare(name, *var_node);
+                    }
+                    self.visit(*type_node);
+                }
+                ASTNode::VarDecl {
+                    var_node,
+                    type_node,
+                    initializer: Some(initializer),
+                } => {
+                    self.visit(*initializer);
+                    if let ASTNode::Var { name } = &ast[*var_node] {
+                        self.declare(name, *var_node);
+                    }
+                    self.visit(*type_node);
+                }
+                ASTNode::TypeDecl {
+                    type_name,
+                    type_node,
+                } => {
+                    self.declare(type_name, node);
+                    if let ASTNode::ClassType { members } = &ast[*type_node] {
+                        let fields = members
+                            .iter()
+                            .filter_map(|member| match &ast[*member] {
+                                ASTNode::VarDecl { var_node, .. } => match &ast[*var_node] {
+                                    ASTNode::Var { name } => Some(name.clone()),
+                                    _ => None,
+                                },
+                                _ => None,
+                            })
+                            .collect();
+                        self.fields.insert(type_name.clone(), fields);
+                    }
+                    self.visit(*type_node);
+                }
+                ASTNode::ClassType { members } => {
+                    for member in members {
+                        match &ast[*member] {
+                            ASTNode::VarDecl { type_node, .. } => self.visit(*type_node),
+                            ASTNode::MethodHeading { params, .. } => {
+                                self.scopes.push(HashMap::new());
+                                for param in params {
+                                    self.visit(*param);
+                                }
+                                self.scopes.pop();
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                // Inside a method the fields hide any outer declaration
+                ASTNode::MethodDecl {
+                    class_name,
+                    params,
+                    block_node,
+                    ..
+                } => {
+                    self.refer(class_name, node);
+                    let fields = self.fields.get(class_name).cloned().unwrap_or_default();
+                    self.scopes
+                        .push(fields.into_iter().map(|f| (f.clone(), f)).collect());
+                    for param in params {
+                        self.visit(*param);
+                    }
+                    self.visit(*block_node);
+                    self.scopes.pop();
+                }
+                ASTNode::FieldAccess { object, .. } => self.visit(*object),
+                // The parameter names are local to the type
+                ASTNode::ProcedureType { params } => {
+                    self.scopes.push(HashMap::new());
+                    for param in params {
+                        self.visit(*param);
+                    }
+                    self.scopes.pop();
+                }
+                ASTNode::Type { value } => self.refer(value, node),
+                ASTNode::Var { name } => self.refer(name, node),
+                ASTNode::ProcedureCall {
+                    proc_name,
+                    arguments,
+                    ..
+                } => {
+                    self.refer(proc_name, node);
+                    for argument in arguments {
+                        self.visit(*argument);
+                    }
+                }
+                _ => {
+                    for child in ast.children(node) {
+                        self.visit(child);
+                    }
+                }
+            }
+        })
+    }
+
+    fn declare(&mut self, name: &str, node: NodeId) {
+        let new_name = self.fresh_name();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), new_name);
+        }
+        self.refer(name, node);
+    }
+
+    // Renames the first token of `node` that spells `name`.
+    fn refer(&mut self, name: &str, node: NodeId) {
+        let Some(new_name) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) else {
+            return;
+        };
+        let Some(mut range) = self.ast.token_range(node) else {
+            return;
+        };
+        let tokens = self.ast.tokens();
+        if let Some(index) =
+            range.find(|&i| matches!(&tokens[i].token, Token::Id(id) if id == name))
+        {
+            self.renamed.insert(index, new_name.clone());
+        }
+    }
+
+    // a, b, ..., z, aa, ab, ..., skipping keywords.
+    fn fresh_name(&mut self) -> String {
+        loop {
+            let mut n = self.next_name;
+            self.next_name += 1;
+            let mut name = vec![];
+            loop {
+                name.push(b'a' + (n % 26) as u8);
+                if n < 26 {
+                    break;
+                }
+                n = n / 26 - 1;
+            }
+            name.reverse();
+            let name = String::from_utf8(name).unwrap_or_default();
+            if !RESERVER_KEYWORDS.contains_key(name.as_str()) {
+                return name;
+            }
+        }
+    }
+}
@@ -0,0 +1,715 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::ast::{ASTNode, Ast, NodeId, ParamMode};
+use crate::directives::Switches;
+use crate::interpreter::{apply_bin_op, apply_unary_op};
+use crate::passes::{Pass, PassManager, Remark};
+use crate::symbols::BuiltinProcedure;
+use crate::token::Token;
+use crate::value::Value;
+use crate::visitor::{ensure_stack, walk_mut, VisitorMut};
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+}
+
+pub fn add_passes(manager: &mut PassManager, level: OptLevel) {
+    if level >= OptLevel::O1 {
+        manager.add(ConstantFolding);
+    }
+    if level >= OptLevel::O2 {
+        manager.add(ConstantPropagation::default());
+        manager.add(DeadStoreElimination::default());
+    }
+}
+
+// Folds literal subexpressions and drops INTEGER identity operands (`+ 0`,
+// `- 0`, `* 1`). Arithmetic goes through the interpreter's own operators so a
+// folded program computes exactly what the unoptimized one would; anything
+// that would fail at runtime (e.g. `1 div 0`) is left in place to fail there.
+// That includes INTEGER overflow and infinite or NaN REALs, which only fail
+// with the corresponding checks on. A condition such as `1 < 2` folds to
+// TRUE like any other expression, but the IF, WHILE or REPEAT around it
+// stays: the semantic analyzer runs on the optimized tree, so dropping the
+// branch that can't run would also drop the errors in it.
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &str {
+        "constant-folding"
+    }
+
+    fn run(&mut self, ast: &mut Ast) -> Result<()> {
+        self.visit_mut(ast, ast.root());
+        Ok(())
+    }
+}
+
+impl VisitorMut for ConstantFolding {
+    fn visit_mut(&mut self, ast: &mut Ast, node: NodeId) {
+        walk_mut(self, ast, node);
+
+        match ast[node].clone() {
+            ASTNode::UnaryOpNode { expr, token } => {
+                let expr = strip_unary_plus(ast, expr);
+                ast[node] = ASTNode::UnaryOpNode {
+                    expr,
+                    token: token.clone(),
+                };
+                if let ASTNode::NumNode { value } = &ast[expr] {
+                    if let Ok(value) = apply_unary_op(&token, value.clone(), &Switches::STRICT) {
+                        ast[node] = ASTNode::NumNode { value };
+                    }
+                }
+            }
+            ASTNode::BinOpNode { left, right, op } => {
+                let left = strip_unary_plus(ast, left);
+                let right = strip_unary_plus(ast, right);
+                ast[node] = ASTNode::BinOpNode {
+                    left,
+                    right,
+                    op: op.clone(),
+                };
+                if let (ASTNode::NumNode { value: l }, ASTNode::NumNode { value: r }) =
+                    (&ast[left], &ast[right])
+                {
+                    if let Ok(value) = apply_bin_op(&op, l.clone(), r.clone(), &Switches::STRICT) {
+                        ast[node] = ASTNode::NumNode { value };
+                    }
+                    return;
+                }
+
+                // A REAL identity such as `+ 0.0` would turn an INTEGER
+                // operand into a REAL, so only INTEGER ones are dropped
+                let kept = match op {
+                    Token::Plus if is_integer(&ast[right], 0) => Some(left),
+                    Token::Plus if is_integer(&ast[left], 0) => Some(right),
+                    Token::Minus if is_integer(&ast[right], 0) => Some(left),
+                    Token::Asterisk if is_integer(&ast[right], 1) => Some(left),
+                    Token::Asterisk if is_integer(&ast[left], 1) => Some(right),
+                    _ => None,
+                };
+                if let Some(kept) = kept {
+                    ast[node] = replacement(ast, kept);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// A unary `+` leaves its operand as it is, so directly under another
+// operator it is a no-op.
+fn strip_unary_plus(ast: &Ast, mut operand: NodeId) -> NodeId {
+    while let ASTNode::UnaryOpNode {
+        expr,
+        token: Token::Plus,
+    } = &ast[operand]
+    {
+        operand = *expr;
+    }
+    operand
+}
+
+fn is_integer(node: &ASTNode, expected: i32) -> bool {
+    matches!(node, ASTNode::NumNode { value: Value::Int(v) } if *v == expected)
+}
+
+// What a node simplified to `operand` becomes. A variable stays its own node
+// behind a no-op unary `+`, so errors about it still point at its name.
+fn replacement(ast: &Ast, operand: NodeId) -> ASTNode {
+    match &ast[operand] {
+        node @ (ASTNode::BinOpNode { .. }
+        | ASTNode::UnaryOpNode { .. }
+        | ASTNode::NumNode { .. }) => node.clone(),
+        _ => ASTNode::UnaryOpNode {
+            expr: operand,
+            token: Token::Plus,
+        },
+    }
+}
+
+// Replaces reads of a routine's own variables with their value where it is
+// known: the variable was last assigned a number of its declared type, and
+// nothing since could have changed it. Initializers count as assignments
+// made when the routine starts. After an IF or CASE a value is only known
+// if every way through leaves it known and the same. A WHILE or REPEAT
+// forgets every variable its body assigns or passes to a call, before the
+// loop. A call may change a variable passed to a VAR or OUT parameter, but
+// nothing else of the caller's. Each
+// replaced expression is folded again, so `x := 2; y := x * 3` makes `y`
+// known to be 6 as well.
+#[derive(Default)]
+pub struct ConstantPropagation {
+    remarks: Vec<Remark>,
+}
+
+impl Pass for ConstantPropagation {
+    fn name(&self) -> &str {
+        "constant-propagation"
+    }
+
+    fn run(&mut self, ast: &mut Ast) -> Result<()> {
+        self.remarks.clear();
+        if let ASTNode::Program { block, .. } = ast[ast.root()] {
+            self.routine(ast, block, &[], &HashMap::new());
+        }
+        Ok(())
+    }
+
+    fn remarks(&self) -> Vec<Remark> {
+        self.remarks.clone()
+    }
+}
+
+impl ConstantPropagation {
+    // `procedures` has the parameter modes of every procedure in scope
+    fn routine(
+        &mut self,
+        ast: &mut Ast,
+        block: NodeId,
+        params: &[NodeId],
+        procedures: &HashMap<String, Vec<ParamMode>>,
+    ) {
+        let ASTNode::Block {
+            declarations,
+            compound_statement,
+        } = ast[block].clone()
+        else {
+            return;
+        };
+
+        // The declared type of each variable whose value may become known.
+        // CONST parameters can't be assigned, so theirs never is.
+        let mut own = HashMap::new();
+        let mut shadowing = HashSet::new();
+        for param in params {
+            let ASTNode::Param {
+                var_node,
+                type_node,
+                mode,
+            } = &ast[*param]
+            else {
+                continue;
+            };
+            let ASTNode::Var { name } = &ast[*var_node] else {
+                continue;
+            };
+            shadowing.insert(name.clone());
+            if *mode == ParamMode::Value {
+                own.insert(name.clone(), type_name(ast, *type_node));
+            }
+        }
+        let mut procedures = procedures.clone();
+        let mut nested = HashSet::new();
+        for declaration in &declarations {
+            match &ast[*declaration] {
+                ASTNode::VarDecl {
+                    var_node,
+                    type_node,
+                    ..
+                } => {
+                    if let ASTNode::Var { name } = &ast[*var_node] {
+                        shadowing.insert(name.clone());
+                        own.insert(name.clone(), type_name(ast, *type_node));
+                    }
+                }
+                ASTNode::ProcedureDecl {
+                    proc_name, params, ..
+                } => {
+                    let modes = params
+                        .iter()
+                        .map(|param| match &ast[*param] {
+                            ASTNode::Param { mode, .. } => *mode,
+                            _ => ParamMode::Var,
+                        })
+                        .collect();
+                    procedures.insert(proc_name.clone(), modes);
+                }
+                _ => {}
+            }
+        }
+        // A variable holding a procedure hides the procedure of that name
+        for name in &shadowing {
+            procedures.remove(name);
+        }
+        for declaration in &declarations {
+            match ast[*declaration].clone() {
+                ASTNode::ProcedureDecl {
+                    params, block_node, ..
+                }
+                | ASTNode::MethodDecl {
+                    params, block_node, ..
+                } => {
+                    self.routine(ast, block_node, &params, &procedures);
+                    names_read(ast, *declaration, &mut nested);
+                }
+                _ => {}
+            }
+        }
+        // Like dead-store elimination, leave alone whatever a nested
+        // routine mentions
+        own.retain(|name, _| !nested.contains(name));
+
+        // Initializers run first, in declaration order
+        let mut known = HashMap::new();
+        for declaration in &declarations {
+            if let ASTNode::VarDecl {
+                var_node,
+                initializer: Some(initializer),
+                ..
+            } = ast[*declaration]
+            {
+                self.assign(ast, var_node, initializer, &own, &mut known);
+            }
+        }
+        let routine = Routine {
+            own,
+            shadowing,
+            procedures,
+        };
+        self.statements(ast, compound_statement, &routine, &mut known);
+    }
+
+    // Propagates into `right` and records what it makes `left` known to be.
+    fn assign(
+        &mut self,
+        ast: &mut Ast,
+        left: NodeId,
+        right: NodeId,
+        own: &HashMap<String, Option<String>>,
+        known: &mut HashMap<String, Value>,
+    ) {
+        self.propagate(ast, right, known);
+        if let ASTNode::Var { name } = &ast[left] {
+            match (&ast[right], own.get(name)) {
+                (ASTNode::NumNode { value }, Some(Some(type_name)))
+                    if value.type_name().eq_ignore_ascii_case(type_name) =>
+                {
+                    known.insert(name.clone(), value.clone());
+                }
+                _ => {
+                    known.remove(name);
+                }
+            }
+        }
+    }
+
+    // Propagates through the statements of `node` in the order they run,
+    // starting from the values in `known` and leaving those known after.
+    fn statements(
+        &mut self,
+        ast: &mut Ast,
+        node: NodeId,
+        routine: &Routine,
+        known: &mut HashMap<String, Value>,
+    ) {
+        ensure_stack(|| {
+            let Routine {
+                own,
+                shadowing,
+                procedures,
+            } = routine;
+            let mut statements = vec![];
+            flatten(ast, node, &mut statements);
+
+            for statement in statements {
+                match ast[statement].clone() {
+                    ASTNode::Assign { left, right, .. } => {
+                        self.assign(ast, left, right, own, known);
+                    }
+                    ASTNode::ProcedureCall {
+                        proc_name,
+                        arguments,
+                        ..
+                    } => {
+                        let modes = match procedures.get(&proc_name) {
+                            Some(modes) => Some(modes.clone()),
+                            None if !shadowing.contains(&proc_name)
+                                && BuiltinProcedure::ALL
+                                    .iter()
+                                    .any(|builtin| builtin.name() == proc_name) =>
+                            {
+                                Some(vec![ParamMode::Value; arguments.len()])
+                            }
+                            None => None,
+                        };
+                        self.arguments(ast, &arguments, modes.as_deref(), known);
+                    }
+                    ASTNode::MethodCall { arguments, .. } => {
+                        self.arguments(ast, &arguments, None, known);
+                    }
+                    ASTNode::If {
+                        condition,
+                        then_branch,
+                        else_branch,
+                    } => {
+                        self.propagate(ast, condition, known);
+                        let mut then_known = known.clone();
+                        self.statements(ast, then_branch, routine, &mut then_known);
+                        if let Some(else_branch) = else_branch {
+                            self.statements(ast, else_branch, routine, known);
+                        }
+                        known.retain(|name, value| then_known.get(name) == Some(value));
+                    }
+                    ASTNode::While { condition, body } => {
+                        let mut changed = HashSet::new();
+                        names_assigned(ast, body, &mut changed);
+                        known.retain(|name, _| !changed.contains(name));
+                        self.propagate(ast, condition, known);
+                        self.statements(ast, body, routine, &mut known.clone());
+                    }
+                    // The body runs at least once, so what it leaves known
+                    // holds after the loop too
+                    ASTNode::Repeat { body, condition } => {
+                        let mut changed = HashSet::new();
+                        names_assigned(ast, statement, &mut changed);
+                        known.retain(|name, _| !changed.contains(name));
+                        for child in body {
+                            self.statements(ast, child, routine, known);
+                        }
+                        self.propagate(ast, condition, known);
+                    }
+                    // Without an ELSE part possibly no arm runs, which leaves
+                    // `known` as it was
+                    ASTNode::Case {
+                        selector,
+                        arms,
+                        else_branch,
+                    } => {
+                        self.propagate(ast, selector, known);
+                        let mut outcomes = vec![];
+                        for arm in arms {
+                            let ASTNode::CaseArm { statement, .. } = ast[arm] else {
+                                continue;
+                            };
+                            let mut arm_known = known.clone();
+                            self.statements(ast, statement, routine, &mut arm_known);
+                            outcomes.push(arm_known);
+                        }
+                        for child in else_branch.unwrap_or_default() {
+                            self.statements(ast, child, routine, known);
+                        }
+                        known.retain(|name, value| {
+                            outcomes
+                                .iter()
+                                .all(|outcome| outcome.get(name) == Some(value))
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        })
+    }
+
+    // Propagates into the arguments of a call whose parameter modes are
+    // `modes`, if known. A variable passed by reference may change, and one
+    // passed where the mode is unknown might be.
+    fn arguments(
+        &mut self,
+        ast: &mut Ast,
+        arguments: &[NodeId],
+        modes: Option<&[ParamMode]>,
+        known: &mut HashMap<String, Value>,
+    ) {
+        for (i, argument) in arguments.iter().enumerate() {
+            let by_value = modes
+                .and_then(|modes| modes.get(i))
+                .is_some_and(|mode| !mode.by_reference());
+            match &ast[*argument] {
+                ASTNode::Var { name } if !by_value => {
+                    known.remove(name);
+                }
+                _ => self.propagate(ast, *argument, known),
+            }
+        }
+    }
+
+    // Replaces the known variables of an expression and folds it again. A
+    // builtin called in the expression may change a variable passed to it,
+    // so those are no longer known.
+    fn propagate(&mut self, ast: &mut Ast, node: NodeId, known: &mut HashMap<String, Value>) {
+        forget_call_arguments(ast, node, known);
+        if self.replace(ast, node, known) {
+            ConstantFolding.visit_mut(ast, node);
+        }
+    }
+
+    fn replace(&mut self, ast: &mut Ast, node: NodeId, known: &HashMap<String, Value>) -> bool {
+        ensure_stack(|| {
+            if let ASTNode::Var { name } = &ast[node] {
+                let Some(value) = known.get(name) else {
+                    return false;
+                };
+                self.remarks.push(Remark {
+                    location: ast.location(node),
+                    message: format!("Replaced '{name}' with its constant value {value}"),
+                });
+                ast[node] = ASTNode::NumNode {
+                    value: value.clone(),
+                };
+                return true;
+            }
+            let mut replaced = false;
+            for child in ast.children(node) {
+                replaced |= self.replace(ast, child, known);
+            }
+            replaced
+        })
+    }
+}
+
+fn forget_call_arguments(ast: &Ast, node: NodeId, known: &mut HashMap<String, Value>) {
+    ensure_stack(|| {
+        if let ASTNode::ProcedureCall { arguments, .. } | ASTNode::MethodCall { arguments, .. } =
+            &ast[node]
+        {
+            for argument in arguments {
+                if let ASTNode::Var { name } = &ast[*argument] {
+                    known.remove(name);
+                }
+            }
+        }
+        for child in ast.children(node) {
+            forget_call_arguments(ast, child, known);
+        }
+    })
+}
+
+// What propagation needs to know about the routine whose statements it
+// walks: the declared types of its own variables, the names that hide a
+// procedure, and the parameter modes of the procedures in scope.
+struct Routine {
+    own: HashMap<String, Option<String>>,
+    shadowing: HashSet<String>,
+    procedures: HashMap<String, Vec<ParamMode>>,
+}
+
+// The name of a type written out as one, lowercase like every identifier.
+fn type_name(ast: &Ast, type_node: NodeId) -> Option<String> {
+    match &ast[type_node] {
+        ASTNode::Type { value } => Some(value.clone()),
+        _ => None,
+    }
+}
+
+// Removes assignments to a routine's own variables whose value is never
+// read: overwritten before the next read or, in a procedure, not read again
+// before it returns. The main program's variables count as read at its
+// end, so the debugger, state dumps and `--emit wat` still see their final
+// values. Only assignments whose right side can't fail are removed: a
+// number, or a variable the routine already assigned or initialized.
+// Initializers themselves are kept. Constant folding
+// turns most constant expressions into numbers first. Assignments in the
+// branches of an IF or CASE or the body of a loop are kept, and whatever they
+// mention counts as read.
+#[derive(Default)]
+pub struct DeadStoreElimination {
+    remarks: Vec<Remark>,
+}
+
+impl Pass for DeadStoreElimination {
+    fn name(&self) -> &str {
+        "dead-store-elimination"
+    }
+
+    fn run(&mut self, ast: &mut Ast) -> Result<()> {
+        self.remarks.clear();
+        if let ASTNode::Program { block, .. } = ast[ast.root()] {
+            self.routine(ast, block, &[], true);
+        }
+        Ok(())
+    }
+
+    fn remarks(&self) -> Vec<Remark> {
+        self.remarks.clone()
+    }
+}
+
+impl DeadStoreElimination {
+    fn routine(&mut self, ast: &mut Ast, block: NodeId, params: &[NodeId], main: bool) {
+        let ASTNode::Block {
+            declarations,
+            compound_statement,
+        } = ast[block].clone()
+        else {
+            return;
+        };
+
+        // Variables whose stores may go, those read after the routine
+        // returns, and those assigned so far, in statement order
+        let mut own = HashSet::new();
+        let mut live_at_exit = HashSet::new();
+        let mut assigned = HashSet::new();
+        for param in params {
+            let ASTNode::Param { var_node, mode, .. } = &ast[*param] else {
+                continue;
+            };
+            let ASTNode::Var { name } = &ast[*var_node] else {
+                continue;
+            };
+            // Assigning a CONST parameter is an error the analyzer reports
+            match mode {
+                ParamMode::Value => {
+                    own.insert(name.clone());
+                }
+                ParamMode::Var | ParamMode::Out => {
+                    live_at_exit.insert(name.clone());
+                }
+                ParamMode::Const => {}
+            }
+            if *mode != ParamMode::Out {
+                assigned.insert(name.clone());
+            }
+        }
+        // Nested routines are handled on their own. Whatever they mention
+        // is left alone here.
+        let mut nested = HashSet::new();
+        for declaration in declarations {
+            match ast[declaration].clone() {
+                ASTNode::VarDecl {
+                    var_node,
+                    initializer,
+                    ..
+                } => {
+                    if let ASTNode::Var { name } = &ast[var_node] {
+                        own.insert(name.clone());
+                        if initializer.is_some() {
+                            assigned.insert(name.clone());
+                        }
+                    }
+                }
+                ASTNode::ProcedureDecl {
+                    params, block_node, ..
+                }
+                | ASTNode::MethodDecl {
+                    params, block_node, ..
+                } => {
+                    self.routine(ast, block_node, &params, false);
+                    names_read(ast, declaration, &mut nested);
+                }
+                _ => {}
+            }
+        }
+        if main {
+            live_at_exit.extend(own.iter().cloned());
+        }
+
+        let mut statements = vec![];
+        flatten(ast, compound_statement, &mut statements);
+
+        let mut removable = HashSet::new();
+        for statement in &statements {
+            if let ASTNode::Assign { left, right, .. } = &ast[*statement] {
+                if cannot_fail(ast, *right, &assigned) {
+                    removable.insert(*statement);
+                }
+                if let ASTNode::Var { name } = &ast[*left] {
+                    assigned.insert(name.clone());
+                }
+            }
+        }
+
+        // Walking backwards, `live` holds the variables read before their
+        // next assignment
+        let mut live = live_at_exit;
+        for statement in statements.into_iter().rev() {
+            let ASTNode::Assign { left, right, .. } = ast[statement] else {
+                names_read(ast, statement, &mut live);
+                continue;
+            };
+            let ASTNode::Var { name } = ast[left].clone() else {
+                names_read(ast, statement, &mut live);
+                continue;
+            };
+            if own.contains(&name)
+                && !live.contains(&name)
+                && !nested.contains(&name)
+                && removable.contains(&statement)
+            {
+                ast[statement] = ASTNode::NoOp;
+                self.remarks.push(Remark {
+                    location: ast.location(statement),
+                    message: format!("Removed assignment to '{name}', whose value is never read"),
+                });
+                continue;
+            }
+            live.remove(&name);
+            names_read(ast, right, &mut live);
+        }
+    }
+}
+
+// The statements of a compound statement and those nested in it, in the
+// order they run.
+fn flatten(ast: &Ast, node: NodeId, statements: &mut Vec<NodeId>) {
+    ensure_stack(|| match &ast[node] {
+        ASTNode::Compound { children } => {
+            for child in children {
+                flatten(ast, *child, statements);
+            }
+        }
+        _ => statements.push(node),
+    })
+}
+
+// Every name under `node` that could be a variable, including procedure
+// variables that are called.
+fn names_read(ast: &Ast, node: NodeId, names: &mut HashSet<String>) {
+    ensure_stack(|| {
+        match &ast[node] {
+            ASTNode::Var { name }
+            | ASTNode::ProcedureCall {
+                proc_name: name, ..
+            } => {
+                names.insert(name.clone());
+            }
+            _ => {}
+        }
+        for child in ast.children(node) {
+            names_read(ast, child, names);
+        }
+    })
+}
+
+// Every variable under `node` that is assigned or passed to a call, where it
+// might be changed.
+fn names_assigned(ast: &Ast, node: NodeId, names: &mut HashSet<String>) {
+    ensure_stack(|| {
+        match &ast[node] {
+            ASTNode::Assign { left, .. } => {
+                if let ASTNode::Var { name } = &ast[*left] {
+                    names.insert(name.clone());
+                }
+            }
+            ASTNode::ProcedureCall { arguments, .. } | ASTNode::MethodCall { arguments, .. } => {
+                for argument in arguments {
+                    if let ASTNode::Var { name } = &ast[*argument] {
+                        names.insert(name.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+        for child in ast.children(node) {
+            names_assigned(ast, child, names);
+        }
+    })
+}
+
+fn cannot_fail(ast: &Ast, node: NodeId, assigned: &HashSet<String>) -> bool {
+    ensure_stack(|| match &ast[node] {
+        ASTNode::NumNode { .. } => true,
+        ASTNode::Var { name } => assigned.contains(name),
+        ASTNode::UnaryOpNode {
+            expr,
+            token: Token::Plus,
+        } => cannot_fail(ast, *expr, assigned),
+        _ => false,
+    })
+}
@@ -1,10 +1,15 @@
-use crate::ast::{ASTNode, BuiltinNumTypes};
-use crate::lexer::Lexer;
+use crate::ast::{ASTNode, Ast, MethodKind, NodeId, ParamMode};
+use crate::check::Suggestion;
+use crate::dialect::Dialect;
+use crate::lexer::TokenSource;
 use crate::symbols::BuiltinTypes;
-use crate::token::{LocatedToken, Token};
+use crate::token::{LocatedToken, Location, Span, Token};
+use crate::value::Value;
 use anyhow::Result;
-use std::cell::RefCell;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::fmt;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct SyntaxError {
@@ -13,11 +18,13 @@ pub struct SyntaxError {
     line: usize,
     column: usize,
     snippet: String,
+    suggestion: Option<Suggestion>,
 }
 
 impl SyntaxError {
     fn with_detail(
         location: &LocatedToken,
+        source: &str,
         title: impl Into<String>,
         detail: Option<String>,
     ) -> Self {
@@ -26,11 +33,17 @@ impl SyntaxError {
             detail,
             line: location.line,
             column: location.column,
-            snippet: location.snippet.clone(),
+            snippet: location.span.snippet(source).to_string(),
+            suggestion: None,
         }
     }
 
-    fn unexpected_token(location: &LocatedToken, expected: Option<&Token>) -> Self {
+    fn with_suggestion(mut self, suggestion: Option<Suggestion>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+
+    fn unexpected_token(location: &LocatedToken, source: &str, expected: Option<&Token>) -> Self {
         let detail = match expected {
             Some(expected_token) => format!(
                 "expected {}, found {}",
@@ -39,7 +52,27 @@ impl SyntaxError {
             ),
             None => format!("found {}", location.token.clone()),
         };
-        Self::with_detail(location, "Unexpected token type", Some(detail))
+        Self::with_detail(location, source, "Unexpected token type", Some(detail))
+    }
+
+    pub fn location(&self) -> Location {
+        Location {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    // An edit that fixes the error, when there is an obvious one.
+    pub fn suggestion(&self) -> Option<&Suggestion> {
+        self.suggestion.as_ref()
+    }
+
+    // The title and detail on one line, without the source snippet.
+    pub fn message(&self) -> String {
+        match &self.detail {
+            Some(detail) => format!("{}: {}", self.title, detail),
+            None => self.title.clone(),
+        }
     }
 }
 
@@ -58,57 +91,338 @@ impl fmt::Display for SyntaxError {
         if let Some(detail) = &self.detail {
             write!(f, "    {}", detail)?;
         }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n    help: {}", suggestion)?;
+        }
         Ok(())
     }
 }
 
 impl std::error::Error for SyntaxError {}
 
-pub struct Parser<'a> {
-    lexer: Lexer<'a>,
+// A common mistake the parser worked around instead of failing, and the
+// edit that fixes it. Only a permissive parser recovers; a strict one
+// reports these as syntax errors.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Recovery {
+    pub location: Location,
+    pub message: String,
+    pub fix: Suggestion,
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.location, self.message, self.fix)
+    }
+}
+
+// Deep enough for any program written by hand, shallow enough that parsing
+// and evaluating the tree stays well within the main thread's stack.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+pub struct Parser<S: TokenSource> {
+    lexer: S,
     current_token: LocatedToken,
+    ast: Ast,
+    keep_tokens: bool,
+    dialect: Dialect,
+    // How deeply expressions, statements and procedures are nested at the
+    // current token, and how deep they may go
+    depth: usize,
+    max_depth: usize,
+    // Index in `ast.tokens()` of each consumed token, by where it starts
+    token_index: HashMap<Location, usize>,
+    // The bytes of each consumed token, by where it starts
+    token_spans: HashMap<Location, Span>,
+    // Where the `(` before each parenthesized expression starts. The
+    // parentheses stay outside the expression's own tokens, but belong to
+    // an operation it is the first operand of.
+    opening_parens: HashMap<NodeId, Location>,
+    // Just after the last consumed token, and its byte offset
+    previous_end: Location,
+    previous_end_offset: usize,
+    strict: bool,
+    recoveries: Vec<Recovery>,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(mut lexer: Lexer<'a>) -> Result<Self> {
+impl<S: TokenSource> Parser<S> {
+    pub fn new(mut lexer: S) -> Result<Self> {
         let current_token = lexer.next_token()?;
+        let keep_tokens = lexer.keeps_trivia();
+        let dialect = lexer.dialect();
         Ok(Parser {
             lexer,
             current_token,
+            ast: Ast::new(),
+            keep_tokens,
+            dialect,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            token_index: HashMap::new(),
+            token_spans: HashMap::new(),
+            opening_parens: HashMap::new(),
+            previous_end: Location { line: 1, column: 1 },
+            previous_end_offset: 0,
+            strict: false,
+            recoveries: vec![],
         })
     }
 
-    pub fn parse(&mut self) -> Result<ASTNode> {
-        self.program()
+    // A strict parser fails on the mistakes a permissive one recovers
+    // from, such as a missing final `.` or `=` written for `:=`.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // What a permissive parser recovered from, in source order.
+    pub fn recoveries(&self) -> &[Recovery] {
+        &self.recoveries
+    }
+
+    // Limits how deeply parentheses, BEGIN blocks and procedures may nest
+    // before parsing fails with a syntax error instead of overflowing the
+    // stack.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // A parser that adds to an existing AST instead of starting a new one;
+    // nodes it creates get ids after the ones already there.
+    pub(crate) fn continuing(lexer: S, ast: Ast) -> Result<Self> {
+        let mut parser = Self::new(lexer)?;
+        parser.ast = ast;
+        Ok(parser)
+    }
+
+    // The AST built so far and the first token not consumed.
+    pub(crate) fn finish(self) -> (Ast, LocatedToken) {
+        (self.ast, self.current_token)
+    }
+
+    pub fn parse(&mut self) -> Result<Ast> {
+        let root = self.program()?;
+        Ok(self.take_ast(root))
+    }
+
+    // Parses a lone expression such as `2 * (a + 1)`, without a PROGRAM
+    // around it. The returned AST is rooted at the expression.
+    pub fn parse_expression(&mut self) -> Result<Ast> {
+        let root = self.expr()?;
+        self.expect_end()?;
+        Ok(self.take_ast(root))
+    }
+
+    // Parses a single statement, e.g. an assignment or a BEGIN ... END
+    // block. The returned AST is rooted at the statement.
+    pub fn parse_statement(&mut self) -> Result<Ast> {
+        let root = self.statement()?;
+        self.expect_end()?;
+        Ok(self.take_ast(root))
+    }
+
+    fn expect_end(&self) -> Result<()> {
+        if self.current_token.token != Token::Eof {
+            let err = SyntaxError::unexpected_token(
+                &self.current_token,
+                self.lexer.source(),
+                Some(&Token::Eof),
+            );
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    fn take_ast(&mut self, root: NodeId) -> Ast {
+        self.ast.set_root(root);
+        if self.keep_tokens {
+            // The end of input carries the trivia after the last token
+            self.ast.push_token(self.current_token.clone());
+        }
+        std::mem::take(&mut self.ast)
+    }
+
+    // Time the lexer has spent producing tokens for this parser.
+    pub fn lex_time(&self) -> Duration {
+        self.lexer.elapsed()
+    }
+
+    fn node(&mut self, location: Location, node: ASTNode) -> NodeId {
+        let id = self.ast.push(node, location);
+        self.record_span(id, location);
+        if self.keep_tokens {
+            self.record_token_range(id, location);
+        }
+        id
+    }
+
+    // The bytes of the tokens `record_token_range` would give the node.
+    fn record_span(&mut self, id: NodeId, location: Location) {
+        let end = self.previous_end_offset;
+        let start = self
+            .ast
+            .children(id)
+            .into_iter()
+            .filter_map(|child| {
+                let span = self.ast.span(child)?;
+                Some(
+                    self.opening_parens
+                        .get(&child)
+                        .and_then(|open| self.token_spans.get(open))
+                        .map_or(span.start, |open| open.start),
+                )
+            })
+            .chain(self.token_spans.get(&location).map(|token| token.start))
+            .min()
+            .filter(|_| !matches!(self.ast[id], ASTNode::NoOp))
+            .unwrap_or(end);
+        self.ast.set_span(id, Span::new(start, end));
+    }
+
+    // A node spans from its own first token (or its first child's, for
+    // operators located at the operator) to the last token consumed.
+    fn record_token_range(&mut self, id: NodeId, location: Location) {
+        let end = self.ast.tokens().len();
+        let start = self
+            .ast
+            .children(id)
+            .into_iter()
+            .filter_map(|child| {
+                let range = self.ast.token_range(child)?;
+                Some(
+                    self.opening_parens
+                        .get(&child)
+                        .and_then(|open| self.token_index.get(open))
+                        .copied()
+                        .unwrap_or(range.start),
+                )
+            })
+            .chain(self.token_index.get(&location).copied())
+            .min()
+            .filter(|_| !matches!(self.ast[id], ASTNode::NoOp))
+            .unwrap_or(end);
+        self.ast.set_token_range(id, start..end);
+    }
+
+    // A node for the single token at `location`, built once later tokens
+    // have been consumed, like the names in `a, b : integer`.
+    fn token_node(&mut self, location: Location, node: ASTNode) -> NodeId {
+        let id = self.ast.push(node, location);
+        if let Some(&index) = self.token_index.get(&location) {
+            self.ast.set_token_range(id, index..index + 1);
+        }
+        if let Some(&span) = self.token_spans.get(&location) {
+            self.ast.set_span(id, span);
+        }
+        id
+    }
+
+    fn location(&self) -> Location {
+        self.current_token.location()
     }
 
     fn current_kind(&self) -> Token {
         self.current_token.token.clone()
     }
 
-    fn current_location(&self) -> &LocatedToken {
-        &self.current_token
+    // The token `n` positions after the current one.
+    fn peek_kind(&mut self, n: usize) -> Result<&Token> {
+        Ok(&self.lexer.peek_nth(n - 1)?.token)
+    }
+
+    fn error(&self, title: impl Into<String>, detail: Option<String>) -> SyntaxError {
+        SyntaxError::with_detail(&self.current_token, self.lexer.source(), title, detail)
+    }
+
+    fn too_deep(&self) -> SyntaxError {
+        self.error(
+            "Nesting too deep",
+            Some(format!(
+                "more than {} levels of nested expressions, statements or procedures",
+                self.max_depth
+            )),
+        )
+    }
+
+    // Runs `parse` one nesting level deeper.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.max_depth {
+            return Err(self.too_deep().into());
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
     fn eat(&mut self, expected_type: Option<&Token>) -> Result<()> {
         if let Some(expected) = expected_type {
             if std::mem::discriminant(&self.current_token.token) != std::mem::discriminant(expected)
             {
-                return Err(
-                    SyntaxError::unexpected_token(&self.current_token, Some(expected)).into(),
-                );
+                return Err(SyntaxError::unexpected_token(
+                    &self.current_token,
+                    self.lexer.source(),
+                    Some(expected),
+                )
+                .with_suggestion(self.suggestion(expected))
+                .into());
             }
         }
-        self.current_token = (&mut self.lexer).next_token()?;
+        let next = self.lexer.next_token()?;
+        let consumed = std::mem::replace(&mut self.current_token, next);
+        let text = &self.lexer.source()[consumed.span.start..consumed.span.end];
+        self.previous_end = Location {
+            line: consumed.line,
+            column: consumed.column + text.chars().count(),
+        };
+        self.previous_end_offset = consumed.span.end;
+        self.token_spans.insert(consumed.location(), consumed.span);
+        if self.keep_tokens {
+            self.token_index
+                .insert(consumed.location(), self.ast.tokens().len());
+            self.ast.push_token(consumed);
+        }
         Ok(())
     }
 
-    fn program(&mut self) -> Result<ASTNode> {
+    // How to get the token `eat` expected instead of the current one, when
+    // that is clear: `=` for `:=`, or a missing `;`, `.` or `)` right after
+    // the previous token.
+    fn suggestion(&self, expected: &Token) -> Option<Suggestion> {
+        let text = match (expected, &self.current_token.token) {
+            (Token::Assign, Token::Equal) => return Some(self.replace_equal()),
+            (Token::Semi, _) => ";",
+            (Token::Dot, _) => ".",
+            (Token::RParenthesis, _) => ")",
+            _ => return None,
+        };
+        Some(Suggestion::insert(
+            self.previous_end,
+            text,
+            format!("insert '{text}' at {}", self.previous_end),
+        ))
+    }
+
+    fn replace_equal(&self) -> Suggestion {
+        let start = self.location();
+        Suggestion {
+            message: "replace '=' with ':='".to_string(),
+            start,
+            end: Location {
+                line: start.line,
+                column: start.column + 1,
+            },
+            replacement: ":=".to_string(),
+        }
+    }
+
+    fn program(&mut self) -> Result<NodeId> {
+        let start = self.location();
         self.eat(Some(&Token::Program))?;
         let var_node = self.variable()?;
-        let ASTNode::Var { name: program_name } = var_node else {
-            let err = SyntaxError::with_detail(
-                self.current_location(),
+        let ASTNode::Var { name: program_name } = self.ast[var_node].clone() else {
+            let err = self.error(
                 "Invalid program declaration",
                 Some("expected a program name after PROGRAM".into()),
             );
@@ -116,67 +430,266 @@ impl<'a> Parser<'a> {
         };
         self.eat(Some(&Token::Semi))?;
         let block = self.block()?;
+        let end = self.location();
+        if self.current_token.token == Token::Eof && !self.strict {
+            self.recoveries.push(Recovery {
+                location: self.previous_end,
+                message: "Missing '.' at the end of the program".to_string(),
+                fix: Suggestion::insert(self.previous_end, ".", "insert '.' after END"),
+            });
+            return Ok(self.node(
+                start,
+                ASTNode::Program {
+                    name: program_name,
+                    block,
+                },
+            ));
+        }
         self.eat(Some(&Token::Dot))?;
-        Ok(ASTNode::Program {
-            name: program_name,
-            block: Box::new(block),
-        })
+        // Anything after the final dot, such as a pasted second copy of the
+        // main block, would otherwise never run without a word
+        if self.current_token.token != Token::Eof {
+            let err = self.error(
+                "Text after the end of the program",
+                Some(format!(
+                    "the program ends with the '.' at line {}, column {}, found {}",
+                    end.line, end.column, self.current_token.token
+                )),
+            );
+            return Err(err.into());
+        }
+        Ok(self.node(
+            start,
+            ASTNode::Program {
+                name: program_name,
+                block,
+            },
+        ))
     }
 
-    fn block(&mut self) -> Result<ASTNode> {
+    fn block(&mut self) -> Result<NodeId> {
+        let start = self.location();
         let declarations = self.declarations()?;
         let cs = self.compound_statement()?;
-        Ok(ASTNode::Block {
-            declarations: declarations,
-            compound_statement: Box::new(cs),
-        })
+        Ok(self.node(
+            start,
+            ASTNode::Block {
+                declarations,
+                compound_statement: cs,
+            },
+        ))
     }
 
-    fn declarations(&mut self) -> Result<Vec<Box<ASTNode>>> {
+    fn declarations(&mut self) -> Result<Vec<NodeId>> {
         let mut declarations = vec![];
 
-        while matches!(self.current_kind(), Token::Var | Token::Procedure) {
-            if matches!(self.current_kind(), Token::Var) {
-                self.eat(Some(&Token::Var))?;
-                while matches!(self.current_kind(), Token::Id(_)) {
-                    let vd = self.variable_declaration()?;
-                    declarations.extend(vd);
-                    self.eat(Some(&Token::Semi))?;
+        loop {
+            match self.current_kind() {
+                Token::Var => {
+                    self.eat(Some(&Token::Var))?;
+                    while matches!(self.current_kind(), Token::Id(_)) && !self.at_generic()? {
+                        let vd = self.variable_declaration(true)?;
+                        declarations.extend(vd);
+                        self.eat(Some(&Token::Semi))?;
+                    }
                 }
-            } else {
-                self.eat(Some(&Token::Procedure))?;
-                let Token::Id(procedure_name) = self.current_kind() else {
-                    let err = SyntaxError::with_detail(
-                        self.current_location(),
-                        "Unexpected token type",
-                        Some("expected identifier after PROCEDURE".into()),
-                    );
-                    return Err(err.into());
-                };
-                self.eat(Some(&Token::Id(String::new())))?;
-
-                let mut params = vec![];
-                if matches!(self.current_kind(), Token::LParenthesis) {
-                    self.eat(Some(&Token::LParenthesis))?;
-                    params = self.formal_parameter_list()?;
-                    self.eat(Some(&Token::RParenthesis))?;
+                Token::Type => {
+                    self.eat(Some(&Token::Type))?;
+                    while matches!(self.current_kind(), Token::Id(_)) && !self.at_generic()? {
+                        let td = self.type_declaration()?;
+                        declarations.push(td);
+                        self.eat(Some(&Token::Semi))?;
+                    }
                 }
-
-                self.eat(Some(&Token::Semi))?;
-                let block = self.block()?;
-                self.eat(Some(&Token::Semi))?;
-                declarations.push(Box::new(ASTNode::ProcedureDecl {
-                    proc_name: procedure_name,
-                    params,
-                    block_node: Box::new(block),
-                }));
+                Token::Id(_) if self.at_generic()? => {
+                    let decl = self.procedure_declaration()?;
+                    declarations.push(decl);
+                }
+                // `procedure TCounter.Add` implements a method
+                Token::Procedure if !matches!(self.peek_kind(2)?, Token::Dot) => {
+                    let decl = self.procedure_declaration()?;
+                    declarations.push(decl);
+                }
+                Token::Procedure | Token::Constructor | Token::Destructor => {
+                    let decl = self.nested(Self::method_declaration)?;
+                    declarations.push(decl);
+                }
+                _ => break,
             }
         }
 
         Ok(declarations)
     }
 
-    fn formal_parameter_list(&mut self) -> Result<Vec<Box<ASTNode>>> {
+    // GENERIC is only a keyword before PROCEDURE, so it can still name a
+    // variable.
+    fn at_generic(&mut self) -> Result<bool> {
+        Ok(
+            matches!(self.current_kind(), Token::Id(id) if id == "generic")
+                && matches!(self.peek_kind(1)?, Token::Procedure),
+        )
+    }
+
+    pub(crate) fn procedure_declaration(&mut self) -> Result<NodeId> {
+        self.nested(Self::procedure_declaration_inner)
+    }
+
+    fn procedure_declaration_inner(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let generic = matches!(self.current_kind(), Token::Id(_));
+        if generic {
+            if !self.dialect.generics() {
+                let err = self.error(
+                    "Generic procedure",
+                    Some(format!("{} does not allow generics", self.dialect)),
+                );
+                return Err(err.into());
+            }
+            self.eat(Some(&Token::Id(String::new())))?;
+        }
+        self.eat(Some(&Token::Procedure))?;
+        let Token::Id(procedure_name) = self.current_kind() else {
+            let err = self.error(
+                "Unexpected token type",
+                Some("expected identifier after PROCEDURE".into()),
+            );
+            return Err(err.into());
+        };
+        self.eat(Some(&Token::Id(String::new())))?;
+        let type_params = if generic { self.type_params()? } else { vec![] };
+
+        let params = self.optional_formal_parameter_list()?;
+
+        self.eat(Some(&Token::Semi))?;
+        let block = self.block()?;
+        self.eat(Some(&Token::Semi))?;
+        Ok(self.node(
+            start,
+            ASTNode::ProcedureDecl {
+                proc_name: procedure_name,
+                type_params,
+                params,
+                block_node: block,
+            },
+        ))
+    }
+
+    // `<T, U>`: the type parameters of a generic procedure
+    fn type_params(&mut self) -> Result<Vec<String>> {
+        self.eat(Some(&Token::LessThan))?;
+        let mut type_params = vec![];
+        loop {
+            let Token::Id(name) = self.current_kind() else {
+                let err = self.error(
+                    "Unexpected token type",
+                    Some("expected a type parameter name".into()),
+                );
+                return Err(err.into());
+            };
+            self.eat(Some(&Token::Id(String::new())))?;
+            type_params.push(name);
+            if !matches!(self.current_kind(), Token::Comma) {
+                break;
+            }
+            self.eat(Some(&Token::Comma))?;
+        }
+        self.eat(Some(&Token::GreaterThan))?;
+        Ok(type_params)
+    }
+
+    fn method_declaration(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let method_kind = self.method_kind()?;
+        let Token::Id(class_name) = self.current_kind() else {
+            let err = self.error(
+                "Unexpected token type",
+                Some(format!("expected class name after {method_kind}")),
+            );
+            return Err(err.into());
+        };
+        self.eat(Some(&Token::Id(String::new())))?;
+        self.eat(Some(&Token::Dot))?;
+        let method_name = self.member_name()?;
+        let params = self.optional_formal_parameter_list()?;
+
+        self.eat(Some(&Token::Semi))?;
+        let block = self.block()?;
+        self.eat(Some(&Token::Semi))?;
+        Ok(self.node(
+            start,
+            ASTNode::MethodDecl {
+                class_name,
+                method_kind,
+                method_name,
+                params,
+                block_node: block,
+            },
+        ))
+    }
+
+    // Eats the PROCEDURE, CONSTRUCTOR or DESTRUCTOR that starts a method.
+    fn method_kind(&mut self) -> Result<MethodKind> {
+        let method_kind = match self.current_kind() {
+            Token::Procedure => MethodKind::Procedure,
+            Token::Constructor => MethodKind::Constructor,
+            Token::Destructor => MethodKind::Destructor,
+            token => {
+                let err = self.error(
+                    "Unexpected token type",
+                    Some(format!("expected a method, found {token}")),
+                );
+                return Err(err.into());
+            }
+        };
+        if !self.dialect.classes() {
+            let err = self.error(
+                "Method",
+                Some(format!("{} does not allow classes", self.dialect)),
+            );
+            return Err(err.into());
+        }
+        self.eat(Some(&self.current_kind()))?;
+        Ok(method_kind)
+    }
+
+    fn member_name(&mut self) -> Result<String> {
+        let Token::Id(name) = self.current_kind() else {
+            let err = self.error(
+                "Unexpected token type",
+                Some("expected a member name after '.'".into()),
+            );
+            return Err(err.into());
+        };
+        self.eat(Some(&Token::Id(String::new())))?;
+        Ok(name)
+    }
+
+    // The parenthesized parameters of a procedure declaration or type, if
+    // there are any.
+    fn optional_formal_parameter_list(&mut self) -> Result<Vec<NodeId>> {
+        let mut params = vec![];
+        if matches!(self.current_kind(), Token::LParenthesis) {
+            self.eat(Some(&Token::LParenthesis))?;
+            if matches!(self.current_kind(), Token::RParenthesis) {
+                if !self.dialect.empty_parameter_lists() {
+                    let err = self.error(
+                        "Empty parameter list",
+                        Some(format!(
+                            "{} does not allow `()` in a procedure declaration",
+                            self.dialect
+                        )),
+                    );
+                    return Err(err.into());
+                }
+            } else {
+                params = self.formal_parameter_list()?;
+            }
+            self.eat(Some(&Token::RParenthesis))?;
+        }
+        Ok(params)
+    }
+
+    fn formal_parameter_list(&mut self) -> Result<Vec<NodeId>> {
         let mut params = self.formal_parameters()?;
 
         while matches!(self.current_kind(), Token::Semi) {
@@ -188,54 +701,81 @@ impl<'a> Parser<'a> {
         Ok(params)
     }
 
-    fn formal_parameters(&mut self) -> Result<Vec<Box<ASTNode>>> {
+    fn formal_parameters(&mut self) -> Result<Vec<NodeId>> {
+        let mode = self.param_mode()?;
         let mut var_names = vec![];
         let Token::Id(var_name) = self.current_kind() else {
-            let err = SyntaxError::with_detail(
-                self.current_location(),
+            let err = self.error(
                 "Unexpected token type",
                 Some("expected identifier in parameter declaration".into()),
             );
             return Err(err.into());
         };
-        var_names.push(var_name);
+        var_names.push((var_name, self.location()));
 
         self.eat(Some(&Token::Id(String::new())))?;
 
         while matches!(self.current_kind(), Token::Comma) {
             self.eat(Some(&Token::Comma))?;
             let Token::Id(var_name) = self.current_kind() else {
-                let err = SyntaxError::with_detail(
-                    self.current_location(),
+                let err = self.error(
                     "Unexpected token type",
                     Some("expected identifier after comma".into()),
                 );
                 return Err(err.into());
             };
-            var_names.push(var_name);
+            var_names.push((var_name, self.location()));
             self.eat(Some(&Token::Id(String::new())))?;
         }
 
         self.eat(Some(&Token::Colon))?;
+        let type_location = self.location();
         let type_spec = self.type_spec()?;
 
-        let result = var_names
-            .iter()
-            .map(|n| {
-                Box::new(ASTNode::Param {
-                    var_node: Box::new(ASTNode::Var { name: n.to_owned() }),
-                    type_node: Box::new(type_spec.clone()),
-                })
-            })
-            .collect();
+        let mut result = vec![];
+        for (name, location) in var_names {
+            let var_node = self.token_node(location, ASTNode::Var { name });
+            let type_node = self.node(type_location, type_spec.clone());
+            result.push(self.node(
+                location,
+                ASTNode::Param {
+                    var_node,
+                    type_node,
+                    mode,
+                },
+            ));
+        }
 
         Ok(result)
     }
 
-    fn proc_call_statement(&mut self) -> Result<ASTNode> {
+    // Eats the VAR, CONST or OUT before a parameter group. OUT is only a
+    // keyword there, so `out` followed by a name is the mode and anything
+    // else a parameter called `out`.
+    fn param_mode(&mut self) -> Result<ParamMode> {
+        let (mode, allowed) = match self.current_kind() {
+            Token::Var => (ParamMode::Var, true),
+            Token::Const => (ParamMode::Const, self.dialect.const_params()),
+            Token::Id(id) if id == "out" && matches!(self.peek_kind(1)?, Token::Id(_)) => {
+                (ParamMode::Out, self.dialect.out_params())
+            }
+            _ => return Ok(ParamMode::Value),
+        };
+        if !allowed {
+            let err = self.error(
+                "Parameter mode",
+                Some(format!("{} does not allow {mode}parameters", self.dialect)),
+            );
+            return Err(err.into());
+        }
+        self.eat(Some(&self.current_kind()))?;
+        Ok(mode)
+    }
+
+    fn procedure_call(&mut self) -> Result<NodeId> {
+        let start = self.location();
         let Token::Id(proc_name) = self.current_kind() else {
-            let err = SyntaxError::with_detail(
-                self.current_location(),
+            let err = self.error(
                 "Expected function name",
                 Some("Expected function identifier before ()".into()),
             );
@@ -243,73 +783,269 @@ impl<'a> Parser<'a> {
         };
 
         self.eat(Some(&Token::Id("".to_string())))?;
+        let argument_nodes = self.arguments()?;
+
+        Ok(self.node(
+            start,
+            ASTNode::ProcedureCall {
+                proc_name,
+                arguments: argument_nodes,
+            },
+        ))
+    }
+
+    fn bare_procedure_call(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let Token::Id(proc_name) = self.current_kind() else {
+            let err = self.error("Unexpected token type", Some("expected identifier".into()));
+            return Err(err.into());
+        };
+        self.eat(Some(&Token::Id("".to_string())))?;
+        Ok(self.node(
+            start,
+            ASTNode::ProcedureCall {
+                proc_name,
+                arguments: vec![],
+            },
+        ))
+    }
+
+    // `(expr, ...)` after the name of a procedure or method.
+    fn arguments(&mut self) -> Result<Vec<NodeId>> {
         self.eat(Some(&Token::LParenthesis))?;
 
         let mut argument_nodes = vec![];
         if !matches!(self.current_kind(), Token::RParenthesis,) {
             let expr = self.expr()?;
-            argument_nodes.push(Box::new(expr));
+            argument_nodes.push(expr);
         }
 
         while let Token::Comma = self.current_kind() {
             self.eat(Some(&Token::Comma))?;
             let expr = self.expr()?;
-            argument_nodes.push(Box::new(expr));
+            argument_nodes.push(expr);
         }
 
         self.eat(Some(&Token::RParenthesis))?;
-
-        Ok(ASTNode::ProcedureCall {
-            proc_name: proc_name,
-            arguments: argument_nodes,
-            proc_symbol: RefCell::new(None),
-        })
+        Ok(argument_nodes)
     }
 
-    fn variable_declaration(&mut self) -> Result<Vec<Box<ASTNode>>> {
+    // `initializers` is false for the fields of a class, which can't have
+    // one.
+    fn variable_declaration(&mut self, initializers: bool) -> Result<Vec<NodeId>> {
         let mut var_names = vec![];
         let Token::Id(var_name) = self.current_kind() else {
-            let err = SyntaxError::with_detail(
-                self.current_location(),
+            let err = self.error(
                 "Unexpected token type",
                 Some("expected identifier in declaration".into()),
             );
             return Err(err.into());
         };
-        var_names.push(var_name);
+        var_names.push((var_name, self.location()));
 
         self.eat(Some(&Token::Id(String::new())))?;
 
         while matches!(self.current_kind(), Token::Comma) {
             self.eat(Some(&Token::Comma))?;
             let Token::Id(var_name) = self.current_kind() else {
-                let err = SyntaxError::with_detail(
-                    self.current_location(),
+                let err = self.error(
                     "Unexpected token type",
                     Some("expected identifier after comma".into()),
                 );
                 return Err(err.into());
             };
-            var_names.push(var_name);
+            var_names.push((var_name, self.location()));
             self.eat(Some(&Token::Id(String::new())))?;
         }
 
         self.eat(Some(&Token::Colon))?;
+        let type_location = self.location();
         let type_spec = self.type_spec()?;
 
-        let result = var_names
-            .iter()
-            .map(|n| {
-                Box::new(ASTNode::VarDecl {
-                    var_node: Box::new(ASTNode::Var { name: n.to_owned() }),
-                    type_node: Box::new(type_spec.clone()),
-                })
-            })
-            .collect();
+        let mut initializer = None;
+        if matches!(self.current_kind(), Token::Equal) {
+            if !initializers {
+                return Err(self
+                    .error(
+                        "Unexpected '='",
+                        Some("fields can't have an initializer".into()),
+                    )
+                    .into());
+            }
+            if !self.dialect.initialized_variables() {
+                let err = self.error(
+                    "Initialized variable",
+                    Some(format!(
+                        "{} does not allow initialized variables",
+                        self.dialect
+                    )),
+                );
+                return Err(err.into());
+            }
+            if var_names.len() > 1 {
+                return Err(self
+                    .error(
+                        "Unexpected '='",
+                        Some("only a single variable can have an initializer".into()),
+                    )
+                    .into());
+            }
+            self.eat(Some(&Token::Equal))?;
+            initializer = Some(self.expr()?);
+        }
+
+        let mut result = vec![];
+        for (name, location) in var_names {
+            let var_node = self.token_node(location, ASTNode::Var { name });
+            let type_node = self.node(type_location, type_spec.clone());
+            result.push(self.node(
+                location,
+                ASTNode::VarDecl {
+                    var_node,
+                    type_node,
+                    initializer,
+                },
+            ));
+        }
 
         Ok(result)
     }
 
+    fn type_declaration(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let Token::Id(type_name) = self.current_kind() else {
+            let err = self.error(
+                "Unexpected token type",
+                Some("expected identifier in type declaration".into()),
+            );
+            return Err(err.into());
+        };
+        self.eat(Some(&Token::Id(String::new())))?;
+        self.eat(Some(&Token::Equal))?;
+
+        let type_location = self.location();
+        let type_node = if matches!(self.current_kind(), Token::Class) {
+            self.class_type()?
+        } else if matches!(self.current_kind(), Token::Procedure) {
+            if !self.dialect.procedural_types() {
+                let err = self.error(
+                    "Procedural type",
+                    Some(format!("{} does not allow procedure types", self.dialect)),
+                );
+                return Err(err.into());
+            }
+            self.eat(Some(&Token::Procedure))?;
+            let params = self.optional_formal_parameter_list()?;
+            self.node(type_location, ASTNode::ProcedureType { params })
+        } else {
+            let type_spec = self.type_spec()?;
+            self.node(type_location, type_spec)
+        };
+
+        Ok(self.node(
+            start,
+            ASTNode::TypeDecl {
+                type_name,
+                type_node,
+            },
+        ))
+    }
+
+    // `class`, then fields, method headings and properties, then `end`.
+    fn class_type(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        if !self.dialect.classes() {
+            let err = self.error(
+                "Class type",
+                Some(format!("{} does not allow classes", self.dialect)),
+            );
+            return Err(err.into());
+        }
+        self.eat(Some(&Token::Class))?;
+
+        let mut members = vec![];
+        loop {
+            match self.current_kind() {
+                Token::Id(_) => members.extend(self.variable_declaration(false)?),
+                Token::Procedure | Token::Constructor | Token::Destructor => {
+                    let heading_start = self.location();
+                    let method_kind = self.method_kind()?;
+                    let name = self.member_name()?;
+                    let params = self.optional_formal_parameter_list()?;
+                    members.push(self.node(
+                        heading_start,
+                        ASTNode::MethodHeading {
+                            method_kind,
+                            name,
+                            params,
+                        },
+                    ));
+                }
+                Token::Property => members.push(self.property()?),
+                _ => break,
+            }
+            self.eat(Some(&Token::Semi))?;
+        }
+        self.eat(Some(&Token::End))?;
+
+        Ok(self.node(start, ASTNode::ClassType { members }))
+    }
+
+    // `property name: type`, then `read reader`, `write writer` or both.
+    // READ and WRITE are only keywords here, so they arrive as identifiers.
+    fn property(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        self.eat(Some(&Token::Property))?;
+        let Token::Id(name) = self.current_kind() else {
+            let err = self.error(
+                "Unexpected token type",
+                Some("expected a property name".into()),
+            );
+            return Err(err.into());
+        };
+        self.eat(Some(&Token::Id(String::new())))?;
+        self.eat(Some(&Token::Colon))?;
+        let type_location = self.location();
+        let type_spec = self.type_spec()?;
+        let type_node = self.node(type_location, type_spec);
+
+        let reader = self.accessor("read")?;
+        let writer = self.accessor("write")?;
+        if reader.is_none() && writer.is_none() {
+            let err = self.error(
+                "Unexpected token type",
+                Some(format!("expected 'read' or 'write' for property '{name}'")),
+            );
+            return Err(err.into());
+        }
+        Ok(self.node(
+            start,
+            ASTNode::Property {
+                name,
+                type_node,
+                reader,
+                writer,
+            },
+        ))
+    }
+
+    // The name after `read` or `write` in a property, if that comes next.
+    fn accessor(&mut self, directive: &str) -> Result<Option<String>> {
+        if !matches!(self.current_kind(), Token::Id(id) if id == directive) {
+            return Ok(None);
+        }
+        self.eat(Some(&Token::Id(String::new())))?;
+        let Token::Id(name) = self.current_kind() else {
+            let err = self.error(
+                "Unexpected token type",
+                Some(format!("expected a field or method after '{directive}'")),
+            );
+            return Err(err.into());
+        };
+        self.eat(Some(&Token::Id(String::new())))?;
+        Ok(Some(name))
+    }
+
     fn type_spec(&mut self) -> Result<ASTNode> {
         match self.current_kind() {
             Token::Integer => {
@@ -324,38 +1060,58 @@ impl<'a> Parser<'a> {
                     value: BuiltinTypes::Real.to_string(),
                 })
             }
-            _ => Err(SyntaxError::with_detail(
-                self.current_location(),
-                "Unsupported variable type",
-                Some(format!("found {}", self.current_location().token.clone())),
-            )
-            .into()),
+            Token::Id(name) => {
+                self.eat(Some(&Token::Id(String::new())))?;
+                Ok(ASTNode::Type { value: name })
+            }
+            _ => Err(self
+                .error(
+                    "Unsupported variable type",
+                    Some(format!("found {}", self.current_kind())),
+                )
+                .into()),
         }
     }
 
-    fn compound_statement(&mut self) -> Result<ASTNode> {
+    fn compound_statement(&mut self) -> Result<NodeId> {
+        let start = self.location();
         self.eat(Some(&Token::Begin))?;
         let statement_list = self.statement_list()?;
         self.eat(Some(&Token::End))?;
-        Ok(ASTNode::Compound {
-            children: statement_list,
-        })
+        Ok(self.node(
+            start,
+            ASTNode::Compound {
+                children: statement_list,
+            },
+        ))
     }
 
-    fn statement_list(&mut self) -> Result<Vec<Box<ASTNode>>> {
-        let statement: ASTNode = self.statement()?;
-        let mut statement_list = vec![Box::new(statement)];
+    fn statement_list(&mut self) -> Result<Vec<NodeId>> {
+        let statement = self.statement()?;
+        let mut statement_list = vec![statement];
 
         while matches!(self.current_kind(), Token::Semi) {
             self.eat(Some(&Token::Semi))?;
-            statement_list.push(Box::new(self.statement()?));
+            statement_list.push(self.statement()?);
         }
 
-        if matches!(self.current_kind(), Token::Id(_)) {
-            let err = SyntaxError::with_detail(
-                self.current_location(),
+        if matches!(
+            self.current_kind(),
+            Token::Id(_) | Token::If | Token::While | Token::Repeat | Token::Case
+        ) {
+            let err = self
+                .error(
+                    "Unexpected token type",
+                    Some("possible missing semicolon between statements".into()),
+                )
+                .with_suggestion(self.suggestion(&Token::Semi));
+            return Err(err.into());
+        }
+        // `IF c THEN a; ELSE b`: the `;` already ended the IF
+        if matches!(self.current_kind(), Token::Else) {
+            let err = self.error(
                 "Unexpected token type",
-                Some("possible missing semicolon between statements".into()),
+                Some("ELSE without IF; a ';' before ELSE ends the IF statement".into()),
             );
             return Err(err.into());
         }
@@ -363,93 +1119,343 @@ impl<'a> Parser<'a> {
         Ok(statement_list)
     }
 
-    fn statement(&mut self) -> Result<ASTNode> {
+    fn statement(&mut self) -> Result<NodeId> {
+        self.nested(Self::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> Result<NodeId> {
         match self.current_kind() {
             Token::Begin => self.compound_statement(),
-            Token::Id(_) => {
-                if let LocatedToken {
-                    token: Token::LParenthesis,
-                    ..
-                } = self.lexer.peek_token()?
-                {
-                    self.proc_call_statement()
-                } else {
-                    self.assignment_statement()
-                }
-            }
+            Token::If => self.if_statement(),
+            Token::While => self.while_statement(),
+            Token::Repeat => self.repeat_statement(),
+            Token::Case => self.case_statement(),
+            Token::Id(_) => match self.peek_kind(1)? {
+                Token::LParenthesis => self.procedure_call(),
+                Token::Dot => self.member_statement(),
+                // `DoThing;` calls a procedure without parameters
+                Token::Semi | Token::End | Token::Until | Token::Else => self.bare_procedure_call(),
+                _ => self.assignment_statement(),
+            },
             _ => self.empty(),
         }
     }
 
-    fn assignment_statement(&mut self) -> Result<ASTNode> {
-        let var_node = self.variable()?;
+    // An ELSE goes with the innermost IF, as the recursive call for the
+    // THEN branch takes it before returning.
+    fn if_statement(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        self.eat(Some(&Token::If))?;
+        let condition = self.expr()?;
+        self.eat(Some(&Token::Then))?;
+        let then_branch = self.statement()?;
+        let else_branch = if let Token::Else = self.current_kind() {
+            self.eat(Some(&Token::Else))?;
+            Some(self.statement()?)
+        } else {
+            None
+        };
+        Ok(self.node(
+            start,
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            },
+        ))
+    }
+
+    fn while_statement(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        self.eat(Some(&Token::While))?;
+        let condition = self.expr()?;
+        self.eat(Some(&Token::Do))?;
+        let body = self.statement()?;
+        Ok(self.node(start, ASTNode::While { condition, body }))
+    }
+
+    // Like BEGIN ... END, REPEAT ... UNTIL holds a statement list.
+    fn repeat_statement(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        self.eat(Some(&Token::Repeat))?;
+        let body = self.statement_list()?;
+        self.eat(Some(&Token::Until))?;
+        let condition = self.expr()?;
+        Ok(self.node(start, ASTNode::Repeat { body, condition }))
+    }
+
+    // A `;` may follow the last arm. An ELSE directly after an arm that is
+    // an IF belongs to that IF, as it would anywhere else.
+    fn case_statement(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        self.eat(Some(&Token::Case))?;
+        let selector = self.expr()?;
+        self.eat(Some(&Token::Of))?;
+        let mut arms = vec![self.case_arm()?];
+        while matches!(self.current_kind(), Token::Semi) {
+            self.eat(Some(&Token::Semi))?;
+            if matches!(self.current_kind(), Token::Else | Token::End) {
+                break;
+            }
+            arms.push(self.case_arm()?);
+        }
+        let else_branch = if let Token::Else = self.current_kind() {
+            if !self.dialect.case_else() {
+                let err = self.error(
+                    "ELSE in CASE",
+                    Some(format!(
+                        "{} does not allow an ELSE part in a CASE statement",
+                        self.dialect
+                    )),
+                );
+                return Err(err.into());
+            }
+            self.eat(Some(&Token::Else))?;
+            Some(self.statement_list()?)
+        } else {
+            None
+        };
+        self.eat(Some(&Token::End))?;
+        Ok(self.node(
+            start,
+            ASTNode::Case {
+                selector,
+                arms,
+                else_branch,
+            },
+        ))
+    }
+
+    fn case_arm(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let mut labels = vec![self.case_label()?];
+        while matches!(self.current_kind(), Token::Comma) {
+            self.eat(Some(&Token::Comma))?;
+            labels.push(self.case_label()?);
+        }
+        self.eat(Some(&Token::Colon))?;
+        let statement = self.statement()?;
+        Ok(self.node(start, ASTNode::CaseArm { labels, statement }))
+    }
+
+    fn case_label(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let low = self.case_constant()?;
+        if !matches!(self.current_kind(), Token::Range) {
+            return Ok(low);
+        }
+        self.eat(Some(&Token::Range))?;
+        let high = self.case_constant()?;
+        Ok(self.node(start, ASTNode::Range { low, high }))
+    }
+
+    // An INTEGER literal with an optional sign. The language has no named
+    // constants yet.
+    fn case_constant(&mut self) -> Result<NodeId> {
+        let start = self.location();
         let token = self.current_kind();
-        self.eat(Some(&Token::Assign))?;
+        if let Token::Plus | Token::Minus = token {
+            self.eat(Some(&token))?;
+            let expr = self.case_integer()?;
+            return Ok(self.node(start, ASTNode::UnaryOpNode { token, expr }));
+        }
+        self.case_integer()
+    }
+
+    fn case_integer(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let Token::IntegerConst(value) = self.current_kind() else {
+            let err = self.error(
+                "Invalid case label",
+                Some("a case label must be an INTEGER constant".into()),
+            );
+            return Err(err.into());
+        };
+        self.eat(Some(&Token::IntegerConst(0)))?;
+        Ok(self.node(
+            start,
+            ASTNode::NumNode {
+                value: Value::Int(value),
+            },
+        ))
+    }
+
+    fn assignment_statement(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let var_node = self.variable()?;
+        self.assign_operator()?;
         let expr_node = self.expr()?;
-        Ok(ASTNode::Assign {
-            left: Box::new(var_node),
-            right: Box::new(expr_node),
-            token: token,
-        })
+        Ok(self.node(
+            start,
+            ASTNode::Assign {
+                left: var_node,
+                right: expr_node,
+                token: Token::Assign,
+            },
+        ))
+    }
+
+    // `:=`, or `=` in its place when the parser is permissive, as is
+    // easy to write after other languages or mathematics.
+    fn assign_operator(&mut self) -> Result<()> {
+        if self.current_token.token == Token::Equal && !self.strict {
+            self.recoveries.push(Recovery {
+                location: self.location(),
+                message: "'=' compares values, assignment is ':='".to_string(),
+                fix: self.replace_equal(),
+            });
+            return self.eat(Some(&Token::Equal));
+        }
+        self.eat(Some(&Token::Assign))
+    }
+
+    // `c.count := 1`, `c.Add(2)` or `c.Free`: a method call, with or
+    // without arguments, unless an assignment follows.
+    fn member_statement(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let designator = self.designator()?;
+        if let Token::Assign | Token::Equal = self.current_kind() {
+            self.assign_operator()?;
+            let expr_node = self.expr()?;
+            return Ok(self.node(
+                start,
+                ASTNode::Assign {
+                    left: designator,
+                    right: expr_node,
+                    token: Token::Assign,
+                },
+            ));
+        }
+        if let ASTNode::FieldAccess { object, field } = &self.ast[designator] {
+            self.ast[designator] = ASTNode::MethodCall {
+                object: *object,
+                method: field.clone(),
+                arguments: vec![],
+            };
+        }
+        Ok(designator)
     }
 
-    fn empty(&mut self) -> Result<ASTNode> {
-        Ok(ASTNode::NoOp)
+    fn empty(&mut self) -> Result<NodeId> {
+        Ok(self.node(self.location(), ASTNode::NoOp))
     }
 
-    fn variable(&mut self) -> Result<ASTNode> {
+    fn variable(&mut self) -> Result<NodeId> {
+        let start = self.location();
         let token = self.current_kind();
         if let Token::Id(name) = token.clone() {
             self.eat(Some(&token))?;
-            Ok(ASTNode::Var { name })
+            Ok(self.node(start, ASTNode::Var { name }))
         } else {
-            let err = SyntaxError::with_detail(
-                self.current_location(),
-                "Unexpected token type",
-                Some("expected identifier".into()),
-            );
+            let err = self.error("Unexpected token type", Some("expected identifier".into()));
             Err(err.into())
         }
     }
 
-    fn factor(&mut self) -> Result<ASTNode> {
-        match self.current_kind() {
-            Token::Plus => {
-                self.eat(Some(&Token::Plus))?;
-                Ok(ASTNode::UnaryOpNode {
-                    token: Token::Plus,
-                    expr: Box::new(self.factor()?),
-                })
+    // A variable or a call `name(args)`, told apart by the token after the
+    // name, followed by any number of `.field` and `.method(args)`.
+    fn designator(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let mut node = match self.peek_kind(1)? {
+            Token::LParenthesis => self.procedure_call()?,
+            _ => self.variable()?,
+        };
+        // Every member nests the designator so far one level deeper
+        let mut chain = 0;
+        while let Token::Dot = self.current_kind() {
+            chain += 1;
+            if self.depth + chain > self.max_depth {
+                return Err(self.too_deep().into());
             }
-            Token::Minus => {
-                self.eat(Some(&Token::Minus))?;
-                Ok(ASTNode::UnaryOpNode {
-                    token: Token::Minus,
-                    expr: Box::new(self.factor()?),
-                })
+            self.eat(Some(&Token::Dot))?;
+            let member = self.member_name()?;
+            let member_node = if let Token::LParenthesis = self.current_kind() {
+                ASTNode::MethodCall {
+                    object: node,
+                    method: member,
+                    arguments: self.arguments()?,
+                }
+            } else {
+                ASTNode::FieldAccess {
+                    object: node,
+                    field: member,
+                }
+            };
+            node = self.node(start, member_node);
+        }
+        Ok(node)
+    }
+
+    fn expr(&mut self) -> Result<NodeId> {
+        self.expression(Precedence::Lowest)
+    }
+
+    // Precedence climbing: parses an operand followed by every binary
+    // operator that binds tighter than `min`, together with its right-hand
+    // side. All binary operators are left-associative.
+    fn expression(&mut self, min: Precedence) -> Result<NodeId> {
+        self.nested(|parser| parser.expression_inner(min))
+    }
+
+    fn expression_inner(&mut self, min: Precedence) -> Result<NodeId> {
+        let mut left = self.prefix()?;
+
+        // A chain like `a + b + c` is a loop here, so only its operands count
+        // towards the depth limit. The tree still nests the chain so far as
+        // the left operand of each operator; code walking it deeply guards
+        // its stack with `visitor::ensure_stack`.
+        while let Some(precedence) = binary_precedence(&self.current_token.token) {
+            if precedence <= min {
+                break;
             }
+            let op = self.current_kind();
+            let at = self.location();
+            self.eat(Some(&op))?;
+            let right = self.expression(precedence)?;
+            left = self.node(at, ASTNode::BinOpNode { left, right, op });
+        }
+
+        Ok(left)
+    }
+
+    fn prefix(&mut self) -> Result<NodeId> {
+        let start = self.location();
+        let token = self.current_kind();
+        if let Some(precedence) = unary_precedence(&token) {
+            self.eat(Some(&token))?;
+            let expr = self.expression(precedence)?;
+            return Ok(self.node(start, ASTNode::UnaryOpNode { token, expr }));
+        }
+
+        match token {
             Token::IntegerConst(val) => {
                 self.eat(Some(&Token::IntegerConst(0)))?;
-                Ok(ASTNode::NumNode {
-                    value: BuiltinNumTypes::I32(val),
-                })
+                Ok(self.node(
+                    start,
+                    ASTNode::NumNode {
+                        value: Value::Int(val),
+                    },
+                ))
             }
             Token::RealConst(val) => {
                 self.eat(Some(&Token::RealConst(0.0)))?;
-                Ok(ASTNode::NumNode {
-                    value: BuiltinNumTypes::F32(val),
-                })
+                Ok(self.node(
+                    start,
+                    ASTNode::NumNode {
+                        value: Value::Real(val),
+                    },
+                ))
             }
             Token::LParenthesis => {
                 self.eat(Some(&Token::LParenthesis))?;
                 let result = self.expr()?;
                 self.eat(Some(&Token::RParenthesis))?;
+                self.opening_parens.insert(result, start);
                 Ok(result)
             }
-            Token::Id(_) => self.variable(),
+            Token::Id(_) => self.designator(),
             _ => {
-                let err = SyntaxError::with_detail(
-                    self.current_location(),
+                let err = self.error(
                     "Unexpected token type",
                     Some("expected numeric literal or factor".into()),
                 );
@@ -457,61 +1463,39 @@ impl<'a> Parser<'a> {
             }
         }
     }
+}
 
-    fn term(&mut self) -> Result<ASTNode> {
-        let mut result = self.factor()?;
-
-        loop {
-            let op = self.current_kind();
-
-            match op {
-                Token::Eof => break,
-                Token::Asterisk | Token::FloatDiv | Token::IntegerDiv => {
-                    self.eat(Some(&op))?;
-
-                    let right_node = self.factor()?;
+// Binding strength of operators, loosest first, following Pascal's levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Precedence {
+    Lowest,
+    Relational,
+    Additive,
+    Multiplicative,
+    Unary,
+}
 
-                    result = ASTNode::BinOpNode {
-                        left: Box::new(result),
-                        right: Box::new(right_node),
-                        op,
-                    }
-                }
-                _ => break,
-            }
+// The operator table: adding an operator to the language only takes an
+// entry here and its evaluation.
+pub(crate) fn binary_precedence(token: &Token) -> Option<Precedence> {
+    match token {
+        Token::Equal
+        | Token::NotEqual
+        | Token::LessThan
+        | Token::LessEqual
+        | Token::GreaterThan
+        | Token::GreaterEqual => Some(Precedence::Relational),
+        Token::Plus | Token::Minus | Token::Or => Some(Precedence::Additive),
+        Token::Asterisk | Token::FloatDiv | Token::IntegerDiv | Token::And => {
+            Some(Precedence::Multiplicative)
         }
-
-        Ok(result)
+        _ => None,
     }
+}
 
-    fn expr(&mut self) -> Result<ASTNode> {
-        let mut result = self.term()?;
-
-        loop {
-            let op = self.current_kind();
-
-            match op {
-                Token::Eof => break,
-                Token::Plus | Token::Minus => {
-                    self.eat(Some(&op))?;
-
-                    let right = self.term()?;
-
-                    match op {
-                        Token::Plus | Token::Minus => {
-                            result = ASTNode::BinOpNode {
-                                left: Box::new(result),
-                                right: Box::new(right),
-                                op,
-                            }
-                        }
-                        _ => break,
-                    }
-                }
-                _ => break,
-            }
-        }
-
-        Ok(result)
+fn unary_precedence(token: &Token) -> Option<Precedence> {
+    match token {
+        Token::Plus | Token::Minus | Token::Not => Some(Precedence::Unary),
+        _ => None,
     }
 }
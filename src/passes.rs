@@ -0,0 +1,72 @@
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::ast::Ast;
+use crate::token::Location;
+
+// Something a pass did to the program, e.g. a statement an optimization
+// removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remark {
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for Remark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+// A unit of work over a whole program: an optimization that rewrites the
+// tree, a check that reports errors, or an analysis that only collects data.
+pub trait Pass {
+    fn name(&self) -> &str;
+
+    fn run(&mut self, ast: &mut Ast) -> Result<()>;
+
+    // What the last run changed, for passes that report it
+    fn remarks(&self) -> Vec<Remark> {
+        vec![]
+    }
+}
+
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        PassManager { passes: vec![] }
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'static) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    // The remarks of every pass, in source order.
+    pub fn remarks(&self) -> Vec<Remark> {
+        let mut remarks = self
+            .passes
+            .iter()
+            .flat_map(|pass| pass.remarks())
+            .collect::<Vec<_>>();
+        remarks.sort_by_key(|remark| remark.location);
+        remarks
+    }
+
+    // Runs every registered pass in order, stopping at the first failure.
+    pub fn run(&mut self, ast: &mut Ast) -> Result<()> {
+        for pass in &mut self.passes {
+            pass.run(ast)?;
+        }
+        Ok(())
+    }
+}
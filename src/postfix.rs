@@ -0,0 +1,236 @@
+use std::fmt::Write;
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::token::Token;
+use crate::visitor::{ensure_stack, walk, Visitor};
+
+// Translates a program to reverse Polish notation, one line per statement,
+// labeled with where the statement starts. Assignments put the target
+// first and `:=` last (`x a 2 * :=` for `x := a * 2`), unary minus
+// becomes `neg`, and procedure calls push their arguments before `name call`.
+// A method call pushes its receiver as the first argument, and a field
+// access is `object field .`. A variable's initializer is an assignment,
+// on its line before the block's statements. An IF is its condition followed by `if`,
+// then the statements of each branch on lines of their own, with `else`
+// and `end if` lines in between and after.
+#[derive(Debug, Default)]
+pub struct PostfixTranslator {
+    output: String,
+}
+
+impl PostfixTranslator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn translate(&mut self, ast: &Ast) -> String {
+        self.output.clear();
+        self.visit(ast, ast.root());
+        std::mem::take(&mut self.output)
+    }
+
+    // The RPN of a single expression or statement.
+    pub fn expression(&self, ast: &Ast, node: NodeId) -> String {
+        let mut terms = vec![];
+        postfix(ast, node, &mut terms);
+        terms.join(" ")
+    }
+}
+
+impl Visitor for PostfixTranslator {
+    fn visit(&mut self, ast: &Ast, node: NodeId) {
+        match &ast[node] {
+            ASTNode::Program { name, .. } => {
+                let _ = writeln!(self.output, "PROGRAM {}", name);
+                walk(self, ast, node);
+            }
+            ASTNode::ProcedureDecl { proc_name, .. } => {
+                let _ = writeln!(self.output, "PROCEDURE {}", proc_name);
+                walk(self, ast, node);
+                let _ = writeln!(self.output, "END {}", proc_name);
+            }
+            ASTNode::MethodDecl {
+                class_name,
+                method_name,
+                ..
+            } => {
+                let name = format!("{}.{}", class_name, method_name);
+                let _ = writeln!(self.output, "PROCEDURE {}", name);
+                walk(self, ast, node);
+                let _ = writeln!(self.output, "END {}", name);
+            }
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let _ = writeln!(
+                    self.output,
+                    "  {:<8}{} if",
+                    format!("{}:", ast.location(node)),
+                    self.expression(ast, *condition)
+                );
+                self.visit(ast, *then_branch);
+                if let Some(else_branch) = else_branch {
+                    let _ = writeln!(self.output, "  {:<8}else", "");
+                    self.visit(ast, *else_branch);
+                }
+                let _ = writeln!(self.output, "  {:<8}end if", "");
+            }
+            ASTNode::While { condition, body } => {
+                let _ = writeln!(
+                    self.output,
+                    "  {:<8}{} while",
+                    format!("{}:", ast.location(node)),
+                    self.expression(ast, *condition)
+                );
+                self.visit(ast, *body);
+                let _ = writeln!(self.output, "  {:<8}end while", "");
+            }
+            ASTNode::Repeat { body, condition } => {
+                let _ = writeln!(
+                    self.output,
+                    "  {:<8}repeat",
+                    format!("{}:", ast.location(node))
+                );
+                for statement in body {
+                    self.visit(ast, *statement);
+                }
+                let _ = writeln!(
+                    self.output,
+                    "  {:<8}{} until",
+                    format!("{}:", ast.location(*condition)),
+                    self.expression(ast, *condition)
+                );
+            }
+            ASTNode::Case {
+                selector,
+                arms,
+                else_branch,
+            } => {
+                let _ = writeln!(
+                    self.output,
+                    "  {:<8}{} case",
+                    format!("{}:", ast.location(node)),
+                    self.expression(ast, *selector)
+                );
+                for arm in arms {
+                    let ASTNode::CaseArm { labels, statement } = &ast[*arm] else {
+                        continue;
+                    };
+                    let labels = labels
+                        .iter()
+                        .map(|label| self.expression(ast, *label))
+                        .collect::<Vec<_>>();
+                    let _ = writeln!(
+                        self.output,
+                        "  {:<8}{} of",
+                        format!("{}:", ast.location(*arm)),
+                        labels.join(", ")
+                    );
+                    self.visit(ast, *statement);
+                }
+                if let Some(else_branch) = else_branch {
+                    let _ = writeln!(self.output, "  {:<8}else", "");
+                    for statement in else_branch {
+                        self.visit(ast, *statement);
+                    }
+                }
+                let _ = writeln!(self.output, "  {:<8}end case", "");
+            }
+            statement if statement.is_statement() => {
+                let _ = writeln!(
+                    self.output,
+                    "  {:<8}{}",
+                    format!("{}:", ast.location(node)),
+                    self.expression(ast, node)
+                );
+            }
+            // Parameters and variables only declare storage, and types
+            // nothing at all. An initializer is an assignment.
+            ASTNode::Param { .. } | ASTNode::VarDecl { .. } | ASTNode::TypeDecl { .. } => {
+                if let ASTNode::VarDecl {
+                    initializer: Some(_),
+                    ..
+                } = &ast[node]
+                {
+                    let _ = writeln!(
+                        self.output,
+                        "  {:<8}{}",
+                        format!("{}:", ast.location(node)),
+                        self.expression(ast, node)
+                    );
+                }
+            }
+            _ => walk(self, ast, node),
+        }
+    }
+}
+
+fn postfix(ast: &Ast, node: NodeId, terms: &mut Vec<String>) {
+    ensure_stack(|| match &ast[node] {
+        ASTNode::NumNode { value } => terms.push(value.to_string()),
+        ASTNode::Var { name } => terms.push(name.clone()),
+        ASTNode::UnaryOpNode { expr, token } => {
+            postfix(ast, *expr, terms);
+            match token {
+                Token::Minus => terms.push("neg".to_string()),
+                Token::Not => terms.push(token.to_string()),
+                _ => {}
+            }
+        }
+        ASTNode::BinOpNode { left, right, op } => {
+            postfix(ast, *left, terms);
+            postfix(ast, *right, terms);
+            terms.push(op.to_string());
+        }
+        ASTNode::Range { low, high } => {
+            postfix(ast, *low, terms);
+            postfix(ast, *high, terms);
+            terms.push("..".to_string());
+        }
+        ASTNode::Assign { left, right, .. }
+        | ASTNode::VarDecl {
+            var_node: left,
+            initializer: Some(right),
+            ..
+        } => {
+            postfix(ast, *left, terms);
+            postfix(ast, *right, terms);
+            terms.push(":=".to_string());
+        }
+        ASTNode::ProcedureCall {
+            proc_name,
+            arguments,
+            ..
+        } => {
+            for argument in arguments {
+                postfix(ast, *argument, terms);
+            }
+            terms.push(proc_name.clone());
+            terms.push("call".to_string());
+        }
+        ASTNode::FieldAccess { object, field } => {
+            postfix(ast, *object, terms);
+            terms.push(field.clone());
+            terms.push(".".to_string());
+        }
+        ASTNode::MethodCall {
+            object,
+            method,
+            arguments,
+        } => {
+            postfix(ast, *object, terms);
+            for argument in arguments {
+                postfix(ast, *argument, terms);
+            }
+            terms.push(method.clone());
+            terms.push("call".to_string());
+        }
+        _ => {
+            for child in ast.children(node) {
+                postfix(ast, child, terms);
+            }
+        }
+    })
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+#[derive(Default)]
+struct LineStats {
+    steps: u64,
+    self_time: Duration,
+}
+
+#[derive(Default)]
+struct ProcedureStats {
+    calls: u64,
+    steps: u64,
+    self_time: Duration,
+    total_time: Duration,
+}
+
+struct Frame {
+    name: String,
+    started: Instant,
+}
+
+// Instruments the interpreter: every evaluated node is timed and its self
+// time (excluding the nodes evaluated beneath it) is charged to the node's
+// source line and to the procedure whose frame is on top of the call stack.
+pub struct Profiler {
+    started: Instant,
+    lines: BTreeMap<usize, LineStats>,
+    procedures: BTreeMap<String, ProcedureStats>,
+    frames: Vec<Frame>,
+    // Start time and time spent in children, per node being evaluated
+    nodes: Vec<(Instant, Duration)>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            started: Instant::now(),
+            lines: BTreeMap::new(),
+            procedures: BTreeMap::new(),
+            frames: vec![],
+            nodes: vec![],
+        }
+    }
+
+    pub fn enter_node(&mut self) {
+        self.nodes.push((Instant::now(), Duration::ZERO));
+    }
+
+    pub fn exit_node(&mut self, line: usize) {
+        let Some((started, children)) = self.nodes.pop() else {
+            return;
+        };
+        let elapsed = started.elapsed();
+        let self_time = elapsed.saturating_sub(children);
+        if let Some((_, parent_children)) = self.nodes.last_mut() {
+            *parent_children += elapsed;
+        }
+
+        let stats = self.lines.entry(line).or_default();
+        stats.steps += 1;
+        stats.self_time += self_time;
+
+        if let Some(frame) = self.frames.last() {
+            let stats = self.procedures.entry(frame.name.clone()).or_default();
+            stats.steps += 1;
+            stats.self_time += self_time;
+        }
+    }
+
+    pub fn enter_procedure(&mut self, name: &str) {
+        self.procedures.entry(name.to_string()).or_default().calls += 1;
+        self.frames.push(Frame {
+            name: name.to_string(),
+            started: Instant::now(),
+        });
+    }
+
+    pub fn exit_procedure(&mut self) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        // Recursive calls are only counted once in the total time of the
+        // outermost activation.
+        if self.frames.iter().any(|f| f.name == frame.name) {
+            return;
+        }
+        let elapsed = frame.started.elapsed();
+        self.procedures.entry(frame.name).or_default().total_time += elapsed;
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let mut procedures = self
+            .procedures
+            .iter()
+            .map(|(name, stats)| ProcedureProfile {
+                name: name.clone(),
+                calls: stats.calls,
+                steps: stats.steps,
+                self_time_us: micros(stats.self_time),
+                total_time_us: micros(stats.total_time),
+            })
+            .collect::<Vec<_>>();
+        procedures.sort_by(|a, b| b.self_time_us.total_cmp(&a.self_time_us));
+
+        ProfileReport {
+            total_time_us: micros(self.started.elapsed()),
+            total_steps: self.lines.values().map(|stats| stats.steps).sum(),
+            procedures,
+            lines: self
+                .lines
+                .iter()
+                .map(|(line, stats)| LineProfile {
+                    line: *line,
+                    steps: stats.steps,
+                    self_time_us: micros(stats.self_time),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileReport {
+    pub total_time_us: f64,
+    pub total_steps: u64,
+    pub procedures: Vec<ProcedureProfile>,
+    pub lines: Vec<LineProfile>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcedureProfile {
+    pub name: String,
+    pub calls: u64,
+    pub steps: u64,
+    pub self_time_us: f64,
+    pub total_time_us: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LineProfile {
+    pub line: usize,
+    pub steps: u64,
+    pub self_time_us: f64,
+}
+
+impl ProfileReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("profile report is always serializable")
+    }
+
+    // Text report; lines are shown next to their source text.
+    pub fn to_text(&self, source: &str) -> String {
+        let source_lines = source.lines().collect::<Vec<_>>();
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "Profile: {} steps in {:.1}us",
+            self.total_steps, self.total_time_us
+        );
+        let _ = writeln!(out, "\nProcedures (by self time):");
+        let _ = writeln!(
+            out,
+            "  {:<20} {:>8} {:>10} {:>12} {:>12}",
+            "name", "calls", "steps", "self (us)", "total (us)"
+        );
+        for p in &self.procedures {
+            let _ = writeln!(
+                out,
+                "  {:<20} {:>8} {:>10} {:>12.1} {:>12.1}",
+                p.name, p.calls, p.steps, p.self_time_us, p.total_time_us
+            );
+        }
+
+        let _ = writeln!(out, "\nLines:");
+        let _ = writeln!(
+            out,
+            "  {:>5} {:>10} {:>12}  source",
+            "line", "steps", "self (us)"
+        );
+        for l in &self.lines {
+            let text = source_lines
+                .get(l.line.wrapping_sub(1))
+                .map_or("", |text| text.trim());
+            let _ = writeln!(
+                out,
+                "  {:>5} {:>10} {:>12.1}  {}",
+                l.line, l.steps, l.self_time_us, text
+            );
+        }
+        out
+    }
+}
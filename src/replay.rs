@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::NodeId;
+use crate::call_stack::{ARType, ActivationRecord, CallStack};
+use crate::value::Value;
+
+// One change the interpreter made to the program state, or the start of a
+// statement. Variable and frame names are indices into the recording's name
+// table, so a long run of the same few names stays small.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    // About to run a statement, `depth` frames deep
+    Statement {
+        node: NodeId,
+        depth: usize,
+    },
+    // A variable in the innermost frame was assigned
+    Assign {
+        name: u32,
+        value: Value,
+    },
+    // A frame was pushed for the program or a procedure, with the arguments
+    // already bound to its parameters
+    Call {
+        name: u32,
+        nesting_level: usize,
+        arguments: Vec<(u32, Value)>,
+    },
+    // The innermost frame was popped
+    Return,
+}
+
+// Everything an interpreter run did to the program state, in order. Since
+// programs are deterministic, replaying the events reproduces every state
+// the run went through without running the program again.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    names: Vec<String>,
+    name_ids: HashMap<String, u32>,
+    events: Vec<Event>,
+    // Index in `events` of each statement
+    statements: Vec<usize>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn name(&self, id: u32) -> &str {
+        &self.names[id as usize]
+    }
+
+    // Number of statements the run executed.
+    pub fn statement_count(&self) -> usize {
+        self.statements.len()
+    }
+
+    // The node and call depth of the `index`th statement executed.
+    pub fn statement(&self, index: usize) -> (NodeId, usize) {
+        match self.events[self.statements[index]] {
+            Event::Statement { node, depth } => (node, depth),
+            _ => unreachable!("statement index points at a non-statement event"),
+        }
+    }
+
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(id) = self.name_ids.get(name) {
+            return *id;
+        }
+        let id = self.names.len() as u32;
+        self.names.push(name.to_string());
+        self.name_ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub(crate) fn record_statement(&mut self, node: NodeId, depth: usize) {
+        self.statements.push(self.events.len());
+        self.events.push(Event::Statement { node, depth });
+    }
+
+    pub(crate) fn record_assign(&mut self, name: &str, value: Value) {
+        let name = self.intern(name);
+        self.events.push(Event::Assign { name, value });
+    }
+
+    pub(crate) fn record_call<'a>(
+        &mut self,
+        name: &str,
+        nesting_level: usize,
+        arguments: impl IntoIterator<Item = (&'a str, &'a Value)>,
+    ) {
+        let name = self.intern(name);
+        let arguments = arguments
+            .into_iter()
+            .map(|(param, value)| (self.intern(param), value.clone()))
+            .collect();
+        self.events.push(Event::Call {
+            name,
+            nesting_level,
+            arguments,
+        });
+    }
+
+    pub(crate) fn record_return(&mut self) {
+        self.events.push(Event::Return);
+    }
+}
+
+// Rebuilds the call stack of a recorded run at any statement. Moving forward
+// applies the events in between; moving back starts over from the beginning.
+pub struct Replay<'a> {
+    recording: &'a Recording,
+    // Number of events applied to `call_stack`
+    applied: usize,
+    call_stack: CallStack,
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(recording: &'a Recording) -> Self {
+        Replay {
+            recording,
+            applied: 0,
+            call_stack: CallStack::new(),
+        }
+    }
+
+    // Moves to just before the `index`th statement ran.
+    pub fn seek(&mut self, index: usize) {
+        let target = self.recording.statements[index];
+        if target < self.applied {
+            self.applied = 0;
+            self.call_stack = CallStack::new();
+        }
+        while self.applied < target {
+            self.apply(&self.recording.events[self.applied]);
+            self.applied += 1;
+        }
+    }
+
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    fn apply(&mut self, event: &Event) {
+        match event {
+            Event::Statement { .. } => {}
+            Event::Assign { name, value } => {
+                if let Some(frame) = self.call_stack.peek() {
+                    frame
+                        .borrow_mut()
+                        .set(self.recording.name(*name), value.clone());
+                }
+            }
+            Event::Call {
+                name,
+                nesting_level,
+                arguments,
+            } => {
+                let ar_type = if self.call_stack.is_empty() {
+                    ARType::Program
+                } else {
+                    ARType::Procedure
+                };
+                let mut ar =
+                    ActivationRecord::new(self.recording.name(*name), ar_type, *nesting_level);
+                for (param, value) in arguments {
+                    ar.set(self.recording.name(*param), value.clone());
+                }
+                self.call_stack.push(Rc::new(RefCell::new(ar)));
+            }
+            Event::Return => {
+                self.call_stack.pop();
+            }
+        }
+    }
+}
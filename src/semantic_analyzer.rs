@@ -1,13 +1,61 @@
 use std::cell::RefCell;
-use std::iter::zip;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::rc::Rc;
 
-use crate::ast::ASTNode;
+use crate::ast::{ASTNode, Ast, MethodKind, NodeId, ParamMode};
+use crate::builtins::Signature;
+use crate::dialect::Dialect;
+use crate::hir::{Callee, Hir};
 use crate::interpreter::{InterpretError, InterpretResult};
-use crate::symbols::{ScopedSymbolTable, Symbol, SymbolKind};
+use crate::parser::{binary_precedence, Precedence};
+use crate::passes::Pass;
+use crate::symbols::{BuiltinProcedure, BuiltinTypes, ScopedSymbolTable, Symbol, SymbolKind};
+use crate::token::Token;
+use crate::visitor::ensure_stack;
+
+// What every name in the program refers to, as worked out by the analyzer.
+// Editor tooling uses it for hover and go-to-definition. The maps are
+// ordered by node, so walking them gives the same result every run.
+#[derive(Debug, Clone, Default)]
+pub struct CrossReferences {
+    // Declaring node to the symbol it declares; see `Symbol::declaration`
+    pub declarations: BTreeMap<NodeId, Symbol>,
+    // `Var` or `ProcedureCall` node to the declaring node of its name
+    pub references: BTreeMap<NodeId, NodeId>,
+    // Call of a generic procedure to the types its type parameters stand for
+    // there, in order
+    pub specializations: BTreeMap<NodeId, Vec<String>>,
+}
 
 pub struct SemanticAnalyzer {
     pub current_scope: Rc<RefCell<ScopedSymbolTable>>,
+    dialect: Dialect,
+    cross_references: CrossReferences,
+    hir: Hir,
+    // The innermost node being analyzed when analysis failed
+    failed_at: Option<NodeId>,
+    // Fields of the class whose method is being analyzed
+    class_fields: Vec<String>,
+    // The declaring `Var` nodes of CONST parameters, and of every variable
+    // or parameter assigned so far
+    const_params: HashSet<NodeId>,
+    assigned: HashSet<NodeId>,
+}
+
+impl Pass for SemanticAnalyzer {
+    fn name(&self) -> &str {
+        "semantic-analysis"
+    }
+
+    fn run(&mut self, ast: &mut Ast) -> anyhow::Result<()> {
+        Ok(self.analyze(ast)?)
+    }
+}
+
+impl Default for SemanticAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SemanticAnalyzer {
@@ -18,96 +66,359 @@ impl SemanticAnalyzer {
                 0,
                 None,
             ))),
+            dialect: Dialect::default(),
+            cross_references: CrossReferences::default(),
+            hir: Hir::default(),
+            failed_at: None,
+            class_fields: vec![],
+            const_params: HashSet::new(),
+            assigned: HashSet::new(),
         }
     }
 
-    pub fn analyze(&mut self, node: &ASTNode) -> InterpretResult<()> {
-        self.visit(node)
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
     }
 
-    fn visit(&mut self, node: &ASTNode) -> InterpretResult<()> {
-        match node {
-            ASTNode::Program { block, .. } => self.visit_program_node(block),
+    // Makes the builtins of `BuiltinRegistry::signatures` callable, and
+    // checks calls to them against their signatures. `write` and `writeln`
+    // are always callable.
+    pub fn with_builtins(self, signatures: impl IntoIterator<Item = Signature>) -> Self {
+        for signature in signatures {
+            if BuiltinProcedure::ALL
+                .iter()
+                .any(|builtin| builtin.name() == signature.name)
+            {
+                continue;
+            }
+            self.current_scope.borrow_mut().define(Symbol {
+                name: signature.name.clone(),
+                kind: SymbolKind::ExternalProcedure(signature),
+                declaration: None,
+            });
+        }
+        self
+    }
+
+    pub fn analyze(&mut self, ast: &Ast) -> InterpretResult<()> {
+        self.visit(ast, ast.root())
+    }
+
+    // Filled in as analysis goes, so after an error it covers the program
+    // up to where analysis stopped.
+    pub fn cross_references(&self) -> &CrossReferences {
+        &self.cross_references
+    }
+
+    // What the interpreter needs to run the program. Only complete once
+    // analysis succeeds.
+    pub fn hir(&self) -> &Hir {
+        &self.hir
+    }
+
+    pub fn failed_at(&self) -> Option<NodeId> {
+        self.failed_at
+    }
+
+    fn visit(&mut self, ast: &Ast, node: NodeId) -> InterpretResult<()> {
+        let result = ensure_stack(|| self.visit_node(ast, node));
+        if result.is_err() && self.failed_at.is_none() {
+            self.failed_at = Some(node);
+        }
+        result
+    }
+
+    fn visit_node(&mut self, ast: &Ast, node: NodeId) -> InterpretResult<()> {
+        match &ast[node] {
+            ASTNode::Program { block, .. } => self.visit_program_node(ast, *block),
             ASTNode::Block {
                 declarations,
                 compound_statement,
-            } => self.visit_block_node(declarations, compound_statement),
+            } => self.visit_block_node(ast, declarations, *compound_statement),
             ASTNode::ProcedureDecl {
                 proc_name,
+                type_params,
                 params,
                 block_node,
-            } => self.visit_procedure_decl_node(proc_name, params, block_node),
+            } => self.visit_procedure_decl_node(
+                ast,
+                node,
+                proc_name,
+                type_params,
+                params,
+                *block_node,
+            ),
             ASTNode::VarDecl {
                 var_node,
                 type_node,
-            } => self.visit_var_decl_node(var_node, type_node),
+                initializer,
+            } => self.visit_var_decl_node(ast, *var_node, *type_node, *initializer),
             ASTNode::Type { .. } => Ok(()),
-            ASTNode::Compound { children } => self.visit_compound_node(children),
-            ASTNode::Assign { left, right, .. } => self.visit_assign_node(left, right),
-            ASTNode::Var { name } => self.visit_var_node(name),
+            ASTNode::TypeDecl {
+                type_name,
+                type_node,
+            } => self.visit_type_decl_node(ast, node, type_name, *type_node),
+            ASTNode::ProcedureType { .. }
+            | ASTNode::ClassType { .. }
+            | ASTNode::MethodHeading { .. }
+            | ASTNode::Property { .. } => Ok(()),
+            ASTNode::MethodDecl {
+                class_name,
+                method_kind,
+                method_name,
+                params,
+                block_node,
+            } => self.visit_method_decl_node(
+                ast,
+                node,
+                class_name,
+                *method_kind,
+                method_name,
+                params,
+                *block_node,
+            ),
+            ASTNode::FieldAccess { object, field } => {
+                self.visit_field_access_node(ast, *object, field)
+            }
+            ASTNode::MethodCall {
+                object,
+                method,
+                arguments,
+            } => self.visit_method_call_node(ast, *object, method, arguments),
+            ASTNode::Compound { children } => self.visit_compound_node(ast, children),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.visit_value(ast, *condition)?;
+                self.visit(ast, *then_branch)?;
+                match else_branch {
+                    Some(else_branch) => self.visit(ast, *else_branch),
+                    None => Ok(()),
+                }
+            }
+            ASTNode::While { condition, body } => {
+                self.visit_value(ast, *condition)?;
+                self.visit(ast, *body)
+            }
+            ASTNode::Repeat { body, condition } => {
+                self.visit_compound_node(ast, body)?;
+                self.visit_value(ast, *condition)
+            }
+            ASTNode::Case {
+                selector,
+                arms,
+                else_branch,
+            } => {
+                self.visit_value(ast, *selector)?;
+                self.check_case_labels(ast, arms)?;
+                for arm in arms {
+                    self.visit(ast, *arm)?;
+                }
+                match else_branch {
+                    Some(else_branch) => self.visit_compound_node(ast, else_branch),
+                    None => Ok(()),
+                }
+            }
+            ASTNode::CaseArm { statement, .. } => self.visit(ast, *statement),
+            ASTNode::Range { .. } => Ok(()),
+            ASTNode::Assign { left, right, .. } => self.visit_assign_node(ast, *left, *right),
+            ASTNode::Var { name } => self.visit_var_node(node, name),
             ASTNode::NoOp => Ok(()),
-            ASTNode::UnaryOpNode { expr, .. } => self.visit(expr),
+            ASTNode::UnaryOpNode { expr, .. } => self.visit_value(ast, *expr),
             ASTNode::BinOpNode { left, right, .. } => {
-                self.visit(left)?;
-                self.visit(right)
+                self.visit_value(ast, *left)?;
+                self.visit_value(ast, *right)
             }
             ASTNode::NumNode { .. } => Ok(()),
             ASTNode::Param { .. } => Ok(()),
             ASTNode::ProcedureCall {
                 proc_name,
                 arguments,
-                proc_symbol,
-            } => self.visit_procedure_call_node(proc_name, arguments, proc_symbol),
+            } => self.visit_procedure_call_node(ast, node, proc_name, arguments),
         }
     }
 
-    fn visit_program_node(&mut self, block: &Box<ASTNode>) -> InterpretResult<()> {
+    // An expression whose value is used. Calls can appear anywhere an
+    // expression can, but only a constructor call or a call to a builtin
+    // with a return type gives a value.
+    fn visit_value(&mut self, ast: &Ast, node: NodeId) -> InterpretResult<()> {
+        let value = match &ast[node] {
+            ASTNode::ProcedureCall { proc_name, .. } => matches!(
+                self.lookup_symbol(proc_name, false),
+                Some(Symbol {
+                    kind: SymbolKind::ExternalProcedure(Signature {
+                        returns: Some(_),
+                        ..
+                    }),
+                    ..
+                })
+            ),
+            ASTNode::MethodCall { object, .. } => self.class_reference(ast, *object).is_some(),
+            _ => true,
+        };
+        if !value {
+            self.failed_at.get_or_insert(node);
+            return Err(InterpretError::NoValue {
+                call: ast.display(node).to_string(),
+            });
+        }
+        self.visit(ast, node)
+    }
+
+    fn visit_program_node(&mut self, ast: &Ast, block: NodeId) -> InterpretResult<()> {
         self.enter_scope("global");
-        let res = self.visit(block);
+        let res = self.visit(ast, block);
         self.exit_scope();
         res
     }
 
     fn visit_block_node(
         &mut self,
-        declarations: &Vec<Box<ASTNode>>,
-        compound_statement: &Box<ASTNode>,
+        ast: &Ast,
+        declarations: &[NodeId],
+        compound_statement: NodeId,
     ) -> InterpretResult<()> {
+        self.check_initializers(ast, declarations)?;
+        let mut seen_procedure = false;
+        for declaration in declarations {
+            match &ast[*declaration] {
+                ASTNode::ProcedureDecl { .. } => seen_procedure = true,
+                ASTNode::VarDecl { var_node, .. }
+                    if seen_procedure && !self.dialect.mixed_declarations() =>
+                {
+                    return Err(InterpretError::NotInDialect {
+                        feature: format!(
+                            "Declaring variable '{}' after a procedure",
+                            ast.display(*var_node)
+                        ),
+                        dialect: self.dialect,
+                    });
+                }
+                _ => {}
+            }
+            self.visit(ast, *declaration)?;
+        }
+        self.visit(ast, compound_statement)
+    }
+
+    // An initializer runs when its block is entered, so of the block's own
+    // variables it may only use those initialized before it.
+    fn check_initializers(&mut self, ast: &Ast, declarations: &[NodeId]) -> InterpretResult<()> {
+        // Each variable of the block, in declaration order, with the names
+        // its initializer uses
+        let mut variables = vec![];
         for declaration in declarations {
-            self.visit(declaration)?;
+            let ASTNode::VarDecl {
+                var_node,
+                initializer,
+                ..
+            } = &ast[*declaration]
+            else {
+                continue;
+            };
+            let ASTNode::Var { name } = &ast[*var_node] else {
+                continue;
+            };
+            let mut uses = BTreeSet::new();
+            if let Some(initializer) = initializer {
+                names_used(ast, *initializer, &mut uses);
+            }
+            variables.push((name.as_str(), *declaration, initializer.is_some(), uses));
+        }
+
+        for (index, (name, declaration, _, uses)) in variables.iter().enumerate() {
+            for used in uses {
+                let Some(position) = variables.iter().position(|(n, ..)| n == used) else {
+                    continue;
+                };
+                if position < index && variables[position].2 {
+                    continue;
+                }
+                self.failed_at.get_or_insert(*declaration);
+                if let Some(mut cycle) = initializer_path(&variables, used, name) {
+                    cycle.insert(0, name.to_string());
+                    return Err(InterpretError::InitializerCycle { names: cycle });
+                }
+                return Err(InterpretError::InitializerUsesUnset {
+                    name: name.to_string(),
+                    uses: used.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Each value may label only one arm of a CASE, and a range must hold at
+    // least one value.
+    fn check_case_labels(&mut self, ast: &Ast, arms: &[NodeId]) -> InterpretResult<()> {
+        let mut seen: Vec<(i32, i32, NodeId)> = vec![];
+        for arm in arms {
+            let ASTNode::CaseArm { labels, .. } = &ast[*arm] else {
+                continue;
+            };
+            for label in labels {
+                let Some((low, high)) = ast.case_label_bounds(*label) else {
+                    continue;
+                };
+                if low > high {
+                    self.failed_at.get_or_insert(*label);
+                    return Err(InterpretError::EmptyCaseRange {
+                        range: ast.display(*label).to_string(),
+                    });
+                }
+                if let Some((.., previous)) = seen.iter().find(|(l, h, _)| low <= *h && *l <= high)
+                {
+                    self.failed_at.get_or_insert(*label);
+                    return Err(InterpretError::DuplicateCaseLabel {
+                        label: ast.display(*label).to_string(),
+                        previous: ast.display(*previous).to_string(),
+                    });
+                }
+                seen.push((low, high, *label));
+            }
         }
-        self.visit(compound_statement)
+        Ok(())
     }
 
-    fn visit_compound_node(&mut self, children: &Vec<Box<ASTNode>>) -> InterpretResult<()> {
+    fn visit_compound_node(&mut self, ast: &Ast, children: &[NodeId]) -> InterpretResult<()> {
         for child in children {
-            self.visit(child)?;
+            self.visit(ast, *child)?;
         }
         Ok(())
     }
 
     fn visit_var_decl_node(
         &mut self,
-        var_node: &Box<ASTNode>,
-        type_node: &Box<ASTNode>,
+        ast: &Ast,
+        var_node: NodeId,
+        type_node: NodeId,
+        initializer: Option<NodeId>,
     ) -> InterpretResult<()> {
-        let ASTNode::Var { name: var_name } = &**var_node else {
+        let ASTNode::Var { name: var_name } = &ast[var_node] else {
             return Err(InterpretError::InvalidVarDeclVarNode);
         };
         let ASTNode::Type {
             value: type_name, ..
-        } = &**type_node
+        } = &ast[type_node]
         else {
             return Err(InterpretError::InvalidVarDeclTypeNode);
         };
 
-        self.lookup_symbol(type_name, false)
+        self.lookup_type(type_name)
             .ok_or_else(|| InterpretError::UndefinedType {
                 type_name: type_name.clone(),
                 var_name: var_name.clone(),
             })?;
 
-        if let Some(_) = self.lookup_symbol(var_name, true) {
+        self.check_not_field(var_name)?;
+        // Variables may shadow builtins such as `writeln`
+        if self
+            .lookup_symbol(var_name, true)
+            .is_some_and(|symbol| symbol.declaration.is_some())
+        {
             return Err(InterpretError::SymbolAlreadyDefined {
                 name: var_name.to_string(),
             });
@@ -118,8 +429,15 @@ impl SemanticAnalyzer {
             kind: SymbolKind::Variable {
                 type_name: type_name.to_owned(),
             },
+            declaration: Some(var_node),
         };
 
+        // Analyzed before the variable is defined, like the right side of
+        // an assignment runs before it
+        if let Some(initializer) = initializer {
+            self.visit_value(ast, initializer)?;
+            self.assigned.insert(var_node);
+        }
         self.define_symbol(symbol);
 
         Ok(())
@@ -127,17 +445,20 @@ impl SemanticAnalyzer {
 
     fn visit_procedure_decl_node(
         &mut self,
+        ast: &Ast,
+        node: NodeId,
         procedure_name: &str,
-        params: &[Box<ASTNode>],
-        block: &Box<ASTNode>,
+        type_params: &[String],
+        params: &[NodeId],
+        block: NodeId,
     ) -> InterpretResult<()> {
         let param_names = params
             .iter()
             .map(|node| {
-                let ASTNode::Param { var_node, .. } = &**node else {
+                let ASTNode::Param { var_node, .. } = &ast[*node] else {
                     return Err(InterpretError::InvalidVarDeclVarNode);
                 };
-                let ASTNode::Var { name } = &**var_node else {
+                let ASTNode::Var { name } = &ast[*var_node] else {
                     return Err(InterpretError::AssignTargetMustBeVar);
                 };
                 Ok(name.clone())
@@ -146,113 +467,763 @@ impl SemanticAnalyzer {
 
         let proc_symbol = Symbol {
             name: procedure_name.to_string(),
-            kind: SymbolKind::Procedure {
-                param_names,
-                block: block.clone(),
-            },
+            kind: SymbolKind::Procedure { param_names },
+            declaration: Some(node),
         };
 
         self.define_symbol(proc_symbol);
 
         self.enter_scope(procedure_name);
+        for type_param in type_params {
+            if self.lookup_symbol(type_param, true).is_some() {
+                return Err(InterpretError::SymbolAlreadyDefined {
+                    name: type_param.clone(),
+                });
+            }
+            self.define_symbol(Symbol {
+                name: type_param.clone(),
+                kind: SymbolKind::TypeParam,
+                declaration: None,
+            });
+        }
+        self.define_params(ast, params)?;
 
-        params
-            .iter()
-            .map(|node| {
-                let ASTNode::Param {
-                    var_node,
-                    type_node,
-                } = &**node
-                else {
-                    return Err(InterpretError::InvalidVarDeclVarNode);
-                };
-                let ASTNode::Var { name } = &**var_node else {
-                    return Err(InterpretError::InvalidVarDeclVarNode);
-                };
-                let ASTNode::Type {
-                    value: type_name, ..
-                } = &**type_node
-                else {
-                    return Err(InterpretError::InvalidVarDeclTypeNode);
-                };
+        let res = self
+            .visit(ast, block)
+            .and_then(|()| check_out_params_assigned(ast, procedure_name, params, &self.assigned));
 
-                let param_symbol = Symbol {
-                    name: name.to_string(),
-                    kind: SymbolKind::Variable {
-                        type_name: type_name.to_string(),
-                    },
-                };
+        self.exit_scope();
 
-                self.define_symbol(param_symbol);
+        res
+    }
 
-                Ok(())
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+    fn define_params(&mut self, ast: &Ast, params: &[NodeId]) -> InterpretResult<()> {
+        for node in params {
+            let ASTNode::Param {
+                var_node,
+                type_node,
+                mode,
+            } = &ast[*node]
+            else {
+                return Err(InterpretError::InvalidVarDeclVarNode);
+            };
+            let ASTNode::Var { name } = &ast[*var_node] else {
+                return Err(InterpretError::InvalidVarDeclVarNode);
+            };
+            let ASTNode::Type {
+                value: type_name, ..
+            } = &ast[*type_node]
+            else {
+                return Err(InterpretError::InvalidVarDeclTypeNode);
+            };
+            self.lookup_type(type_name)
+                .ok_or_else(|| InterpretError::UndefinedType {
+                    type_name: type_name.clone(),
+                    var_name: name.clone(),
+                })?;
+            self.check_not_field(name)?;
+            if *mode == ParamMode::Const {
+                self.const_params.insert(*var_node);
+            }
+
+            let param_symbol = Symbol {
+                name: name.to_string(),
+                kind: SymbolKind::Variable {
+                    type_name: type_name.to_string(),
+                },
+                declaration: Some(*var_node),
+            };
 
-        let res = self.visit(block);
+            self.define_symbol(param_symbol);
+        }
+        Ok(())
+    }
+
+    // The fields of a class are in scope in its methods, and FPC doesn't
+    // let a parameter or local variable there hide one.
+    fn check_not_field(&self, name: &str) -> InterpretResult<()> {
+        if self.class_fields.iter().any(|field| field == name) {
+            return Err(InterpretError::SymbolAlreadyDefined {
+                name: name.to_string(),
+            });
+        }
+        Ok(())
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn visit_method_decl_node(
+        &mut self,
+        ast: &Ast,
+        node: NodeId,
+        class_name: &str,
+        method_kind: MethodKind,
+        method_name: &str,
+        params: &[NodeId],
+        block: NodeId,
+    ) -> InterpretResult<()> {
+        let Some(members) = self.class_members(ast, class_name) else {
+            return Err(InterpretError::UndefinedClass {
+                name: class_name.to_string(),
+            });
+        };
+        let declared =
+            method_heading(ast, members, method_name).is_some_and(|(kind, heading_params)| {
+                kind == method_kind && heading_params.len() == params.len()
+            });
+        if !declared {
+            return Err(InterpretError::UnknownMember {
+                class_name: class_name.to_string(),
+                member: method_name.to_string(),
+            });
+        }
+        self.record_reference_to_type(node, class_name);
+
+        self.enter_scope(class_name);
+        let mut fields = vec![];
+        for (var_node, type_node) in class_fields(ast, members) {
+            let (ASTNode::Var { name }, ASTNode::Type { value }) =
+                (&ast[var_node], &ast[type_node])
+            else {
+                return Err(InterpretError::InvalidVarDeclVarNode);
+            };
+            fields.push(name.clone());
+            self.define_symbol(Symbol {
+                name: name.clone(),
+                kind: SymbolKind::Variable {
+                    type_name: value.clone(),
+                },
+                declaration: Some(var_node),
+            });
+        }
+        let enclosing_fields = std::mem::replace(&mut self.class_fields, fields);
+
+        self.enter_scope(method_name);
+        self.define_symbol(Symbol {
+            name: "self".to_string(),
+            kind: SymbolKind::Variable {
+                type_name: class_name.to_string(),
+            },
+            declaration: None,
+        });
+        let res = self
+            .define_params(ast, params)
+            .and_then(|()| self.visit(ast, block))
+            .and_then(|()| {
+                let name = format!("{class_name}.{method_name}");
+                check_out_params_assigned(ast, &name, params, &self.assigned)
+            });
         self.exit_scope();
 
+        self.class_fields = enclosing_fields;
+        self.exit_scope();
         res
     }
 
+    fn visit_field_access_node(
+        &mut self,
+        ast: &Ast,
+        object: NodeId,
+        field: &str,
+    ) -> InterpretResult<()> {
+        // `TCounter.Create` calls a constructor without arguments
+        if let Some(class_name) = self.class_reference(ast, object) {
+            return self.check_constructor(ast, object, &class_name, field, &[]);
+        }
+        self.check_member_access(ast, object, field, false)
+    }
+
+    // A field, or a property with an accessor for reading or writing it.
+    fn check_member_access(
+        &mut self,
+        ast: &Ast,
+        object: NodeId,
+        field: &str,
+        write: bool,
+    ) -> InterpretResult<()> {
+        self.visit(ast, object)?;
+        let class_name = self.object_class(ast, object)?;
+        let members = self.class_members(ast, &class_name).unwrap_or_default();
+        if field_type(ast, members, field).is_none() {
+            return Err(InterpretError::UnknownMember {
+                class_name,
+                member: field.to_string(),
+            });
+        }
+        match property(ast, members, field) {
+            Some((_, None)) if write => Err(InterpretError::ReadOnlyProperty {
+                class_name,
+                property: field.to_string(),
+            }),
+            Some((None, _)) if !write => Err(InterpretError::WriteOnlyProperty {
+                class_name,
+                property: field.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn visit_method_call_node(
+        &mut self,
+        ast: &Ast,
+        object: NodeId,
+        method: &str,
+        arguments: &[NodeId],
+    ) -> InterpretResult<()> {
+        if let Some(class_name) = self.class_reference(ast, object) {
+            self.check_constructor(ast, object, &class_name, method, arguments)?;
+        } else {
+            self.visit(ast, object)?;
+            let class_name = self.object_class(ast, object)?;
+            let members = self.class_members(ast, &class_name).unwrap_or_default();
+            let params = match method_heading(ast, members, method) {
+                Some((_, params)) => params,
+                // Every class has `Free`, which runs `Destroy` if there is one
+                None if method == FREE => &[],
+                None => {
+                    return Err(InterpretError::UnknownMember {
+                        class_name,
+                        member: method.to_string(),
+                    })
+                }
+            };
+            if params.len() != arguments.len() {
+                return Err(InterpretError::ProcCallMissingArgs {
+                    proc_name: format!("{class_name}.{method}"),
+                    expected: params.len(),
+                    got: arguments.len(),
+                });
+            }
+            self.check_reference_arguments(
+                ast,
+                &format!("{class_name}.{method}"),
+                params,
+                arguments,
+            )?;
+        }
+
+        for arg in arguments {
+            self.visit_value(ast, *arg)?;
+        }
+        Ok(())
+    }
+
+    fn check_constructor(
+        &mut self,
+        ast: &Ast,
+        object: NodeId,
+        class_name: &str,
+        method: &str,
+        arguments: &[NodeId],
+    ) -> InterpretResult<()> {
+        self.record_reference_to_type(object, class_name);
+        let members = self.class_members(ast, class_name).unwrap_or_default();
+        let Some((MethodKind::Constructor, params)) = method_heading(ast, members, method) else {
+            return Err(InterpretError::UnknownMember {
+                class_name: class_name.to_string(),
+                member: method.to_string(),
+            });
+        };
+        if params.len() != arguments.len() {
+            return Err(InterpretError::ProcCallMissingArgs {
+                proc_name: format!("{class_name}.{method}"),
+                expected: params.len(),
+                got: arguments.len(),
+            });
+        }
+        self.check_reference_arguments(ast, &format!("{class_name}.{method}"), params, arguments)
+    }
+
+    // The argument for a VAR or OUT parameter must be something that can be
+    // assigned: a variable other than a CONST parameter, or a field.
+    fn check_reference_arguments(
+        &mut self,
+        ast: &Ast,
+        proc_name: &str,
+        params: &[NodeId],
+        arguments: &[NodeId],
+    ) -> InterpretResult<()> {
+        for (param, argument) in params.iter().zip(arguments) {
+            let ASTNode::Param { var_node, mode, .. } = &ast[*param] else {
+                continue;
+            };
+            let param = ast.display(*var_node).to_string();
+            self.check_reference_argument(ast, proc_name, &param, *mode, *argument)?;
+        }
+        Ok(())
+    }
+
+    fn check_reference_argument(
+        &mut self,
+        ast: &Ast,
+        proc_name: &str,
+        param: &str,
+        mode: ParamMode,
+        argument: NodeId,
+    ) -> InterpretResult<()> {
+        if !mode.by_reference() {
+            return Ok(());
+        }
+        let assignable = match &ast[argument] {
+            ASTNode::Var { name } => match self.lookup_symbol(name, false) {
+                Some(Symbol {
+                    kind: SymbolKind::Variable { .. },
+                    declaration: Some(declaration),
+                    ..
+                }) => {
+                    if self.const_params.contains(&declaration) {
+                        return Err(InterpretError::ConstParamAssignment { name: name.clone() });
+                    }
+                    self.assigned.insert(declaration);
+                    true
+                }
+                _ => false,
+            },
+            ASTNode::FieldAccess { object, field } => {
+                self.class_reference(ast, *object).is_none()
+                    && self.object_class(ast, *object).is_ok_and(|class_name| {
+                        let members = self.class_members(ast, &class_name).unwrap_or_default();
+                        property(ast, members, field).is_none()
+                    })
+            }
+            _ => false,
+        };
+        if !assignable {
+            return Err(InterpretError::ArgumentMustBeVariable {
+                proc_name: proc_name.to_string(),
+                param: param.to_string(),
+                mode,
+            });
+        }
+        Ok(())
+    }
+
+    // The class `node` names, when it is the name of a class rather than a
+    // variable holding an instance.
+    fn class_reference(&self, ast: &Ast, node: NodeId) -> Option<String> {
+        let ASTNode::Var { name } = &ast[node] else {
+            return None;
+        };
+        self.lookup_symbol(name, false)
+            .filter(|symbol| matches!(symbol.kind, SymbolKind::Type { .. }))?;
+        self.class_members(ast, name).map(|_| name.clone())
+    }
+
+    // The class of the instance an expression evaluates to.
+    fn object_class(&self, ast: &Ast, node: NodeId) -> InterpretResult<String> {
+        let type_name = match &ast[node] {
+            ASTNode::Var { name } => match self.lookup_symbol(name, false) {
+                Some(Symbol {
+                    kind: SymbolKind::Variable { type_name },
+                    ..
+                }) => Some(type_name),
+                _ => None,
+            },
+            ASTNode::FieldAccess { object, field } => match self.class_reference(ast, *object) {
+                Some(class_name) => Some(class_name),
+                None => {
+                    let class_name = self.object_class(ast, *object)?;
+                    let members = self.class_members(ast, &class_name).unwrap_or_default();
+                    field_type(ast, members, field)
+                }
+            },
+            ASTNode::MethodCall { object, .. } => self.class_reference(ast, *object),
+            _ => None,
+        };
+        type_name
+            .filter(|type_name| self.class_members(ast, type_name).is_some())
+            .ok_or_else(|| InterpretError::NotAnObject {
+                expression: ast.display(node).to_string(),
+            })
+    }
+
+    fn record_reference_to_type(&mut self, node: NodeId, type_name: &str) {
+        if let Some(symbol) = self.lookup_symbol(type_name, false) {
+            self.record_reference(node, &symbol);
+        }
+    }
+
     fn visit_procedure_call_node(
         &mut self,
+        ast: &Ast,
+        node: NodeId,
         proc_name: &str,
-        arguments: &Vec<Box<ASTNode>>,
-        proc_symbol: &RefCell<Option<Box<Symbol>>>,
+        arguments: &[NodeId],
     ) -> InterpretResult<()> {
         let Some(proc_decl_symb) = self.lookup_symbol(proc_name, false) else {
             return Err(InterpretError::UndefinedFunction {
                 name: proc_name.to_string(),
             });
         };
+        self.record_reference(node, &proc_decl_symb);
 
-        let Symbol {
-            kind: SymbolKind::Procedure { param_names, .. },
-            ..
-        } = proc_decl_symb.clone()
-        else {
+        let builtin = match &proc_decl_symb.kind {
+            SymbolKind::BuiltinProcedure(builtin) => {
+                Some((Callee::Builtin(*builtin), builtin.signature()))
+            }
+            SymbolKind::ExternalProcedure(signature) => Some((Callee::External, signature.clone())),
+            _ => None,
+        };
+        if let Some((callee, signature)) = builtin {
+            self.check_builtin_arguments(ast, &signature, arguments)?;
+            self.hir.callees.insert(node, callee);
+            return Ok(());
+        }
+
+        // A procedure, or a variable holding one
+        let callee = match (&proc_decl_symb.kind, proc_decl_symb.declaration) {
+            (SymbolKind::Variable { .. }, _) => Callee::Variable,
+            (_, Some(declaration)) => Callee::Procedure(declaration),
+            (_, None) => {
+                return Err(InterpretError::UndefinedFunction {
+                    name: proc_name.to_string(),
+                })
+            }
+        };
+        let (type_params, params) = match (&proc_decl_symb.kind, proc_decl_symb.declaration) {
+            (SymbolKind::Procedure { .. }, Some(declaration)) => match &ast[declaration] {
+                ASTNode::ProcedureDecl {
+                    type_params,
+                    params,
+                    ..
+                } => (type_params.as_slice(), Some(params.as_slice())),
+                _ => (&[][..], None),
+            },
+            (SymbolKind::Variable { type_name }, _) => {
+                (&[][..], self.procedure_type_params(ast, type_name))
+            }
+            _ => (&[][..], None),
+        };
+        let Some(params) = params else {
             return Err(InterpretError::UndefinedFunction {
                 name: proc_name.to_string(),
             });
         };
 
-        if param_names.len() != arguments.len() {
+        if params.len() != arguments.len() {
             return Err(InterpretError::ProcCallMissingArgs {
                 proc_name: proc_name.to_string(),
-                expected: param_names.len(),
+                expected: params.len(),
                 got: arguments.len(),
             });
         }
+        self.check_reference_arguments(ast, proc_name, params, arguments)?;
 
-        for tup in zip(arguments, param_names) {
-            let (arg, ..) = tup;
-            self.visit(&arg)?;
+        for arg in arguments {
+            self.visit_value(ast, *arg)?;
+        }
+        if !type_params.is_empty() {
+            self.specialize(ast, node, proc_name, type_params, params, arguments)?;
         }
 
-        *proc_symbol.borrow_mut() = Some(Box::new(proc_decl_symb));
+        self.hir.callees.insert(node, callee);
+        Ok(())
+    }
 
+    // Checks the arguments of a call against the builtin's signature. Their
+    // types are only checked where they are known before running: numbers,
+    // comparisons and variables of builtin types. The interpreter checks
+    // the rest.
+    fn check_builtin_arguments(
+        &mut self,
+        ast: &Ast,
+        signature: &Signature,
+        arguments: &[NodeId],
+    ) -> InterpretResult<()> {
+        if !signature.accepts_count(arguments.len()) {
+            if signature.variadic {
+                return Err(InterpretError::TooFewArguments {
+                    proc_name: signature.name.clone(),
+                    at_least: signature.params.len() - 1,
+                    got: arguments.len(),
+                });
+            }
+            return Err(InterpretError::ProcCallMissingArgs {
+                proc_name: signature.name.clone(),
+                expected: signature.params.len(),
+                got: arguments.len(),
+            });
+        }
+        for (index, argument) in arguments.iter().enumerate() {
+            let Some((param_type, mode)) = signature.param(index) else {
+                continue;
+            };
+            let param = format!("#{}", index + 1);
+            self.check_reference_argument(ast, &signature.name, &param, mode, *argument)?;
+            self.visit_value(ast, *argument)?;
+            let found = self.static_type(ast, *argument);
+            if let Some(found) = found.filter(|found| !param_type.accepts(found)) {
+                self.failed_at.get_or_insert(*argument);
+                return Err(InterpretError::ArgumentType {
+                    proc_name: signature.name.clone(),
+                    position: index + 1,
+                    expected: param_type.to_string(),
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // The type of an expression where it is known without running it.
+    fn static_type(&self, ast: &Ast, node: NodeId) -> Option<String> {
+        match &ast[node] {
+            ASTNode::NumNode { value } => Some(value.type_name().to_string()),
+            ASTNode::BinOpNode { op, .. }
+                if binary_precedence(op) == Some(Precedence::Relational)
+                    || matches!(op, Token::And | Token::Or) =>
+            {
+                Some("BOOLEAN".to_string())
+            }
+            ASTNode::UnaryOpNode {
+                token: Token::Not, ..
+            } => Some("BOOLEAN".to_string()),
+            ASTNode::Var { name } => match self.lookup_symbol(name, false)?.kind {
+                SymbolKind::Variable { type_name }
+                    if ["integer", "real"].contains(&type_name.as_str()) =>
+                {
+                    Some(type_name.to_uppercase())
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Works out what each type parameter of a generic procedure stands for
+    // at a call from the types of the arguments, like FPC's implicit
+    // specialization. The procedure itself runs unchanged, since values
+    // carry their types at runtime.
+    fn specialize(
+        &mut self,
+        ast: &Ast,
+        node: NodeId,
+        proc_name: &str,
+        type_params: &[String],
+        params: &[NodeId],
+        arguments: &[NodeId],
+    ) -> InterpretResult<()> {
+        let mut types: Vec<Option<String>> = vec![None; type_params.len()];
+        for (param, argument) in params.iter().zip(arguments) {
+            let ASTNode::Param { type_node, .. } = &ast[*param] else {
+                continue;
+            };
+            let ASTNode::Type { value } = &ast[*type_node] else {
+                continue;
+            };
+            let Some(index) = type_params.iter().position(|name| name == value) else {
+                continue;
+            };
+            let Some(argument_type) = self.expression_type(ast, *argument) else {
+                continue;
+            };
+            match &types[index] {
+                Some(first) if *first != argument_type => {
+                    return Err(InterpretError::TypeParamMismatch {
+                        proc_name: proc_name.to_string(),
+                        type_param: value.clone(),
+                        first: first.clone(),
+                        second: argument_type,
+                    });
+                }
+                Some(_) => {}
+                None => types[index] = Some(argument_type),
+            }
+        }
+        let types = type_params
+            .iter()
+            .zip(types)
+            .map(|(type_param, found)| {
+                found.ok_or_else(|| InterpretError::CannotInferTypeParam {
+                    proc_name: proc_name.to_string(),
+                    type_param: type_param.clone(),
+                })
+            })
+            .collect::<InterpretResult<Vec<_>>>()?;
+        self.cross_references.specializations.insert(node, types);
         Ok(())
     }
 
-    fn visit_assign_node(&mut self, left: &ASTNode, right: &ASTNode) -> InterpretResult<()> {
-        let ASTNode::Var { .. } = left else {
-            return Err(InterpretError::AssignTargetMustBeVar);
+    // The declared type of a variable or field, or the type an arithmetic
+    // expression of them gives, where that is known before running.
+    fn expression_type(&self, ast: &Ast, node: NodeId) -> Option<String> {
+        ensure_stack(|| match &ast[node] {
+            ASTNode::Var { name } => match self.lookup_symbol(name, false)?.kind {
+                SymbolKind::Variable { type_name } => Some(type_name),
+                _ => None,
+            },
+            ASTNode::FieldAccess { object, field } => {
+                let class_name = self.object_class(ast, *object).ok()?;
+                field_type(ast, self.class_members(ast, &class_name)?, field)
+            }
+            ASTNode::NumNode { value } => Some(value.type_name().to_string()),
+            ASTNode::UnaryOpNode { expr, .. } => self.expression_type(ast, *expr),
+            ASTNode::BinOpNode { left, right, op } => {
+                let integer = BuiltinTypes::Integer.to_string();
+                let real = BuiltinTypes::Real.to_string();
+                let left = self.expression_type(ast, *left)?;
+                let right = self.expression_type(ast, *right)?;
+                match op {
+                    Token::IntegerDiv => Some(integer),
+                    Token::FloatDiv => Some(real),
+                    _ if left == integer && right == integer => Some(integer),
+                    _ if [&left, &right]
+                        .iter()
+                        .all(|t| **t == integer || **t == real) =>
+                    {
+                        Some(real)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+    }
+
+    fn visit_type_decl_node(
+        &mut self,
+        ast: &Ast,
+        node: NodeId,
+        type_name: &str,
+        type_node: NodeId,
+    ) -> InterpretResult<()> {
+        if self
+            .lookup_symbol(type_name, true)
+            .is_some_and(|symbol| symbol.declaration.is_some())
+        {
+            return Err(InterpretError::SymbolAlreadyDefined {
+                name: type_name.to_string(),
+            });
+        }
+
+        let symbol = Symbol {
+            name: type_name.to_string(),
+            kind: SymbolKind::Type {
+                definition: type_node,
+            },
+            declaration: Some(node),
+        };
+        // A class can have fields of its own type
+        if let ASTNode::ClassType { members } = &ast[type_node] {
+            self.define_symbol(symbol.clone());
+            let mut names = HashSet::new();
+            for member in members {
+                let name = match &ast[*member] {
+                    ASTNode::VarDecl { var_node, .. } => ast.display(*var_node).to_string(),
+                    ASTNode::MethodHeading { name, .. } | ASTNode::Property { name, .. } => {
+                        name.clone()
+                    }
+                    _ => continue,
+                };
+                if !names.insert(name.clone()) {
+                    return Err(InterpretError::SymbolAlreadyDefined { name });
+                }
+            }
+            for member in members {
+                if let ASTNode::Property {
+                    name,
+                    type_node,
+                    reader,
+                    writer,
+                } = &ast[*member]
+                {
+                    let property_type = ast.display(*type_node).to_string();
+                    check_accessors(
+                        ast,
+                        type_name,
+                        members,
+                        name,
+                        &property_type,
+                        reader,
+                        writer,
+                    )?;
+                }
+            }
+        }
+
+        // Parameter names of a procedure type or method heading only
+        // document it, so just their types have to exist
+        let params = |params: &[NodeId]| {
+            params
+                .iter()
+                .map(|param| ast.children(*param))
+                .filter_map(|children| Some((*children.first()?, *children.get(1)?)))
+                .collect::<Vec<_>>()
         };
+        let typed = match &ast[type_node] {
+            ASTNode::ProcedureType { params: p } => params(p),
+            ASTNode::ClassType { members } => members
+                .iter()
+                .flat_map(|member| match &ast[*member] {
+                    ASTNode::MethodHeading { params: p, .. } => params(p),
+                    ASTNode::Property { type_node, .. } => vec![(*member, *type_node)],
+                    _ => params(std::slice::from_ref(member)),
+                })
+                .collect(),
+            _ => vec![(node, type_node)],
+        };
+        for (named, type_node) in typed {
+            let ASTNode::Type { value } = &ast[type_node] else {
+                return Err(InterpretError::InvalidVarDeclTypeNode);
+            };
+            if self.lookup_type(value).is_none() {
+                return Err(InterpretError::UndefinedType {
+                    type_name: value.clone(),
+                    var_name: match &ast[named] {
+                        ASTNode::Var { name } | ASTNode::Property { name, .. } => name.clone(),
+                        _ => type_name.to_string(),
+                    },
+                });
+            }
+        }
+
+        self.define_symbol(symbol);
+        Ok(())
+    }
 
-        self.visit(left)?;
+    fn visit_assign_node(&mut self, ast: &Ast, left: NodeId, right: NodeId) -> InterpretResult<()> {
+        match &ast[left] {
+            ASTNode::Var { name } => {
+                self.visit(ast, left)?;
+                if let Some(declaration) = self
+                    .lookup_symbol(name, false)
+                    .and_then(|symbol| symbol.declaration)
+                {
+                    if self.const_params.contains(&declaration) {
+                        return Err(InterpretError::ConstParamAssignment { name: name.clone() });
+                    }
+                    self.assigned.insert(declaration);
+                }
+            }
+            ASTNode::FieldAccess { object, field }
+                if self.class_reference(ast, *object).is_none() =>
+            {
+                self.check_member_access(ast, *object, field, true)?
+            }
+            _ => return Err(InterpretError::AssignTargetMustBeVar),
+        }
 
-        self.visit(right)
+        self.visit_value(ast, right)
     }
 
-    fn visit_var_node(&self, name: &String) -> InterpretResult<()> {
-        if self.lookup_symbol(name, false).is_none() {
-            return Err(InterpretError::UndefinedVariable { name: name.clone() });
+    fn visit_var_node(&mut self, node: NodeId, name: &str) -> InterpretResult<()> {
+        let Some(symbol) = self.lookup_symbol(name, false) else {
+            return Err(InterpretError::UndefinedVariable {
+                name: name.to_string(),
+            });
+        };
+        self.record_reference(node, &symbol);
+        if let SymbolKind::Variable { type_name } = symbol.kind {
+            self.hir.types.insert(node, type_name);
         }
         Ok(())
     }
 
+    fn record_reference(&mut self, node: NodeId, symbol: &Symbol) {
+        if let Some(declaration) = symbol.declaration {
+            self.cross_references.references.insert(node, declaration);
+        }
+    }
+
     fn enter_scope(&mut self, scope_name: &str) {
         let scope_level = self.current_scope.borrow().scope_level + 1;
 
@@ -266,14 +1237,14 @@ impl SemanticAnalyzer {
     }
 
     fn exit_scope(&mut self) {
-        // println!("Exiting Scope:\n{}", self.current_scope.borrow());
+        tracing::trace!(scope = %self.current_scope.borrow(), "exiting scope");
 
         let parent = self
             .current_scope
             .borrow()
             .enclosing_scope
             .as_ref()
-            .map(|p| Rc::clone(p));
+            .map(Rc::clone);
 
         if let Some(parent) = parent {
             self.current_scope = parent;
@@ -281,9 +1252,57 @@ impl SemanticAnalyzer {
     }
 
     fn define_symbol(&mut self, symbol: Symbol) {
+        if let Some(declaration) = symbol.declaration {
+            self.cross_references
+                .declarations
+                .insert(declaration, symbol.clone());
+        }
         self.current_scope.borrow_mut().define(symbol);
     }
 
+    // A builtin or declared type of that name.
+    fn lookup_type(&self, name: &str) -> Option<Symbol> {
+        self.lookup_symbol(name, false).filter(|symbol| {
+            matches!(
+                symbol.kind,
+                SymbolKind::BuiltinType(_) | SymbolKind::Type { .. } | SymbolKind::TypeParam
+            )
+        })
+    }
+
+    // The `ProcedureType` or `ClassType` a declared type stands for,
+    // following aliases.
+    fn type_definition(&self, ast: &Ast, type_name: &str) -> Option<NodeId> {
+        let mut type_name = type_name.to_string();
+        // An alias can only refer to a type declared before it, so this ends
+        for _ in 0..=ast.len() {
+            let SymbolKind::Type { definition } = self.lookup_type(&type_name)?.kind else {
+                return None;
+            };
+            match &ast[definition] {
+                ASTNode::Type { value } => type_name = value.clone(),
+                _ => return Some(definition),
+            }
+        }
+        None
+    }
+
+    // The parameters of a procedure type, or `None` if `type_name` names
+    // some other type.
+    fn procedure_type_params<'a>(&self, ast: &'a Ast, type_name: &str) -> Option<&'a [NodeId]> {
+        match &ast[self.type_definition(ast, type_name)?] {
+            ASTNode::ProcedureType { params } => Some(params),
+            _ => None,
+        }
+    }
+
+    fn class_members<'a>(&self, ast: &'a Ast, type_name: &str) -> Option<&'a [NodeId]> {
+        match &ast[self.type_definition(ast, type_name)?] {
+            ASTNode::ClassType { members } => Some(members),
+            _ => None,
+        }
+    }
+
     fn lookup_symbol(&self, name: &str, current_scope_only: bool) -> Option<Symbol> {
         // Look in current scope
         if let Some(sym) = self.current_scope.borrow().lookup(name, current_scope_only) {
@@ -292,3 +1311,221 @@ impl SemanticAnalyzer {
         None
     }
 }
+
+// Every name under `node` that could be a variable, including procedure
+// variables that are called.
+fn names_used(ast: &Ast, node: NodeId, names: &mut BTreeSet<String>) {
+    ensure_stack(|| {
+        match &ast[node] {
+            ASTNode::Var { name }
+            | ASTNode::ProcedureCall {
+                proc_name: name, ..
+            } => {
+                names.insert(name.clone());
+            }
+            _ => {}
+        }
+        for child in ast.children(node) {
+            names_used(ast, child, names);
+        }
+    })
+}
+
+// The names from `from` to `to` following what initializers use, both
+// included, if there is such a path.
+fn initializer_path(
+    variables: &[(&str, NodeId, bool, BTreeSet<String>)],
+    from: &str,
+    to: &str,
+) -> Option<Vec<String>> {
+    let mut path = vec![from.to_string()];
+    let mut visited = HashSet::new();
+    extend_path(variables, to, &mut path, &mut visited).then_some(path)
+}
+
+fn extend_path(
+    variables: &[(&str, NodeId, bool, BTreeSet<String>)],
+    to: &str,
+    path: &mut Vec<String>,
+    visited: &mut HashSet<String>,
+) -> bool {
+    ensure_stack(|| {
+        let current = path.last().cloned().unwrap_or_default();
+        if current == to {
+            return true;
+        }
+        if !visited.insert(current.clone()) {
+            return false;
+        }
+        let Some((_, _, _, uses)) = variables.iter().find(|(name, ..)| *name == current) else {
+            return false;
+        };
+        for used in uses {
+            path.push(used.clone());
+            if extend_path(variables, to, path, visited) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    })
+}
+
+// The method every class has without declaring it
+const FREE: &str = "free";
+
+// The `Var` and `Type` node of each field of a class.
+fn class_fields(ast: &Ast, members: &[NodeId]) -> Vec<(NodeId, NodeId)> {
+    members
+        .iter()
+        .filter_map(|member| match &ast[*member] {
+            ASTNode::VarDecl {
+                var_node,
+                type_node,
+                ..
+            } => Some((*var_node, *type_node)),
+            _ => None,
+        })
+        .collect()
+}
+
+// The type of `object.field`, where the field can also be a property.
+fn field_type(ast: &Ast, members: &[NodeId], field: &str) -> Option<String> {
+    let property_type = members.iter().find_map(|member| match &ast[*member] {
+        ASTNode::Property {
+            name, type_node, ..
+        } if name == field => Some(*type_node),
+        _ => None,
+    });
+    let type_node = property_type.or_else(|| {
+        class_fields(ast, members)
+            .into_iter()
+            .find(|(var_node, _)| matches!(&ast[*var_node], ASTNode::Var { name } if name == field))
+            .map(|(_, type_node)| type_node)
+    })?;
+    match &ast[type_node] {
+        ASTNode::Type { value } => Some(value.clone()),
+        _ => None,
+    }
+}
+
+// An OUT parameter starts out with no value, so the procedure has to assign
+// it, either directly or by passing it on as a VAR or OUT argument.
+fn check_out_params_assigned(
+    ast: &Ast,
+    proc_name: &str,
+    params: &[NodeId],
+    assigned: &HashSet<NodeId>,
+) -> InterpretResult<()> {
+    for param in params {
+        if let ASTNode::Param {
+            var_node,
+            mode: ParamMode::Out,
+            ..
+        } = &ast[*param]
+        {
+            if !assigned.contains(var_node) {
+                return Err(InterpretError::OutParamNotAssigned {
+                    proc_name: proc_name.to_string(),
+                    param: ast.display(*var_node).to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// The reader and writer of a property.
+fn property<'a>(
+    ast: &'a Ast,
+    members: &[NodeId],
+    property: &str,
+) -> Option<(Option<&'a str>, Option<&'a str>)> {
+    members.iter().find_map(|member| match &ast[*member] {
+        ASTNode::Property {
+            name,
+            reader,
+            writer,
+            ..
+        } if name == property => Some((reader.as_deref(), writer.as_deref())),
+        _ => None,
+    })
+}
+
+// A property reads a field of its type, and writes one or passes the value
+// to a procedure method taking just that.
+fn check_accessors(
+    ast: &Ast,
+    class_name: &str,
+    members: &[NodeId],
+    property: &str,
+    property_type: &str,
+    reader: &Option<String>,
+    writer: &Option<String>,
+) -> InterpretResult<()> {
+    let field_of_type = |accessor: &str| {
+        class_fields(ast, members)
+            .into_iter()
+            .find_map(|(var_node, type_node)| {
+                matches!(&ast[var_node], ASTNode::Var { name } if name == accessor)
+                    .then(|| ast.display(type_node).to_string() == property_type)
+            })
+    };
+    let unknown = |accessor: &str| InterpretError::UnknownMember {
+        class_name: class_name.to_string(),
+        member: accessor.to_string(),
+    };
+    let invalid = |accessor: &str, expected: String| InterpretError::InvalidPropertyAccessor {
+        property: property.to_string(),
+        accessor: accessor.to_string(),
+        expected,
+    };
+
+    if let Some(reader) = reader {
+        match field_of_type(reader) {
+            Some(true) => {}
+            Some(false) => return Err(invalid(reader, format!("a field of type {property_type}"))),
+            // There are no functions to read a property through
+            None if method_heading(ast, members, reader).is_some() => {
+                return Err(invalid(reader, format!("a field of type {property_type}")))
+            }
+            None => return Err(unknown(reader)),
+        }
+    }
+    if let Some(writer) = writer {
+        let expected =
+            || format!("a field of type {property_type} or a procedure taking one {property_type}");
+        match (field_of_type(writer), method_heading(ast, members, writer)) {
+            (Some(true), _) => {}
+            (Some(false), _) => return Err(invalid(writer, expected())),
+            (None, Some((MethodKind::Procedure, [param]))) if matches!(&ast[*param], ASTNode::Param { mode, .. } if !mode.by_reference()) =>
+            {
+                let param_type = ast
+                    .children(*param)
+                    .get(1)
+                    .map(|t| ast.display(*t).to_string());
+                if param_type.as_deref() != Some(property_type) {
+                    return Err(invalid(writer, expected()));
+                }
+            }
+            (None, Some(_)) => return Err(invalid(writer, expected())),
+            (None, None) => return Err(unknown(writer)),
+        }
+    }
+    Ok(())
+}
+
+fn method_heading<'a>(
+    ast: &'a Ast,
+    members: &[NodeId],
+    method: &str,
+) -> Option<(MethodKind, &'a [NodeId])> {
+    members.iter().find_map(|member| match &ast[*member] {
+        ASTNode::MethodHeading {
+            method_kind,
+            name,
+            params,
+        } if name == method => Some((*method_kind, params.as_slice())),
+        _ => None,
+    })
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+
+use memmap2::Mmap;
+
+// How the bytes of a source file become text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    // UTF-8, or UTF-16 if the file starts with its byte order mark. A file
+    // that is neither is read as Latin-1.
+    #[default]
+    Auto,
+    Utf8,
+    // ISO 8859-1, where every byte is the character with that code
+    Latin1,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Encoding::Auto),
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(format!("Unknown encoding '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Auto => write!(f, "auto"),
+            Encoding::Utf8 => write!(f, "UTF-8"),
+            Encoding::Latin1 => write!(f, "Latin-1"),
+        }
+    }
+}
+
+// The first byte that doesn't continue valid UTF-8, at the line and column
+// the lexer would report for the character there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidUtf8 {
+    pub byte: u8,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid UTF-8 byte 0x{:02X} at line {}, column {}",
+            self.byte, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for InvalidUtf8 {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    pub text: String,
+    // Why `Encoding::Auto` fell back to Latin-1
+    pub fallback: Option<InvalidUtf8>,
+}
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+// The text of a source file, without a byte order mark, which would
+// otherwise end up in front of the first token.
+pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<Decoded, InvalidUtf8> {
+    let text = match encoding {
+        Encoding::Latin1 => latin1(bytes),
+        Encoding::Utf8 => utf8(bytes)?,
+        Encoding::Auto => {
+            if let Some(rest) = bytes.strip_prefix(UTF16_LE_BOM) {
+                utf16(rest, u16::from_le_bytes)
+            } else if let Some(rest) = bytes.strip_prefix(UTF16_BE_BOM) {
+                utf16(rest, u16::from_be_bytes)
+            } else {
+                match utf8(bytes) {
+                    Ok(text) => text,
+                    Err(invalid) => {
+                        return Ok(Decoded {
+                            text: latin1(bytes),
+                            fallback: Some(invalid),
+                        })
+                    }
+                }
+            }
+        }
+    };
+    Ok(Decoded {
+        text,
+        fallback: None,
+    })
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, InvalidUtf8> {
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+    // Copied before it is checked: `bytes` may be a mapped file that another
+    // process changes meanwhile, and only the copy stays as it was checked
+    String::from_utf8(bytes.to_vec()).map_err(|e| {
+        let (bytes, valid_up_to) = (e.as_bytes(), e.utf8_error().valid_up_to());
+        let valid = std::str::from_utf8(&bytes[..valid_up_to]).expect("checked");
+        let line_start = valid.rfind('\n').map_or(0, |i| i + 1);
+        InvalidUtf8 {
+            byte: bytes[valid_up_to],
+            line: valid.matches('\n').count() + 1,
+            column: valid[line_start..].chars().count() + 1,
+        }
+    })
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| char::from(byte)).collect()
+}
+
+// Unpaired surrogates and a trailing odd byte become U+FFFD.
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let chunks = bytes.chunks_exact(2);
+    let odd = !chunks.remainder().is_empty();
+    let units = chunks.map(|pair| unit([pair[0], pair[1]]));
+    let mut text: String = char::decode_utf16(units)
+        .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    if odd {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    text
+}
+
+// Files at least this big are decoded straight from a mapping rather than
+// read into a buffer first.
+const MAP_THRESHOLD: u64 = 1 << 20;
+
+// The text of a source file, as `read` returns it. Tokens and AST nodes
+// only keep offsets into it.
+pub struct SourceFile {
+    text: String,
+    // Why `Encoding::Auto` fell back to Latin-1
+    pub fallback: Option<InvalidUtf8>,
+}
+
+impl Deref for SourceFile {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+// Reads the file at `path` like `decode` would. Invalid UTF-8 with
+// `Encoding::Utf8` is an `InvalidData` error wrapping `InvalidUtf8`.
+pub fn read(path: &Path, encoding: Encoding) -> io::Result<SourceFile> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() >= MAP_THRESHOLD {
+        // SAFETY: the map is only read, by `decode`, which copies UTF-8 out
+        // of it before checking it and converts other encodings byte by
+        // byte, so the text never borrows from the map. Another process
+        // writing the file meanwhile can only garble the text, though one
+        // truncating it can still kill this process with SIGBUS, as with any
+        // mapped file.
+        let map = unsafe { Mmap::map(&file)? };
+        return from_bytes(&map, encoding);
+    }
+
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+    from_bytes(&bytes, encoding)
+}
+
+fn from_bytes(bytes: &[u8], encoding: Encoding) -> io::Result<SourceFile> {
+    let decoded =
+        decode(bytes, encoding).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(SourceFile {
+        text: decoded.text,
+        fallback: decoded.fallback,
+    })
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::token::{Location, Token};
+
+// Plain counters bumped by the interpreter as it runs. Everything is a field
+// increment so keeping them always on costs next to nothing.
+#[derive(Debug, Default, Clone)]
+pub struct RuntimeStats {
+    pub statements: u64,
+    pub procedure_calls: u64,
+    pub max_call_depth: usize,
+    pub arithmetic: ArithmeticCounts,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ArithmeticCounts {
+    pub add: u64,
+    pub subtract: u64,
+    pub multiply: u64,
+    pub real_divide: u64,
+    pub integer_divide: u64,
+    pub negate: u64,
+}
+
+impl ArithmeticCounts {
+    pub fn count_binary(&mut self, op: &Token) {
+        match op {
+            Token::Plus => self.add += 1,
+            Token::Minus => self.subtract += 1,
+            Token::Asterisk => self.multiply += 1,
+            Token::FloatDiv => self.real_divide += 1,
+            Token::IntegerDiv => self.integer_divide += 1,
+            _ => {}
+        }
+    }
+
+    pub fn count_unary(&mut self, op: &Token) {
+        if let Token::Minus = op {
+            self.negate += 1;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.add
+            + self.subtract
+            + self.multiply
+            + self.real_divide
+            + self.integer_divide
+            + self.negate
+    }
+}
+
+// What a run used, for hosts such as graders that enforce or record limits.
+// `Interpreter::interpret` returns it; after a failed run
+// `Interpreter::run_report` gives the figures up to the failure.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunReport {
+    // Nodes visited, as in `Interpreter::steps`
+    pub steps: u64,
+    pub max_stack_depth: usize,
+    pub wall_time: Duration,
+    // Written by `write`, `writeln` and builtin providers
+    pub io_bytes: u64,
+    pub warnings: Vec<RuntimeWarning>,
+}
+
+// Something that went wrong without stopping the program: an INTEGER that
+// wrapped around or a REAL that became infinite or NaN, which only stop it
+// with overflow checks or `--real-policy=error`, or an operand converted
+// with a loss when `Interpreter::enable_conversion_warnings` is on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeWarning {
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for RuntimeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+// Wall time of each pipeline stage. Lexing happens on demand while parsing,
+// so `parse` excludes the time spent inside the lexer.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StageTimes {
+    pub lex: Duration,
+    pub parse: Duration,
+    pub optimize: Duration,
+    pub analyze: Duration,
+    pub execute: Duration,
+}
+
+impl fmt::Display for RuntimeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ops = &self.arithmetic;
+        writeln!(f, "statements executed : {}", self.statements)?;
+        writeln!(f, "procedure calls     : {}", self.procedure_calls)?;
+        writeln!(f, "max call depth      : {}", self.max_call_depth)?;
+        writeln!(f, "arithmetic ops      : {}", ops.total())?;
+        writeln!(f, "  +   : {}", ops.add)?;
+        writeln!(f, "  -   : {}", ops.subtract)?;
+        writeln!(f, "  *   : {}", ops.multiply)?;
+        writeln!(f, "  /   : {}", ops.real_divide)?;
+        writeln!(f, "  DIV : {}", ops.integer_divide)?;
+        write!(f, "  neg : {}", ops.negate)
+    }
+}
+
+impl fmt::Display for StageTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "lex      : {:?}", self.lex)?;
+        writeln!(f, "parse    : {:?}", self.parse)?;
+        writeln!(f, "optimize : {:?}", self.optimize)?;
+        writeln!(f, "analyze  : {:?}", self.analyze)?;
+        write!(f, "execute  : {:?}", self.execute)
+    }
+}
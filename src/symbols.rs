@@ -1,24 +1,33 @@
 use core::fmt;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
-use crate::ast::ASTNode;
+use crate::ast::{NodeId, ParamMode};
+use crate::builtins::{BuiltinType, Signature};
 
 #[derive(Debug, Clone)]
 pub struct Symbol {
     pub name: String,
     pub kind: SymbolKind,
+    // The node that declares it: the `Var` of a variable or parameter, the
+    // `ProcedureDecl` or the `TypeDecl`. Builtins have none.
+    pub declaration: Option<NodeId>,
 }
 
 #[derive(Debug, Clone)]
 pub enum SymbolKind {
     BuiltinType(BuiltinTypes),
-    Variable {
-        type_name: String,
-    },
-    Procedure {
-        param_names: Vec<String>,
-        block: Box<ASTNode>,
-    },
+    Variable { type_name: String },
+    // The `ProcedureDecl` is the symbol's `declaration`; its block is run
+    // from there rather than copied
+    Procedure { param_names: Vec<String> },
+    BuiltinProcedure(BuiltinProcedure),
+    // A procedure of a `BuiltinProvider`
+    ExternalProcedure(Signature),
+    // A type from a TYPE section; `definition` is its `Type` or
+    // `ProcedureType` node
+    Type { definition: NodeId },
+    // The `T` of a generic procedure, in scope in its parameters and body
+    TypeParam,
 }
 
 #[derive(Debug, Clone)]
@@ -36,8 +45,41 @@ impl fmt::Display for BuiltinTypes {
     }
 }
 
+// Procedures the interpreter provides. They take any number of arguments
+// of any type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinProcedure {
+    Write,
+    Writeln,
+}
+
+impl BuiltinProcedure {
+    pub const ALL: [BuiltinProcedure; 2] = [BuiltinProcedure::Write, BuiltinProcedure::Writeln];
+
+    // Lowercase, like every identifier once lexed
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinProcedure::Write => "write",
+            BuiltinProcedure::Writeln => "writeln",
+        }
+    }
+
+    pub fn signature(self) -> Signature {
+        Signature::new(self.name())
+            .with_param(BuiltinType::Any, ParamMode::Value)
+            .with_variadic()
+    }
+}
+
+impl fmt::Display for BuiltinProcedure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 pub struct ScopedSymbolTable {
-    table: HashMap<String, Symbol>,
+    // Ordered by name, which is how the table prints
+    table: BTreeMap<String, Symbol>,
     scope_name: String,
     pub enclosing_scope: Option<Rc<RefCell<ScopedSymbolTable>>>,
     pub scope_level: u32,
@@ -50,9 +92,9 @@ impl ScopedSymbolTable {
         enclosing_scope: Option<Rc<RefCell<ScopedSymbolTable>>>,
     ) -> Self {
         let mut table = ScopedSymbolTable {
-            table: HashMap::new(),
+            table: BTreeMap::new(),
             scope_name,
-            enclosing_scope: enclosing_scope,
+            enclosing_scope,
             scope_level,
         };
         table.init_builtins();
@@ -63,11 +105,20 @@ impl ScopedSymbolTable {
         self.define(Symbol {
             name: BuiltinTypes::Integer.to_string(),
             kind: SymbolKind::BuiltinType(BuiltinTypes::Integer),
+            declaration: None,
         });
         self.define(Symbol {
             name: BuiltinTypes::Real.to_string(),
             kind: SymbolKind::BuiltinType(BuiltinTypes::Real),
+            declaration: None,
         });
+        for builtin in BuiltinProcedure::ALL {
+            self.define(Symbol {
+                name: builtin.name().to_string(),
+                kind: SymbolKind::BuiltinProcedure(builtin),
+                declaration: None,
+            });
+        }
     }
 
     pub fn define(&mut self, symbol: Symbol) {
@@ -85,7 +136,7 @@ impl ScopedSymbolTable {
         }
 
         // Look in parent scopes
-        if let Some(scope) = self.enclosing_scope.as_ref().map(|s| Rc::clone(s)) {
+        if let Some(scope) = self.enclosing_scope.as_ref().map(Rc::clone) {
             return scope.borrow().lookup(name, false);
         }
 
@@ -111,10 +162,15 @@ impl fmt::Display for ScopedSymbolTable {
                     let params = param_names.join(", ");
                     format!("Procedure([{}])", params)
                 }
+                SymbolKind::BuiltinProcedure(builtin) => format!("BuiltinProcedure({builtin})"),
+                SymbolKind::ExternalProcedure(signature) => {
+                    format!("ExternalProcedure({signature})")
+                }
+                SymbolKind::Type { .. } => "Type".to_string(),
+                SymbolKind::TypeParam => "TypeParam".to_string(),
             };
             rows.push((name.clone(), desc));
         }
-        rows.sort_by(|a, b| a.0.cmp(&b.0));
         let name_max = rows.iter().map(|(n, _)| n.len()).max().unwrap_or(4);
         let desc_max = rows.iter().map(|(_, d)| d.len()).max().unwrap_or(4);
         let name_width = name_max.max(4);
@@ -1,7 +1,10 @@
 use phf::phf_map;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::value::real_literal;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Token {
     Program,
     Var,
@@ -26,23 +29,121 @@ pub enum Token {
     Semi,
     Eof,
     Procedure,
+    Type,
+    Equal,
+    Class,
+    Constructor,
+    Destructor,
+    Property,
+    Const,
+    LessThan,
+    GreaterThan,
+    NotEqual,
+    LessEqual,
+    GreaterEqual,
+    If,
+    Then,
+    Else,
+    While,
+    Do,
+    Repeat,
+    Until,
+    Case,
+    Of,
+    // `..` between the bounds of a range
+    Range,
+    And,
+    Or,
+    Not,
+}
+
+// Byte range of a token in the source text. Tokens keep only offsets; the
+// surrounding line is sliced out of the source when a diagnostic needs it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn snippet<'s>(&self, source: &'s str) -> &'s str {
+        let start = source[..self.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = source[self.start..]
+            .find('\n')
+            .map_or(source.len(), |i| self.start + i);
+        &source[start..end]
+    }
+}
+
+// 1-based line and column of the first character of a token or AST node.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize,
+)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriviaKind {
+    // A run of spaces and tabs
+    Whitespace,
+    Newline,
+    Comment,
+}
+
+// Source text between tokens. Only collected when the lexer is created with
+// `Lexer::with_trivia`; the text itself is recovered from the span.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+impl Trivia {
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        &source[self.span.start..self.span.end]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LocatedToken {
     pub token: Token,
     pub line: usize,
     pub column: usize,
-    pub snippet: String,
+    pub span: Span,
+    // Trivia before the token that isn't the previous token's trailing trivia
+    pub leading: Vec<Trivia>,
+    // Trivia after the token up to and including the end of its line
+    pub trailing: Vec<Trivia>,
 }
 
 impl LocatedToken {
-    pub fn new(token: Token, line: usize, column: usize, snippet: String) -> Self {
+    pub fn new(token: Token, line: usize, column: usize, span: Span) -> Self {
         Self {
             token,
             line,
             column,
-            snippet,
+            span,
+            leading: vec![],
+            trailing: vec![],
+        }
+    }
+
+    pub fn location(&self) -> Location {
+        Location {
+            line: self.line,
+            column: self.column,
         }
     }
 }
@@ -56,8 +157,82 @@ pub static RESERVER_KEYWORDS: phf::Map<&'static str, Token> = phf_map! {
     "integer" => Token::Integer,
     "real" => Token::Real,
     "procedure" => Token::Procedure,
+    "type" => Token::Type,
+    "class" => Token::Class,
+    "constructor" => Token::Constructor,
+    "destructor" => Token::Destructor,
+    "property" => Token::Property,
+    "const" => Token::Const,
+    "if" => Token::If,
+    "then" => Token::Then,
+    "else" => Token::Else,
+    "while" => Token::While,
+    "do" => Token::Do,
+    "repeat" => Token::Repeat,
+    "until" => Token::Until,
+    "case" => Token::Case,
+    "of" => Token::Of,
+    "and" => Token::And,
+    "or" => Token::Or,
+    "not" => Token::Not,
 };
 
+impl Token {
+    // The name of the variant, without any value.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Token::Program => "Program",
+            Token::Var => "Var",
+            Token::Colon => "Colon",
+            Token::Comma => "Comma",
+            Token::IntegerConst(_) => "IntegerConst",
+            Token::Integer => "Integer",
+            Token::IntegerDiv => "IntegerDiv",
+            Token::RealConst(_) => "RealConst",
+            Token::Real => "Real",
+            Token::FloatDiv => "FloatDiv",
+            Token::Plus => "Plus",
+            Token::Minus => "Minus",
+            Token::Asterisk => "Asterisk",
+            Token::LParenthesis => "LParenthesis",
+            Token::RParenthesis => "RParenthesis",
+            Token::Begin => "Begin",
+            Token::End => "End",
+            Token::Dot => "Dot",
+            Token::Id(_) => "Id",
+            Token::Assign => "Assign",
+            Token::Semi => "Semi",
+            Token::Eof => "Eof",
+            Token::Procedure => "Procedure",
+            Token::Type => "Type",
+            Token::Equal => "Equal",
+            Token::Class => "Class",
+            Token::Constructor => "Constructor",
+            Token::Destructor => "Destructor",
+            Token::Property => "Property",
+            Token::Const => "Const",
+            Token::LessThan => "LessThan",
+            Token::GreaterThan => "GreaterThan",
+            Token::NotEqual => "NotEqual",
+            Token::LessEqual => "LessEqual",
+            Token::GreaterEqual => "GreaterEqual",
+            Token::If => "If",
+            Token::Then => "Then",
+            Token::Else => "Else",
+            Token::While => "While",
+            Token::Do => "Do",
+            Token::Repeat => "Repeat",
+            Token::Until => "Until",
+            Token::Case => "Case",
+            Token::Of => "Of",
+            Token::Range => "Range",
+            Token::And => "And",
+            Token::Or => "Or",
+            Token::Not => "Not",
+        }
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -80,10 +255,35 @@ impl fmt::Display for Token {
             Token::Comma => write!(f, ","),
             Token::Integer => write!(f, "INTEGER"),
             Token::IntegerDiv => write!(f, "DIV"),
-            Token::RealConst(v) => write!(f, "RealConst({v})"),
+            Token::RealConst(v) => write!(f, "RealConst({})", real_literal(*v)),
             Token::Real => write!(f, "REAL"),
             Token::FloatDiv => write!(f, "/"),
             Token::Procedure => write!(f, "PROCEDURE"),
+            Token::Type => write!(f, "TYPE"),
+            Token::Equal => write!(f, "="),
+            Token::Class => write!(f, "CLASS"),
+            Token::Constructor => write!(f, "CONSTRUCTOR"),
+            Token::Destructor => write!(f, "DESTRUCTOR"),
+            Token::Property => write!(f, "PROPERTY"),
+            Token::Const => write!(f, "CONST"),
+            Token::LessThan => write!(f, "<"),
+            Token::GreaterThan => write!(f, ">"),
+            Token::NotEqual => write!(f, "<>"),
+            Token::LessEqual => write!(f, "<="),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::If => write!(f, "IF"),
+            Token::Then => write!(f, "THEN"),
+            Token::Else => write!(f, "ELSE"),
+            Token::While => write!(f, "WHILE"),
+            Token::Do => write!(f, "DO"),
+            Token::Repeat => write!(f, "REPEAT"),
+            Token::Until => write!(f, "UNTIL"),
+            Token::Case => write!(f, "CASE"),
+            Token::Of => write!(f, "OF"),
+            Token::Range => write!(f, ".."),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
         }
     }
 }
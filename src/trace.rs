@@ -0,0 +1,146 @@
+use std::io::{self, Write};
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::ast::NodeId;
+use crate::value::Value;
+
+// One line of an execution trace: something the interpreter did,
+// `time_us` microseconds after the run started. `depth` is the number of
+// frames on the call stack, 1 in the main program. Values have the same
+// JSON form as in `--emit ast-json`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    // About to run the statement `node`, which starts at `line`:`column`
+    Statement {
+        time_us: f64,
+        node: NodeId,
+        line: usize,
+        column: usize,
+        depth: usize,
+    },
+    // The program or a procedure was entered, with the arguments already
+    // bound to its parameters. An OUT parameter has no value on entry and
+    // is left out.
+    Call {
+        time_us: f64,
+        name: String,
+        depth: usize,
+        arguments: Vec<TraceArgument>,
+    },
+    // The program or a procedure returned, normally or with an error
+    Return {
+        time_us: f64,
+        name: String,
+        depth: usize,
+    },
+    // A variable in the innermost frame was assigned
+    Assign {
+        time_us: f64,
+        name: String,
+        value: Value,
+        depth: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceArgument {
+    pub name: String,
+    pub value: Value,
+}
+
+// Writes trace events as JSON Lines while the program runs, so a trace
+// survives a program that fails or never finishes. Writing stops at the
+// first I/O error, which `finish` reports.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    start: Instant,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Tracer {
+            out,
+            start: Instant::now(),
+            error: None,
+        }
+    }
+
+    pub(crate) fn statement(&mut self, node: NodeId, line: usize, column: usize, depth: usize) {
+        let time_us = self.time_us();
+        self.emit(&TraceEvent::Statement {
+            time_us,
+            node,
+            line,
+            column,
+            depth,
+        });
+    }
+
+    pub(crate) fn call<'a>(
+        &mut self,
+        name: &str,
+        depth: usize,
+        arguments: impl IntoIterator<Item = (&'a str, &'a Value)>,
+    ) {
+        let time_us = self.time_us();
+        let arguments = arguments
+            .into_iter()
+            .map(|(name, value)| TraceArgument {
+                name: name.to_string(),
+                value: value.clone(),
+            })
+            .collect();
+        self.emit(&TraceEvent::Call {
+            time_us,
+            name: name.to_string(),
+            depth,
+            arguments,
+        });
+    }
+
+    pub(crate) fn ret(&mut self, name: &str, depth: usize) {
+        let time_us = self.time_us();
+        self.emit(&TraceEvent::Return {
+            time_us,
+            name: name.to_string(),
+            depth,
+        });
+    }
+
+    pub(crate) fn assign(&mut self, name: &str, value: &Value, depth: usize) {
+        let time_us = self.time_us();
+        self.emit(&TraceEvent::Assign {
+            time_us,
+            name: name.to_string(),
+            value: value.clone(),
+            depth,
+        });
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+
+    fn time_us(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1e6
+    }
+
+    fn emit(&mut self, event: &TraceEvent) {
+        if self.error.is_some() {
+            return;
+        }
+        let res = serde_json::to_writer(&mut self.out, event)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(self.out));
+        if let Err(e) = res {
+            self.error = Some(e);
+        }
+    }
+}
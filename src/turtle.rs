@@ -0,0 +1,171 @@
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::ast::ParamMode;
+use crate::builtins::{BuiltinProvider, BuiltinType, Signature};
+use crate::value::Value;
+
+// Blank space around the lines in the SVG
+const MARGIN: f64 = 10.0;
+
+// Turtle graphics: `PenDown`, `PenUp`, `Forward(n)` and `Turn(d)` move a
+// turtle over a canvas, and every move with the pen down draws a line. The
+// turtle starts at the origin facing right with the pen up, and `Turn`
+// turns it counterclockwise by `d` degrees.
+pub struct Turtle {
+    x: f64,
+    y: f64,
+    // In degrees, counterclockwise from facing right
+    heading: f64,
+    pen_down: bool,
+    drawing: Rc<RefCell<Drawing>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Drawing {
+    lines: Vec<Line>,
+}
+
+// In turtle coordinates, where y grows upwards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Line {
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+}
+
+impl Default for Turtle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Turtle {
+    pub fn new() -> Self {
+        Turtle {
+            x: 0.0,
+            y: 0.0,
+            heading: 0.0,
+            pen_down: false,
+            drawing: Rc::new(RefCell::new(Drawing::default())),
+        }
+    }
+
+    // Shared with the turtle, so it still shows what was drawn once the
+    // interpreter owns the turtle.
+    pub fn drawing(&self) -> Rc<RefCell<Drawing>> {
+        Rc::clone(&self.drawing)
+    }
+
+    fn forward(&mut self, distance: f64) {
+        let from = (self.x, self.y);
+        let radians = self.heading.to_radians();
+        self.x += distance * radians.cos();
+        self.y += distance * radians.sin();
+        if self.pen_down {
+            self.drawing.borrow_mut().lines.push(Line {
+                from,
+                to: (self.x, self.y),
+            });
+        }
+    }
+}
+
+impl BuiltinProvider for Turtle {
+    fn signatures(&self) -> Vec<Signature> {
+        vec![
+            Signature::new("pendown"),
+            Signature::new("penup"),
+            Signature::new("forward").with_param(BuiltinType::Real, ParamMode::Value),
+            Signature::new("turn").with_param(BuiltinType::Real, ParamMode::Value),
+        ]
+    }
+
+    // The registry has checked the arguments, so the amounts are REAL
+    fn call(
+        &mut self,
+        name: &str,
+        arguments: &mut [Value],
+        _output: &mut dyn Write,
+    ) -> Result<Option<Value>, String> {
+        match (name, &*arguments) {
+            ("pendown" | "penup", []) => self.pen_down = name == "pendown",
+            ("forward", [Value::Real(distance)]) => self.forward(f64::from(*distance)),
+            ("turn", [Value::Real(degrees)]) => {
+                self.heading = (self.heading + f64::from(*degrees)) % 360.0
+            }
+            _ => {
+                return Err(format!(
+                    "Unexpected call with {} arguments",
+                    arguments.len()
+                ))
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drawing {
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    // Just big enough for the lines and the starting point, with y flipped
+    // so up in turtle coordinates is up in the picture.
+    pub fn to_svg(&self) -> String {
+        let points = self
+            .lines
+            .iter()
+            .flat_map(|line| [line.from, line.to])
+            .map(|(x, y)| (x, -y));
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+        for (x, y) in points {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        let (left, top) = (min_x - MARGIN, min_y - MARGIN);
+        let width = max_x - min_x + 2.0 * MARGIN;
+        let height = max_y - min_y + 2.0 * MARGIN;
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            r#"<svg width="{}" height="{}" viewBox="{} {} {} {}" xmlns="http://www.w3.org/2000/svg">"#,
+            round(width),
+            round(height),
+            round(left),
+            round(top),
+            round(width),
+            round(height)
+        );
+        let _ = write!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white" />"#,
+            round(left),
+            round(top),
+            round(width),
+            round(height)
+        );
+        for line in &self.lines {
+            let _ = write!(
+                svg,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="2" stroke-linecap="round" />"#,
+                round(line.from.0),
+                round(-line.from.1),
+                round(line.to.0),
+                round(-line.to.1)
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+// Two decimals are plenty on screen, and they hide the error sin and cos
+// leave after a full turn. Adding zero turns -0 into 0.
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0 + 0.0
+}
@@ -0,0 +1,389 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, rc::Rc, str::FromStr};
+
+use crate::ast::{NodeId, ParamMode};
+
+// Runtime value of a Pascal expression or variable. Aggregates sit behind an
+// `Rc` so copying a value (into a variable, an activation record or an
+// argument list) never copies the elements themselves.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    Int(i32),
+    Real(f32),
+    Bool(bool),
+    Char(char),
+    Str(Rc<str>),
+    Array(Rc<Vec<Value>>),
+    Record(Rc<Vec<(String, Value)>>),
+    Procedure(Rc<ProcedureValue>),
+    Object(ObjectRef),
+    Nil,
+}
+
+// An instance of a class on the interpreter's `Heap`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectRef {
+    pub class_name: Rc<str>,
+    pub id: usize,
+}
+
+// A procedure used as a value, e.g. assigned to a variable of a procedure
+// type or passed as an argument.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcedureValue {
+    pub name: String,
+    pub param_names: Vec<String>,
+    pub param_modes: Vec<ParamMode>,
+    pub block: NodeId,
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "INTEGER",
+            Value::Real(_) => "REAL",
+            Value::Bool(_) => "BOOLEAN",
+            Value::Char(_) => "CHAR",
+            Value::Str(_) => "STRING",
+            Value::Array(_) => "ARRAY",
+            Value::Record(_) => "RECORD",
+            Value::Procedure(_) => "PROCEDURE",
+            Value::Object(_) => "OBJECT",
+            Value::Nil => "NIL",
+        }
+    }
+
+    // Numeric view of the value, with INTEGER widened to REAL.
+    pub fn as_real(&self) -> Option<f32> {
+        match self {
+            Value::Int(v) => Some(*v as f32),
+            Value::Real(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    // The value as `write` and `writeln` print it, following Free Pascal:
+    // booleans as `TRUE`/`FALSE`, a CHAR as the character itself rather than
+    // its ordinal, and a REAL in FPC's default exponent format. The debugger
+    // and the call stack log show values the same way.
+    pub fn display(&self) -> ValueDisplay<'_> {
+        ValueDisplay(self)
+    }
+
+    pub fn pretty(&self, options: PrettyOptions) -> PrettyValue<'_> {
+        PrettyValue {
+            value: self,
+            options,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        let Value::Record(fields) = self else {
+            return None;
+        };
+        fields
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, field)| field)
+    }
+
+    // Aggregates are copy-on-write: the fields are only cloned when this
+    // value shares them with another variable at the time of the write.
+    pub fn set_field(&mut self, name: &str, field: Value) -> Option<()> {
+        let Value::Record(fields) = self else {
+            return None;
+        };
+        let (_, slot) = Rc::make_mut(fields)
+            .iter_mut()
+            .find(|(field_name, _)| field_name == name)?;
+        *slot = field;
+        Some(())
+    }
+}
+
+// The compact form used in IR dumps, diagrams and diagnostics, where a REAL
+// reads like the literal it came from. Programs print with `Value::display`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(val) => write!(f, "{}", val),
+            Value::Real(val) => write!(f, "{}", real_literal(*val)),
+            Value::Bool(true) => write!(f, "TRUE"),
+            Value::Bool(false) => write!(f, "FALSE"),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Record(fields) => {
+                write!(f, "(")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, ")")
+            }
+            Value::Procedure(procedure) => write!(f, "<procedure {}>", procedure.name),
+            Value::Object(object) => write!(f, "<{} #{}>", object.class_name, object.id),
+            Value::Nil => write!(f, "NIL"),
+        }
+    }
+}
+
+pub struct ValueDisplay<'a>(&'a Value);
+
+impl fmt::Display for ValueDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_pretty(f, self.0, &PrettyOptions::UNLIMITED, 0)
+    }
+}
+
+// How `write` prints a REAL, with this many digits after the decimal point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RealFormat {
+    // FPC's default: ` 3.5000000000000000E+000`
+    Scientific(u8),
+    // `3.50`, like `:0:2` in FPC
+    Fixed(u8),
+}
+
+impl RealFormat {
+    pub const DEFAULT: RealFormat = RealFormat::Scientific(16);
+    pub const MAX_DIGITS: u8 = 16;
+}
+
+impl Default for RealFormat {
+    fn default() -> Self {
+        RealFormat::DEFAULT
+    }
+}
+
+// `scientific`, `scientific:N` or `fixed:N`.
+impl FromStr for RealFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, digits) = match s.split_once(':') {
+            Some((kind, digits)) => (kind, Some(digits)),
+            None => (s, None),
+        };
+        let digits = match digits.map(str::parse::<u8>) {
+            Some(Ok(digits)) if digits <= RealFormat::MAX_DIGITS => Some(digits),
+            Some(_) => {
+                return Err(format!(
+                    "The digits of real format '{s}' must be a number from 0 to {}",
+                    RealFormat::MAX_DIGITS
+                ))
+            }
+            None => None,
+        };
+        match (kind, digits) {
+            ("scientific", digits) => Ok(RealFormat::Scientific(digits.unwrap_or(16))),
+            ("fixed", Some(digits)) => Ok(RealFormat::Fixed(digits)),
+            ("fixed", None) => Err(format!(
+                "Real format '{s}' needs the number of digits, e.g. 'fixed:2'"
+            )),
+            _ => Err(format!("Unknown real format '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for RealFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RealFormat::Scientific(digits) => write!(f, "scientific:{digits}"),
+            RealFormat::Fixed(digits) => write!(f, "fixed:{digits}"),
+        }
+    }
+}
+
+// How `Value::pretty` lays out arrays and records for people to read, in
+// the debugger, the call stack log and state dumps. Scalars print as
+// `Value::display` prints them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrettyOptions {
+    // Arrays and records nested deeper than this show as `[...]` and
+    // `(...)`; 0 hides the contents of even the outermost one
+    pub max_depth: usize,
+    // Elements or fields past this many are left out and counted
+    pub max_elements: usize,
+    // One element or field per line instead of all on one line
+    pub multiline: bool,
+    // Spaces in front of every line after the first, so a multiline value
+    // lines up inside an indented listing
+    pub indent: usize,
+    pub real_format: RealFormat,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        PrettyOptions {
+            max_depth: 3,
+            max_elements: 10,
+            multiline: false,
+            indent: 0,
+            real_format: RealFormat::DEFAULT,
+        }
+    }
+}
+
+impl PrettyOptions {
+    // Everything, on one line, the way `write` prints it
+    pub const UNLIMITED: PrettyOptions = PrettyOptions {
+        max_depth: usize::MAX,
+        max_elements: usize::MAX,
+        multiline: false,
+        indent: 0,
+        real_format: RealFormat::DEFAULT,
+    };
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_max_elements(mut self, max_elements: usize) -> Self {
+        self.max_elements = max_elements;
+        self
+    }
+
+    pub fn multiline(mut self, indent: usize) -> Self {
+        self.multiline = true;
+        self.indent = indent;
+        self
+    }
+
+    pub fn with_real_format(mut self, real_format: RealFormat) -> Self {
+        self.real_format = real_format;
+        self
+    }
+}
+
+pub struct PrettyValue<'a> {
+    value: &'a Value,
+    options: PrettyOptions,
+}
+
+impl fmt::Display for PrettyValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_pretty(f, self.value, &self.options, 0)
+    }
+}
+
+// `depth` counts the arrays and records around `value`.
+fn write_pretty(
+    f: &mut fmt::Formatter<'_>,
+    value: &Value,
+    options: &PrettyOptions,
+    depth: usize,
+) -> fmt::Result {
+    let (open, close, separator, items): (_, _, _, Vec<(Option<&str>, &Value)>) = match value {
+        Value::Real(val) => return write_real(f, *val, options.real_format),
+        Value::Array(elements) => ("[", "]", ",", elements.iter().map(|e| (None, e)).collect()),
+        Value::Record(fields) => (
+            "(",
+            ")",
+            ";",
+            fields
+                .iter()
+                .map(|(name, field)| (Some(name.as_str()), field))
+                .collect(),
+        ),
+        value => return write!(f, "{}", value),
+    };
+    if items.is_empty() {
+        return write!(f, "{open}{close}");
+    }
+    if depth >= options.max_depth {
+        return write!(f, "{open}...{close}");
+    }
+
+    let shown = items.len().min(options.max_elements);
+    let hidden = items.len() - shown;
+    let item_indent = options.indent + 2 * (depth + 1);
+    write!(f, "{open}")?;
+    for (i, (name, item)) in items.iter().take(shown).enumerate() {
+        if options.multiline {
+            write!(f, "\n{:item_indent$}", "")?;
+        } else if i > 0 {
+            write!(f, " ")?;
+        }
+        if let Some(name) = name {
+            write!(f, "{name}: ")?;
+        }
+        write_pretty(f, item, options, depth + 1)?;
+        if i + 1 < shown || hidden > 0 {
+            write!(f, "{separator}")?;
+        }
+    }
+    if hidden > 0 {
+        if options.multiline {
+            write!(f, "\n{:item_indent$}", "")?;
+        } else if shown > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "... {hidden} more")?;
+    }
+    if options.multiline {
+        write!(f, "\n{:width$}", "", width = item_indent - 2)?;
+    }
+    write!(f, "{close}")
+}
+
+// A REAL as a Pascal literal: the shortest digits that read back as the
+// same value, always with a `.` and at least one digit after it, so `3.0`
+// stays a REAL when the output is parsed again. Rust's float formatting and
+// parsing never look at the locale, so neither does this or `parse_real`.
+// Infinity and NaN have no literal and are written like `writeln` does.
+pub fn real_literal(val: f32) -> String {
+    if !val.is_finite() {
+        return ValueDisplay(&Value::Real(val)).to_string();
+    }
+    let mut text = val.to_string();
+    if !text.contains('.') {
+        text.push_str(".0");
+    }
+    text
+}
+
+// Reads the digits of a real literal, as produced by the lexer or by
+// `real_literal`. Only `.` is accepted as the decimal separator.
+pub fn parse_real(text: &str) -> Option<f32> {
+    text.parse().ok()
+}
+
+// FPC writes a REAL as a double with 16 fractional digits and a signed
+// three-digit exponent, with a space in place of the sign of a positive
+// number: ` 3.5000000000000000E+000`. REAL is an f32 here, so it is widened
+// through its shortest decimal form; 0.1 then prints as FPC's 0.1 would
+// instead of as the f32 nearest to it. Other formats round the same
+// widened value.
+fn write_real(f: &mut fmt::Formatter<'_>, val: f32, format: RealFormat) -> fmt::Result {
+    if val.is_nan() {
+        return write!(f, "Nan");
+    }
+    if val.is_infinite() {
+        return write!(f, "{}Inf", if val > 0.0 { '+' } else { '-' });
+    }
+    let wide: f64 = val.to_string().parse().unwrap_or(f64::from(val));
+    let precision = match format {
+        RealFormat::Scientific(digits) => usize::from(digits),
+        RealFormat::Fixed(digits) => {
+            return write!(f, "{wide:.precision$}", precision = usize::from(digits))
+        }
+    };
+    let digits = format!("{:.precision$e}", wide.abs());
+    let (mantissa, exponent) = digits.split_once('e').unwrap_or((&digits, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if wide.is_sign_negative() { '-' } else { ' ' };
+    write!(f, "{sign}{mantissa}E{exponent:+04}")
+}
@@ -0,0 +1,92 @@
+use std::cell::Cell;
+
+use corosensei::stack::{DefaultStack, Stack};
+
+use crate::ast::{Ast, NodeId};
+
+// Read-only traversal. Implementors override `visit` for the nodes they care
+// about and call `walk` to continue into the children.
+pub trait Visitor {
+    fn visit(&mut self, ast: &Ast, node: NodeId) {
+        walk(self, ast, node);
+    }
+}
+
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, node: NodeId) {
+    ensure_stack(|| {
+        for child in ast.children(node) {
+            visitor.visit(ast, child);
+        }
+    })
+}
+
+// Rewriting traversal. Implementors may replace `ast[node]` wholesale.
+pub trait VisitorMut {
+    fn visit_mut(&mut self, ast: &mut Ast, node: NodeId) {
+        walk_mut(self, ast, node);
+    }
+}
+
+pub fn walk_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, node: NodeId) {
+    ensure_stack(|| {
+        for child in ast.children(node) {
+            visitor.visit_mut(ast, child);
+        }
+    })
+}
+
+// A chain like `1 + 1 + ... + 1` nests as deeply as it is long, which the
+// parser's depth limit doesn't cover. Code that recurses into the tree runs
+// each level through this, which moves on to a fresh stack segment when
+// the current one runs low instead of overflowing.
+pub fn ensure_stack<R>(f: impl FnOnce() -> R) -> R {
+    match STACK_LIMIT.get() {
+        None => stacker::maybe_grow(RED_ZONE, SEGMENT, f),
+        Some(limit) if stack_position().saturating_sub(limit) >= RED_ZONE => f(),
+        Some(_) => {
+            let stack = new_stack();
+            let limit = stack.limit().get();
+            corosensei::on_stack(stack, || with_stack_limit(Some(limit), f))
+        }
+    }
+}
+
+const RED_ZONE: usize = 256 * 1024;
+const SEGMENT: usize = 4 * 1024 * 1024;
+
+thread_local! {
+    // The lowest address of the stack the thread is on, while that is one
+    // made here, such as a budgeted run's. stacker only keeps track of the
+    // thread's own stack and the segments it makes itself.
+    static STACK_LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// Roughly where the top of the stack is, which is all the red zone needs
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+// A stack segment for code that recurses into the tree.
+pub(crate) fn new_stack() -> DefaultStack {
+    DefaultStack::new(SEGMENT).expect("failed to allocate a stack segment")
+}
+
+// Runs `f`, which has just switched to a stack made with `new_stack`, with
+// that stack's `limit` for `ensure_stack` to check against.
+pub(crate) fn with_stack_limit<R>(limit: Option<usize>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<usize>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            STACK_LIMIT.set(self.0);
+        }
+    }
+    let _restore = Restore(STACK_LIMIT.replace(limit));
+    f()
+}
+
+// Runs `f`, which switches to another coroutine and back, and restores the
+// limit of the stack it started on once it is back there.
+pub(crate) fn keep_stack_limit<R>(f: impl FnOnce() -> R) -> R {
+    with_stack_limit(STACK_LIMIT.get(), f)
+}
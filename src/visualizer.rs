@@ -1,5 +1,7 @@
-use crate::ast::{ASTNode, BuiltinNumTypes};
+use crate::ast::{ASTNode, Ast, NodeId};
 use crate::token::Token;
+use crate::value::real_literal;
+use crate::visitor::ensure_stack;
 
 struct DrawNode {
     #[allow(dead_code)]
@@ -18,6 +20,12 @@ pub struct Visualizer {
     background_color: String,
 }
 
+impl Default for Visualizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Visualizer {
     pub fn new() -> Self {
         Self {
@@ -29,12 +37,12 @@ impl Visualizer {
         }
     }
 
-    pub fn generate_svg(&mut self, ast: &ASTNode) -> String {
+    pub fn generate_svg(&mut self, ast: &Ast) -> String {
         self.nodes.clear();
         self.next_id = 0;
         self.next_x = 50.0; // Start with some padding
 
-        self.build_tree(ast, 0);
+        self.build_tree(ast, ast.root(), 0);
 
         // Calculate canvas size
         let max_x = self.nodes.iter().map(|n| n.x).fold(0.0f32, f32::max);
@@ -120,132 +128,111 @@ impl Visualizer {
             Token::Comma => ",".to_string(),
             Token::Integer => "INTEGER".to_string(),
             Token::IntegerDiv => "DIV".to_string(),
-            Token::RealConst(v) => v.to_string(),
+            Token::RealConst(v) => real_literal(*v),
             Token::Real => "REAL".to_string(),
             Token::Procedure => "PROCEDURE".to_string(),
+            Token::Type => "TYPE".to_string(),
+            Token::Equal => "=".to_string(),
+            Token::Class => "CLASS".to_string(),
+            Token::Constructor => "CONSTRUCTOR".to_string(),
+            Token::Destructor => "DESTRUCTOR".to_string(),
+            Token::Property => "PROPERTY".to_string(),
+            Token::Const => "CONST".to_string(),
+            Token::LessThan => "<".to_string(),
+            Token::GreaterThan => ">".to_string(),
+            Token::NotEqual => "<>".to_string(),
+            Token::LessEqual => "<=".to_string(),
+            Token::GreaterEqual => ">=".to_string(),
+            Token::If => "IF".to_string(),
+            Token::Then => "THEN".to_string(),
+            Token::Else => "ELSE".to_string(),
+            Token::While => "WHILE".to_string(),
+            Token::Do => "DO".to_string(),
+            Token::Repeat => "REPEAT".to_string(),
+            Token::Until => "UNTIL".to_string(),
+            Token::Case => "CASE".to_string(),
+            Token::Of => "OF".to_string(),
+            Token::Range => "..".to_string(),
+            Token::And => "AND".to_string(),
+            Token::Or => "OR".to_string(),
+            Token::Not => "NOT".to_string(),
         }
     }
 
-    fn build_tree(&mut self, node: &ASTNode, depth: usize) -> usize {
-        let id = self.next_id;
-        self.next_id += 1;
-
-        // Placeholder
-        self.nodes.push(DrawNode {
-            id,
-            label: String::new(),
-            x: 0.0,
-            y: (depth as f32) * self.level_height + 40.0,
-            children: Vec::new(),
-        });
-
-        let (label, children_indices) = match node {
-            ASTNode::Compound { children } => {
-                let mut indices = Vec::new();
-                for child in children {
-                    indices.push(self.build_tree(child, depth + 1));
+    fn build_tree(&mut self, ast: &Ast, node: NodeId, depth: usize) -> usize {
+        ensure_stack(|| {
+            let id = self.next_id;
+            self.next_id += 1;
+
+            // Placeholder
+            self.nodes.push(DrawNode {
+                id,
+                label: String::new(),
+                x: 0.0,
+                y: (depth as f32) * self.level_height + 40.0,
+                children: Vec::new(),
+            });
+
+            let label = match &ast[node] {
+                ASTNode::Compound { .. } => "Compound".to_string(),
+                ASTNode::If { .. } => "If".to_string(),
+                ASTNode::While { .. } => "While".to_string(),
+                ASTNode::Repeat { .. } => "Repeat".to_string(),
+                ASTNode::Case { .. } => "Case".to_string(),
+                ASTNode::CaseArm { .. } => "CaseArm".to_string(),
+                ASTNode::Range { .. } => "Range".to_string(),
+                ASTNode::Assign { token, .. } => {
+                    format!("Assign({})", Self::token_to_string(token))
                 }
-                ("Compound".to_string(), indices)
-            }
-            ASTNode::Assign { left, right, token } => {
-                let l = self.build_tree(left, depth + 1);
-                let r = self.build_tree(right, depth + 1);
-                (
-                    format!("Assign({})", Self::token_to_string(token)),
-                    vec![l, r],
-                )
-            }
-            ASTNode::Var { name: value } => (format!("Var({})", value), vec![]),
-            ASTNode::NoOp => ("NoOp".to_string(), vec![]),
-            ASTNode::UnaryOpNode { expr, token } => {
-                let e = self.build_tree(expr, depth + 1);
-                (format!("Unary({})", Self::token_to_string(token)), vec![e])
-            }
-            ASTNode::BinOpNode { left, right, op } => {
-                let l = self.build_tree(left, depth + 1);
-                let r = self.build_tree(right, depth + 1);
-                (format!("BinOp({})", Self::token_to_string(op)), vec![l, r])
-            }
-            ASTNode::NumNode { value, .. } => {
-                let value_str = match value {
-                    BuiltinNumTypes::I32(i) => i.to_string(),
-                    BuiltinNumTypes::F32(f) => f.to_string(),
-                };
-                (format!("Num({})", value_str), vec![])
-            }
-            ASTNode::Program { name, block } => {
-                let child = self.build_tree(block, depth + 1);
-                (format!("Program({})", name), vec![child])
-            }
-            ASTNode::Block {
-                declarations,
-                compound_statement,
-            } => {
-                let mut indices = Vec::new();
-                for decl in declarations {
-                    indices.push(self.build_tree(decl, depth + 1));
+                ASTNode::Var { name: value } => format!("Var({})", value),
+                ASTNode::NoOp => "NoOp".to_string(),
+                ASTNode::UnaryOpNode { token, .. } => {
+                    format!("Unary({})", Self::token_to_string(token))
                 }
-                indices.push(self.build_tree(compound_statement, depth + 1));
-                ("Block".to_string(), indices)
-            }
-            ASTNode::VarDecl {
-                var_node,
-                type_node,
-            } => {
-                let v = self.build_tree(var_node, depth + 1);
-                let t = self.build_tree(type_node, depth + 1);
-                ("VarDecl".to_string(), vec![v, t])
-            }
-            ASTNode::Type { value, .. } => (format!("Type({})", value), vec![]),
-            ASTNode::ProcedureDecl {
-                proc_name,
-                params,
-                block_node,
-            } => {
-                let mut indices = Vec::new();
-                for child in params {
-                    indices.push(self.build_tree(child, depth + 1));
-                }
-                let k = self.build_tree(&block_node, depth + 1);
-                indices.push(k);
-                (format!("Function({proc_name})"), indices)
-            }
-            ASTNode::Param {
-                var_node,
-                type_node,
-            } => {
-                let v = self.build_tree(&var_node, depth + 1);
-                let k = self.build_tree(&type_node, depth + 1);
-                (format!("Param"), vec![v, k])
-            }
-            ASTNode::ProcedureCall {
-                proc_name,
-                arguments,
-                ..
-            } => {
-                let mut indices = Vec::new();
-                for arg in arguments {
-                    indices.push(self.build_tree(arg, depth + 1));
+                ASTNode::BinOpNode { op, .. } => format!("BinOp({})", Self::token_to_string(op)),
+                ASTNode::NumNode { value, .. } => format!("Num({})", value),
+                ASTNode::Program { name, .. } => format!("Program({})", name),
+                ASTNode::Block { .. } => "Block".to_string(),
+                ASTNode::VarDecl { .. } => "VarDecl".to_string(),
+                ASTNode::Type { value, .. } => format!("Type({})", value),
+                ASTNode::TypeDecl { type_name, .. } => format!("TypeDecl({type_name})"),
+                ASTNode::ProcedureType { .. } => "ProcedureType".to_string(),
+                ASTNode::ClassType { .. } => "ClassType".to_string(),
+                ASTNode::MethodHeading { name, .. } => format!("MethodHeading({name})"),
+                ASTNode::Property { name, .. } => format!("Property({name})"),
+                ASTNode::MethodDecl {
+                    class_name,
+                    method_name,
+                    ..
+                } => format!("Method({class_name}.{method_name})"),
+                ASTNode::FieldAccess { field, .. } => format!("Field({field})"),
+                ASTNode::MethodCall { method, .. } => format!("MethodCall({method})"),
+                ASTNode::ProcedureDecl { proc_name, .. } => format!("Function({proc_name})"),
+                ASTNode::Param { .. } => "Param".to_string(),
+                ASTNode::ProcedureCall { proc_name, .. } => format!("ProcedureCall({})", proc_name),
+            };
+
+            let children_indices = ast
+                .children(node)
+                .into_iter()
+                .map(|child| self.build_tree(ast, child, depth + 1))
+                .collect::<Vec<usize>>();
+
+            let my_x = match (children_indices.first(), children_indices.last()) {
+                (Some(first), Some(last)) => (self.nodes[*first].x + self.nodes[*last].x) / 2.0,
+                _ => {
+                    let x = self.next_x;
+                    self.next_x += 80.0; // Spacing
+                    x
                 }
-                (format!("ProcedureCall({})", proc_name), indices)
-            }
-        };
-
-        let my_x = if children_indices.is_empty() {
-            let x = self.next_x;
-            self.next_x += 80.0; // Spacing
-            x
-        } else {
-            let first_child = &self.nodes[children_indices[0]];
-            let last_child = &self.nodes[*children_indices.last().unwrap()];
-            (first_child.x + last_child.x) / 2.0
-        };
-
-        let node_ref = &mut self.nodes[id];
-        node_ref.label = label;
-        node_ref.children = children_indices;
-        node_ref.x = my_x;
-
-        id
+            };
+
+            let node_ref = &mut self.nodes[id];
+            node_ref.label = label;
+            node_ref.children = children_indices;
+            node_ref.x = my_x;
+
+            id
+        })
     }
 }
@@ -0,0 +1,478 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fmt::Write;
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::symbols::BuiltinProcedure;
+use crate::token::Token;
+use crate::value::Value;
+use crate::visitor::ensure_stack;
+
+// Like the JIT, the generated code follows the interpreter's typing. An
+// integer literal is an i32 and a real one an f32. `+`, `-` and `*` on two
+// i32s give an i32 with `i32.add`, `i32.sub` and `i32.mul`, wrapping around
+// on overflow, and `div` always gives an i32. `/`, or an f32 operand, gives
+// an f32. A variable has the type of the value last assigned to it, which is
+// known statically since the code is straight-line, and procedure
+// parameters are f32. At the end of the program every program variable is
+// passed to the imported `print_i32` or `print_f32`, whichever fits.
+
+#[derive(Debug, Clone)]
+pub enum WasmError {
+    Unsupported { construct: String },
+}
+
+impl fmt::Display for WasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasmError::Unsupported { construct } => {
+                write!(f, "WebAssembly backend does not support {construct}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WasmError {}
+
+pub type WasmResult<T> = std::result::Result<T, WasmError>;
+
+fn unsupported<T>(construct: impl Into<String>) -> WasmResult<T> {
+    Err(WasmError::Unsupported {
+        construct: construct.into(),
+    })
+}
+
+// A call the program's own procedures don't account for. Output goes
+// through the imported `print_*` functions at the end of the program
+// instead of `write` and `writeln`.
+fn unsupported_call<T>(proc_name: &str) -> WasmResult<T> {
+    if BuiltinProcedure::ALL
+        .iter()
+        .any(|builtin| builtin.name() == proc_name)
+    {
+        unsupported(format!("the builtin procedure '{proc_name}'"))
+    } else {
+        unsupported(format!("call to unknown procedure '{proc_name}'"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Kind {
+    I32,
+    F32,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::I32 => "i32",
+            Kind::F32 => "f32",
+        }
+    }
+}
+
+// Compiles an analyzed program into a standalone module in WebAssembly text
+// format. It imports `env.print_i32` and `env.print_f32` and exports the
+// program as `main`.
+pub fn emit_wat(ast: &Ast) -> WasmResult<String> {
+    let ASTNode::Program { block, .. } = &ast[ast.root()] else {
+        return unsupported("a tree without a PROGRAM root");
+    };
+    let ASTNode::Block {
+        compound_statement, ..
+    } = &ast[*block]
+    else {
+        return unsupported("a program without a block");
+    };
+
+    let mut compiler = Compiler {
+        ast,
+        procedures: vec![],
+        calls: HashMap::new(),
+        scopes: vec![],
+    };
+    compiler.collect_block(*block)?;
+
+    let mut out = String::new();
+    let _ = writeln!(out, "(module");
+    let _ = writeln!(
+        out,
+        "  (import \"env\" \"print_i32\" (func $print_i32 (param i32)))"
+    );
+    let _ = writeln!(
+        out,
+        "  (import \"env\" \"print_f32\" (func $print_f32 (param f32)))"
+    );
+    for procedure in &compiler.procedures {
+        let mut function = FunctionTranslator::new(ast, &compiler.calls);
+        for name in &procedure.params {
+            function.local(name, Kind::F32);
+            function.kinds.insert(name.clone(), Kind::F32);
+        }
+        function.params = procedure.params.len();
+        function.statement(procedure.body)?;
+        let params = procedure
+            .params
+            .iter()
+            .map(|name| format!(" (param {} f32)", local_name(name, Kind::F32)))
+            .collect::<String>();
+        function.write(&mut out, &format!("${}{}", procedure.label, params));
+    }
+
+    let mut main = FunctionTranslator::new(ast, &compiler.calls);
+    main.statement(*compound_statement)?;
+    let globals = main.kinds.clone().into_iter().collect::<BTreeMap<_, _>>();
+    for (name, kind) in globals {
+        main.emit(format!("local.get {}", local_name(&name, kind)));
+        main.emit(format!("call $print_{}", kind.name()));
+    }
+    main.write(&mut out, "$main (export \"main\")");
+    let _ = writeln!(out, ")");
+    Ok(out)
+}
+
+fn local_name(name: &str, kind: Kind) -> String {
+    format!("${}.{}", name, kind.name())
+}
+
+struct Procedure {
+    label: String,
+    params: Vec<String>,
+    body: NodeId,
+}
+
+struct Compiler<'a> {
+    ast: &'a Ast,
+    procedures: Vec<Procedure>,
+    // Call sites are resolved up front, keyed by node id, so that shadowed
+    // procedure names bind the way the semantic analyzer binds them.
+    calls: HashMap<NodeId, String>,
+    scopes: Vec<HashMap<String, String>>,
+}
+
+impl Compiler<'_> {
+    fn collect_block(&mut self, block: NodeId) -> WasmResult<()> {
+        let ast = self.ast;
+        let ASTNode::Block {
+            declarations,
+            compound_statement,
+        } = &ast[block]
+        else {
+            return unsupported(format!("'{}' in place of a block", ast.display(block)));
+        };
+
+        self.scopes.push(HashMap::new());
+        for declaration in declarations {
+            match &ast[*declaration] {
+                ASTNode::VarDecl {
+                    var_node,
+                    initializer: Some(_),
+                    ..
+                } => {
+                    return unsupported(format!("the initializer of '{}'", ast.display(*var_node)));
+                }
+                ASTNode::VarDecl { .. } => {}
+                ASTNode::ProcedureDecl {
+                    proc_name,
+                    params,
+                    block_node,
+                    ..
+                } => {
+                    let params = params
+                        .iter()
+                        .map(|param| match &ast[*param] {
+                            // VAR and OUT parameters would need the caller's
+                            // variable written back
+                            ASTNode::Param { var_node, mode, .. } if !mode.by_reference() => {
+                                match &ast[*var_node] {
+                                    ASTNode::Var { name } => Ok(name.clone()),
+                                    _ => {
+                                        unsupported(format!("parameter '{}'", ast.display(*param)))
+                                    }
+                                }
+                            }
+                            _ => unsupported(format!("parameter '{}'", ast.display(*param))),
+                        })
+                        .collect::<WasmResult<Vec<_>>>()?;
+
+                    let label = format!("proc{}_{}", self.procedures.len(), proc_name);
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert(proc_name.clone(), label.clone());
+                    }
+
+                    let ASTNode::Block {
+                        compound_statement: body,
+                        ..
+                    } = &ast[*block_node]
+                    else {
+                        return unsupported(format!("procedure '{proc_name}' without a block"));
+                    };
+                    self.procedures.push(Procedure {
+                        label,
+                        params,
+                        body: *body,
+                    });
+                    self.collect_block(*block_node)?;
+                }
+                _ => return unsupported(format!("declaration '{}'", ast.display(*declaration))),
+            }
+        }
+        self.resolve_calls(*compound_statement)?;
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn resolve_calls(&mut self, statement: NodeId) -> WasmResult<()> {
+        let ast = self.ast;
+        match &ast[statement] {
+            ASTNode::Compound { children } => {
+                for child in children {
+                    self.resolve_calls(*child)?;
+                }
+            }
+            ASTNode::ProcedureCall { proc_name, .. } => {
+                let Some(label) = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(proc_name))
+                else {
+                    return unsupported_call(proc_name);
+                };
+                self.calls.insert(statement, label.clone());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+struct FunctionTranslator<'b> {
+    ast: &'b Ast,
+    calls: &'b HashMap<NodeId, String>,
+    // Wasm locals have one type each, so a variable gets one local per kind
+    // it is ever assigned. The first `params` of them are the parameters.
+    locals: Vec<(String, Kind)>,
+    params: usize,
+    kinds: HashMap<String, Kind>,
+    body: Vec<String>,
+    temps: usize,
+}
+
+impl<'b> FunctionTranslator<'b> {
+    fn new(ast: &'b Ast, calls: &'b HashMap<NodeId, String>) -> Self {
+        FunctionTranslator {
+            ast,
+            calls,
+            locals: vec![],
+            params: 0,
+            kinds: HashMap::new(),
+            body: vec![],
+            temps: 0,
+        }
+    }
+
+    fn emit(&mut self, instruction: impl Into<String>) {
+        self.body.push(instruction.into());
+    }
+
+    fn write(&self, out: &mut String, signature: &str) {
+        let _ = writeln!(out, "  (func {}", signature);
+        for (name, kind) in self.locals.iter().skip(self.params) {
+            let _ = writeln!(
+                out,
+                "    (local {} {})",
+                local_name(name, *kind),
+                kind.name()
+            );
+        }
+        for instruction in &self.body {
+            let _ = writeln!(out, "    {}", instruction);
+        }
+        let _ = writeln!(out, "  )");
+    }
+
+    fn local(&mut self, name: &str, kind: Kind) -> String {
+        if !self.locals.iter().any(|(n, k)| n == name && *k == kind) {
+            self.locals.push((name.to_string(), kind));
+        }
+        local_name(name, kind)
+    }
+
+    // A fresh scratch local, named so it cannot clash with a Pascal
+    // identifier.
+    fn temp(&mut self, kind: Kind) -> String {
+        self.temps += 1;
+        self.local(&format!("%t{}", self.temps), kind)
+    }
+
+    fn statement(&mut self, node: NodeId) -> WasmResult<()> {
+        let ast = self.ast;
+        match &ast[node] {
+            ASTNode::Compound { children } => {
+                for child in children {
+                    self.statement(*child)?;
+                }
+                Ok(())
+            }
+            ASTNode::NoOp => Ok(()),
+            ASTNode::Assign { left, right, .. } => {
+                let ASTNode::Var { name } = &ast[*left] else {
+                    return unsupported(format!("assignment to '{}'", ast.display(*left)));
+                };
+                let kind = self.expr(*right)?;
+                let local = self.local(name, kind);
+                self.emit(format!("local.set {}", local));
+                self.kinds.insert(name.clone(), kind);
+                Ok(())
+            }
+            ASTNode::ProcedureCall {
+                proc_name,
+                arguments,
+                ..
+            } => {
+                let Some(label) = self.calls.get(&node).cloned() else {
+                    return unsupported_call(proc_name);
+                };
+                for argument in arguments {
+                    let kind = self.expr(*argument)?;
+                    self.coerce_to_f32(kind);
+                }
+                self.emit(format!("call ${}", label));
+                Ok(())
+            }
+            // The kind of each variable is only tracked through straight-line
+            // code
+            ASTNode::If { .. } => unsupported("IF statements"),
+            ASTNode::While { .. } => unsupported("WHILE loops"),
+            ASTNode::Repeat { .. } => unsupported("REPEAT loops"),
+            ASTNode::Case { .. } => unsupported("CASE statements"),
+            _ => unsupported(format!("statement '{}'", ast.display(node))),
+        }
+    }
+
+    fn coerce_to_f32(&mut self, kind: Kind) {
+        if kind == Kind::I32 {
+            self.emit("f32.convert_i32_s");
+        }
+    }
+
+    // Leaves the expression's value on the stack.
+    fn expr(&mut self, node: NodeId) -> WasmResult<Kind> {
+        ensure_stack(|| {
+            let ast = self.ast;
+            match &ast[node] {
+                ASTNode::NumNode {
+                    value: Value::Int(v),
+                } => {
+                    self.emit(format!("i32.const {}", v));
+                    Ok(Kind::I32)
+                }
+                ASTNode::NumNode {
+                    value: Value::Real(v),
+                } => {
+                    self.emit(format!("f32.const {:?}", v));
+                    Ok(Kind::F32)
+                }
+                ASTNode::Var { name } => {
+                    // Control flow is straight-line, so whether a variable holds
+                    // a value (and of which kind) is known statically.
+                    let Some(kind) = self.kinds.get(name).copied() else {
+                        return unsupported(format!("reading '{name}' before it is assigned"));
+                    };
+                    let local = self.local(name, kind);
+                    self.emit(format!("local.get {}", local));
+                    Ok(kind)
+                }
+                ASTNode::UnaryOpNode { expr, token } => {
+                    let kind = self.expr(*expr)?;
+                    match (token, kind) {
+                        (Token::Plus, _) => {}
+                        (Token::Minus, Kind::I32) => {
+                            let operand = self.temp(Kind::I32);
+                            self.emit(format!("local.set {}", operand));
+                            self.emit("i32.const 0");
+                            self.emit(format!("local.get {}", operand));
+                            self.emit("i32.sub");
+                        }
+                        (Token::Minus, Kind::F32) => self.emit("f32.neg"),
+                        _ => return unsupported(format!("unary operator '{token}'")),
+                    }
+                    Ok(kind)
+                }
+                ASTNode::BinOpNode { left, right, op } => {
+                    let left_kind = self.expr(*left)?;
+                    if *op == Token::IntegerDiv {
+                        self.coerce_to_i32(left_kind);
+                        let right_kind = self.expr(*right)?;
+                        self.coerce_to_i32(right_kind);
+                        self.integer_div();
+                        return Ok(Kind::I32);
+                    }
+                    let right_kind = self.expr(*right)?;
+                    // Like the interpreter, two INTEGERs give an INTEGER, wrapping
+                    // around on overflow; `/` always gives a REAL
+                    if left_kind == Kind::I32 && right_kind == Kind::I32 && *op != Token::FloatDiv {
+                        match op {
+                            Token::Plus => self.emit("i32.add"),
+                            Token::Minus => self.emit("i32.sub"),
+                            Token::Asterisk => self.emit("i32.mul"),
+                            _ => return unsupported(format!("binary operator '{op}'")),
+                        }
+                        return Ok(Kind::I32);
+                    }
+                    self.coerce_to_f32(right_kind);
+                    if left_kind == Kind::I32 {
+                        // The left operand is under the right one on the stack
+                        let right = self.temp(Kind::F32);
+                        self.emit(format!("local.set {}", right));
+                        self.coerce_to_f32(left_kind);
+                        self.emit(format!("local.get {}", right));
+                    }
+                    match op {
+                        Token::Plus => self.emit("f32.add"),
+                        Token::Minus => self.emit("f32.sub"),
+                        Token::Asterisk => self.emit("f32.mul"),
+                        Token::FloatDiv => self.emit("f32.div"),
+                        _ => return unsupported(format!("binary operator '{op}'")),
+                    }
+                    Ok(Kind::F32)
+                }
+                _ => unsupported(format!("expression '{}'", ast.display(node))),
+            }
+        })
+    }
+
+    fn coerce_to_i32(&mut self, kind: Kind) {
+        if kind == Kind::F32 {
+            self.emit("i32.trunc_sat_f32_s");
+        }
+    }
+
+    // Mirrors the interpreter: wrap on `i32::MIN div -1`, which `i32.div_s`
+    // would trap on. Division by zero traps. REAL operands were already
+    // truncated with `as i32` semantics.
+    fn integer_div(&mut self) {
+        let divisor = self.temp(Kind::I32);
+        let dividend = self.temp(Kind::I32);
+        self.emit(format!("local.set {}", divisor));
+        self.emit(format!("local.set {}", dividend));
+
+        // divisor == -1 ? 0 - dividend : dividend / (divisor == -1 ? 1 : divisor)
+        self.emit("i32.const 0");
+        self.emit(format!("local.get {}", dividend));
+        self.emit("i32.sub");
+        self.emit(format!("local.get {}", dividend));
+        self.emit("i32.const 1");
+        self.emit(format!("local.get {}", divisor));
+        self.emit(format!("local.get {}", divisor));
+        self.emit("i32.const -1");
+        self.emit("i32.eq");
+        self.emit("select");
+        self.emit("i32.div_s");
+        self.emit(format!("local.get {}", divisor));
+        self.emit("i32.const -1");
+        self.emit("i32.eq");
+        self.emit("select");
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::ast::{ASTNode, Ast, NodeId};
+use crate::semantic_analyzer::CrossReferences;
+use crate::symbols::{BuiltinProcedure, SymbolKind};
+use crate::token::Location;
+use crate::visitor::ensure_stack;
+
+// The call graph and symbol cross-references of an analyzed program in one
+// document, for tools outside the interpreter. Routines are named as in
+// `Metrics`: the program, procedures, and `Class.Method` for methods.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Xref {
+    pub routines: Vec<Routine>,
+    // One edge per caller and callee, in order of the first call
+    pub calls: Vec<CallEdge>,
+    // In declaration order
+    pub symbols: Vec<SymbolXref>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Routine {
+    pub name: String,
+    // `program`, `procedure` or `method`
+    pub kind: &'static str,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CallEdge {
+    pub caller: String,
+    pub callee: String,
+    // `procedure`, `method`, `builtin`, `external` for a procedure of a
+    // plugin, or `indirect` for a call through a procedure variable
+    pub kind: &'static str,
+    pub sites: Vec<Location>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SymbolXref {
+    pub name: String,
+    pub kind: &'static str,
+    // The declared type of a variable or parameter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    pub declared_at: Location,
+    pub references: Vec<Location>,
+}
+
+pub fn export(ast: &Ast, cross_references: &CrossReferences) -> Xref {
+    let mut xref = Xref {
+        routines: vec![],
+        calls: vec![],
+        symbols: vec![],
+    };
+    if let ASTNode::Program { name, block } = &ast[ast.root()] {
+        xref.routine(
+            ast,
+            cross_references,
+            ast.root(),
+            name.clone(),
+            "program",
+            *block,
+        );
+    }
+
+    let mut references: BTreeMap<NodeId, Vec<Location>> = BTreeMap::new();
+    for (reference, declaration) in &cross_references.references {
+        references
+            .entry(*declaration)
+            .or_default()
+            .push(ast.location(*reference));
+    }
+    for (declaration, symbol) in &cross_references.declarations {
+        let (kind, type_name) = match &symbol.kind {
+            SymbolKind::Variable { type_name } => ("variable", Some(type_name.clone())),
+            SymbolKind::Procedure { .. } => ("procedure", None),
+            SymbolKind::Type { .. } => ("type", None),
+            SymbolKind::TypeParam => ("type-parameter", None),
+            SymbolKind::BuiltinType(_) => ("builtin-type", None),
+            SymbolKind::BuiltinProcedure(_) => ("builtin", None),
+            SymbolKind::ExternalProcedure(_) => ("external", None),
+        };
+        xref.symbols.push(SymbolXref {
+            name: symbol.name.clone(),
+            kind,
+            type_name,
+            declared_at: ast.location(*declaration),
+            references: references.remove(declaration).unwrap_or_default(),
+        });
+    }
+    xref
+}
+
+impl Xref {
+    fn routine(
+        &mut self,
+        ast: &Ast,
+        cross_references: &CrossReferences,
+        node: NodeId,
+        name: String,
+        kind: &'static str,
+        block: NodeId,
+    ) {
+        let ASTNode::Block {
+            declarations,
+            compound_statement,
+        } = &ast[block]
+        else {
+            return;
+        };
+        self.routines.push(Routine {
+            name: name.clone(),
+            kind,
+            location: ast.location(node),
+        });
+        self.calls_in(ast, cross_references, &name, *compound_statement);
+
+        for declaration in declarations {
+            match &ast[*declaration] {
+                ASTNode::ProcedureDecl {
+                    proc_name,
+                    block_node,
+                    ..
+                } => self.routine(
+                    ast,
+                    cross_references,
+                    *declaration,
+                    proc_name.clone(),
+                    "procedure",
+                    *block_node,
+                ),
+                ASTNode::MethodDecl {
+                    class_name,
+                    method_name,
+                    block_node,
+                    ..
+                } => self.routine(
+                    ast,
+                    cross_references,
+                    *declaration,
+                    format!("{class_name}.{method_name}"),
+                    "method",
+                    *block_node,
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    fn calls_in(
+        &mut self,
+        ast: &Ast,
+        cross_references: &CrossReferences,
+        caller: &str,
+        node: NodeId,
+    ) {
+        ensure_stack(|| {
+            let call = match &ast[node] {
+                ASTNode::ProcedureCall { proc_name, .. } => {
+                    let symbol = cross_references
+                        .references
+                        .get(&node)
+                        .and_then(|declaration| cross_references.declarations.get(declaration));
+                    let kind = match symbol.map(|symbol| &symbol.kind) {
+                        Some(SymbolKind::Procedure { .. }) => "procedure",
+                        Some(_) => "indirect",
+                        None if BuiltinProcedure::ALL
+                            .iter()
+                            .any(|builtin| builtin.name() == proc_name) =>
+                        {
+                            "builtin"
+                        }
+                        None => "external",
+                    };
+                    Some((proc_name.clone(), kind))
+                }
+                ASTNode::MethodCall { object, method, .. } => {
+                    let class_name = cross_references
+                        .references
+                        .get(object)
+                        .and_then(|declaration| cross_references.declarations.get(declaration))
+                        .and_then(|symbol| match &symbol.kind {
+                            SymbolKind::Variable { type_name } => Some(type_name.clone()),
+                            SymbolKind::Type { .. } => Some(symbol.name.clone()),
+                            _ => None,
+                        });
+                    let callee = match class_name {
+                        Some(class_name) => format!("{class_name}.{method}"),
+                        None => method.clone(),
+                    };
+                    Some((callee, "method"))
+                }
+                _ => None,
+            };
+            if let Some((callee, kind)) = call {
+                let location = ast.location(node);
+                match self
+                    .calls
+                    .iter_mut()
+                    .find(|edge| edge.caller == caller && edge.callee == callee)
+                {
+                    Some(edge) => edge.sites.push(location),
+                    None => self.calls.push(CallEdge {
+                        caller: caller.to_string(),
+                        callee,
+                        kind,
+                        sites: vec![location],
+                    }),
+                }
+            }
+
+            for child in ast.children(node) {
+                self.calls_in(ast, cross_references, caller, child);
+            }
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("cross-references are always serializable")
+    }
+}
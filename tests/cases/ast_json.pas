@@ -0,0 +1,5 @@
+program Golden;
+var total : real;
+begin
+   total := -(2 + 3) * 1.5
+end.
@@ -0,0 +1,19 @@
+program BareProcedureCall;
+
+procedure Tick;
+begin
+   writeln(1)
+end;
+
+procedure Tock;
+begin
+   writeln(2);
+   Tick
+end;
+
+begin
+   Tick;
+   Tock();
+   writeln;
+   Tock
+end.
@@ -0,0 +1,5 @@
+﻿{ Starts with a UTF-8 byte order mark }
+program BomSource;
+begin
+   writeln(42)
+end.
@@ -0,0 +1,10 @@
+program Forever;
+
+procedure Recurse(n : integer);
+begin
+   Recurse(n + 1)
+end;
+
+begin
+   Recurse(0)
+end.
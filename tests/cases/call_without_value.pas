@@ -0,0 +1,12 @@
+program CallWithoutValue;
+var x : integer;
+
+procedure Double(a: integer);
+begin
+   writeln(a * 2)
+end;
+
+begin
+   x := 1;
+   Double(Double(x) + 1)
+end.
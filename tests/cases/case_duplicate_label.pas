@@ -0,0 +1,10 @@
+program CaseDuplicateLabel;
+var
+  n : integer;
+begin
+  n := 5;
+  case n of
+    1..5: writeln(1);
+    4..6: writeln(2) //~ ERROR case label '4..6' overlaps '1..5'
+  end
+end.
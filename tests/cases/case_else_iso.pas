@@ -0,0 +1,11 @@
+program CaseElseIso;
+var
+  n : integer;
+begin
+  n := 2;
+  case n of
+    1: writeln(1)
+  else
+    writeln(0)
+  end
+end.
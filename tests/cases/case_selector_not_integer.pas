@@ -0,0 +1,11 @@
+program CaseSelectorNotInteger;
+var
+  x : real;
+begin
+  x := 2.5;
+  case x of
+    1: writeln(1)
+  else
+    writeln(0)
+  end
+end.
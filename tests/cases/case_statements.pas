@@ -0,0 +1,59 @@
+program CaseStatements;
+var
+  small, large, digits : integer;
+
+procedure Tick;
+begin
+  writeln(0)
+end;
+
+procedure Classify(n : integer; var small, large, digits : integer);
+begin
+  case n of
+    -3..-1: writeln(-1);
+    0: Tick;
+    1, 2, 3: small := small + 1;
+    4..9: begin
+      digits := digits + 1;
+      writeln(n)
+    end;
+  else
+    large := large + 1;
+    writeln(n * 100)
+  end
+end;
+
+begin
+  small := 0;
+  large := 0;
+  digits := 0;
+  Classify(-3, small, large, digits);
+  Classify(-2, small, large, digits);
+  Classify(-1, small, large, digits);
+  Classify(0, small, large, digits);
+  Classify(1, small, large, digits);
+  Classify(2, small, large, digits);
+  Classify(3, small, large, digits);
+  Classify(4, small, large, digits);
+  Classify(5, small, large, digits);
+  Classify(6, small, large, digits);
+  Classify(7, small, large, digits);
+  Classify(8, small, large, digits);
+  Classify(9, small, large, digits);
+  Classify(10, small, large, digits);
+  Classify(11, small, large, digits);
+  Classify(12, small, large, digits);
+  writeln(small);
+  writeln(large);
+  writeln(digits);
+  { Without an ELSE, a value no label matches runs nothing }
+  case 42 of
+    1: writeln(1)
+  end;
+  case small * 2 of
+    6: case large of
+         3: writeln(63)
+       end;
+    7: writeln(7)
+  end
+end.
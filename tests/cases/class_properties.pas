@@ -0,0 +1,35 @@
+program Props;
+type
+  TTemp = class
+    fcelsius: integer;
+    writes: integer;
+    constructor Create;
+    procedure SetCelsius(c: integer);
+    property Celsius: integer read fcelsius write SetCelsius;
+    property Count: integer read writes;
+    property Raw: integer write fcelsius;
+  end;
+var
+  t: TTemp;
+
+constructor TTemp.Create;
+begin
+  fcelsius := 0
+end;
+
+procedure TTemp.SetCelsius(c: integer);
+begin
+  writes := writes + 1;
+  fcelsius := c * 10
+end;
+
+begin
+  t := TTemp.Create;
+  t.Celsius := 4;
+  writeln(t.Celsius);
+  t.Celsius := t.Celsius + 1;
+  writeln(t.Celsius, t.Count);
+  t.Raw := 7;
+  writeln(t.Celsius, t.Count);
+  t.Free
+end.
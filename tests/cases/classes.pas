@@ -0,0 +1,59 @@
+program Classes;
+type
+  TCounter = class
+    count: integer;
+    step: integer;
+    constructor Create(start: integer);
+    procedure Add(times: integer);
+    procedure Show;
+    destructor Destroy;
+  end;
+  TPair = class
+    left, right: TCounter;
+    constructor Create;
+  end;
+var
+  c: TCounter;
+  p: TPair;
+
+constructor TCounter.Create(start: integer);
+begin
+  count := start;
+  step := 1
+end;
+
+procedure TCounter.Add(times: integer);
+begin
+  count := count + step * times
+end;
+
+procedure TCounter.Show;
+begin
+  writeln(count, step)
+end;
+
+destructor TCounter.Destroy;
+begin
+  writeln(0 - count)
+end;
+
+constructor TPair.Create;
+begin
+  left := TCounter.Create(1);
+  self.right := TCounter.Create(2)
+end;
+
+begin
+  c := TCounter.Create(10);
+  c.Add(3);
+  c.step := 5;
+  c.Add(2);
+  c.Show;
+  writeln(c.count * 2);
+  p := TPair.Create;
+  p.left.Add(4);
+  p.right.Show();
+  writeln(p.left.count);
+  p.left.Free;
+  c.Destroy
+end.
@@ -0,0 +1,10 @@
+program ConditionNotBoolean;
+var
+  count : integer;
+begin
+  count := 2;
+  writeln(count);
+  { A number is not a condition }
+  if count then
+    writeln(0)
+end.
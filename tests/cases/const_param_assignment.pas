@@ -0,0 +1,14 @@
+program ConstParam;
+var
+  a: integer;
+
+procedure Show(const n: integer);
+begin
+  n := n + 1;
+  writeln(n)
+end;
+
+begin
+  a := 1;
+  Show(a)
+end.
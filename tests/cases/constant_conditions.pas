@@ -0,0 +1,16 @@
+program ConstantConditions;
+var
+  x : integer;
+begin
+  x := 0;
+  if 1 < 2 then
+    x := 1
+  else
+    x := 2;
+  while 0 > 1 do
+    x := x + 1;
+  repeat
+    x := x * 3
+  until 2 * 2 = 4;
+  writeln(x)
+end.
@@ -0,0 +1,22 @@
+PROGRAM Propagation;
+VAR width, height, area, half : INTEGER;
+    ratio : REAL;
+
+PROCEDURE Grow(VAR size : INTEGER; step : INTEGER);
+VAR twice : INTEGER;
+BEGIN
+   step := step + 1;
+   twice := 2;
+   size := size + step * twice
+END;
+
+BEGIN
+   width := 3;
+   height := width + 4;
+   area := width * height;
+   ratio := 1.5;
+   writeln(area, ratio * 2);
+   Grow(width, height);
+   half := width div 2;
+   writeln(half, area - height)
+END.
@@ -0,0 +1,6 @@
+program Cst; { header }
+var a, b : integer;
+begin
+   a := (1 + 2) * 3; // trailing
+   b := a
+end.
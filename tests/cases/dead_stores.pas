@@ -0,0 +1,22 @@
+PROGRAM DeadStores;
+VAR x, y : INTEGER;
+
+PROCEDURE Scale(n : INTEGER; VAR result : INTEGER);
+VAR t : INTEGER;
+BEGIN
+   t := 5;
+   n := 2;
+   t := n;
+   result := t * 3;
+   n := 7;
+   t := 1
+END;
+
+BEGIN
+   x := 1;
+   x := 2;
+   y := x + 1;
+   Scale(y, x);
+   y := x div 0;
+   y := 4
+END.
@@ -0,0 +1,14 @@
+program Recovery;
+var
+  count : integer;
+
+procedure Show(a, b : integer);
+begin
+  writeln(a, b)
+end;
+
+begin
+  count = 1; //~ WARNING assignment is ':='
+  Show(count)
+  //~^ ERROR expects 2 arguments
+end.
@@ -0,0 +1,7 @@
+program Syntax;
+var
+  count : integer;
+begin
+  count := 2
+  writeln(count) //~ ERROR possible missing semicolon
+end.
@@ -0,0 +1,6 @@
+program Diagnostics;
+var
+  count : integer;
+begin
+  count := total + 1 //~ ERROR undefined variable 'total'
+end //~ WARNING missing '.'
@@ -0,0 +1,17 @@
+{ CLASS, PROPERTY, CONSTRUCTOR and DESTRUCTOR are only keywords in Free
+  Pascal, so an ISO program may use them as names }
+program DialectKeywords;
+var
+   class, property : integer;
+
+procedure Constructor(var destructor : integer);
+begin
+   destructor := destructor + 1
+end;
+
+begin
+   class := 1;
+   property := class * 2;
+   Constructor(property);
+   writeln(class, property)
+end.
@@ -0,0 +1,6 @@
+program DivideByZero;
+var a, b : integer;
+begin
+   b := 0;
+   a := 10 div b
+end.
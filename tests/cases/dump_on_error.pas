@@ -0,0 +1,29 @@
+program Dump;
+type
+  TBox = class
+    value: integer;
+    constructor Create(v: integer);
+  end;
+var
+  box, gone: TBox;
+  n: integer;
+
+constructor TBox.Create(v: integer);
+begin
+  value := v
+end;
+
+procedure Divide(a, b: integer);
+var
+  q: integer;
+begin
+  q := a div b
+end;
+
+begin
+  box := TBox.Create(7);
+  gone := TBox.Create(1);
+  gone.Free;
+  n := 0;
+  Divide(box.value, n)
+end.
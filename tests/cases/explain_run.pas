@@ -0,0 +1,18 @@
+program Explain;
+var a, b : integer;
+
+procedure Show(n : integer);
+begin
+   write(n);
+   write(n * 2)
+end;
+
+begin
+   a := 6;
+   b := a * 7;
+   writeln(a, b);
+   Show(a);
+   writeln();
+   writeln();
+   writeln(b div 2)
+end.
@@ -0,0 +1,19 @@
+program GenericMismatch;
+var
+  count: integer;
+  ratio: real;
+
+generic procedure Swap<T>(var a, b: T);
+var
+  tmp: T;
+begin
+  tmp := a;
+  a := b;
+  b := tmp
+end;
+
+begin
+  count := 1;
+  ratio := 0.5;
+  Swap(count, ratio)
+end.
@@ -0,0 +1,31 @@
+program Generics;
+
+var
+   i, j : integer;
+   x, y : real;
+
+generic procedure Swap<T>(var a, b : T);
+var tmp : T;
+begin
+   tmp := a;
+   a := b;
+   b := tmp
+end;
+
+generic procedure Show<T, U>(a : T; b : U);
+begin
+   writeln(a, b)
+end;
+
+begin
+   i := 1;
+   j := 2;
+   Swap(i, j);
+   writeln(i, j);
+   x := 1.5;
+   y := 2.5;
+   Swap(x, y);
+   writeln(x, y);
+   Show(i, x);
+   Show(3 div 2, 1 / 2)
+end.
@@ -0,0 +1,37 @@
+program Branches;
+var
+  a, b, big : integer;
+  r : real;
+
+procedure Sign(n : integer);
+begin
+  if n < 0 then
+    writeln(-1)
+  else if n = 0 then
+    writeln(0)
+  else
+    writeln(1)
+end;
+
+begin
+  a := 3;
+  b := 7;
+  if a < b then big := b else big := a;
+  writeln(big);
+  if a >= b then
+  begin
+    writeln(a)
+  end
+  else
+  begin
+    writeln(b);
+    writeln(a <> b)
+  end;
+  r := 2.5;
+  if r > a then writeln(1) else writeln(0);
+  if a = 3 then if b = 8 then writeln(8) else writeln(9);
+  Sign(-5);
+  Sign(0);
+  Sign(b);
+  writeln(a <= 3, b >= 8)
+end.
@@ -0,0 +1,7 @@
+program InitializerCycle;
+var
+  first : integer = second + 1;
+  second : integer = first * 2;
+begin
+  writeln(first)
+end.
@@ -0,0 +1,25 @@
+program Initializers;
+var
+  base : integer = 2 * 3;
+  scale : real = base / 4;
+  total : integer = base + 1;
+  plain : integer;
+
+procedure Show(n : integer);
+var
+  twice : integer = n * 2;
+  next : integer = twice + 1;
+begin
+  writeln(twice);
+  writeln(next)
+end;
+
+begin
+  plain := total * 10;
+  writeln(base);
+  writeln(scale);
+  writeln(total);
+  writeln(plain);
+  Show(5);
+  Show(7)
+end.
@@ -0,0 +1,10 @@
+{$Q+}
+program IntegerOverflowChecked;
+var big : integer;
+begin
+   big := 2147483646;
+   big := big + 1;
+   writeln(big);
+   big := big + 1;
+   writeln(big)
+end.
@@ -0,0 +1,12 @@
+program IntegerWraparound;
+var big, small : integer;
+begin
+   big := 2147483647;
+   small := -big - 1;
+   writeln(big + 1);
+   writeln(-small);
+   writeln(small div -1);
+   writeln(big * 2);
+   writeln(7 div 2);
+   writeln(7 / 2)
+end.
@@ -0,0 +1,10 @@
+program IrNames;
+var
+  t1, l1 : integer;
+begin
+  { Temporaries and labels never clash with these names }
+  t1 := 2;
+  l1 := 0;
+  while l1 < t1 * 3 do
+    if l1 > t1 then l1 := l1 + 2 else l1 := (l1 + 1) * t1
+end.
@@ -0,0 +1,7 @@
+{ A Latin-1 file: caf, nave. Read as Latin-1 after a warning }
+program Latin1Source;
+var x : integer;
+begin
+   x := 7;
+   writeln(x)
+end.
@@ -0,0 +1,9 @@
+program LogicalOperandNotBoolean;
+var
+  a : integer;
+begin
+  a := 6;
+  writeln((a > 1) and (a < 10));
+  { AND takes BOOLEANs only, so this needs parentheses: (a > 1) and (a < 10) }
+  writeln(a > 1 and a < 10)
+end.
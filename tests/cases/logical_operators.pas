@@ -0,0 +1,18 @@
+program LogicalOperators;
+var
+  a, b : integer;
+begin
+  a := 3;
+  b := 7;
+  writeln((a < b) and (b < 10), (a > b) and (b < 10));
+  writeln((a > b) or (b < 10), (a > b) or (b > 10));
+  writeln(not (a < b), not (a > b));
+  { NOT binds tightest, then AND like *, then OR like + }
+  writeln(not (a < b) and (b < a));
+  writeln((a > b) and (b > a) or (a < b));
+  writeln((a < b) or (a > b) and (b < a));
+  writeln(not not (a = 3));
+  while (a > 0) and not (a = b) do
+    a := a - 1;
+  writeln(a)
+end.
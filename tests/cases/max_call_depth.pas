@@ -0,0 +1,13 @@
+program MaxCallDepth;
+
+procedure Countdown(n : integer);
+begin
+   writeln(n);
+   if n > 0 then
+      Countdown(n - 1)
+end;
+
+begin
+   Countdown(3);
+   Countdown(10)
+end.
@@ -0,0 +1,29 @@
+PROGRAM Metrics;
+VAR
+   total, count : INTEGER;
+
+PROCEDURE Add(value : INTEGER; VAR sum : INTEGER);
+VAR
+   doubled : INTEGER;
+
+   PROCEDURE Report(n : INTEGER);
+   BEGIN
+      writeln(n)
+   END;
+
+BEGIN
+   doubled := value * 2;
+   BEGIN
+      sum := sum + doubled;
+      BEGIN
+         Report(sum)
+      END
+   END
+END;
+
+BEGIN
+   total := 0;
+   count := 3;
+   Add(count, total);
+   writeln(total)
+END.
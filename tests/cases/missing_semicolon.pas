@@ -0,0 +1,6 @@
+program MissingSemicolon;
+var a : integer;
+begin
+   a := 1
+   a := 2
+end.
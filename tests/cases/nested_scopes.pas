@@ -0,0 +1,21 @@
+program NestedScopes;
+var y : real;
+
+procedure Outer(a : integer);
+var y : integer;
+
+   procedure Inner(b : integer);
+   var half : real;
+   begin
+      half := b / 2
+   end;
+
+begin
+   y := a + 1;
+   Inner(y)
+end;
+
+begin
+   y := 3.5;
+   Outer(4)
+end.
@@ -0,0 +1,6 @@
+PROGRAM p;
+VAR _x1, y_2: INTEGER;
+BEGIN
+  _x1 := 3;
+  y_2 := 123abc + _x1;
+END.
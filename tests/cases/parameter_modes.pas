@@ -0,0 +1,33 @@
+program Modes;
+var
+  a, b, total: integer;
+
+procedure Swap(var x, y: integer);
+var
+  t: integer;
+begin
+  t := x;
+  x := y;
+  y := t
+end;
+
+procedure Sum(const x, y: integer; out s: integer);
+begin
+  s := x + y
+end;
+
+procedure Twice(var n: integer);
+begin
+  Sum(n, n, n)
+end;
+
+begin
+  a := 1;
+  b := 2;
+  Swap(a, b);
+  writeln(a, b);
+  Sum(a, b, total);
+  writeln(total);
+  Twice(total);
+  writeln(total)
+end.
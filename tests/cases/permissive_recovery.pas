@@ -0,0 +1,6 @@
+program Sloppy;
+var a : integer;
+begin
+   a = 1;
+   writeln(a)
+end
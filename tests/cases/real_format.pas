@@ -0,0 +1,15 @@
+program RealFormat;
+{ Print REALs the way a textbook would, without an exponent }
+{$REALFORMAT fixed:2}
+var
+  price, total : real;
+  count : integer;
+begin
+  price := 2.5;
+  count := 3;
+  total := price * count;
+  writeln(total);
+  writeln(total / 7);
+  writeln(-price);
+  writeln(count, 0.126)
+end.
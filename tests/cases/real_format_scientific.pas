@@ -0,0 +1,7 @@
+program RealFormatScientific;
+{$REALFORMAT scientific:3}
+begin
+  writeln(1234.5);
+  writeln(-0.00025);
+  writeln(1 / 3)
+end.
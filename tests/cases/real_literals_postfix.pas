@@ -0,0 +1,11 @@
+PROGRAM RealLiterals;
+VAR
+   a, b, c, d : REAL;
+BEGIN
+   a := 3.0;
+   b := 0.1;
+   c := 2.;
+   d := 123456.75;
+   writeln(a, b, c, d);
+   writeln(a + b)
+END.
@@ -0,0 +1,10 @@
+program RealNonFinite;
+var zero, infinity : real;
+begin
+   zero := 0.0;
+   infinity := 1 / zero;
+   writeln(infinity);
+   writeln(-infinity);
+   writeln(zero / zero);
+   writeln(infinity - infinity)
+end.
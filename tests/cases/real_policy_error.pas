@@ -0,0 +1,8 @@
+program RealPolicyError;
+var zero : real;
+begin
+   zero := 0.0;
+   writeln(1.5 / 3);
+   writeln(1 / zero);
+   writeln(2)
+end.
@@ -0,0 +1,17 @@
+program RelationalOperators;
+var
+  a, b : integer;
+  x : real;
+begin
+  a := 3;
+  b := 7;
+  writeln(a = b, a <> b, a < b, a <= b, a > b, a >= b);
+  writeln(a = 3, a <> 3, a < 3, a <= 3, a > 3, a >= 3);
+  { Arithmetic binds tighter, so these compare 4 with 14 and 10 with 10 }
+  writeln(a + 1 < b * 2, a + b = 10, b - a * 2 > 0);
+  writeln(-a < -b, (a < b) = (b > a));
+  { An INTEGER compared with a REAL is converted first }
+  x := 3.0;
+  writeln(a = x, x < b, 2.5 >= a div 2);
+  writeln(a < b > (b < a))
+end.
@@ -0,0 +1,30 @@
+program RepeatLoops;
+var
+  n, digits, i, total : integer;
+
+procedure Tick;
+begin
+  writeln(0)
+end;
+
+begin
+  n := 12345;
+  digits := 0;
+  repeat
+    digits := digits + 1;
+    n := n div 10
+  until n = 0;
+  writeln(digits);
+  { The body runs once even though the condition already holds }
+  repeat
+    writeln(n);
+  until n = 0;
+  i := 0;
+  total := 0;
+  repeat
+    i := i + 1;
+    while total < i * 10 do total := total + 7
+  until i >= 3;
+  writeln(total);
+  repeat Tick until i > 0
+end.
@@ -0,0 +1,9 @@
+program RepeatNotBoolean;
+var
+  count : integer;
+begin
+  count := 3;
+  repeat
+    count := count - 1
+  until count
+end.
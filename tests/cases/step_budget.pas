@@ -0,0 +1,14 @@
+program StepBudget;
+{ Runs 20 steps at a time, pausing and resuming with a fresh budget
+  between them. Each turn goes on where the last one stopped. }
+var i, total : integer;
+begin
+   i := 0;
+   total := 0;
+   while i < 5 do
+   begin
+      total := total + i;
+      writeln(total);
+      i := i + 1
+   end
+end.
@@ -0,0 +1,10 @@
+program Duplicated;
+var a : integer;
+begin
+   a := 1;
+   writeln(a)
+end.
+begin
+   a := 2;
+   writeln(a)
+end.
@@ -0,0 +1,5 @@
+program T;
+var x : real;
+begin
+   x := 2.5 div 1 { ignored }
+end.
@@ -0,0 +1,17 @@
+program Traced;
+var
+  total: integer;
+
+procedure Show(n: integer);
+var
+  doubled: integer;
+begin
+  doubled := n * 2;
+  writeln(doubled)
+end;
+
+begin
+  total := 1;
+  Show(total + 2);
+  writeln(total)
+end.
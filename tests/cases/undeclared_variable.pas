@@ -0,0 +1,5 @@
+program Undeclared;
+var total : integer;
+begin
+   total := count + 1
+end.
@@ -0,0 +1,20 @@
+program UseAfterFree;
+type
+  TBox = class
+    value: integer;
+    constructor Create(v: integer);
+  end;
+var
+  a, b: TBox;
+
+constructor TBox.Create(v: integer);
+begin
+  value := v
+end;
+
+begin
+  a := TBox.Create(7);
+  b := a;
+  a.Free;
+  writeln(b.value)
+end.
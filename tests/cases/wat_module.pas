@@ -0,0 +1,16 @@
+program WatModule;
+var
+  count, total : integer;
+  average : real;
+
+procedure Nothing(x : integer);
+begin
+end;
+
+begin
+  count := 4;
+  total := count * 3 + 2;
+  Nothing(total);
+  total := total div 3;
+  average := total / count
+end.
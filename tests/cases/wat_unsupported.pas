@@ -0,0 +1,8 @@
+program WatUnsupported;
+var
+  x : integer;
+begin
+  x := 1;
+  if x > 0 then
+    x := 2
+end.
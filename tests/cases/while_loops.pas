@@ -0,0 +1,34 @@
+program Loops;
+var
+  n, steps, i, total : integer;
+
+procedure Countdown(n : integer);
+begin
+  while n > 0 do
+  begin
+    writeln(n);
+    n := n - 1
+  end
+end;
+
+begin
+  n := 100;
+  steps := 0;
+  while n > 1 do
+  begin
+    steps := steps + 1;
+    n := n div 2
+  end;
+  writeln(steps, n);
+  { The condition is checked first, so this body never runs }
+  while n > 1 do writeln(0);
+  i := 0;
+  total := 0;
+  while i < 4 do
+  begin
+    i := i + 1;
+    if i = 2 then total := total + 10 else total := total + i
+  end;
+  writeln(total);
+  Countdown(3)
+end.
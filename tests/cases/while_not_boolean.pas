@@ -0,0 +1,9 @@
+program WhileNotBoolean;
+var
+  count : integer;
+begin
+  count := 3;
+  { A number is not a condition; compare it instead }
+  while count do
+    count := count - 1
+end.
@@ -0,0 +1,15 @@
+program WritelnValues;
+var count, half : integer;
+    ratio : real;
+begin
+   count := 7;
+   half := count div 2;
+   ratio := 0.1;
+   write(count);
+   writeln(-count);
+   writeln(half);
+   writeln(ratio, -ratio);
+   writeln(3.5);
+   writeln(1 / 0);
+   writeln()
+end.
@@ -0,0 +1,45 @@
+program ProcValues;
+type
+  TCallback = procedure(x: integer);
+  TAction = procedure;
+  TSame = TCallback;
+var
+  cb: TCallback;
+  other: TSame;
+
+procedure Twice(x: integer);
+begin
+  writeln(x * 2)
+end;
+
+procedure Square(x: integer);
+begin
+  writeln(x * x)
+end;
+
+procedure Hello;
+begin
+  writeln(42)
+end;
+
+procedure Apply(f: TCallback; v: integer);
+begin
+  f(v)
+end;
+
+procedure Run(a: TAction);
+begin
+  a()
+end;
+
+begin
+  cb := Twice;
+  cb(5);
+  cb := Square;
+  cb(5);
+  Apply(Twice, 7);
+  Apply(cb, 3);
+  other := cb;
+  other(4);
+  Run(Hello)
+end.